@@ -1,9 +1,33 @@
-use super::model::{AppState, HopView};
+use super::model::{AppState, HopView, RTT_HISTORY_LEN};
+use std::collections::VecDeque;
+
+/// Braille cells in the RTT history graph; each holds two columns.
+const GRAPH_CELLS: usize = 4;
+const GRAPH_COLUMNS: usize = GRAPH_CELLS * 2;
+/// RTT at which the graph tops out.
+const GRAPH_MAX_RTT_MS: f64 = 200.0;
+/// One character per RTT level (25 ms each) for `--ascii-only`.
+const ASCII_LEVELS: [char; 8] = ['_', '.', '-', '+', '*', '#', '@', '!'];
+/// Braille dot bits of the left and right columns, bottom row first.
+const BRAILLE_LEFT: [u32; 4] = [0x40, 0x04, 0x02, 0x01];
+const BRAILLE_RIGHT: [u32; 4] = [0x80, 0x20, 0x10, 0x08];
 
 #[derive(Debug, Clone, Copy)]
 pub struct UiOpts {
     pub plain: bool,
     pub ascii_only: bool,
+    pub warn_rtt: f64,
+    pub bad_rtt: f64,
+    pub warn_loss: f64,
+    pub bad_loss: f64,
+    pub show_mpls: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    Ok,
+    Warn,
+    Bad,
 }
 
 pub fn render_map(state: &AppState, opts: &UiOpts, term_w: u16, _term_h: u16) -> String {
@@ -11,39 +35,52 @@ pub fn render_map(state: &AppState, opts: &UiOpts, term_w: u16, _term_h: u16) ->
     let width = term_w as usize;
     let banner = "PATH TRACEROUTE INVADERS";
     lines.push(center_line(banner, width));
-    lines.push(center_line(&format!("WAVE {}", state.wave), width));
+    let wave = center_line(&state.wave_label(), width);
+    lines.push(if opts.plain {
+        wave
+    } else {
+        format!("\x1b[1m{wave}\x1b[0m")
+    });
     lines.push("".to_string());
 
     let legend = "OK=green WARN=yellow BAD=red UNKNOWN=dim";
     lines.push(center_line(legend, width));
+    let visible = state.visible_targets();
+    let mut controls = format!("SORT={} (s to cycle)", state.sort_mode.label());
+    if visible.len() < state.targets.len() {
+        controls.push_str(&format!(
+            " HIDDEN={} (loss < {:.0}%)",
+            state.targets.len() - visible.len(),
+            state.filter_min_loss * 100.0
+        ));
+    }
+    lines.push(center_line(&controls, width));
     lines.push("".to_string());
 
+    let ship = "<^>";
+    let inv = "W";
+
     let max_hops = max_hops(state);
+    let cell_width = cell_width(state, inv, opts);
     let ttl_header = (1..=max_hops)
-        .map(|n| (n % 10).to_string())
+        .map(|n| format!("{:<cell_width$}", n % 10))
         .collect::<Vec<_>>()
         .join(" ");
-    lines.push(format!("TTL: {ttl_header}"));
-
-    let ship = if opts.plain || opts.ascii_only {
-        "<^>"
-    } else {
-        "<^>"
-    };
-    let inv = if opts.plain || opts.ascii_only {
-        "W"
-    } else {
-        "W"
-    };
+    lines.push(format!("TTL: {}", ttl_header.trim_end()));
 
-    for target in &state.targets {
-        let row = render_row(inv, &target.hops, max_hops);
-        lines.push(format!("{ship} {row}  {}", target.name));
+    for target in visible {
+        let row = render_row(inv, &target.hops, max_hops, cell_width, opts);
+        if target.rtt_history.is_empty() {
+            lines.push(format!("{ship} {row}  {}", target.name));
+        } else {
+            let graph = rtt_graph(&target.rtt_history, opts.ascii_only);
+            lines.push(format!("{ship} {row}  {graph} {}", target.name));
+        }
     }
 
     lines.push("".to_string());
     if let Some(detail) = &state.last_detail {
-        lines.push(format!("{detail}"));
+        lines.push(detail.to_string());
     } else {
         lines.push("Last hop: (none)".to_string());
     }
@@ -61,18 +98,110 @@ fn max_hops(state: &AppState) -> u32 {
         .max(1)
 }
 
-fn render_row(inv: &str, hops: &[HopView], max_hops: u32) -> String {
+fn cell_width(state: &AppState, inv: &str, opts: &UiOpts) -> usize {
+    state
+        .targets
+        .iter()
+        .flat_map(|t| t.hops.iter())
+        .map(|hop| cell_text(inv, hop, opts).len())
+        .max()
+        .unwrap_or(0)
+        .max(1)
+}
+
+fn cell_text(inv: &str, hop: &HopView, opts: &UiOpts) -> String {
+    match hop.mpls_label {
+        Some(label) if opts.show_mpls => format!("{inv}[{label}]"),
+        _ => inv.to_string(),
+    }
+}
+
+fn render_row(
+    inv: &str,
+    hops: &[HopView],
+    max_hops: u32,
+    cell_width: usize,
+    opts: &UiOpts,
+) -> String {
     let mut cells = Vec::new();
     for idx in 0..max_hops {
-        if let Some(_) = hops.get(idx as usize) {
-            cells.push(inv.to_string());
+        if let Some(hop) = hops.get(idx as usize).filter(|hop| hop.loss.is_some()) {
+            let text = cell_text(inv, hop, opts);
+            let pad = " ".repeat(cell_width.saturating_sub(text.len()));
+            cells.push(format!(
+                "{}{pad}",
+                colorize(&text, hop_severity(hop, opts), opts)
+            ));
         } else {
-            cells.push(".".to_string());
+            cells.push(format!("{:<cell_width$}", "."));
         }
     }
     cells.join("-")
 }
 
+/// Newest-on-the-right graph of the last [`RTT_HISTORY_LEN`] RTTs, each
+/// column showing the worst of two waves in one of 8 levels over
+/// 0-200 ms. Braille draws the levels as 1-4 dot bars, two columns per
+/// cell; ASCII uses one `_.-+*#@!` character per column.
+fn rtt_graph(history: &VecDeque<f64>, ascii_only: bool) -> String {
+    let per_column = RTT_HISTORY_LEN / GRAPH_COLUMNS;
+    let skip = history.len().saturating_sub(RTT_HISTORY_LEN);
+    let missing = RTT_HISTORY_LEN - (history.len() - skip);
+    let mut columns: [Option<usize>; GRAPH_COLUMNS] = [None; GRAPH_COLUMNS];
+    for (slot, rtt) in (missing..).zip(history.iter().skip(skip)) {
+        let level = ((rtt / GRAPH_MAX_RTT_MS * ASCII_LEVELS.len() as f64).max(0.0) as usize)
+            .min(ASCII_LEVELS.len() - 1);
+        let column = &mut columns[slot / per_column];
+        *column = Some(column.map_or(level, |current| current.max(level)));
+    }
+
+    if ascii_only {
+        return columns
+            .iter()
+            .map(|column| column.map_or(' ', |level| ASCII_LEVELS[level]))
+            .collect();
+    }
+    columns
+        .chunks(2)
+        .map(|pair| {
+            let dots = |column: Option<usize>, bits: &[u32; 4]| {
+                column.map_or(0, |level| bits[..level / 2 + 1].iter().sum::<u32>())
+            };
+            let bits = dots(pair[0], &BRAILLE_LEFT) | dots(pair[1], &BRAILLE_RIGHT);
+            char::from_u32(0x2800 + bits).unwrap_or(' ')
+        })
+        .collect()
+}
+
+fn hop_severity(hop: &HopView, opts: &UiOpts) -> Severity {
+    let loss = hop.loss.unwrap_or(0.0);
+    let loss = if loss > opts.bad_loss {
+        Severity::Bad
+    } else if loss > opts.warn_loss {
+        Severity::Warn
+    } else {
+        Severity::Ok
+    };
+    let rtt = match hop.median_rtt {
+        Some(rtt) if rtt > opts.bad_rtt => Severity::Bad,
+        Some(rtt) if rtt > opts.warn_rtt => Severity::Warn,
+        _ => Severity::Ok,
+    };
+    loss.max(rtt)
+}
+
+fn colorize(cell: &str, severity: Severity, opts: &UiOpts) -> String {
+    if opts.plain {
+        return cell.to_string();
+    }
+    let code = match severity {
+        Severity::Ok => "32",
+        Severity::Warn => "33",
+        Severity::Bad => "31",
+    };
+    format!("\x1b[{code}m{cell}\x1b[0m")
+}
+
 fn center_line(text: &str, width: usize) -> String {
     if text.len() >= width {
         return text.to_string();
@@ -84,26 +213,32 @@ fn center_line(text: &str, width: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::invade::model::{AppState, HopView, TargetView};
+    use crate::invade::model::{AppState, HopView, SortMode, TargetView};
 
     #[test]
     fn render_contains_banner_and_rows() {
         let state = AppState {
             wave: 1,
+            total_waves: 1,
             targets: vec![TargetView {
                 name: "1.1.1.1".to_string(),
+                rtt_history: VecDeque::new(),
                 hops: vec![HopView {
                     ttl: 1,
                     ip: Some("1.1.1.1".to_string()),
-                    loss: 0.0,
+                    loss: Some(0.0),
                     median_rtt: Some(10.0),
+                    mpls_label: None,
                 }],
             }],
             last_detail: Some("Last hop demo".to_string()),
+            sort_mode: SortMode::Name,
+            filter_min_loss: 0.0,
         };
         let opts = UiOpts {
             plain: true,
             ascii_only: true,
+            ..test_opts()
         };
         let output = render_map(&state, &opts, 80, 24);
         assert!(output.contains("PATH TRACEROUTE INVADERS"));
@@ -111,18 +246,245 @@ mod tests {
         assert!(output.contains("TTL:"));
     }
 
+    #[test]
+    fn wave_progress_is_shown() {
+        let state = AppState {
+            wave: 2,
+            total_waves: 3,
+            targets: vec![],
+            last_detail: None,
+            sort_mode: SortMode::Name,
+            filter_min_loss: 0.0,
+        };
+        let output = render_map(&state, &test_opts(), 60, 20);
+        assert!(output.contains("\x1b[1m"));
+        assert!(output.contains("WAVE 2 / 3"));
+    }
+
     #[test]
     fn plain_mode_has_no_ansi() {
         let state = AppState {
             wave: 1,
+            total_waves: 1,
             targets: vec![],
             last_detail: None,
+            sort_mode: SortMode::Name,
+            filter_min_loss: 0.0,
         };
         let opts = UiOpts {
             plain: true,
             ascii_only: true,
+            ..test_opts()
         };
         let output = render_map(&state, &opts, 60, 20);
         assert!(!output.contains("\x1b"));
     }
+
+    #[test]
+    fn hop_cells_are_colored_by_severity() {
+        let hop = |ttl: u32, loss: f64, median_rtt: Option<f64>| HopView {
+            ttl,
+            ip: Some("10.0.0.1".to_string()),
+            loss: Some(loss),
+            median_rtt,
+            mpls_label: None,
+        };
+        let opts = test_opts();
+
+        let ok = render_row("W", &[hop(1, 0.0, Some(10.0))], 1, 1, &opts);
+        assert_eq!(ok, "\x1b[32mW\x1b[0m");
+
+        let warn_rtt = render_row("W", &[hop(1, 0.0, Some(100.0))], 1, 1, &opts);
+        assert_eq!(warn_rtt, "\x1b[33mW\x1b[0m");
+
+        let bad_rtt = render_row("W", &[hop(1, 0.5, Some(250.0))], 1, 1, &opts);
+        assert_eq!(bad_rtt, "\x1b[31mW\x1b[0m");
+
+        let warn_loss = render_row("W", &[hop(1, 0.5, Some(10.0))], 1, 1, &opts);
+        assert_eq!(warn_loss, "\x1b[33mW\x1b[0m");
+
+        let bad_loss = render_row("W", &[hop(1, 1.0, None)], 1, 1, &opts);
+        assert_eq!(bad_loss, "\x1b[31mW\x1b[0m");
+
+        let gap = HopView {
+            loss: None,
+            ..hop(0, 0.0, None)
+        };
+        let padded = render_row("W", &[gap, hop(2, 0.0, Some(10.0))], 2, 1, &opts);
+        assert_eq!(padded, ".-\x1b[32mW\x1b[0m");
+    }
+
+    #[test]
+    fn mpls_label_shown_only_when_enabled() {
+        let state = AppState {
+            wave: 1,
+            total_waves: 1,
+            targets: vec![TargetView {
+                name: "1.1.1.1".to_string(),
+                rtt_history: VecDeque::new(),
+                hops: vec![
+                    HopView {
+                        ttl: 1,
+                        ip: Some("10.0.0.1".to_string()),
+                        loss: Some(0.0),
+                        median_rtt: Some(10.0),
+                        mpls_label: Some(101234),
+                    },
+                    HopView {
+                        ttl: 2,
+                        ip: Some("10.0.0.2".to_string()),
+                        loss: Some(0.0),
+                        median_rtt: Some(11.0),
+                        mpls_label: None,
+                    },
+                ],
+            }],
+            last_detail: None,
+            sort_mode: SortMode::Name,
+            filter_min_loss: 0.0,
+        };
+
+        let shown = UiOpts {
+            plain: true,
+            ascii_only: true,
+            show_mpls: true,
+            ..test_opts()
+        };
+        let output = render_map(&state, &shown, 80, 24);
+        assert!(output.contains("W[101234]-W        "));
+        assert!(output.contains("TTL: 1         2"));
+
+        let hidden = UiOpts {
+            plain: true,
+            ascii_only: true,
+            ..test_opts()
+        };
+        let output = render_map(&state, &hidden, 80, 24);
+        assert!(!output.contains("[101234]"));
+        assert!(output.contains("W-W  1.1.1.1"));
+    }
+
+    fn lossy_targets() -> Vec<TargetView> {
+        [("a.example", 0.0), ("b.example", 0.67), ("c.example", 0.34)]
+            .into_iter()
+            .map(|(name, loss)| TargetView {
+                name: name.to_string(),
+                rtt_history: VecDeque::new(),
+                hops: vec![HopView {
+                    ttl: 1,
+                    ip: Some("10.0.0.1".to_string()),
+                    loss: Some(loss),
+                    median_rtt: Some(10.0),
+                    mpls_label: None,
+                }],
+            })
+            .collect()
+    }
+
+    fn row_order(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .filter(|line| line.starts_with("<^>"))
+            .filter_map(|line| line.split_whitespace().last())
+            .collect()
+    }
+
+    #[test]
+    fn loss_sort_puts_worst_target_first() {
+        let mut state = AppState {
+            wave: 1,
+            total_waves: 1,
+            targets: lossy_targets(),
+            last_detail: None,
+            sort_mode: SortMode::Loss,
+            filter_min_loss: 0.0,
+        };
+        let opts = UiOpts {
+            plain: true,
+            ..test_opts()
+        };
+
+        let output = render_map(&state, &opts, 80, 24);
+        assert_eq!(
+            row_order(&output),
+            vec!["b.example", "c.example", "a.example"]
+        );
+        assert!(output.contains("SORT=LOSS"));
+
+        state.sort_mode = state.sort_mode.next().next();
+        let output = render_map(&state, &opts, 80, 24);
+        assert_eq!(
+            row_order(&output),
+            vec!["a.example", "b.example", "c.example"]
+        );
+    }
+
+    #[test]
+    fn min_loss_filter_hides_healthy_targets() {
+        let state = AppState {
+            wave: 1,
+            total_waves: 1,
+            targets: lossy_targets(),
+            last_detail: None,
+            sort_mode: SortMode::Name,
+            filter_min_loss: 0.5,
+        };
+        let opts = UiOpts {
+            plain: true,
+            ..test_opts()
+        };
+
+        let output = render_map(&state, &opts, 80, 24);
+        assert_eq!(row_order(&output), vec!["b.example"]);
+        assert!(output.contains("HIDDEN=2 (loss < 50%)"));
+    }
+
+    #[test]
+    fn rtt_history_draws_braille_or_ascii_graph() {
+        let full: VecDeque<f64> = [
+            0.0, 0.0, 30.0, 10.0, 60.0, 60.0, 80.0, 90.0, 110.0, 100.0, 130.0, 140.0, 170.0, 160.0,
+            500.0, 250.0,
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rtt_graph(&full, false), "\u{28c0}\u{28e4}\u{28f6}\u{28ff}");
+        assert_eq!(rtt_graph(&full, true), "_.-+*#@!");
+
+        let one: VecDeque<f64> = [250.0].into_iter().collect();
+        assert_eq!(rtt_graph(&one, false), "\u{2800}\u{2800}\u{2800}\u{28b8}");
+        assert_eq!(rtt_graph(&one, true), "       !");
+
+        let state = AppState {
+            wave: 2,
+            total_waves: 2,
+            targets: vec![TargetView {
+                name: "1.1.1.1".to_string(),
+                rtt_history: full,
+                hops: Vec::new(),
+            }],
+            last_detail: None,
+            sort_mode: SortMode::Name,
+            filter_min_loss: 0.0,
+        };
+        let output = render_map(&state, &test_opts(), 80, 24);
+        assert!(output.contains("\u{28c0}\u{28e4}\u{28f6}\u{28ff} 1.1.1.1"));
+        let opts = UiOpts {
+            plain: true,
+            ascii_only: true,
+            ..test_opts()
+        };
+        assert!(render_map(&state, &opts, 80, 24).contains("  _.-+*#@! 1.1.1.1"));
+    }
+
+    fn test_opts() -> UiOpts {
+        UiOpts {
+            plain: false,
+            ascii_only: false,
+            warn_rtt: 80.0,
+            bad_rtt: 200.0,
+            warn_loss: 0.34,
+            bad_loss: 0.67,
+            show_mpls: false,
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use super::model::HopView;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct HopLine<'a> {
+    ts: u64,
+    target: &'a str,
+    ttl: u32,
+    ip: Option<&'a str>,
+    loss: Option<f64>,
+    median_rtt: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct ShutdownLine {
+    event: &'static str,
+    ts: u64,
+}
+
+/// NDJSON log of hop updates (`--log-raw`), one object per line, flushed
+/// after every write so the file stays readable while invade is running.
+pub struct HopLog<W: Write> {
+    writer: W,
+}
+
+impl HopLog<File> {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| anyhow!("failed to open log {:?}: {}", path, err))?;
+        Ok(Self::new(file))
+    }
+}
+
+impl<W: Write> HopLog<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn hop(&mut self, ts_ms: u64, target: &str, hop: &HopView) -> Result<()> {
+        self.write_line(&HopLine {
+            ts: ts_ms,
+            target,
+            ttl: hop.ttl,
+            ip: hop.ip.as_deref(),
+            loss: hop.loss,
+            median_rtt: hop.median_rtt,
+        })
+    }
+
+    /// Writes the `{"event":"shutdown"}` sentinel marking a clean exit.
+    pub fn shutdown(&mut self, ts_ms: u64) -> Result<()> {
+        self.write_line(&ShutdownLine {
+            event: "shutdown",
+            ts: ts_ms,
+        })
+    }
+
+    fn write_line<T: Serialize>(&mut self, line: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, line)
+            .map_err(|err| anyhow!("failed to write log line: {err}"))?;
+        self.writer
+            .write_all(b"\n")
+            .and_then(|()| self.writer.flush())
+            .map_err(|err| anyhow!("failed to write log line: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn writes_one_line_per_event_plus_shutdown() {
+        let path =
+            std::env::temp_dir().join(format!("ptroute-invade-log-{}.ndjson", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let events: Vec<HopView> = (1..=4)
+            .map(|ttl| HopView {
+                ttl,
+                ip: (ttl != 2).then(|| format!("10.0.0.{ttl}")),
+                loss: Some(if ttl == 2 { 1.0 } else { 0.0 }),
+                median_rtt: (ttl != 2).then_some(ttl as f64 * 1.5),
+                mpls_label: None,
+            })
+            .collect();
+
+        let mut log = HopLog::open(&path).unwrap();
+        for (i, hop) in events.iter().enumerate() {
+            log.hop(1_000 + i as u64, "1.1.1.1", hop).unwrap();
+        }
+        log.shutdown(2_000).unwrap();
+        drop(log);
+
+        let text = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), events.len() + 1);
+        assert_eq!(lines[0]["target"], "1.1.1.1");
+        assert_eq!(lines[0]["ttl"], 1);
+        assert_eq!(lines[0]["median_rtt"], 1.5);
+        assert!(lines[1]["ip"].is_null());
+        assert_eq!(lines[1]["loss"], 1.0);
+        assert_eq!(lines[4]["event"], "shutdown");
+        assert_eq!(lines[4]["ts"], 2_000);
+    }
+}
@@ -1,5 +1,9 @@
 pub mod draw;
+pub mod log;
 pub mod model;
+pub mod receipt;
 
 pub use draw::{render_map, UiOpts};
-pub use model::{AppState, HopView, TargetView};
+pub use log::HopLog;
+pub use model::{AppState, HopView, SortMode, TargetView};
+pub use receipt::{trace_file, InvadeReceipt};
@@ -1,21 +1,331 @@
-#[derive(Debug, Clone)]
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+/// Waves of end-to-end RTT kept per target for the history graph.
+pub const RTT_HISTORY_LEN: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct HopView {
     pub ttl: u32,
     pub ip: Option<String>,
-    pub loss: f64,
+    /// Share of probes lost, `0.0..=1.0`; `None` for a TTL slot that only
+    /// pads the gap before an out-of-order hop and has not reported yet.
+    pub loss: Option<f64>,
     pub median_rtt: Option<f64>,
+    pub mpls_label: Option<u32>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TargetView {
     pub name: String,
+    /// Median RTT of the furthest answering hop at the end of each wave,
+    /// oldest first, at most [`RTT_HISTORY_LEN`] entries.
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    pub rtt_history: VecDeque<f64>,
     pub hops: Vec<HopView>,
 }
 
+impl TargetView {
+    /// Appends `rtt`, dropping the oldest entry once the history is full.
+    pub fn push_rtt(&mut self, rtt: f64) {
+        if self.rtt_history.len() == RTT_HISTORY_LEN {
+            self.rtt_history.pop_front();
+        }
+        self.rtt_history.push_back(rtt);
+    }
+
+    /// Worst loss over the hops that reported; 0.0 before any has.
+    pub fn max_loss(&self) -> f64 {
+        self.hops
+            .iter()
+            .filter_map(|hop| hop.loss)
+            .fold(0.0, f64::max)
+    }
+
+    /// Slowest median RTT over all hops that answered.
+    pub fn max_rtt(&self) -> Option<f64> {
+        self.hops
+            .iter()
+            .filter_map(|hop| hop.median_rtt)
+            .max_by(f64::total_cmp)
+    }
+}
+
+/// Row order of the invade map; `s` cycles through them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortMode {
+    #[default]
+    Name,
+    /// Highest [`TargetView::max_loss`] first.
+    Loss,
+    /// Highest [`TargetView::max_rtt`] first; targets without replies last.
+    Rtt,
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Loss,
+            SortMode::Loss => SortMode::Rtt,
+            SortMode::Rtt => SortMode::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Name => "NAME",
+            SortMode::Loss => "LOSS",
+            SortMode::Rtt => "RTT",
+        }
+    }
+
+    fn compare(self, a: &TargetView, b: &TargetView) -> Ordering {
+        let by_metric = match self {
+            SortMode::Name => Ordering::Equal,
+            SortMode::Loss => b.max_loss().total_cmp(&a.max_loss()),
+            SortMode::Rtt => match (a.max_rtt(), b.max_rtt()) {
+                (Some(a), Some(b)) => b.total_cmp(&a),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+        by_metric.then_with(|| a.name.cmp(&b.name))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub wave: u32,
+    /// Planned number of waves; `u32::MAX` means run until stopped.
+    pub total_waves: u32,
     pub targets: Vec<TargetView>,
     pub last_detail: Option<String>,
+    pub sort_mode: SortMode,
+    /// Targets whose [`TargetView::max_loss`] is below this are not drawn.
+    pub filter_min_loss: f64,
+}
+
+impl AppState {
+    /// Stores `hop` at its TTL slot, replacing any earlier report for that TTL.
+    pub fn update_hop(&mut self, target: usize, hop: HopView) {
+        let Some(view) = self.targets.get_mut(target) else {
+            return;
+        };
+        let idx = hop.ttl.saturating_sub(1) as usize;
+        if view.hops.len() <= idx {
+            view.hops.resize_with(idx + 1, || HopView {
+                ttl: 0,
+                ip: None,
+                loss: None,
+                median_rtt: None,
+                mpls_label: None,
+            });
+        }
+        view.hops[idx] = hop;
+    }
+
+    /// Sets the loss of an already reported hop, returning the updated hop.
+    pub fn update_loss(&mut self, target: usize, ttl: u32, loss: f64) -> Option<&HopView> {
+        let hop = self
+            .targets
+            .get_mut(target)?
+            .hops
+            .get_mut(ttl.checked_sub(1)? as usize)
+            .filter(|hop| hop.ttl == ttl)?;
+        hop.loss = Some(loss);
+        Some(hop)
+    }
+
+    /// Targets to draw, filtered by `filter_min_loss` and ordered by
+    /// `sort_mode`. `targets` itself keeps its order so indices stay valid.
+    pub fn visible_targets(&self) -> Vec<&TargetView> {
+        let mut visible: Vec<&TargetView> = self
+            .targets
+            .iter()
+            .filter(|target| target.max_loss() >= self.filter_min_loss)
+            .collect();
+        visible.sort_by(|a, b| self.sort_mode.compare(a, b));
+        visible
+    }
+
+    /// Records each target's furthest answering hop RTT in its history;
+    /// called once a wave finishes.
+    pub fn record_rtt_history(&mut self) {
+        for target in &mut self.targets {
+            if let Some(rtt) = target.hops.iter().rev().find_map(|hop| hop.median_rtt) {
+                target.push_rtt(rtt);
+            }
+        }
+    }
+
+    pub fn has_more_waves(&self) -> bool {
+        self.wave < self.total_waves
+    }
+
+    /// Starts the next wave. Every target's hops are cleared so the new
+    /// trace replaces the previous one instead of mixing with it.
+    pub fn next_wave(&mut self) {
+        self.wave = self.wave.saturating_add(1);
+        for target in &mut self.targets {
+            target.hops.clear();
+        }
+    }
+
+    pub fn wave_label(&self) -> String {
+        if self.total_waves == u32::MAX {
+            format!("WAVE {}", self.wave)
+        } else {
+            format!("WAVE {} / {}", self.wave, self.total_waves)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(ttl: u32, ip: &str, rtt: f64) -> HopView {
+        HopView {
+            ttl,
+            ip: Some(ip.to_string()),
+            loss: Some(0.0),
+            median_rtt: Some(rtt),
+            mpls_label: None,
+        }
+    }
+
+    fn state(total_waves: u32) -> AppState {
+        AppState {
+            wave: 1,
+            total_waves,
+            targets: vec![TargetView {
+                name: "1.1.1.1".to_string(),
+                rtt_history: VecDeque::new(),
+                hops: Vec::new(),
+            }],
+            last_detail: None,
+            sort_mode: SortMode::Name,
+            filter_min_loss: 0.0,
+        }
+    }
+
+    #[test]
+    fn waves_count_up_to_total() {
+        let mut state = state(2);
+        assert!(state.has_more_waves());
+        state.next_wave();
+        assert_eq!(state.wave, 2);
+        assert!(!state.has_more_waves());
+        assert_eq!(state.wave_label(), "WAVE 2 / 2");
+
+        let mut endless = self::state(u32::MAX);
+        endless.next_wave();
+        assert!(endless.has_more_waves());
+        assert_eq!(endless.wave_label(), "WAVE 2");
+    }
+
+    #[test]
+    fn update_loss_only_touches_reported_hops() {
+        let mut state = state(1);
+        state.update_hop(0, hop(2, "10.0.0.2", 5.0));
+        assert!(state.update_loss(0, 1, 0.5).is_none());
+        assert_eq!(
+            state.update_loss(0, 2, 0.5).map(|hop| hop.loss),
+            Some(Some(0.5))
+        );
+        assert!(state.update_loss(0, 3, 0.5).is_none());
+        assert!(state.update_loss(1, 2, 0.5).is_none());
+    }
+
+    #[test]
+    fn rtt_history_keeps_the_latest_waves() {
+        let mut state = state(1);
+        state.record_rtt_history();
+        assert!(state.targets[0].rtt_history.is_empty());
+
+        state.update_hop(0, hop(1, "10.0.0.1", 5.0));
+        state.update_hop(
+            0,
+            HopView {
+                median_rtt: None,
+                ..hop(2, "10.0.0.2", 0.0)
+            },
+        );
+        for _ in 0..RTT_HISTORY_LEN {
+            state.record_rtt_history();
+        }
+        state.update_hop(0, hop(2, "10.0.0.2", 9.0));
+        state.record_rtt_history();
+
+        let history = &state.targets[0].rtt_history;
+        assert_eq!(history.len(), RTT_HISTORY_LEN);
+        assert_eq!(history.front(), Some(&5.0));
+        assert_eq!(history.back(), Some(&9.0));
+    }
+
+    #[test]
+    fn rtt_sort_puts_silent_targets_last() {
+        let mut state = state(1);
+        state.targets = ["quiet", "fast", "slow"]
+            .into_iter()
+            .map(|name| TargetView {
+                name: name.to_string(),
+                rtt_history: VecDeque::new(),
+                hops: Vec::new(),
+            })
+            .collect();
+        state.update_hop(1, hop(1, "10.0.0.1", 5.0));
+        state.update_hop(2, hop(1, "10.0.0.1", 5.0));
+        state.update_hop(2, hop(2, "10.0.0.2", 90.0));
+        state.sort_mode = SortMode::Rtt;
+
+        let names: Vec<&str> = state
+            .visible_targets()
+            .iter()
+            .map(|target| target.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["slow", "fast", "quiet"]);
+        assert_eq!(state.targets[0].name, "quiet");
+        assert_eq!(SortMode::Rtt.next(), SortMode::Name);
+    }
+
+    #[test]
+    fn gap_placeholders_do_not_count_as_loss() {
+        let mut state = state(1);
+        state.update_hop(0, hop(3, "10.0.0.3", 4.0));
+        let view = &state.targets[0];
+        assert_eq!(view.hops.len(), 3);
+        assert!(view.hops[..2].iter().all(|hop| hop.loss.is_none()));
+        assert_eq!(view.max_loss(), 0.0);
+
+        state.filter_min_loss = 0.1;
+        assert!(state.visible_targets().is_empty());
+        state.update_loss(0, 3, 0.5);
+        assert_eq!(state.visible_targets().len(), 1);
+        assert_eq!(state.targets[0].max_loss(), 0.5);
+    }
+
+    #[test]
+    fn new_wave_overwrites_hops() {
+        let mut state = state(3);
+        state.update_hop(0, hop(1, "10.0.0.1", 1.0));
+        state.update_hop(0, hop(3, "10.0.0.3", 9.0));
+        assert_eq!(state.targets[0].hops.len(), 3);
+        assert!(state.targets[0].hops[1].ip.is_none());
+
+        state.next_wave();
+        assert!(state.targets[0].hops.is_empty());
+        state.update_hop(0, hop(1, "10.0.0.9", 2.5));
+        let hops = &state.targets[0].hops;
+        assert_eq!(hops.len(), 1);
+        assert_eq!(hops[0].ip.as_deref(), Some("10.0.0.9"));
+        assert_eq!(hops[0].median_rtt, Some(2.5));
+
+        state.record_rtt_history();
+        assert_eq!(state.targets[0].rtt_history.back(), Some(&2.5));
+    }
 }
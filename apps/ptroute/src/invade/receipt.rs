@@ -0,0 +1,56 @@
+use super::model::{AppState, HopView, TargetView};
+use ptroute_model::{Hop, TraceFile, TraceProtocol, TraceRun};
+use serde::{Deserialize, Serialize};
+
+/// Final invade state written to `--out-dir` on a clean exit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvadeReceipt {
+    pub wave: u32,
+    pub targets: Vec<TargetView>,
+    pub finished_at_utc: String,
+}
+
+impl InvadeReceipt {
+    pub fn from_state(state: &AppState, finished_at_utc: &str) -> Self {
+        Self {
+            wave: state.wave,
+            targets: state.targets.clone(),
+            finished_at_utc: finished_at_utc.to_string(),
+        }
+    }
+}
+
+/// One run per target from the latest hops (`--save-traces`). Only the
+/// median RTT survives, so each hop carries it as a single probe; hops
+/// without replies get one lost probe. Gap placeholders are left out.
+pub fn trace_file(state: &AppState, timestamp_utc: &str) -> TraceFile {
+    TraceFile {
+        version: TraceFile::VERSION,
+        runs: state
+            .targets
+            .iter()
+            .map(|target| TraceRun {
+                target: target.name.clone(),
+                timestamp_utc: timestamp_utc.to_string(),
+                hops: target
+                    .hops
+                    .iter()
+                    .filter(|hop| hop.loss.is_some())
+                    .map(trace_hop)
+                    .collect(),
+                label: None,
+                source_ip: None,
+                protocol: TraceProtocol::Udp,
+            })
+            .collect(),
+    }
+}
+
+fn trace_hop(hop: &HopView) -> Hop {
+    Hop {
+        ttl: hop.ttl,
+        ip: hop.ip.clone(),
+        rtt_ms: vec![hop.median_rtt],
+        mpls_labels: hop.mpls_label.into_iter().collect(),
+    }
+}
@@ -1,22 +1,42 @@
 use anyhow::{anyhow, Result};
 mod invade;
+mod watch;
 use chrono::{SecondsFormat, Utc};
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use crossterm::{cursor, event, execute, terminal};
-use ptroute_graph::{build_graph, layout_graph};
-use ptroute_model::{SceneFile, TraceFile, TraceRun};
-use ptroute_render::{render_scene, render_scene_progressive, write_png, RenderSettings};
-use ptroute_trace::{run_traces, TraceJobResult, TraceSettings};
-use ptroute_trace::{stream_for_target, TraceEvent};
+use ptroute_graph::pagerank::{DEFAULT_DAMPING, DEFAULT_ITERATIONS};
+use ptroute_graph::{
+    annotate_page_rank, betweenness_centrality, betweenness_centrality_weighted,
+    build_graph_with_options, layout_graph_with_options, normalize_scene, to_adjacency_csv,
+    to_gexf, BuildOptions, GraphQuery, LayoutAlgo, LayoutOptions, DEFAULT_FORCE_ITERATIONS,
+};
+use ptroute_model::{Edge, GraphFile, Node, SceneFile, SceneLayoutDiff, TraceFile, TraceRun};
+use ptroute_render::{
+    render_scene_comparison, render_scene_importance_map, render_scene_progressive_to_apng,
+    render_scene_progressive_to_file, render_scene_to_file, scene_bvh, to_obj, to_svg, write_png,
+    CameraStyle, NodeColorScheme, NodeMaterial, RenderSettings,
+};
+use ptroute_trace::{
+    detect_traceroute_flags, run_traces_batched, timeout_mode_warning, TimeoutMode, TraceJobResult,
+    TraceSettings,
+};
+use ptroute_trace::{loss_fraction, spawn_traceroute_stream, TraceEvent};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::collections::VecDeque;
 use std::fs;
-use std::io::Write;
 use std::io::{self, IsTerminal};
-use std::path::PathBuf;
-use std::process::Command;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_SEED: u64 = 1;
+/// Frames in a `render --apng` animation when `--progressive-every` is unset.
+const APNG_DEFAULT_FRAMES: u32 = 16;
 
 #[derive(Parser)]
 #[command(name = "ptroute", version, about = "PathTraceRoute CLI")]
@@ -31,6 +51,9 @@ enum Commands {
     Build(BuildArgs),
     Layout(LayoutArgs),
     Render(RenderArgs),
+    Compare(CompareArgs),
+    Export(ExportArgs),
+    Stats(StatsArgs),
     Run(RunArgs),
     Doctor(DoctorArgs),
     Invade(InvadeArgs),
@@ -59,14 +82,43 @@ struct TraceArgs {
     #[arg(long, default_value_t = 2000)]
     timeout_ms: u64,
 
+    /// `per-probe` (`-w`) or `per-hop` (probes sent at once where traceroute
+    /// supports `-N`, else a split `-w` of at least a second per probe).
+    #[arg(long, default_value = "per-probe")]
+    timeout_mode: TimeoutMode,
+
+    #[arg(long)]
+    total_timeout_ms: Option<u64>,
+
+    /// Retry a traceroute that fails to start (not one that fails to parse).
+    #[arg(long, default_value_t = 0)]
+    max_retries: u32,
+
+    #[arg(long, default_value_t = 500)]
+    retry_delay_ms: u64,
+
     #[arg(long, default_value_t = 4)]
     concurrency: usize,
 
+    /// Trace at most this many targets at a time (0 = all at once).
+    #[arg(long, default_value_t = 0)]
+    batch_size: usize,
+
     #[arg(long, default_value_t = 1)]
     repeat: u32,
 
     #[arg(long, default_value_t = 0)]
     interval_ms: u64,
+
+    #[arg(long)]
+    label: Option<String>,
+
+    #[arg(long)]
+    detect_source_ip: bool,
+
+    /// Probe packet length in bytes (e.g. 1400 to find path MTU problems).
+    #[arg(long)]
+    probe_size_bytes: Option<u32>,
 }
 
 #[derive(Args)]
@@ -76,6 +128,42 @@ struct BuildArgs {
 
     #[arg(long)]
     out: PathBuf,
+
+    #[arg(long)]
+    no_unknown: bool,
+
+    #[arg(long)]
+    filter_private_ips: bool,
+
+    #[arg(long)]
+    no_deduplicate: bool,
+
+    #[arg(long = "filter-target")]
+    filter_targets: Vec<String>,
+
+    #[arg(long)]
+    center: Option<String>,
+
+    #[arg(long, default_value_t = 2, requires = "center")]
+    depth: u32,
+
+    #[arg(long)]
+    annotate_pagerank: bool,
+
+    #[arg(long)]
+    max_runs_per_target: Option<usize>,
+
+    /// Drop nodes and edges seen fewer than this many times.
+    #[arg(long)]
+    min_seen: Option<u32>,
+
+    /// Refuse traces that fail `TraceFile::validate`.
+    #[arg(long)]
+    strict: bool,
+
+    /// Keep only the minimum spanning tree by `rtt_delta_ms_avg`.
+    #[arg(long)]
+    mst: bool,
 }
 
 #[derive(Args)]
@@ -86,8 +174,50 @@ struct LayoutArgs {
     #[arg(long)]
     out: PathBuf,
 
-    #[arg(long, default_value_t = 1)]
-    seed: u64,
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[arg(long)]
+    seed_from_time: bool,
+
+    #[arg(long)]
+    normalize: bool,
+
+    /// `depth` (hop depth along X), `circular` (concentric rings by depth)
+    /// or `force3d` (3D force-directed).
+    #[arg(long, default_value = "depth")]
+    algo: LayoutAlgo,
+
+    /// Iterations for `--algo force3d`.
+    #[arg(long, default_value_t = DEFAULT_FORCE_ITERATIONS)]
+    force_iterations: usize,
+
+    /// Print to stderr how the new layout differs from this scene.json.
+    #[arg(long)]
+    compare_scene: Option<PathBuf>,
+
+    /// Fix a node at a position, as `<ip>=<x>,<y>,<z>`; repeatable.
+    #[arg(
+        long = "pin",
+        value_name = "IP=X,Y,Z",
+        value_parser = parse_pin,
+        conflicts_with_all = ["normalize", "fit"]
+    )]
+    pins: Vec<(String, [f32; 3])>,
+
+    /// Scale the layout to fit a box this size centered on the origin.
+    #[arg(
+        long,
+        num_args = 3,
+        value_names = ["WIDTH", "HEIGHT", "DEPTH"],
+        value_parser = parse_fit_size,
+        conflicts_with = "normalize"
+    )]
+    fit: Option<Vec<f32>>,
+
+    /// With `--fit`, fill the box on every axis instead of keeping proportions.
+    #[arg(long, requires = "fit")]
+    fit_stretch: bool,
 }
 
 #[derive(Args)]
@@ -121,9 +251,175 @@ struct RenderArgs {
 
     #[arg(long, default_value_t = 0)]
     progressive_every: u32,
+
+    #[arg(long, default_value_t = 1.0)]
+    emission_scale: f32,
+
+    #[arg(long = "emission-rtt-knee", default_value_t = 50.0)]
+    emission_rtt_knee_ms: f32,
+
+    #[arg(long, default_value_t = 0.0)]
+    emission_min: f32,
+
+    /// Dim links by `exp(-depth * (decay - 1))`; 1.0 keeps every hop equal.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_depth_decay)]
+    emission_depth_decay: f32,
+
+    #[arg(long, default_value_t = 0.15)]
+    node_radius_base: f32,
+
+    #[arg(long = "node-radius-scale", default_value_t = 0.05)]
+    node_radius_seen_scale: f32,
+
+    #[arg(long = "node-color", default_value = "id")]
+    node_color: NodeColorScheme,
+
+    /// Shorthand for `--node-color rtt`.
+    #[arg(long, conflicts_with = "node_color")]
+    color_by_rtt: bool,
+
+    /// Node RTT drawn fully red by `--node-color rtt`.
+    #[arg(long, default_value_t = 200.0)]
+    rtt_color_scale_ms: f64,
+
+    #[arg(long)]
+    show_labels: bool,
+
+    /// `perspective`, `topdown` or `isometric` (both orthographic; CPU only).
+    #[arg(long, default_value = "perspective")]
+    camera: CameraStyle,
+
+    /// `svg` skips path tracing and writes a flat vector drawing instead.
+    #[arg(long, value_enum, default_value_t = RenderFormat::Png)]
+    format: RenderFormat,
+
+    /// Only trace pixels with x_min <= x < x_max and y_min <= y < y_max;
+    /// the rest of the full-size image stays black.
+    #[arg(
+        long,
+        num_args = 4,
+        value_names = ["X_MIN", "Y_MIN", "X_MAX", "Y_MAX"],
+        conflicts_with = "importance_map"
+    )]
+    crop: Option<Vec<u32>>,
+
+    /// Leave spheres outside the camera view out of the BVH (off-screen links
+    /// then no longer light the scene).
+    #[arg(long)]
+    frustum_cull: bool,
+
+    /// Draw a grey floor under the scene (CPU only).
+    #[arg(long)]
+    ground_plane: bool,
+
+    /// `diffuse`, `metal` or `glass` node spheres. Metal and glass turn on
+    /// reflection and refraction (CPU only).
+    #[arg(long, default_value = "diffuse")]
+    node_material: NodeMaterial,
+
+    /// Print BVH statistics before rendering.
+    #[arg(long)]
+    verbose: bool,
+
+    /// Print the BVH's SAH cost, average depth and empty leaves before
+    /// rendering.
+    #[arg(long)]
+    bvh_report: bool,
+
+    /// Trace on the GPU (needs the `gpu` build feature); falls back to the
+    /// CPU with a warning when no adapter is available.
+    #[arg(long, conflicts_with = "progressive_every")]
+    gpu: bool,
+
+    /// Write a heatmap of per-pixel sample variance (blue low, red high)
+    /// after spp/4 samples instead of the render.
+    #[arg(long, conflicts_with_all = ["progressive_every", "gpu"])]
+    importance_map: bool,
+
+    /// Write the render's convergence as an animated PNG, one frame per
+    /// `--progressive-every` samples (spp/16 when unset).
+    #[arg(long, conflicts_with_all = ["gpu", "importance_map"])]
+    apng: bool,
+
+    /// Frame rate of the `--apng` animation.
+    #[arg(long, default_value_t = 5, requires = "apng")]
+    fps: u32,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RenderFormat {
+    Png,
+    Svg,
 }
 
 #[derive(Args)]
+#[command(about = "Render two scenes side by side with a difference panel.")]
+struct CompareArgs {
+    #[arg(long)]
+    before: PathBuf,
+
+    #[arg(long)]
+    after: PathBuf,
+
+    #[arg(long)]
+    out: PathBuf,
+
+    #[arg(long, default_value_t = 800)]
+    width: u32,
+
+    #[arg(long, default_value_t = 450)]
+    height: u32,
+
+    #[arg(long, default_value_t = 64)]
+    spp: u32,
+
+    #[arg(long, default_value_t = 6)]
+    bounces: u32,
+
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+}
+
+#[derive(Args)]
+#[command(about = "Convert graph.json (or scene.json for obj) into formats used by other tools.")]
+struct ExportArgs {
+    #[arg(long, value_enum)]
+    format: ExportFormat,
+
+    #[arg(long = "in")]
+    in_path: PathBuf,
+
+    #[arg(long)]
+    out: PathBuf,
+}
+
+#[derive(Args)]
+#[command(about = "Print summary statistics for graph.json.")]
+struct StatsArgs {
+    #[arg(long = "in")]
+    in_path: PathBuf,
+
+    /// Print the top 5 nodes by betweenness centrality.
+    #[arg(long)]
+    centrality: bool,
+
+    /// Weight centrality shortest paths by rtt_delta_ms_avg instead of hops.
+    #[arg(long, requires = "centrality")]
+    weighted: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Gexf,
+    AdjacencyMatrix,
+    /// Wavefront OBJ from scene.json, with a .mtl written next to it.
+    Obj,
+}
+
+#[derive(Args, Clone)]
 struct RunArgs {
     #[arg(long)]
     targets: Option<PathBuf>,
@@ -134,8 +430,14 @@ struct RunArgs {
     #[arg(long)]
     out_dir: Option<PathBuf>,
 
-    #[arg(long, default_value_t = 1)]
-    seed: u64,
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[arg(long)]
+    seed_from_time: bool,
+
+    #[arg(long)]
+    normalize: bool,
 
     #[arg(long, default_value_t = 1600)]
     width: u32,
@@ -158,6 +460,31 @@ struct RunArgs {
     #[arg(long, default_value_t = 0)]
     progressive_every: u32,
 
+    #[arg(long, default_value_t = 1.0)]
+    emission_scale: f32,
+
+    #[arg(long = "emission-rtt-knee", default_value_t = 50.0)]
+    emission_rtt_knee_ms: f32,
+
+    #[arg(long, default_value_t = 0.0)]
+    emission_min: f32,
+
+    /// Dim links by `exp(-depth * (decay - 1))`; 1.0 keeps every hop equal.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_depth_decay)]
+    emission_depth_decay: f32,
+
+    #[arg(long, default_value_t = 0.15)]
+    node_radius_base: f32,
+
+    #[arg(long = "node-radius-scale", default_value_t = 0.05)]
+    node_radius_seen_scale: f32,
+
+    #[arg(long = "node-color", default_value = "id")]
+    node_color: NodeColorScheme,
+
+    #[arg(long)]
+    show_labels: bool,
+
     #[arg(long, default_value_t = 30)]
     max_hops: u32,
 
@@ -167,15 +494,44 @@ struct RunArgs {
     #[arg(long, default_value_t = 2000)]
     timeout_ms: u64,
 
+    #[arg(long)]
+    total_timeout_ms: Option<u64>,
+
+    /// Retry a traceroute that fails to start (not one that fails to parse).
+    #[arg(long, default_value_t = 0)]
+    max_retries: u32,
+
+    #[arg(long, default_value_t = 500)]
+    retry_delay_ms: u64,
+
     #[arg(long, default_value_t = 4)]
     concurrency: usize,
 
+    /// Trace at most this many targets at a time (0 = all at once).
+    #[arg(long, default_value_t = 0)]
+    batch_size: usize,
+
     #[arg(long, default_value_t = 1)]
     repeat: u32,
 
     #[arg(long, default_value_t = 0)]
     interval_ms: u64,
 
+    #[arg(long)]
+    label: Option<String>,
+
+    #[arg(long)]
+    detect_source_ip: bool,
+
+    #[arg(long)]
+    no_unknown: bool,
+
+    #[arg(long)]
+    filter_private_ips: bool,
+
+    #[arg(long)]
+    no_deduplicate: bool,
+
     #[arg(long)]
     resume: bool,
 
@@ -187,6 +543,29 @@ struct RunArgs {
 
     #[arg(long)]
     open: bool,
+
+    #[arg(long)]
+    watch: bool,
+
+    #[arg(long, default_value_t = 300)]
+    interval_secs: u64,
+
+    /// Prune all but the newest n watch runs; must be at least 1 so the run
+    /// just written survives.
+    #[arg(long, value_parser = parse_keep)]
+    keep: Option<usize>,
+
+    #[arg(long)]
+    on_change_only: bool,
+
+    /// Print the run.json receipt to stdout when the run finishes, or
+    /// `{"success": false, "error": ...}` when it fails.
+    #[arg(long, conflicts_with = "watch")]
+    output_json: bool,
+
+    /// Like `--output-json`, without pretty-printing.
+    #[arg(long, conflicts_with_all = ["watch", "output_json"])]
+    output_json_compact: bool,
 }
 
 #[derive(Clone, Copy)]
@@ -255,6 +634,14 @@ impl Ui {
 struct DoctorArgs {
     #[arg(long, default_value = "output")]
     out_dir: PathBuf,
+
+    /// Host to ping for the reachability check (default 1.1.1.1, warn only).
+    #[arg(long)]
+    check_target: Option<String>,
+
+    /// Print the check results as JSON on stdout.
+    #[arg(long)]
+    json: bool,
 }
 
 #[derive(Args)]
@@ -286,6 +673,9 @@ struct InvadeArgs {
     #[arg(long, default_value_t = 2000)]
     wave_interval_ms: u64,
 
+    #[arg(long)]
+    infinite_waves: bool,
+
     #[arg(long, default_value_t = 80)]
     refresh_ms: u64,
 
@@ -307,6 +697,9 @@ struct InvadeArgs {
     #[arg(long)]
     ascii_only: bool,
 
+    #[arg(long)]
+    show_mpls: bool,
+
     #[arg(long)]
     no_ansi: bool,
 
@@ -314,10 +707,18 @@ struct InvadeArgs {
     save_traces: Option<PathBuf>,
 
     #[arg(long)]
-    log_raw: bool,
+    log_raw: Option<PathBuf>,
 
     #[arg(long)]
     out_dir: Option<PathBuf>,
+
+    /// Row order; `s` cycles it while running.
+    #[arg(long, value_enum, default_value_t = invade::SortMode::Name)]
+    sort_by: invade::SortMode,
+
+    /// Hide targets whose worst hop loss (0.0-1.0) is below this.
+    #[arg(long, default_value_t = 0.0)]
+    filter_min_loss: f64,
 }
 
 #[derive(Serialize)]
@@ -326,6 +727,8 @@ struct RunArgsSummary {
     targets: Vec<String>,
     out_dir: PathBuf,
     seed: u64,
+    seed_from_time: bool,
+    normalize: bool,
     width: u32,
     height: u32,
     spp: u32,
@@ -333,16 +736,37 @@ struct RunArgsSummary {
     progress_every: u32,
     threads: usize,
     progressive_every: u32,
+    emission_scale: f32,
+    emission_rtt_knee_ms: f32,
+    emission_min: f32,
+    emission_depth_decay: f32,
+    node_radius_base: f32,
+    node_radius_seen_scale: f32,
+    node_color: &'static str,
+    show_labels: bool,
     max_hops: u32,
     probes: u32,
     timeout_ms: u64,
+    total_timeout_ms: Option<u64>,
     concurrency: usize,
+    batch_size: usize,
     repeat: u32,
     interval_ms: u64,
+    label: Option<String>,
+    detect_source_ip: bool,
+    no_unknown: bool,
+    filter_private_ips: bool,
+    no_deduplicate: bool,
     resume: bool,
     force: bool,
     plain: bool,
     open: bool,
+    watch: bool,
+    interval_secs: u64,
+    keep: Option<usize>,
+    on_change_only: bool,
+    output_json: bool,
+    output_json_compact: bool,
 }
 
 #[derive(Serialize)]
@@ -385,6 +809,9 @@ fn run() -> Result<()> {
         Commands::Build(args) => run_build(args),
         Commands::Layout(args) => run_layout(args),
         Commands::Render(args) => run_render(args),
+        Commands::Compare(args) => run_compare(args),
+        Commands::Export(args) => run_export(args),
+        Commands::Stats(args) => run_stats(args),
         Commands::Run(args) => run_run(args),
         Commands::Doctor(args) => run_doctor(args),
         Commands::Invade(args) => run_invade(args),
@@ -416,24 +843,48 @@ fn run_trace(args: TraceArgs) -> Result<()> {
         max_hops: args.max_hops,
         probes: args.probes,
         timeout_ms: args.timeout_ms,
+        detect_source_ip: args.detect_source_ip,
+        total_timeout_ms: args.total_timeout_ms,
+        max_retries: args.max_retries,
+        retry_delay_ms: args.retry_delay_ms,
+        probe_size_bytes: args.probe_size_bytes,
+        timeout_mode: args.timeout_mode,
     };
+    if cfg!(not(target_os = "windows")) {
+        if let Some(warning) =
+            timeout_mode_warning(&settings, &detect_traceroute_flags("traceroute"))
+        {
+            eprintln!("warning: {warning}");
+        }
+    }
 
-    let results = run_traces(
+    let results = run_traces_batched(
         &targets,
         &settings,
         args.repeat,
         args.interval_ms,
         args.concurrency,
+        args.batch_size,
     );
 
     let mut runs: Vec<TraceRun> = Vec::new();
 
     for TraceJobResult {
-        target: _,
-        repeat: _,
+        target,
+        repeat,
         result,
+        timed_out,
+        attempts,
     } in results
     {
+        if timed_out {
+            eprintln!(
+                "warning: traceroute for {target} (repeat {repeat}) hit --total-timeout-ms; keeping partial hops"
+            );
+        }
+        if attempts > 1 {
+            eprintln!("note: traceroute for {target} (repeat {repeat}) needed {attempts} attempts");
+        }
         match result {
             Ok(parsed) => {
                 let timestamp_utc = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
@@ -441,6 +892,9 @@ fn run_trace(args: TraceArgs) -> Result<()> {
                     target: parsed.target,
                     timestamp_utc,
                     hops: parsed.hops,
+                    label: args.label.clone(),
+                    source_ip: parsed.source_ip,
+                    protocol: parsed.protocol,
                 });
             }
             Err(message) => {
@@ -449,32 +903,174 @@ fn run_trace(args: TraceArgs) -> Result<()> {
         }
     }
 
-    write_json(&args.out, &TraceFile { version: 1, runs })
+    write_value(
+        &args.out,
+        &TraceFile {
+            version: TraceFile::VERSION,
+            runs,
+        },
+    )
 }
 
 fn run_build(args: BuildArgs) -> Result<()> {
-    let contents = fs::read_to_string(&args.in_path)
-        .map_err(|err| anyhow!("failed to read input {:?}: {}", args.in_path, err))?;
-    let trace_file: TraceFile = serde_json::from_str(&contents)
-        .map_err(|err| anyhow!("failed to parse traces {:?}: {}", args.in_path, err))?;
-    let graph = build_graph(&trace_file);
-    write_json(&args.out, &graph)
+    let mut trace_file: TraceFile = read_value(&args.in_path, "traces")?;
+    trace_file.migrate_to_current();
+    if args.strict {
+        let errors = trace_file.validate();
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            return Err(anyhow!(
+                "{} failed validation:\n  {}",
+                args.in_path.display(),
+                errors.join("\n  ")
+            ));
+        }
+    }
+    if !args.filter_targets.is_empty() {
+        let targets: Vec<&str> = args.filter_targets.iter().map(String::as_str).collect();
+        trace_file = trace_file.filter_targets(&targets);
+        if trace_file.runs.is_empty() {
+            return Err(anyhow!(
+                "no runs match --filter-target {}",
+                args.filter_targets.join(", ")
+            ));
+        }
+    }
+    let options = BuildOptions {
+        filter_unknown: args.no_unknown,
+        filter_private_ips: args.filter_private_ips,
+        keep_duplicate_hops: args.no_deduplicate,
+        max_runs_per_target: args.max_runs_per_target,
+    };
+    let mut graph = build_graph_with_options(&trace_file, &options);
+    if let Some(min_seen) = args.min_seen {
+        graph = graph.prune_cold_nodes(min_seen);
+    }
+    if let Some(center) = &args.center {
+        if !graph.nodes.iter().any(|node| &node.id == center) {
+            return Err(anyhow!("--center {} is not a node in the graph", center));
+        }
+        graph = graph.subgraph_around(center, args.depth);
+    }
+    if args.mst {
+        graph = graph.minimum_spanning_tree();
+    }
+    if args.annotate_pagerank {
+        annotate_page_rank(&mut graph, DEFAULT_DAMPING, DEFAULT_ITERATIONS);
+    }
+    write_value(&args.out, &graph)
 }
 
 fn run_layout(args: LayoutArgs) -> Result<()> {
-    let contents = fs::read_to_string(&args.in_path)
-        .map_err(|err| anyhow!("failed to read input {:?}: {}", args.in_path, err))?;
-    let graph: ptroute_model::GraphFile = serde_json::from_str(&contents)
-        .map_err(|err| anyhow!("failed to parse graph {:?}: {}", args.in_path, err))?;
-    let scene: SceneFile = layout_graph(&graph, args.seed);
-    write_json(&args.out, &scene)
+    let graph: GraphFile = read_value(&args.in_path, "graph")?;
+    let seed = resolve_seed(args.seed, args.seed_from_time, SystemTime::now());
+    let options = LayoutOptions {
+        algo: args.algo,
+        force_iterations: args.force_iterations,
+        pinned_nodes: args.pins.into_iter().collect(),
+        target_bbox: args.fit.map(|fit| [fit[0], fit[1], fit[2]]),
+        uniform_scale: !args.fit_stretch,
+        ..LayoutOptions::default()
+    };
+    let mut scene = layout_graph_with_options(&graph, seed, &options);
+    if args.normalize {
+        scene = normalize_scene(&scene);
+    }
+    if let Some(path) = &args.compare_scene {
+        let previous: SceneFile = read_value(path, "scene")?;
+        print_layout_diff(path, &previous.layout_diff(&scene, LAYOUT_MOVED_THRESHOLD));
+    }
+    write_value(&args.out, &scene)
+}
+
+/// Parses a `layout --pin` value: `<ip>=<x>,<y>,<z>`.
+fn parse_pin(value: &str) -> Result<(String, [f32; 3]), String> {
+    let invalid = || format!("invalid pin {value:?} (expected <ip>=<x>,<y>,<z>)");
+    let (id, coords) = value.split_once('=').ok_or_else(invalid)?;
+    let coords: Vec<f32> = coords
+        .split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    match (id.trim(), coords.as_slice()) {
+        ("", _) => Err(invalid()),
+        (id, &[x, y, z]) if coords.iter().all(|c| c.is_finite()) => Ok((id.to_string(), [x, y, z])),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_fit_size(value: &str) -> Result<f32, String> {
+    match value.trim().parse::<f32>() {
+        Ok(size) if size.is_finite() && size > 0.0 => Ok(size),
+        _ => Err(format!(
+            "invalid fit size {value:?} (expected a finite number above 0)"
+        )),
+    }
+}
+
+fn parse_keep(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("--keep must be at least 1 (omit it to keep every run)".to_string()),
+        Ok(keep) => Ok(keep),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parses `--emission-depth-decay`. Below 1.0 deeper links would glow
+/// brighter without bound, so those values are rejected.
+fn parse_depth_decay(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(decay) if decay >= 1.0 && decay.is_finite() => Ok(decay),
+        Ok(_) => Err("--emission-depth-decay must be a finite value of at least 1.0".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Distance a node has to move before `layout --compare-scene` reports it.
+const LAYOUT_MOVED_THRESHOLD: f32 = 0.5;
+
+fn print_layout_diff(path: &Path, diff: &SceneLayoutDiff) {
+    eprintln!(
+        "layout: vs {}: {} moved, {} added, {} removed",
+        path.display(),
+        diff.moved.len(),
+        diff.added.len(),
+        diff.removed.len()
+    );
+    let fmt_pos = |p: [f32; 3]| format!("({:.3}, {:.3}, {:.3})", p[0], p[1], p[2]);
+    for (id, distance, before, after) in &diff.moved {
+        eprintln!(
+            "  moved   {id}  {distance:.3}  {} -> {}",
+            fmt_pos(*before),
+            fmt_pos(*after)
+        );
+    }
+    for id in &diff.added {
+        eprintln!("  added   {id}");
+    }
+    for id in &diff.removed {
+        eprintln!("  removed {id}");
+    }
 }
 
 fn run_render(args: RenderArgs) -> Result<()> {
-    let contents = fs::read_to_string(&args.in_path)
-        .map_err(|err| anyhow!("failed to read input {:?}: {}", args.in_path, err))?;
-    let scene: SceneFile = serde_json::from_str(&contents)
-        .map_err(|err| anyhow!("failed to parse scene {:?}: {}", args.in_path, err))?;
+    let scene: SceneFile = read_value(&args.in_path, "scene")?;
+
+    let crop_region = args
+        .crop
+        .as_deref()
+        .map(|crop| crop_region(crop, args.width, args.height))
+        .transpose()?;
+
+    if let RenderFormat::Svg = args.format {
+        if crop_region.is_some() {
+            return Err(anyhow!(
+                "--crop only applies to path-traced renders, not svg"
+            ));
+        }
+        let svg = to_svg(&scene, args.width, args.height);
+        return atomic_write(&args.out, svg.as_bytes());
+    }
 
     let settings = RenderSettings {
         width: args.width,
@@ -484,8 +1080,47 @@ fn run_render(args: RenderArgs) -> Result<()> {
         seed: args.seed,
         progress_every: args.progress_every,
         threads: args.threads,
+        emission_scale: args.emission_scale,
+        emission_rtt_knee_ms: args.emission_rtt_knee_ms,
+        emission_min: args.emission_min,
+        emission_depth_decay: args.emission_depth_decay,
+        node_radius_base: args.node_radius_base,
+        node_radius_seen_scale: args.node_radius_seen_scale,
+        node_color_scheme: if args.color_by_rtt {
+            NodeColorScheme::ByRtt
+        } else {
+            args.node_color
+        },
+        rtt_color_scale_ms: args.rtt_color_scale_ms,
+        show_labels: args.show_labels,
+        camera_style: args.camera,
+        crop_region,
+        frustum_cull: args.frustum_cull,
+        ground_plane: args.ground_plane,
+        node_material: args.node_material,
+        high_quality_materials: args.node_material != NodeMaterial::Diffuse,
+        ..RenderSettings::default()
     };
 
+    if args.verbose || args.bvh_report {
+        let bvh = scene_bvh(&scene, &settings);
+        if args.verbose {
+            let stats = bvh.stats();
+            eprintln!(
+                "render: bvh {} nodes, {} leaves (avg {:.2} spheres), depth {}, {} spheres, quality {:.3}",
+                stats.node_count,
+                stats.leaf_count,
+                stats.avg_leaf_size,
+                stats.max_depth,
+                stats.total_primitives,
+                stats.quality_score()
+            );
+        }
+        if args.bvh_report {
+            eprintln!("render: {}", bvh.quality_report());
+        }
+    }
+
     if let Some(parent) = args.out.parent() {
         if !parent.as_os_str().is_empty() {
             fs::create_dir_all(parent).map_err(|err| {
@@ -494,35 +1129,205 @@ fn run_render(args: RenderArgs) -> Result<()> {
         }
     }
 
-    if args.progressive_every > 0 {
-        let mut write_error: Option<anyhow::Error> = None;
-        render_scene_progressive(&scene, &settings, args.progressive_every, |image, done| {
-            if write_error.is_some() {
-                return;
+    if args.importance_map {
+        let image = render_scene_importance_map(&scene, &settings);
+        write_png(&args.out, &image).map_err(|err| anyhow!("failed to write png: {err}"))
+    } else if args.apng {
+        let pass_size = match args.progressive_every {
+            0 => settings.spp.div_ceil(APNG_DEFAULT_FRAMES).max(1),
+            every => every,
+        };
+        render_scene_progressive_to_apng(&scene, &settings, pass_size, args.fps, &args.out)
+            .map_err(|err| anyhow!("failed to write apng: {err}"))
+    } else if args.progressive_every > 0 {
+        render_scene_progressive_to_file(
+            &scene,
+            &settings,
+            args.progressive_every,
+            &args.out,
+            |done| eprintln!("render: wrote {} spp to {:?}", done, args.out),
+        )
+        .map_err(|err| anyhow!("failed to write png: {err}"))
+    } else {
+        render_png(&scene, &settings, args.gpu, &args.out)
+    }
+}
+
+/// Checks `render --crop` values against the image size.
+fn crop_region(crop: &[u32], width: u32, height: u32) -> Result<[u32; 4]> {
+    let &[x_min, y_min, x_max, y_max] = crop else {
+        return Err(anyhow!("--crop takes 4 values, got {}", crop.len()));
+    };
+    if x_min >= x_max || y_min >= y_max || x_max > width || y_max > height {
+        return Err(anyhow!(
+            "--crop {x_min} {y_min} {x_max} {y_max} is not a non-empty region inside the {width}x{height} image"
+        ));
+    }
+    Ok([x_min, y_min, x_max, y_max])
+}
+
+fn render_png(scene: &SceneFile, settings: &RenderSettings, gpu: bool, out: &Path) -> Result<()> {
+    if gpu {
+        #[cfg(feature = "gpu")]
+        match ptroute_render::render_scene_gpu(scene, settings) {
+            Ok(image) => {
+                return write_png(out, &image).map_err(|err| anyhow!("failed to write png: {err}"));
             }
+            Err(err) => eprintln!("warning: {err}; rendering on the CPU"),
+        }
+        #[cfg(not(feature = "gpu"))]
+        eprintln!("warning: ptroute was built without the `gpu` feature; rendering on the CPU");
+    }
+    render_scene_to_file(scene, settings, out).map_err(|err| anyhow!("failed to write png: {err}"))
+}
 
-            match write_png(&args.out, image) {
-                Ok(()) => eprintln!("render: wrote {} spp to {:?}", done, args.out),
-                Err(err) => {
-                    write_error = Some(anyhow!("failed to write png: {err}"));
-                }
-            };
+fn run_compare(args: CompareArgs) -> Result<()> {
+    let before: SceneFile = read_value(&args.before, "scene")?;
+    let after: SceneFile = read_value(&args.after, "scene")?;
+
+    let settings = RenderSettings {
+        width: args.width,
+        height: args.height,
+        spp: args.spp,
+        bounces: args.bounces,
+        seed: args.seed,
+        progress_every: 0,
+        threads: args.threads,
+        ..RenderSettings::default()
+    };
+
+    if let Some(parent) = args.out.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|err| {
+                anyhow!("failed to create output directory {:?}: {}", parent, err)
+            })?;
+        }
+    }
+
+    let image = render_scene_comparison(&before, &after, &settings);
+    write_png(&args.out, &image).map_err(|err| anyhow!("failed to write png: {err}"))
+}
+
+fn run_export(args: ExportArgs) -> Result<()> {
+    match args.format {
+        ExportFormat::Gexf => {
+            let graph: GraphFile = read_value(&args.in_path, "graph")?;
+            atomic_write(&args.out, to_gexf(&graph).as_bytes())
+        }
+        ExportFormat::AdjacencyMatrix => {
+            let graph: GraphFile = read_value(&args.in_path, "graph")?;
+            atomic_write(&args.out, to_adjacency_csv(&graph).as_bytes())
+        }
+        ExportFormat::Obj => {
+            let scene: SceneFile = read_value(&args.in_path, "scene")?;
+            let mtl_path = args.out.with_extension("mtl");
+            let mtl_file = mtl_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| anyhow!("invalid --out path {}", args.out.display()))?;
+            let export = to_obj(&scene, mtl_file);
+            atomic_write(&mtl_path, export.mtl.as_bytes())?;
+            atomic_write(&args.out, export.obj.as_bytes())
+        }
+    }
+}
+
+fn run_stats(args: StatsArgs) -> Result<()> {
+    let graph: GraphFile = read_value(&args.in_path, "graph")?;
+    println!("nodes: {}", graph.nodes.len());
+    println!("edges: {}", graph.edges.len());
+
+    if !graph.edges.is_empty() {
+        let mut jittery: Vec<&Edge> = graph.edges.iter().collect();
+        jittery.sort_by(|a, b| {
+            b.rtt_delta_ms_stddev
+                .total_cmp(&a.rtt_delta_ms_stddev)
+                .then_with(|| (&a.from, &a.to).cmp(&(&b.from, &b.to)))
         });
-        if let Some(err) = write_error {
-            Err(err)
+        println!("top jitter (rtt delta ms):");
+        println!("  {:<40}  {:>9}  {:>9}", "edge", "avg", "stddev");
+        for edge in jittery.into_iter().take(5) {
+            let name = format!("{} -> {}", edge.from, edge.to);
+            println!(
+                "  {name:<40}  {:>9.3}  {:>9.3}",
+                edge.rtt_delta_ms_avg, edge.rtt_delta_ms_stddev
+            );
+        }
+    }
+
+    let mut slowest: Vec<&Node> = graph
+        .nodes
+        .iter()
+        .filter(|node| node.rtt_p95_ms.is_some())
+        .collect();
+    if !slowest.is_empty() {
+        slowest.sort_by(|a, b| {
+            b.rtt_p95_ms
+                .unwrap_or_default()
+                .total_cmp(&a.rtt_p95_ms.unwrap_or_default())
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        let ms = |value: Option<f64>| value.map_or("-".to_string(), |ms| format!("{ms:.3}"));
+        println!("top rtt p95 (ms):");
+        println!("  {:<40}  {:>9}  {:>9}  {:>9}", "node", "avg", "p50", "p95");
+        for node in slowest.into_iter().take(5) {
+            println!(
+                "  {:<40}  {:>9}  {:>9}  {:>9}",
+                node.id,
+                ms(node.rtt_ms_avg),
+                ms(node.rtt_p50_ms),
+                ms(node.rtt_p95_ms)
+            );
+        }
+    }
+
+    if args.centrality {
+        let scores = if args.weighted {
+            betweenness_centrality_weighted(&graph)
         } else {
-            Ok(())
+            betweenness_centrality(&graph)
+        };
+        let mut ranked: Vec<(&String, &f64)> = scores.iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        println!("top betweenness:");
+        for (id, score) in ranked.into_iter().take(5) {
+            println!("  {id}  {score:.4}");
         }
-    } else {
-        let image = render_scene(&scene, &settings);
-        write_png(&args.out, &image).map_err(|err| anyhow!("failed to write png: {err}"))?;
-        Ok(())
     }
+    Ok(())
 }
 
 fn run_run(args: RunArgs) -> Result<()> {
-    let started = SystemTime::now();
-    let started_at_utc = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    run_run_to(args, &mut io::stdout())
+}
+
+/// Runs the pipeline and, with `--output-json[-compact]`, reports the outcome
+/// to `out` as JSON.
+fn run_run_to(args: RunArgs, out: &mut impl Write) -> Result<()> {
+    let json = (args.output_json || args.output_json_compact).then_some(args.output_json_compact);
+    let result = run_run_steps(args);
+    if let Some(compact) = json {
+        let text = match &result {
+            Ok(receipt) => json_text(receipt, compact)?,
+            Err(err) => json_text(
+                &serde_json::json!({ "success": false, "error": err.to_string() }),
+                compact,
+            )?,
+        };
+        writeln!(out, "{text}")?;
+    }
+    result.map(|_| ())
+}
+
+fn json_text<T: Serialize>(value: &T, compact: bool) -> Result<String> {
+    Ok(if compact {
+        serde_json::to_string(value)?
+    } else {
+        serde_json::to_string_pretty(value)?
+    })
+}
+
+fn run_run_steps(args: RunArgs) -> Result<Option<RunReceipt>> {
     let ui = Ui::new(args.plain);
 
     ui.banner();
@@ -534,6 +1339,10 @@ fn run_run(args: RunArgs) -> Result<()> {
         eprintln!("warning: --plain is not implemented yet; output will be standard text");
     }
 
+    if args.watch {
+        return run_watch(args, &ui).map(|_| None);
+    }
+
     let out_dir = args.out_dir.clone().unwrap_or_else(default_out_dir);
 
     if out_dir.exists() {
@@ -554,17 +1363,75 @@ fn run_run(args: RunArgs) -> Result<()> {
             .map_err(|err| anyhow!("failed to create output directory {:?}: {}", out_dir, err))?;
     }
 
+    run_pipeline(args, out_dir, &ui, None).map(|(_, receipt)| Some(receipt))
+}
+
+fn run_watch(mut args: RunArgs, ui: &Ui) -> Result<()> {
+    let base = args
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("output"));
+    let opts = watch::WatchOpts {
+        interval: Duration::from_secs(args.interval_secs.max(1)),
+        keep: args.keep,
+    };
+
+    let running = Arc::new(AtomicBool::new(true));
+    let running_ctrlc = Arc::clone(&running);
+    ctrlc::set_handler(move || {
+        running_ctrlc.store(false, Ordering::SeqCst);
+    })
+    .map_err(|err| anyhow!("failed to install ctrl-c handler: {err}"))?;
+
+    let mut previous: Option<PreviousRun> = None;
+    watch::watch_loop(&base, &opts, &running, || {
+        let run_dir = base.join(run_stamp());
+        if run_dir.exists() {
+            return Err(anyhow!("run directory {:?} already exists", run_dir));
+        }
+        fs::create_dir_all(&run_dir)
+            .map_err(|err| anyhow!("failed to create output directory {:?}: {}", run_dir, err))?;
+        let (graph, receipt) = run_pipeline(args.clone(), run_dir.clone(), ui, previous.as_ref())?;
+        if let Some(graph) = graph {
+            previous = Some(PreviousRun {
+                graph,
+                render: receipt.outputs.render,
+            });
+        }
+        args.open = false;
+        Ok(run_dir)
+    })
+}
+
+/// What a watch cycle hands to the next one for `--on-change-only`.
+struct PreviousRun {
+    graph: GraphFile,
+    render: PathBuf,
+}
+
+fn run_pipeline(
+    args: RunArgs,
+    out_dir: PathBuf,
+    ui: &Ui,
+    previous: Option<&PreviousRun>,
+) -> Result<(Option<GraphFile>, RunReceipt)> {
+    let started = SystemTime::now();
+    let started_at_utc = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+
     let traces_path = out_dir.join("traces.json");
     let graph_path = out_dir.join("graph.json");
     let scene_path = out_dir.join("scene.json");
     let render_path = out_dir.join("render.png");
     let run_path = out_dir.join("run.json");
+    let seed = resolve_seed(args.seed, args.seed_from_time, started);
 
     let args_summary = RunArgsSummary {
         targets_file: args.targets.clone(),
         targets: args.target_list.clone(),
         out_dir: out_dir.clone(),
-        seed: args.seed,
+        seed,
+        seed_from_time: args.seed_from_time,
+        normalize: args.normalize,
         width: args.width,
         height: args.height,
         spp: args.spp,
@@ -572,16 +1439,37 @@ fn run_run(args: RunArgs) -> Result<()> {
         progress_every: args.progress_every,
         threads: args.threads,
         progressive_every: args.progressive_every,
+        emission_scale: args.emission_scale,
+        emission_rtt_knee_ms: args.emission_rtt_knee_ms,
+        emission_min: args.emission_min,
+        emission_depth_decay: args.emission_depth_decay,
+        node_radius_base: args.node_radius_base,
+        node_radius_seen_scale: args.node_radius_seen_scale,
+        node_color: args.node_color.name(),
+        show_labels: args.show_labels,
         max_hops: args.max_hops,
         probes: args.probes,
         timeout_ms: args.timeout_ms,
+        total_timeout_ms: args.total_timeout_ms,
         concurrency: args.concurrency,
+        batch_size: args.batch_size,
         repeat: args.repeat,
         interval_ms: args.interval_ms,
+        label: args.label.clone(),
+        detect_source_ip: args.detect_source_ip,
+        no_unknown: args.no_unknown,
+        filter_private_ips: args.filter_private_ips,
+        no_deduplicate: args.no_deduplicate,
         resume: args.resume,
         force: args.force,
         plain: args.plain,
         open: args.open,
+        watch: args.watch,
+        interval_secs: args.interval_secs,
+        keep: args.keep,
+        on_change_only: args.on_change_only,
+        output_json: args.output_json,
+        output_json_compact: args.output_json_compact,
     };
 
     let allow_skip = args.resume && !args.force;
@@ -597,9 +1485,17 @@ fn run_run(args: RunArgs) -> Result<()> {
             max_hops: args.max_hops,
             probes: args.probes,
             timeout_ms: args.timeout_ms,
+            total_timeout_ms: args.total_timeout_ms,
+            max_retries: args.max_retries,
+            retry_delay_ms: args.retry_delay_ms,
             concurrency: args.concurrency,
+            batch_size: args.batch_size,
             repeat: args.repeat,
             interval_ms: args.interval_ms,
+            label: args.label,
+            detect_source_ip: args.detect_source_ip,
+            probe_size_bytes: None,
+            timeout_mode: TimeoutMode::default(),
         })?;
         ui.step_ok(
             "trace ",
@@ -619,6 +1515,17 @@ fn run_run(args: RunArgs) -> Result<()> {
         run_build(BuildArgs {
             in_path: traces_path.clone(),
             out: graph_path.clone(),
+            no_unknown: args.no_unknown,
+            filter_private_ips: args.filter_private_ips,
+            no_deduplicate: args.no_deduplicate,
+            filter_targets: Vec::new(),
+            center: None,
+            depth: 2,
+            annotate_pagerank: false,
+            max_runs_per_target: None,
+            min_seen: None,
+            strict: false,
+            mst: false,
         })?;
         let (nodes, edges) = graph_counts(&graph_path);
         ui.step_ok(
@@ -639,17 +1546,49 @@ fn run_run(args: RunArgs) -> Result<()> {
         run_layout(LayoutArgs {
             in_path: graph_path.clone(),
             out: scene_path.clone(),
-            seed: args.seed,
+            seed: Some(seed),
+            seed_from_time: false,
+            normalize: args.normalize,
+            algo: LayoutAlgo::Depth,
+            force_iterations: DEFAULT_FORCE_ITERATIONS,
+            compare_scene: None,
+            pins: Vec::new(),
+            fit: None,
+            fit_stretch: false,
         })?;
         ui.step_ok(
             "layout",
-            &format!("{} (seed {})", scene_path.display(), args.seed),
+            &format!("{} (seed {})", scene_path.display(), seed),
         );
     }
 
+    let graph = read_graph(&graph_path);
+    let unchanged = previous.filter(|prev| {
+        args.on_change_only
+            && prev.render.exists()
+            && matches!(&graph, Some(current) if watch::same_topology(&prev.graph, current))
+    });
+
     let skip_render = allow_skip && render_path.exists();
     if skip_render {
         ui.step_skip("render", &format!("{}", render_path.display()));
+    } else if let Some(prev) = unchanged {
+        fs::copy(&prev.render, &render_path).map_err(|err| {
+            anyhow!(
+                "failed to copy {:?} to {:?}: {}",
+                prev.render,
+                render_path,
+                err
+            )
+        })?;
+        ui.step_skip(
+            "render",
+            &format!(
+                "{} (topology unchanged, copied from {})",
+                render_path.display(),
+                prev.render.display()
+            ),
+        );
     } else {
         run_render(RenderArgs {
             in_path: scene_path.clone(),
@@ -658,10 +1597,32 @@ fn run_run(args: RunArgs) -> Result<()> {
             height: args.height,
             spp: args.spp,
             bounces: args.bounces,
-            seed: args.seed,
+            seed,
             progress_every: args.progress_every,
             threads: args.threads,
             progressive_every: args.progressive_every,
+            emission_scale: args.emission_scale,
+            emission_rtt_knee_ms: args.emission_rtt_knee_ms,
+            emission_min: args.emission_min,
+            emission_depth_decay: args.emission_depth_decay,
+            node_radius_base: args.node_radius_base,
+            node_radius_seen_scale: args.node_radius_seen_scale,
+            node_color: args.node_color,
+            color_by_rtt: false,
+            rtt_color_scale_ms: 200.0,
+            show_labels: args.show_labels,
+            camera: CameraStyle::default(),
+            format: RenderFormat::Png,
+            crop: None,
+            frustum_cull: false,
+            ground_plane: false,
+            node_material: NodeMaterial::Diffuse,
+            verbose: false,
+            bvh_report: false,
+            gpu: false,
+            importance_map: false,
+            apng: false,
+            fps: 5,
         })?;
         ui.step_ok(
             "render",
@@ -696,7 +1657,7 @@ fn run_run(args: RunArgs) -> Result<()> {
         },
     };
 
-    write_json(&run_path, &receipt)?;
+    write_value(&run_path, &receipt)?;
 
     if args.open && render_path.exists() {
         open_file(&render_path)?;
@@ -705,7 +1666,7 @@ fn run_run(args: RunArgs) -> Result<()> {
     let elapsed = started.elapsed().unwrap_or_default().as_secs_f64();
     ui.done(&format!("elapsed {:.1}s", elapsed));
 
-    Ok(())
+    Ok((graph, receipt))
 }
 
 fn run_invade(args: InvadeArgs) -> Result<()> {
@@ -735,6 +1696,12 @@ fn run_invade(args: InvadeArgs) -> Result<()> {
         return Ok(());
     }
 
+    let mut hop_log = args
+        .log_raw
+        .as_deref()
+        .map(invade::HopLog::open)
+        .transpose()?;
+
     let running = Arc::new(AtomicBool::new(true));
     let running_ctrlc = Arc::clone(&running);
     ctrlc::set_handler(move || {
@@ -742,7 +1709,7 @@ fn run_invade(args: InvadeArgs) -> Result<()> {
     })
     .map_err(|err| anyhow!("failed to install ctrl-c handler: {err}"))?;
 
-    let _guard = TermGuard::enter()?;
+    let guard = TermGuard::enter()?;
 
     let (term_w, term_h) = terminal::size().unwrap_or((80, 24));
 
@@ -751,30 +1718,51 @@ fn run_invade(args: InvadeArgs) -> Result<()> {
         max_hops: args.max_hops,
         probes: args.probes,
         timeout_ms: args.timeout_ms,
+        ..TraceSettings::default()
     };
     let target = targets[0].clone();
-    let rx = stream_for_target(&target, &settings)?;
+    let (mut tx, mut rx) = mpsc::channel();
+    let mut handle = spawn_traceroute_stream(&target, &settings, tx)?;
+    let mut next_wave_at: Option<Instant> = None;
 
     let mut state = invade::AppState {
         wave: 1,
+        total_waves: if args.infinite_waves {
+            u32::MAX
+        } else {
+            args.waves.max(1)
+        },
         targets: vec![invade::TargetView {
             name: target.clone(),
+            rtt_history: VecDeque::new(),
             hops: Vec::new(),
         }],
         last_detail: None,
+        sort_mode: args.sort_by,
+        filter_min_loss: args.filter_min_loss,
     };
 
     while running.load(Ordering::SeqCst) {
+        if next_wave_at.is_some_and(|at| Instant::now() >= at) {
+            next_wave_at = None;
+            handle.kill();
+            (tx, rx) = mpsc::channel();
+            handle = spawn_traceroute_stream(&target, &settings, tx)?;
+            state.next_wave();
+        }
+
         while let Ok(event) = rx.try_recv() {
             match event {
-                TraceEvent::HopUpdate { ttl, ip, rtts } => {
-                    let loss = if rtts.is_empty() {
-                        1.0
-                    } else {
-                        let lost =
-                            rtts.iter().filter(|v: &&Option<f64>| v.is_none()).count() as f64;
-                        lost / rtts.len() as f64
-                    };
+                TraceEvent::HopUpdate {
+                    ttl,
+                    ip,
+                    rtts,
+                    mpls_labels,
+                } => {
+                    // Every update goes to the log with its loss already
+                    // known; a hop with probes is reported on screen by the
+                    // PacketLoss event that follows it.
+                    let reported = rtts.is_empty();
                     let mut rtts_vals: Vec<f64> = rtts.iter().copied().flatten().collect();
                     rtts_vals.sort_by(|a: &f64, b: &f64| {
                         a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
@@ -787,36 +1775,38 @@ fn run_invade(args: InvadeArgs) -> Result<()> {
 
                     let hop = invade::HopView {
                         ttl,
-                        ip: ip.clone(),
-                        loss,
+                        ip,
+                        loss: Some(loss_fraction(&rtts).unwrap_or(1.0)),
                         median_rtt,
+                        mpls_label: mpls_labels.first().copied(),
                     };
-                    let hops = &mut state.targets[0].hops;
-                    let idx = (ttl.saturating_sub(1)) as usize;
-                    if hops.len() <= idx {
-                        hops.resize_with(idx + 1, || invade::HopView {
-                            ttl: 0,
-                            ip: None,
-                            loss: 1.0,
-                            median_rtt: None,
-                        });
+                    if let Some(log) = hop_log.as_mut() {
+                        log.hop(unix_ms(), &target, &hop)?;
+                    }
+                    if reported {
+                        state.last_detail = Some(hop_detail(&target, &hop));
+                    }
+                    state.update_hop(0, hop);
+                }
+                TraceEvent::PacketLoss { ttl, loss_fraction } => {
+                    if let Some(hop) = state.update_loss(0, ttl, loss_fraction) {
+                        let detail = hop_detail(&target, hop);
+                        state.last_detail = Some(detail);
                     }
-                    hops[idx] = hop;
-                    state.last_detail = Some(format!(
-                        "target={} ttl={} ip={} rtt={:.1?}ms loss={:.0}%",
-                        target,
-                        ttl,
-                        ip.unwrap_or_else(|| "*".to_string()),
-                        median_rtt,
-                        loss * 100.0
-                    ));
                 }
                 TraceEvent::Done { .. } => {
-                    running.store(false, Ordering::SeqCst);
+                    state.record_rtt_history();
+                    if state.has_more_waves() {
+                        next_wave_at =
+                            Some(Instant::now() + Duration::from_millis(args.wave_interval_ms));
+                    } else {
+                        running.store(false, Ordering::SeqCst);
+                    }
                 }
                 TraceEvent::Error { message } => {
                     state.last_detail = Some(message);
                 }
+                _ => {}
             }
         }
 
@@ -825,63 +1815,104 @@ fn run_invade(args: InvadeArgs) -> Result<()> {
             &invade::UiOpts {
                 plain: args.plain,
                 ascii_only: args.ascii_only,
+                warn_rtt: args.warn_rtt,
+                bad_rtt: args.bad_rtt,
+                warn_loss: args.warn_loss,
+                bad_loss: args.bad_loss,
+                show_mpls: args.show_mpls,
             },
             term_w,
             term_h,
         );
         draw_frame(&buffer)?;
 
-        if event::poll(std::time::Duration::from_millis(args.refresh_ms))
+        if event::poll(Duration::from_millis(args.refresh_ms))
             .map_err(|err| anyhow!("event poll failed: {err}"))?
         {
             if let event::Event::Key(key) =
                 event::read().map_err(|err| anyhow!("event read failed: {err}"))?
             {
-                if matches!(
-                    key.code,
-                    event::KeyCode::Char('q') | event::KeyCode::Char('Q')
-                ) {
-                    break;
+                match key.code {
+                    event::KeyCode::Char('q') | event::KeyCode::Char('Q') => break,
+                    event::KeyCode::Char('s') | event::KeyCode::Char('S') => {
+                        state.sort_mode = state.sort_mode.next();
+                    }
+                    _ => {}
                 }
             }
         }
     }
 
+    handle.kill();
+    if let Some(log) = hop_log.as_mut() {
+        log.shutdown(unix_ms())?;
+    }
+    drop(guard);
+    save_invade_outputs(&args, &state)
+}
+
+/// Writes the `--out-dir` snapshot and `--save-traces` file, if requested.
+fn save_invade_outputs(args: &InvadeArgs, state: &invade::AppState) -> Result<()> {
+    let finished_at_utc = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    if let Some(out_dir) = &args.out_dir {
+        let path = out_dir.join(format!("invade-{}.json", run_stamp()));
+        write_value(
+            &path,
+            &invade::InvadeReceipt::from_state(state, &finished_at_utc),
+        )?;
+        eprintln!("invade: wrote {}", path.display());
+    }
+    if let Some(path) = &args.save_traces {
+        write_value(path, &invade::trace_file(state, &finished_at_utc))?;
+        eprintln!("invade: wrote {}", path.display());
+    }
     Ok(())
 }
 
 fn render_invade_demo(term_w: u16, plain: bool) -> String {
     let state = invade::AppState {
         wave: 1,
+        total_waves: 1,
         targets: vec![
             invade::TargetView {
                 name: "1.1.1.1".to_string(),
+                rtt_history: VecDeque::new(),
                 hops: (1..=6)
                     .map(|ttl| invade::HopView {
                         ttl,
                         ip: Some("10.0.0.1".to_string()),
-                        loss: 0.0,
+                        loss: Some(0.0),
                         median_rtt: Some(10.0 + ttl as f64),
+                        mpls_label: None,
                     })
                     .collect(),
             },
             invade::TargetView {
                 name: "8.8.8.8".to_string(),
+                rtt_history: VecDeque::new(),
                 hops: (1..=5)
                     .map(|ttl| invade::HopView {
                         ttl,
                         ip: Some("192.168.0.1".to_string()),
-                        loss: 0.0,
+                        loss: Some(0.0),
                         median_rtt: Some(12.0 + ttl as f64),
+                        mpls_label: None,
                     })
                     .collect(),
             },
         ],
         last_detail: Some("Last hop: demo ttl=4 ip=10.0.0.1 rtt=12.3ms loss=0%".to_string()),
+        sort_mode: invade::SortMode::Name,
+        filter_min_loss: 0.0,
     };
     let opts = invade::UiOpts {
         plain,
         ascii_only: plain,
+        warn_rtt: 80.0,
+        bad_rtt: 200.0,
+        warn_loss: 0.34,
+        bad_loss: 0.67,
+        show_mpls: false,
     };
     invade::render_map(&state, &opts, term_w, 24)
 }
@@ -923,17 +1954,144 @@ impl Drop for TermGuard {
     }
 }
 
+const DOCTOR_PING_TARGET: &str = "1.1.1.1";
+const DOCTOR_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+struct DoctorReport {
+    ok: bool,
+    os_supported: bool,
+    traceroute: bool,
+    traceroute6: bool,
+    output_dir_writable: bool,
+    network_target: String,
+    network_reachable: bool,
+    /// Flags found in `traceroute --help`; `None` without traceroute.
+    traceroute_flags: Option<DoctorTracerouteFlags>,
+}
+
+#[derive(Serialize)]
+struct DoctorTracerouteFlags {
+    wait: bool,
+    long_wait: bool,
+    sim_queries: bool,
+    extensions: bool,
+}
+
 fn run_doctor(args: DoctorArgs) -> Result<()> {
     let mut ok = true;
+    let os_supported = if cfg!(target_os = "windows") {
+        pathping_available()
+    } else {
+        cfg!(target_os = "macos") || cfg!(target_os = "linux")
+    };
+    let mut output_dir_writable = false;
 
-    if cfg!(target_os = "macos") || cfg!(target_os = "linux") {
+    if os_supported {
         eprintln!("[OK ] os: tracing supported");
+    } else if cfg!(target_os = "windows") {
+        eprintln!("[FAIL] os: tracing needs pathping.exe on PATH");
+        eprintln!("       tip: you can still use build/layout/render with existing traces.json");
+        ok = false;
     } else {
-        eprintln!("[FAIL] os: tracing unsupported (macOS/Linux only)");
+        eprintln!("[FAIL] os: tracing unsupported (macOS/Linux/Windows only)");
         eprintln!("       tip: you can still use build/layout/render with existing traces.json");
         ok = false;
     }
 
+    let (traceroute, traceroute6) = if cfg!(target_os = "windows") {
+        // pathping stands in for both traceroute binaries.
+        if os_supported {
+            eprintln!("[OK ] pathping: available");
+        }
+        (os_supported, os_supported)
+    } else {
+        check_traceroute_binaries(&mut ok)
+    };
+
+    let traceroute_flags = (traceroute && !cfg!(target_os = "windows")).then(|| {
+        let flags = detect_traceroute_flags("traceroute");
+        let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+        eprintln!(
+            "[OK ] traceroute flags: -w {}, --wait {}, -N {}, -e {}",
+            yes_no(flags.wait),
+            yes_no(flags.long_wait),
+            yes_no(flags.sim_queries),
+            yes_no(flags.extensions)
+        );
+        DoctorTracerouteFlags {
+            wait: flags.wait,
+            long_wait: flags.long_wait,
+            sim_queries: flags.sim_queries,
+            extensions: flags.extensions,
+        }
+    });
+
+    if let Err(err) = fs::create_dir_all(&args.out_dir) {
+        eprintln!("[FAIL] output dir: {:?} ({})", args.out_dir, err);
+        ok = false;
+    } else {
+        let probe = args.out_dir.join(".ptroute-write-test");
+        match fs::write(&probe, b"ok") {
+            Ok(_) => {
+                let _ = fs::remove_file(&probe);
+                eprintln!("[OK ] output dir: writable ({:?})", args.out_dir);
+                output_dir_writable = true;
+            }
+            Err(err) => {
+                eprintln!(
+                    "[FAIL] output dir: {:?} not writable ({})",
+                    args.out_dir, err
+                );
+                ok = false;
+            }
+        }
+    }
+
+    // Only an explicit --check-target fails the doctor; the default probe
+    // just warns so offline build/layout/render setups still pass.
+    let network_target = args
+        .check_target
+        .clone()
+        .unwrap_or_else(|| DOCTOR_PING_TARGET.to_string());
+    let network_reachable = probe_reachable(ping_command(&network_target), DOCTOR_PING_TIMEOUT);
+    if network_reachable {
+        eprintln!("[OK ] network: {network_target} reachable");
+    } else if args.check_target.is_some() {
+        eprintln!("[FAIL] network: {network_target} unreachable (ping failed)");
+        ok = false;
+    } else {
+        eprintln!("[WARN] network: {network_target} unreachable (ping failed)");
+        eprintln!("       tip: pass --check-target <ip> to probe a host you expect to reach");
+    }
+
+    if args.json {
+        let report = DoctorReport {
+            ok,
+            os_supported,
+            traceroute,
+            traceroute6,
+            output_dir_writable,
+            network_target,
+            network_reachable,
+            traceroute_flags,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+
+    if ok {
+        Ok(())
+    } else {
+        Err(anyhow!("doctor found issues"))
+    }
+}
+
+/// Probes `traceroute` and `traceroute6`, reporting each; only a missing or
+/// failing `traceroute` clears `ok`.
+fn check_traceroute_binaries(ok: &mut bool) -> (bool, bool) {
+    let mut traceroute = false;
+    let mut traceroute6 = false;
+
     match Command::new("traceroute")
         .arg("-n")
         .arg("-m")
@@ -944,6 +2102,7 @@ fn run_doctor(args: DoctorArgs) -> Result<()> {
         Ok(output) => {
             if output.status.success() {
                 eprintln!("[OK ] traceroute: available");
+                traceroute = true;
             } else {
                 let stderr = String::from_utf8_lossy(&output.stderr);
                 eprintln!("[FAIL] traceroute: command failed");
@@ -953,55 +2112,153 @@ fn run_doctor(args: DoctorArgs) -> Result<()> {
                 eprintln!(
                     "       tip: install traceroute (e.g., apt/yum/pacman install traceroute)"
                 );
-                ok = false;
+                *ok = false;
             }
         }
         Err(_) => {
             eprintln!("[FAIL] traceroute: not found on PATH");
             eprintln!("       tip: install traceroute (e.g., apt/yum/pacman install traceroute)");
-            ok = false;
+            *ok = false;
         }
     }
 
-    if let Err(err) = fs::create_dir_all(&args.out_dir) {
-        eprintln!("[FAIL] output dir: {:?} ({})", args.out_dir, err);
-        ok = false;
+    match Command::new("traceroute6")
+        .arg("-n")
+        .arg("-m")
+        .arg("1")
+        .arg("::1")
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            eprintln!("[OK ] traceroute6: available");
+            traceroute6 = true;
+        }
+        Ok(_) => {
+            eprintln!(
+                "[WARN] traceroute6: command failed; IPv6 targets fall back to traceroute -6"
+            );
+        }
+        Err(_) => {
+            eprintln!(
+                "[WARN] traceroute6: not found on PATH; IPv6 targets fall back to traceroute -6"
+            );
+            eprintln!(
+                "       tip: install traceroute6 (often in the iputils or traceroute package)"
+            );
+        }
+    }
+
+    (traceroute, traceroute6)
+}
+
+/// Whether `pathping.exe`, used for tracing on Windows, can be started.
+fn pathping_available() -> bool {
+    Command::new("pathping")
+        .arg("/?")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// A single `ping` to `target` that waits at most one second for the reply.
+fn ping_command(target: &str) -> Command {
+    let mut cmd = Command::new("ping");
+    if cfg!(target_os = "windows") {
+        // Windows ping counts with -n and takes -w in milliseconds.
+        cmd.args(["-n", "1", "-w", "1000", target])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        return cmd;
+    }
+    // macOS ping takes -W in milliseconds; -t is its overall timeout in seconds.
+    let wait = if cfg!(target_os = "macos") {
+        "-t"
     } else {
-        let probe = args.out_dir.join(".ptroute-write-test");
-        match fs::write(&probe, b"ok") {
-            Ok(_) => {
-                let _ = fs::remove_file(&probe);
-                eprintln!("[OK ] output dir: writable ({:?})", args.out_dir);
-            }
-            Err(err) => {
-                eprintln!(
-                    "[FAIL] output dir: {:?} not writable ({})",
-                    args.out_dir, err
-                );
-                ok = false;
+        "-W"
+    };
+    cmd.args(["-c", "1", wait, "1", target])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    cmd
+}
+
+/// Runs `command` and reports whether it exited successfully within
+/// `timeout`; a command that cannot start or runs too long is killed and
+/// counts as unreachable.
+fn probe_reachable(mut command: Command, timeout: Duration) -> bool {
+    let Ok(mut child) = command.spawn() else {
+        return false;
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return status.success(),
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
             }
         }
     }
+}
 
-    if ok {
-        Ok(())
+fn graph_counts(path: &Path) -> (usize, usize) {
+    read_graph(path)
+        .map(|graph| (graph.nodes.len(), graph.edges.len()))
+        .unwrap_or((0, 0))
+}
+
+fn read_graph(path: &Path) -> Option<GraphFile> {
+    let reader = open_input(path).ok()?;
+    GraphFile::from_reader(reader).ok()
+}
+
+/// Reads JSON, or YAML when the path ends in `.yaml`/`.yml`.
+fn read_value<T: DeserializeOwned>(path: &Path, what: &str) -> Result<T> {
+    if is_yaml_path(path) {
+        let text = fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read input {:?}: {}", path, err))?;
+        ptroute_model::yaml::from_str(&text)
+            .map_err(|err| anyhow!("failed to parse {} {:?}: {}", what, path, err))
     } else {
-        Err(anyhow!("doctor found issues"))
+        serde_json::from_reader(open_input(path)?)
+            .map_err(|err| anyhow!("failed to parse {} {:?}: {}", what, path, err))
     }
 }
 
-fn graph_counts(path: &PathBuf) -> (usize, usize) {
-    if let Ok(contents) = fs::read_to_string(path) {
-        if let Ok(graph) = serde_json::from_str::<ptroute_model::GraphFile>(&contents) {
-            return (graph.nodes.len(), graph.edges.len());
-        }
+fn is_yaml_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+}
+
+fn open_input(path: &Path) -> Result<BufReader<fs::File>> {
+    let file =
+        fs::File::open(path).map_err(|err| anyhow!("failed to read input {:?}: {}", path, err))?;
+    Ok(BufReader::new(file))
+}
+
+/// An explicit `--seed` wins; otherwise `--seed-from-time` uses the Unix time
+/// in milliseconds, falling back to the fixed default seed.
+fn resolve_seed(seed: Option<u64>, from_time: bool, now: SystemTime) -> u64 {
+    match seed {
+        Some(seed) => seed,
+        None if from_time => now
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(DEFAULT_SEED),
+        None => DEFAULT_SEED,
     }
-    (0, 0)
 }
 
 fn default_out_dir() -> PathBuf {
-    let stamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
-    PathBuf::from("output").join(stamp)
+    PathBuf::from("output").join(run_stamp())
+}
+
+fn run_stamp() -> String {
+    Utc::now().format("%Y%m%d-%H%M%S").to_string()
 }
 
 fn open_file(path: &PathBuf) -> Result<()> {
@@ -1027,12 +2284,17 @@ fn open_file(path: &PathBuf) -> Result<()> {
     }
 }
 
-fn write_json<T: Serialize>(path: &PathBuf, value: &T) -> Result<()> {
-    let json = serde_json::to_vec_pretty(value)?;
-    atomic_write(path, &json)
+/// Writes pretty JSON, or YAML when the path ends in `.yaml`/`.yml`.
+fn write_value<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let data = if is_yaml_path(path) {
+        ptroute_model::yaml::to_string(value)?.into_bytes()
+    } else {
+        serde_json::to_vec_pretty(value)?
+    };
+    atomic_write(path, &data)
 }
 
-fn atomic_write(path: &PathBuf, data: &[u8]) -> Result<()> {
+fn atomic_write(path: &Path, data: &[u8]) -> Result<()> {
     let parent = path.parent().unwrap_or_else(|| std::path::Path::new("."));
     if !parent.as_os_str().is_empty() {
         fs::create_dir_all(parent)
@@ -1059,7 +2321,7 @@ fn atomic_write(path: &PathBuf, data: &[u8]) -> Result<()> {
     Ok(())
 }
 
-fn temp_path(path: &PathBuf) -> PathBuf {
+fn temp_path(path: &Path) -> PathBuf {
     let parent = path.parent().unwrap_or_else(|| std::path::Path::new("."));
     let file_name = path
         .file_name()
@@ -1073,3 +2335,444 @@ fn temp_path(path: &PathBuf) -> PathBuf {
     let tmp_name = format!(".{}.part-{}-{}", file_name, pid, stamp);
     parent.join(tmp_name)
 }
+
+fn hop_detail(target: &str, hop: &invade::HopView) -> String {
+    format!(
+        "target={} ttl={} ip={} rtt={:.1?}ms loss={}",
+        target,
+        hop.ttl,
+        hop.ip.as_deref().unwrap_or("*"),
+        hop.median_rtt,
+        hop.loss
+            .map_or_else(|| "?".to_string(), |loss| format!("{:.0}%", loss * 100.0))
+    )
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_from_time_follows_the_clock() {
+        let first = UNIX_EPOCH + Duration::from_millis(1_760_000_000_000);
+        let later = first + Duration::from_millis(250);
+
+        assert_eq!(resolve_seed(None, true, first), 1_760_000_000_000);
+        assert_ne!(
+            resolve_seed(None, true, first),
+            resolve_seed(None, true, later)
+        );
+    }
+
+    #[test]
+    fn explicit_seed_beats_seed_from_time() {
+        let now = UNIX_EPOCH + Duration::from_secs(42);
+
+        assert_eq!(resolve_seed(Some(7), true, now), 7);
+        assert_eq!(resolve_seed(None, false, now), DEFAULT_SEED);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probe_reports_exit_status() {
+        let timeout = Duration::from_secs(2);
+        assert!(probe_reachable(Command::new("true"), timeout));
+        assert!(!probe_reachable(Command::new("false"), timeout));
+        assert!(!probe_reachable(
+            Command::new("ptroute-no-such-ping"),
+            timeout
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probe_kills_commands_past_the_timeout() {
+        let mut slow = Command::new("sleep");
+        slow.arg("5");
+        let started = Instant::now();
+        assert!(!probe_reachable(slow, Duration::from_millis(100)));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn ping_command_sends_one_probe_to_target() {
+        let cmd = ping_command("192.0.2.1");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(cmd.get_program(), "ping");
+        if cfg!(target_os = "windows") {
+            assert_eq!(args[..2], ["-n", "1"]);
+        } else {
+            assert_eq!(args[..2], ["-c", "1"]);
+        }
+        assert_eq!(args.last().unwrap(), &"192.0.2.1");
+    }
+
+    fn run_args(out_dir: &Path, extra: &[&str]) -> RunArgs {
+        let mut argv: Vec<&std::ffi::OsStr> = ["ptroute", "run", "--out-dir"]
+            .iter()
+            .map(|arg| arg.as_ref())
+            .collect();
+        argv.push(out_dir.as_os_str());
+        let tiny = [
+            "--width",
+            "8",
+            "--height",
+            "8",
+            "--spp",
+            "1",
+            "--bounces",
+            "1",
+        ];
+        for arg in tiny.iter().chain(extra) {
+            argv.push(arg.as_ref());
+        }
+        let Commands::Run(args) = Cli::try_parse_from(argv).unwrap().command else {
+            panic!("expected run");
+        };
+        args
+    }
+
+    fn two_hop_trace() -> TraceFile {
+        TraceFile {
+            version: TraceFile::VERSION,
+            runs: vec![TraceRun {
+                target: "10.0.0.2".to_string(),
+                timestamp_utc: "2026-02-01T12:00:00Z".to_string(),
+                hops: ["10.0.0.1", "10.0.0.2"]
+                    .iter()
+                    .zip(1..)
+                    .map(|(ip, ttl)| ptroute_model::Hop {
+                        ttl,
+                        ip: Some(ip.to_string()),
+                        rtt_ms: vec![Some(ttl as f64)],
+                        mpls_labels: Vec::new(),
+                    })
+                    .collect(),
+                label: None,
+                source_ip: None,
+                protocol: ptroute_model::TraceProtocol::Udp,
+            }],
+        }
+    }
+
+    #[test]
+    fn run_output_json_prints_receipt_and_failures() {
+        let dir = std::env::temp_dir().join(format!("ptroute-run-json-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let trace = two_hop_trace();
+        write_value(&dir.join("traces.json"), &trace).unwrap();
+
+        let mut stdout = Vec::new();
+        run_run_to(run_args(&dir, &["--resume", "--output-json"]), &mut stdout).unwrap();
+        let receipt: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("run.json")).unwrap()).unwrap();
+        assert_eq!(receipt, written);
+        assert_eq!(
+            receipt["outputs"]["render"].as_str(),
+            dir.join("render.png").to_str()
+        );
+        assert!(dir.join("render.png").exists());
+        assert!(String::from_utf8(stdout).unwrap().lines().count() > 1);
+
+        let mut stdout = Vec::new();
+        let err = run_run_to(run_args(&dir, &["--output-json-compact"]), &mut stdout).unwrap_err();
+        let _ = fs::remove_dir_all(&dir);
+        let text = String::from_utf8(stdout).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        let failure: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(failure["success"], false);
+        assert_eq!(failure["error"], err.to_string());
+    }
+
+    #[test]
+    fn unchanged_watch_cycle_still_has_the_render_it_names() {
+        let base =
+            std::env::temp_dir().join(format!("ptroute-run-unchanged-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let ui = Ui::new(true);
+        let mut previous: Option<PreviousRun> = None;
+        let mut renders = Vec::new();
+        for name in ["20260101-000001", "20260101-000002"] {
+            let dir = base.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            write_value(&dir.join("traces.json"), &two_hop_trace()).unwrap();
+            let args = run_args(&dir, &["--resume", "--on-change-only"]);
+            let (graph, receipt) = run_pipeline(args, dir, &ui, previous.as_ref()).unwrap();
+            assert!(receipt.outputs.render.exists());
+            renders.push(fs::read(&receipt.outputs.render).unwrap());
+            previous = Some(PreviousRun {
+                graph: graph.unwrap(),
+                render: receipt.outputs.render,
+            });
+        }
+        let _ = fs::remove_dir_all(&base);
+        assert_eq!(renders[0], renders[1]);
+    }
+
+    #[test]
+    fn run_accepts_retry_flags() {
+        let dir = Path::new("out");
+        let args = run_args(dir, &[]);
+        assert_eq!((args.max_retries, args.retry_delay_ms), (0, 500));
+        let args = run_args(dir, &["--max-retries", "2", "--retry-delay-ms", "100"]);
+        assert_eq!((args.max_retries, args.retry_delay_ms), (2, 100));
+    }
+
+    #[test]
+    fn run_accepts_batch_size() {
+        let dir = Path::new("out");
+        assert_eq!(run_args(dir, &[]).batch_size, 0);
+        assert_eq!(run_args(dir, &["--batch-size", "8"]).batch_size, 8);
+    }
+
+    #[test]
+    fn run_keep_rejects_zero() {
+        let dir = Path::new("out");
+        assert_eq!(run_args(dir, &["--watch", "--keep", "2"]).keep, Some(2));
+        assert!(Cli::try_parse_from(["ptroute", "run", "--watch", "--keep", "0"]).is_err());
+    }
+
+    #[test]
+    fn render_node_material_parses() {
+        let node_material = |extra: &[&str]| {
+            let mut argv = vec![
+                "ptroute",
+                "render",
+                "--in",
+                "scene.json",
+                "--out",
+                "out.png",
+            ];
+            argv.extend(extra);
+            let Commands::Render(args) = Cli::try_parse_from(argv)?.command else {
+                panic!("expected render");
+            };
+            Ok::<_, clap::Error>(args.node_material)
+        };
+        assert_eq!(node_material(&[]).unwrap(), NodeMaterial::Diffuse);
+        assert_eq!(
+            node_material(&["--node-material", "metal"]).unwrap(),
+            NodeMaterial::Metal
+        );
+        assert!(node_material(&["--node-material", "chrome"]).is_err());
+    }
+
+    #[test]
+    fn render_crop_parses_four_values_inside_the_image() {
+        let cli = Cli::try_parse_from([
+            "ptroute",
+            "render",
+            "--in",
+            "scene.json",
+            "--out",
+            "out.png",
+            "--crop",
+            "400",
+            "300",
+            "800",
+            "600",
+        ])
+        .unwrap();
+        let Commands::Render(args) = cli.command else {
+            panic!("expected render");
+        };
+        let crop = args.crop.unwrap();
+        assert_eq!(
+            crop_region(&crop, args.width, args.height).unwrap(),
+            [400, 300, 800, 600]
+        );
+
+        assert!(Cli::try_parse_from([
+            "ptroute", "render", "--in", "s", "--out", "o", "--crop", "1", "2"
+        ])
+        .is_err());
+        for bad in [
+            [10, 0, 10, 5],
+            [0, 6, 10, 5],
+            [0, 0, 1601, 900],
+            [0, 0, 1600, 901],
+        ] {
+            assert!(crop_region(&bad, 1600, 900).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn layout_pins_parse_from_cli() {
+        let cli = Cli::try_parse_from([
+            "ptroute",
+            "layout",
+            "--in",
+            "graph.json",
+            "--out",
+            "scene.json",
+            "--pin",
+            "10.0.0.1=1,-2.5,0",
+            "--pin",
+            "2001:db8::1= 0.5, 3 ,4e1",
+        ])
+        .unwrap();
+        let Commands::Layout(args) = cli.command else {
+            panic!("expected layout");
+        };
+        assert_eq!(
+            args.pins,
+            vec![
+                ("10.0.0.1".to_string(), [1.0, -2.5, 0.0]),
+                ("2001:db8::1".to_string(), [0.5, 3.0, 40.0]),
+            ]
+        );
+
+        for bad in [
+            "10.0.0.1",
+            "=1,2,3",
+            "10.0.0.1=1,2",
+            "10.0.0.1=1,2,3,4",
+            "a=1,x,3",
+            "a=1,NaN,3",
+        ] {
+            assert!(parse_pin(bad).is_err(), "{bad}");
+        }
+
+        assert!(Cli::try_parse_from([
+            "ptroute",
+            "layout",
+            "--in",
+            "graph.json",
+            "--out",
+            "scene.json",
+            "--pin",
+            "10.0.0.1=1,2,3",
+            "--normalize",
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from([
+            "ptroute",
+            "layout",
+            "--in",
+            "graph.json",
+            "--out",
+            "scene.json",
+            "--pin",
+            "10.0.0.1=1,2,3",
+            "--fit",
+            "2",
+            "2",
+            "2",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn layout_fit_rejects_degenerate_sizes() {
+        let cli = Cli::try_parse_from([
+            "ptroute",
+            "layout",
+            "--in",
+            "graph.json",
+            "--out",
+            "scene.json",
+            "--fit",
+            "2",
+            "1.5",
+            "4e1",
+        ])
+        .unwrap();
+        let Commands::Layout(args) = cli.command else {
+            panic!("expected layout");
+        };
+        assert_eq!(args.fit, Some(vec![2.0, 1.5, 40.0]));
+
+        for bad in ["0", "-1", "NaN", "inf", "x"] {
+            assert!(parse_fit_size(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn depth_decay_below_one_is_rejected() {
+        assert_eq!(parse_depth_decay("1"), Ok(1.0));
+        assert_eq!(parse_depth_decay("2.5"), Ok(2.5));
+        for bad in ["0.5", "-1", "NaN", "inf", "x"] {
+            assert!(parse_depth_decay(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn invade_writes_snapshot_and_traces_on_exit() {
+        let dir = std::env::temp_dir().join(format!("ptroute-invade-out-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let traces_path = dir.join("traces.json");
+        let cli = Cli::try_parse_from([
+            "ptroute".as_ref(),
+            "invade".as_ref(),
+            "--target".as_ref(),
+            "1.1.1.1".as_ref(),
+            "--waves".as_ref(),
+            "1".as_ref(),
+            "--out-dir".as_ref(),
+            dir.as_os_str(),
+            "--save-traces".as_ref(),
+            traces_path.as_os_str(),
+        ])
+        .unwrap();
+        let Commands::Invade(args) = cli.command else {
+            panic!("expected invade");
+        };
+        let mut state = invade::AppState {
+            wave: 1,
+            total_waves: args.waves,
+            targets: vec![invade::TargetView {
+                name: "1.1.1.1".to_string(),
+                rtt_history: VecDeque::new(),
+                hops: Vec::new(),
+            }],
+            last_detail: None,
+            sort_mode: args.sort_by,
+            filter_min_loss: args.filter_min_loss,
+        };
+        for (ttl, ip) in [(1, Some("10.0.0.1")), (2, None), (3, Some("1.1.1.1"))] {
+            state.update_hop(
+                0,
+                invade::HopView {
+                    ttl,
+                    ip: ip.map(str::to_string),
+                    loss: Some(if ip.is_some() { 0.0 } else { 1.0 }),
+                    median_rtt: ip.map(|_| ttl as f64 * 2.0),
+                    mpls_label: None,
+                },
+            );
+        }
+
+        save_invade_outputs(&args, &state).unwrap();
+
+        let snapshot = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.starts_with("invade-") && name.ends_with(".json")
+            })
+            .expect("snapshot written");
+        let receipt: invade::InvadeReceipt = read_value(&snapshot, "snapshot").unwrap();
+        let traces: TraceFile = read_value(&traces_path, "traces").unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(receipt.wave, 1);
+        assert_eq!(receipt.targets[0].name, "1.1.1.1");
+        assert_eq!(receipt.targets[0].hops.len(), 3);
+        assert!(!receipt.finished_at_utc.is_empty());
+        assert_eq!(traces.version, TraceFile::VERSION);
+        assert_eq!(traces.runs.len(), 1);
+        let hops = &traces.runs[0].hops;
+        assert_eq!(hops[0].rtt_ms, vec![Some(2.0)]);
+        assert_eq!(hops[1].ip, None);
+        assert_eq!(hops[1].rtt_ms, vec![None]);
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use ptroute_model::GraphFile;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const LATEST: &str = "latest";
+
+#[derive(Debug, Clone)]
+pub struct WatchOpts {
+    pub interval: Duration,
+    pub keep: Option<usize>,
+}
+
+/// Repeats `cycle` until `running` is cleared. Each successful cycle returns
+/// the run directory it wrote, which becomes `base/latest`; old runs beyond
+/// `opts.keep` are pruned afterwards. A failed cycle is reported and the loop
+/// carries on with the next interval.
+pub fn watch_loop<F>(
+    base: &Path,
+    opts: &WatchOpts,
+    running: &AtomicBool,
+    mut cycle: F,
+) -> Result<()>
+where
+    F: FnMut() -> Result<PathBuf>,
+{
+    fs::create_dir_all(base)
+        .map_err(|err| anyhow!("failed to create output directory {:?}: {}", base, err))?;
+
+    while running.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        match cycle() {
+            Ok(run_dir) => {
+                update_latest(base, &run_dir)?;
+                if let Some(keep) = opts.keep {
+                    prune_runs(base, keep)?;
+                }
+            }
+            Err(err) => eprintln!("error: watch cycle failed: {err}"),
+        }
+
+        while running.load(Ordering::SeqCst) && started.elapsed() < opts.interval {
+            let left = opts.interval.saturating_sub(started.elapsed());
+            thread::sleep(left.min(Duration::from_millis(100)));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_run_dir_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() == 15
+        && bytes[8] == b'-'
+        && bytes
+            .iter()
+            .enumerate()
+            .all(|(idx, b)| idx == 8 || b.is_ascii_digit())
+}
+
+/// Lists timestamped run directories under `base`, oldest first.
+pub fn list_runs(base: &Path) -> Result<Vec<PathBuf>> {
+    let entries =
+        fs::read_dir(base).map_err(|err| anyhow!("failed to list {:?}: {}", base, err))?;
+    let mut runs: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().map(|ty| ty.is_dir()).unwrap_or(false))
+        .filter(|entry| entry.file_name().to_str().is_some_and(is_run_dir_name))
+        .map(|entry| entry.path())
+        .collect();
+    runs.sort();
+    Ok(runs)
+}
+
+/// Removes all but the newest `keep` run directories and returns the removed paths.
+pub fn prune_runs(base: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let runs = list_runs(base)?;
+    let excess = runs.len().saturating_sub(keep);
+    let removed: Vec<PathBuf> = runs.into_iter().take(excess).collect();
+    for dir in &removed {
+        fs::remove_dir_all(dir).map_err(|err| anyhow!("failed to prune {:?}: {}", dir, err))?;
+    }
+    Ok(removed)
+}
+
+#[cfg(unix)]
+pub fn update_latest(base: &Path, run_dir: &Path) -> Result<()> {
+    let name = run_dir
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid run directory {:?}", run_dir))?;
+    let link = base.join(LATEST);
+    let tmp = base.join(format!(".{}.part-{}", LATEST, std::process::id()));
+    let _ = fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(name, &tmp)
+        .map_err(|err| anyhow!("failed to create symlink {:?}: {}", tmp, err))?;
+    if let Err(err) = fs::rename(&tmp, &link) {
+        let _ = fs::remove_file(&tmp);
+        return Err(anyhow!("failed to update {:?}: {}", link, err));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn update_latest(base: &Path, run_dir: &Path) -> Result<()> {
+    let link = base.join(LATEST);
+    let tmp = base.join(format!(".{}.part-{}", LATEST, std::process::id()));
+    let _ = fs::remove_dir_all(&tmp);
+    fs::create_dir_all(&tmp)
+        .map_err(|err| anyhow!("failed to create directory {:?}: {}", tmp, err))?;
+    let entries =
+        fs::read_dir(run_dir).map_err(|err| anyhow!("failed to list {:?}: {}", run_dir, err))?;
+    for entry in entries.flatten() {
+        if entry.file_type().map(|ty| ty.is_file()).unwrap_or(false) {
+            fs::copy(entry.path(), tmp.join(entry.file_name()))
+                .map_err(|err| anyhow!("failed to copy {:?}: {}", entry.path(), err))?;
+        }
+    }
+    let _ = fs::remove_dir_all(&link);
+    fs::rename(&tmp, &link).map_err(|err| anyhow!("failed to update {:?}: {}", link, err))
+}
+
+/// True when both graphs contain the same node ids and the same directed edges.
+pub fn same_topology(a: &GraphFile, b: &GraphFile) -> bool {
+    fn nodes(graph: &GraphFile) -> BTreeSet<&str> {
+        graph.nodes.iter().map(|node| node.id.as_str()).collect()
+    }
+    fn edges(graph: &GraphFile) -> BTreeSet<(&str, &str)> {
+        graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect()
+    }
+    nodes(a) == nodes(b) && edges(a) == edges(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ptroute_model::{Edge, Node};
+    use std::cell::Cell;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ptroute-watch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn node(id: &str) -> Node {
+        Node {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn edge(from: &str, to: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn loop_updates_latest_and_prunes_old_runs() {
+        let base = scratch_dir("loop");
+        let running = AtomicBool::new(true);
+        let cycles = Cell::new(0);
+        let opts = WatchOpts {
+            interval: Duration::ZERO,
+            keep: Some(2),
+        };
+
+        watch_loop(&base, &opts, &running, || {
+            cycles.set(cycles.get() + 1);
+            let run_dir = base.join(format!("20260101-00000{}", cycles.get()));
+            fs::create_dir_all(&run_dir)?;
+            fs::write(run_dir.join("run.json"), cycles.get().to_string())?;
+            if cycles.get() == 4 {
+                running.store(false, Ordering::SeqCst);
+            }
+            Ok(run_dir)
+        })
+        .unwrap();
+
+        let names: Vec<String> = list_runs(&base)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["20260101-000003", "20260101-000004"]);
+        assert_eq!(
+            fs::read_to_string(base.join(LATEST).join("run.json")).unwrap(),
+            "4"
+        );
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn prune_ignores_unrelated_entries() {
+        let base = scratch_dir("prune");
+        for name in ["20260101-000001", "20260101-000002", "run1", "notes"] {
+            fs::create_dir_all(base.join(name)).unwrap();
+        }
+
+        let removed = prune_runs(&base, 1).unwrap();
+        assert_eq!(removed, vec![base.join("20260101-000001")]);
+        assert!(base.join("run1").exists());
+        assert!(base.join("notes").exists());
+        assert!(base.join("20260101-000002").exists());
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn topology_ignores_counts() {
+        let graph = |seen: u32, to: &str| GraphFile {
+            version: 1,
+            nodes: vec![Node { seen, ..node("a") }, Node { seen, ..node(to) }],
+            edges: vec![Edge {
+                seen,
+                rtt_delta_ms_avg: seen as f64,
+                ..edge("a", to)
+            }],
+        };
+
+        assert!(same_topology(&graph(1, "b"), &graph(5, "b")));
+        assert!(!same_topology(&graph(1, "b"), &graph(1, "c")));
+    }
+}
@@ -0,0 +1,69 @@
+use ptroute_model::GraphFile;
+use std::fmt::Write;
+
+/// Writes [`GraphFile::to_adjacency_matrix`] as CSV. The first row and the
+/// first column hold the node ids; the top-left cell is empty.
+pub fn to_adjacency_csv(graph: &GraphFile) -> String {
+    let (ids, matrix) = graph.to_adjacency_matrix();
+    let mut out = String::new();
+    for id in &ids {
+        out.push(',');
+        out.push_str(&escape_csv(id));
+    }
+    out.push('\n');
+    for (id, row) in ids.iter().zip(&matrix) {
+        out.push_str(&escape_csv(id));
+        for value in row {
+            let _ = write!(out, ",{value}");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ptroute_model::{Edge, Node};
+
+    fn node(id: &str) -> Node {
+        Node {
+            id: id.to_string(),
+            seen: 1,
+            ..Default::default()
+        }
+    }
+
+    fn edge(from: &str, to: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_ids_as_header_row_and_column() {
+        let graph = GraphFile {
+            version: 1,
+            nodes: vec![node("10.0.0.1"), node("a,b")],
+            edges: vec![Edge {
+                seen: 2,
+                rtt_delta_ms_avg: 3.5,
+                ..edge("10.0.0.1", "a,b")
+            }],
+        };
+        assert_eq!(
+            to_adjacency_csv(&graph),
+            ",10.0.0.1,\"a,b\"\n10.0.0.1,0,3.5\n\"a,b\",0,0\n"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+use ptroute_model::ip_class::is_private_ip;
 use ptroute_model::{Edge, GraphFile, Hop, Node, TraceFile};
 use std::collections::{HashMap, HashSet};
 
@@ -5,23 +7,85 @@ use std::collections::{HashMap, HashSet};
 struct NodeStats {
     seen: u32,
     loss_probes: u32,
+    sent_probes: u32,
+    /// Every answered probe, across all runs.
+    rtts: Vec<f64>,
 }
 
 #[derive(Default)]
 struct EdgeStats {
     seen: u32,
     sum_delta: f64,
+    sum_sq_delta: f64,
     delta_count: u32,
+    min_ttl: Option<u32>,
+}
+
+impl EdgeStats {
+    fn mean_delta(&self) -> f64 {
+        if self.delta_count > 0 {
+            self.sum_delta / self.delta_count as f64
+        } else {
+            0.0
+        }
+    }
+
+    fn stddev_delta(&self) -> f64 {
+        if self.delta_count == 0 {
+            return 0.0;
+        }
+        let mean = self.mean_delta();
+        (self.sum_sq_delta / self.delta_count as f64 - mean * mean)
+            .max(0.0)
+            .sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Drop anonymous (`*`) hops and connect the known hops around them.
+    pub filter_unknown: bool,
+    /// Drop RFC1918, link-local and RFC4193 hops the same way.
+    pub filter_private_ips: bool,
+    /// Build from the hops as recorded instead of running
+    /// `TraceFile::deduplicate_all` first.
+    pub keep_duplicate_hops: bool,
+    /// Build from only the newest `n` runs of each target, by `timestamp_utc`.
+    pub max_runs_per_target: Option<usize>,
 }
 
 pub fn build_graph(trace_file: &TraceFile) -> GraphFile {
+    build_graph_with_options(trace_file, &BuildOptions::default())
+}
+
+pub fn build_graph_with_options(trace_file: &TraceFile, options: &BuildOptions) -> GraphFile {
+    let mut prepared: Option<TraceFile> = options
+        .max_runs_per_target
+        .map(|max| latest_runs_per_target(trace_file, max));
+    if !options.keep_duplicate_hops {
+        prepared
+            .get_or_insert_with(|| trace_file.clone())
+            .deduplicate_all();
+    }
+    let trace_file = prepared.as_ref().unwrap_or(trace_file);
+
     let mut node_stats: HashMap<String, NodeStats> = HashMap::new();
     let mut edge_stats: HashMap<(String, String), EdgeStats> = HashMap::new();
 
     for run in &trace_file.runs {
         let mut seen_this_run: HashSet<String> = HashSet::new();
+        let hops: Vec<&Hop> = run
+            .hops
+            .iter()
+            .filter(|hop| keep_hop(hop, options))
+            .collect();
 
-        for hop in &run.hops {
+        if hops.is_empty() && !run.hops.is_empty() && keep_id(&run.target, options) {
+            node_stats.entry(run.target.clone()).or_default().seen += 1;
+            continue;
+        }
+
+        for hop in &hops {
             let id = hop_id(hop);
             if seen_this_run.insert(id.clone()) {
                 node_stats.entry(id.clone()).or_default().seen += 1;
@@ -31,17 +95,24 @@ pub fn build_graph(trace_file: &TraceFile) -> GraphFile {
                 .iter()
                 .filter(|probe| probe.is_none())
                 .count() as u32;
-            node_stats.entry(id).or_default().loss_probes += loss_count;
+            let stats = node_stats.entry(id).or_default();
+            stats.loss_probes += loss_count;
+            stats.sent_probes += hop.rtt_ms.len() as u32;
+            stats.rtts.extend(hop.rtt_ms.iter().flatten());
         }
 
-        for window in run.hops.windows(2) {
-            let from = hop_id(&window[0]);
-            let to = hop_id(&window[1]);
+        for window in hops.windows(2) {
+            let from = hop_id(window[0]);
+            let to = hop_id(window[1]);
             let stats = edge_stats.entry((from.clone(), to.clone())).or_default();
             stats.seen += 1;
+            let ttl = window[0].ttl;
+            stats.min_ttl = Some(stats.min_ttl.map_or(ttl, |min| min.min(ttl)));
 
-            if let (Some(rtt_a), Some(rtt_b)) = (first_rtt(&window[0]), first_rtt(&window[1])) {
-                stats.sum_delta += rtt_b - rtt_a;
+            if let (Some(rtt_a), Some(rtt_b)) = (first_rtt(window[0]), first_rtt(window[1])) {
+                let delta = rtt_b - rtt_a;
+                stats.sum_delta += delta;
+                stats.sum_sq_delta += delta * delta;
                 stats.delta_count += 1;
             }
         }
@@ -49,10 +120,21 @@ pub fn build_graph(trace_file: &TraceFile) -> GraphFile {
 
     let mut nodes: Vec<Node> = node_stats
         .into_iter()
-        .map(|(id, stats)| Node {
-            id,
-            seen: stats.seen,
-            loss_probes: stats.loss_probes,
+        .map(|(id, mut stats)| {
+            let rtt_ms_avg = (!stats.rtts.is_empty())
+                .then(|| stats.rtts.iter().sum::<f64>() / stats.rtts.len() as f64);
+            stats.rtts.sort_by(f64::total_cmp);
+            Node {
+                id,
+                seen: stats.seen,
+                loss_probes: stats.loss_probes,
+                sent_probes: stats.sent_probes,
+                pagerank: None,
+                rtt_ms_avg,
+                rtt_p50_ms: percentile(&stats.rtts, 0.50),
+                rtt_p95_ms: percentile(&stats.rtts, 0.95),
+                asn: None,
+            }
         })
         .collect();
     nodes.sort_by(|a, b| a.id.cmp(&b.id));
@@ -63,11 +145,9 @@ pub fn build_graph(trace_file: &TraceFile) -> GraphFile {
             from,
             to,
             seen: stats.seen,
-            rtt_delta_ms_avg: if stats.delta_count > 0 {
-                stats.sum_delta / stats.delta_count as f64
-            } else {
-                0.0
-            },
+            rtt_delta_ms_avg: stats.mean_delta(),
+            rtt_delta_ms_stddev: stats.stddev_delta(),
+            depth: stats.min_ttl.unwrap_or(0),
         })
         .collect();
     edges.sort_by(|a, b| match a.from.cmp(&b.from) {
@@ -82,10 +162,73 @@ pub fn build_graph(trace_file: &TraceFile) -> GraphFile {
     }
 }
 
+/// Copy keeping the `max` most recent runs of each target in their original
+/// order. Runs whose timestamp does not parse count as oldest; ties go to
+/// the run that appears later in the file.
+fn latest_runs_per_target(trace_file: &TraceFile, max: usize) -> TraceFile {
+    let timestamps: Vec<Option<DateTime<Utc>>> = trace_file
+        .runs
+        .iter()
+        .map(|run| {
+            DateTime::parse_from_rfc3339(&run.timestamp_utc)
+                .ok()
+                .map(|time| time.with_timezone(&Utc))
+        })
+        .collect();
+    let mut by_target: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, run) in trace_file.runs.iter().enumerate() {
+        by_target.entry(&run.target).or_default().push(index);
+    }
+
+    let mut keep: HashSet<usize> = HashSet::new();
+    for runs in by_target.values_mut() {
+        runs.sort_by_key(|&index| (timestamps[index], index));
+        keep.extend(runs.iter().rev().take(max));
+    }
+
+    TraceFile {
+        version: trace_file.version,
+        runs: trace_file
+            .runs
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| keep.contains(index))
+            .map(|(_, run)| run.clone())
+            .collect(),
+    }
+}
+
+fn keep_hop(hop: &Hop, options: &BuildOptions) -> bool {
+    keep_id(&hop_id(hop), options)
+}
+
+fn keep_id(id: &str, options: &BuildOptions) -> bool {
+    if options.filter_unknown && is_unknown_id(id) {
+        return false;
+    }
+    !(options.filter_private_ips && is_private_ip(id))
+}
+
+/// Matches the id given to anonymous hops, including the per-TTL
+/// `unknown:ttl:N` form.
+pub fn is_unknown_id(id: &str) -> bool {
+    id == "unknown" || id.starts_with("unknown:ttl:")
+}
+
 fn hop_id(hop: &Hop) -> String {
     hop.ip.clone().unwrap_or_else(|| "unknown".to_string())
 }
 
+/// Linear interpolation between the closest ranks of ascending `sorted`;
+/// `None` when it is empty.
+fn percentile(sorted: &[f64], fraction: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = fraction.clamp(0.0, 1.0) * last as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    let weight = rank - below as f64;
+    Some(sorted[below] + (sorted[above] - sorted[below]) * weight)
+}
+
 fn first_rtt(hop: &Hop) -> Option<f64> {
     hop.rtt_ms.iter().copied().flatten().next()
 }
@@ -0,0 +1,310 @@
+use ptroute_model::GraphFile;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+
+/// Floor for RTT edge weights in [`betweenness_centrality_weighted`]. RTT
+/// deltas can be zero or negative, which Dijkstra cannot handle.
+pub const MIN_RTT_WEIGHT_MS: f64 = 0.001;
+
+/// Normalized betweenness centrality keyed by node id, using Brandes'
+/// algorithm over unweighted shortest paths. Edges are treated as undirected
+/// and scores are divided by `(V-1)(V-2)/2`, so they fall in `[0, 1]`.
+/// Edges whose endpoints are missing from the node list are ignored.
+pub fn betweenness_centrality(graph: &GraphFile) -> HashMap<String, f64> {
+    betweenness(graph, false)
+}
+
+/// Like [`betweenness_centrality`], but shortest paths are weighted by
+/// `rtt_delta_ms_avg`, floored at [`MIN_RTT_WEIGHT_MS`].
+pub fn betweenness_centrality_weighted(graph: &GraphFile) -> HashMap<String, f64> {
+    betweenness(graph, true)
+}
+
+fn betweenness(graph: &GraphFile, weighted: bool) -> HashMap<String, f64> {
+    let count = graph.nodes.len();
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+
+    // Both directions of a link collapse into one undirected edge with the
+    // smaller weight. Ordered so neighbor lists, and with them the order
+    // path counts are summed in, do not depend on hashing.
+    let mut links: BTreeMap<(usize, usize), f64> = BTreeMap::new();
+    for edge in &graph.edges {
+        let (Some(&from), Some(&to)) = (index.get(edge.from.as_str()), index.get(edge.to.as_str()))
+        else {
+            continue;
+        };
+        if from == to {
+            continue;
+        }
+        let weight = if weighted {
+            edge.rtt_delta_ms_avg.max(MIN_RTT_WEIGHT_MS)
+        } else {
+            1.0
+        };
+        links
+            .entry((from.min(to), from.max(to)))
+            .and_modify(|existing| *existing = existing.min(weight))
+            .or_insert(weight);
+    }
+    let mut neighbors: Vec<Vec<(usize, f64)>> = vec![Vec::new(); count];
+    for (&(a, b), &weight) in &links {
+        neighbors[a].push((b, weight));
+        neighbors[b].push((a, weight));
+    }
+
+    let mut score = vec![0.0; count];
+    for source in 0..count {
+        let paths = if weighted {
+            dijkstra_paths(&neighbors, source)
+        } else {
+            bfs_paths(&neighbors, source)
+        };
+        let mut delta = vec![0.0; count];
+        for &node in paths.order.iter().rev() {
+            for &pred in &paths.preds[node] {
+                delta[pred] += paths.sigma[pred] / paths.sigma[node] * (1.0 + delta[node]);
+            }
+            if node != source {
+                score[node] += delta[node];
+            }
+        }
+    }
+
+    // Every pair is counted once from each end.
+    let pairs = if count > 2 {
+        ((count - 1) * (count - 2)) as f64 / 2.0
+    } else {
+        1.0
+    };
+    graph
+        .nodes
+        .iter()
+        .zip(score)
+        .map(|(node, score)| (node.id.clone(), score / 2.0 / pairs))
+        .collect()
+}
+
+/// Shortest-path DAG from one source: nodes in order of distance, their
+/// predecessors and the number of shortest paths reaching each.
+struct Paths {
+    order: Vec<usize>,
+    preds: Vec<Vec<usize>>,
+    sigma: Vec<f64>,
+}
+
+fn bfs_paths(neighbors: &[Vec<(usize, f64)>], source: usize) -> Paths {
+    let count = neighbors.len();
+    let mut order = Vec::with_capacity(count);
+    let mut preds = vec![Vec::new(); count];
+    let mut sigma = vec![0.0; count];
+    let mut dist = vec![usize::MAX; count];
+    sigma[source] = 1.0;
+    dist[source] = 0;
+
+    let mut queue = VecDeque::from([source]);
+    while let Some(node) = queue.pop_front() {
+        order.push(node);
+        for &(next, _) in &neighbors[node] {
+            if dist[next] == usize::MAX {
+                dist[next] = dist[node] + 1;
+                queue.push_back(next);
+            }
+            if dist[next] == dist[node] + 1 {
+                sigma[next] += sigma[node];
+                preds[next].push(node);
+            }
+        }
+    }
+    Paths {
+        order,
+        preds,
+        sigma,
+    }
+}
+
+/// Path length ordered by `total_cmp`, so the heap never meets an
+/// incomparable pair.
+struct Dist(f64);
+
+impl PartialEq for Dist {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Dist {}
+
+impl PartialOrd for Dist {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Dist {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn dijkstra_paths(neighbors: &[Vec<(usize, f64)>], source: usize) -> Paths {
+    let count = neighbors.len();
+    let mut order = Vec::with_capacity(count);
+    let mut preds = vec![Vec::new(); count];
+    let mut sigma = vec![0.0; count];
+    let mut best = vec![f64::INFINITY; count];
+    let mut settled = vec![false; count];
+    best[source] = 0.0;
+
+    // Entries carry the predecessor that discovered them; its path count is
+    // added once the entry is settled. Equal distances settle the lower node
+    // index first.
+    let mut heap = BinaryHeap::from([Reverse((Dist(0.0), source, source))]);
+    while let Some(Reverse((Dist(dist), node, pred))) = heap.pop() {
+        if settled[node] {
+            continue;
+        }
+        settled[node] = true;
+        sigma[node] += if node == source { 1.0 } else { sigma[pred] };
+        order.push(node);
+        for &(next, weight) in &neighbors[node] {
+            if settled[next] {
+                continue;
+            }
+            let through = dist + weight;
+            match through.total_cmp(&best[next]) {
+                Ordering::Less => {
+                    best[next] = through;
+                    sigma[next] = 0.0;
+                    preds[next] = vec![node];
+                    heap.push(Reverse((Dist(through), next, node)));
+                }
+                Ordering::Equal => {
+                    sigma[next] += sigma[node];
+                    preds[next].push(node);
+                }
+                Ordering::Greater => {}
+            }
+        }
+    }
+    Paths {
+        order,
+        preds,
+        sigma,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ptroute_model::{Edge, Node};
+
+    fn node(id: &str) -> Node {
+        Node {
+            id: id.to_string(),
+            seen: 1,
+            ..Default::default()
+        }
+    }
+
+    fn edge(from: &str, to: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            seen: 1,
+            ..Default::default()
+        }
+    }
+
+    fn graph(ids: &[&str], edges: &[(&str, &str, f64)]) -> GraphFile {
+        GraphFile {
+            version: 1,
+            nodes: ids.iter().map(|&id| node(id)).collect(),
+            edges: edges
+                .iter()
+                .map(|&(from, to, rtt)| Edge {
+                    rtt_delta_ms_avg: rtt,
+                    ..edge(from, to)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn middle_of_a_path_is_the_bridge() {
+        let scores = betweenness_centrality(&graph(
+            &["a", "b", "c"],
+            &[("a", "b", 1.0), ("b", "c", 1.0)],
+        ));
+        assert_eq!(scores["b"], 1.0);
+        assert_eq!(scores["a"], 0.0);
+        assert_eq!(scores["c"], 0.0);
+    }
+
+    #[test]
+    fn scores_are_normalized() {
+        // A star with a tail: hub "h" is on every path but those from the tail.
+        let star = graph(
+            &["h", "a", "b", "c", "d", "e"],
+            &[
+                ("h", "a", 1.0),
+                ("h", "b", 1.0),
+                ("h", "c", 1.0),
+                ("a", "b", 1.0),
+                ("c", "d", 1.0),
+                ("d", "e", 1.0),
+            ],
+        );
+        for scores in [
+            betweenness_centrality(&star),
+            betweenness_centrality_weighted(&star),
+        ] {
+            assert!(scores.values().all(|score| (0.0..=1.0).contains(score)));
+            assert!(scores["h"] > scores["d"]);
+            assert!(scores["d"] > scores["e"]);
+        }
+    }
+
+    #[test]
+    fn weighted_ties_are_deterministic() {
+        // Two equally fast routes from a to d.
+        let diamond = graph(
+            &["a", "b", "c", "d"],
+            &[
+                ("a", "b", 0.5),
+                ("a", "c", 0.5),
+                ("b", "d", 0.5),
+                ("c", "d", 0.5),
+            ],
+        );
+        let first = betweenness_centrality_weighted(&diamond);
+        assert_eq!(first["b"], first["c"]);
+        assert!(first["b"] > 0.0);
+        for _ in 0..10 {
+            assert_eq!(betweenness_centrality_weighted(&diamond), first);
+        }
+    }
+
+    #[test]
+    fn weighted_paths_avoid_slow_links() {
+        // a-d is a single hop, but slower than going through b and c.
+        let ring = graph(
+            &["a", "b", "c", "d"],
+            &[
+                ("a", "b", 1.0),
+                ("b", "c", 1.0),
+                ("c", "d", 1.0),
+                ("a", "d", 10.0),
+            ],
+        );
+        let hops = betweenness_centrality(&ring);
+        let rtt = betweenness_centrality_weighted(&ring);
+        assert_eq!(hops["b"], hops["d"]);
+        assert!(rtt["b"] > hops["b"]);
+        assert!(rtt["d"] < hops["d"]);
+    }
+}
@@ -0,0 +1,82 @@
+use ptroute_model::GraphFile;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// In-memory IP to location table used for map exports.
+#[derive(Debug, Clone, Default)]
+pub struct GeoDb {
+    locations: HashMap<String, GeoPoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+impl GeoDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, ip: impl Into<String>, lat: f64, lng: f64) {
+        self.locations.insert(ip.into(), GeoPoint { lat, lng });
+    }
+
+    pub fn lookup(&self, ip: &str) -> Option<GeoPoint> {
+        self.locations.get(ip).copied()
+    }
+}
+
+/// Exports the graph as a GeoJSON `FeatureCollection`: one `Point` per node
+/// and one `LineString` per edge. Nodes the database cannot place are put at
+/// `[0, 0]` and flagged with `"no_geo": true`, as are edges touching them.
+pub fn to_geojson(graph: &GraphFile, geo: &GeoDb) -> String {
+    let coordinates = |id: &str| -> (Value, bool) {
+        match geo.lookup(id) {
+            Some(point) => (json!([point.lng, point.lat]), false),
+            None => (json!([0.0, 0.0]), true),
+        }
+    };
+
+    let mut features = Vec::with_capacity(graph.nodes.len() + graph.edges.len());
+    for node in &graph.nodes {
+        let (point, no_geo) = coordinates(&node.id);
+        let mut properties = json!({
+            "kind": "node",
+            "id": node.id,
+            "seen": node.seen,
+            "loss_probes": node.loss_probes,
+        });
+        if no_geo {
+            properties["no_geo"] = Value::Bool(true);
+        }
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": point },
+            "properties": properties,
+        }));
+    }
+
+    for edge in &graph.edges {
+        let (from, from_missing) = coordinates(&edge.from);
+        let (to, to_missing) = coordinates(&edge.to);
+        let mut properties = json!({
+            "kind": "edge",
+            "from": edge.from,
+            "to": edge.to,
+            "seen": edge.seen,
+            "rtt_delta_ms_avg": edge.rtt_delta_ms_avg,
+        });
+        if from_missing || to_missing {
+            properties["no_geo"] = Value::Bool(true);
+        }
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": [from, to] },
+            "properties": properties,
+        }));
+    }
+
+    json!({ "type": "FeatureCollection", "features": features }).to_string()
+}
@@ -0,0 +1,99 @@
+use ptroute_model::GraphFile;
+use std::fmt::Write;
+
+/// Serializes the graph as a directed GEXF 1.3 document for Gephi. Node and
+/// edge counters are exported as typed attributes.
+pub fn to_gexf(graph: &GraphFile) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(
+        "<gexf xmlns=\"http://gexf.net/1.3\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xsi:schemaLocation=\"http://gexf.net/1.3 http://gexf.net/1.3/gexf.xsd\" version=\"1.3\">\n",
+    );
+    out.push_str("  <meta>\n    <creator>ptroute</creator>\n  </meta>\n");
+    out.push_str("  <graph mode=\"static\" defaultedgetype=\"directed\">\n");
+    out.push_str("    <attributes class=\"node\">\n");
+    out.push_str("      <attribute id=\"seen\" title=\"seen\" type=\"integer\"/>\n");
+    out.push_str("      <attribute id=\"loss_probes\" title=\"loss_probes\" type=\"integer\"/>\n");
+    out.push_str("    </attributes>\n");
+    out.push_str("    <attributes class=\"edge\">\n");
+    out.push_str("      <attribute id=\"seen\" title=\"seen\" type=\"integer\"/>\n");
+    out.push_str(
+        "      <attribute id=\"rtt_delta_ms_avg\" title=\"rtt_delta_ms_avg\" type=\"double\"/>\n",
+    );
+    out.push_str("    </attributes>\n");
+
+    out.push_str("    <nodes>\n");
+    for node in &graph.nodes {
+        let id = escape_xml(&node.id);
+        let _ = writeln!(out, "      <node id=\"{id}\" label=\"{id}\">");
+        out.push_str("        <attvalues>\n");
+        let _ = writeln!(
+            out,
+            "          <attvalue for=\"seen\" value=\"{}\"/>",
+            node.seen
+        );
+        let _ = writeln!(
+            out,
+            "          <attvalue for=\"loss_probes\" value=\"{}\"/>",
+            node.loss_probes
+        );
+        out.push_str("        </attvalues>\n");
+        out.push_str("      </node>\n");
+    }
+    out.push_str("    </nodes>\n");
+
+    out.push_str("    <edges>\n");
+    for (idx, edge) in graph.edges.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "      <edge id=\"{}\" source=\"{}\" target=\"{}\">",
+            idx,
+            escape_xml(&edge.from),
+            escape_xml(&edge.to)
+        );
+        out.push_str("        <attvalues>\n");
+        let _ = writeln!(
+            out,
+            "          <attvalue for=\"seen\" value=\"{}\"/>",
+            edge.seen
+        );
+        let _ = writeln!(
+            out,
+            "          <attvalue for=\"rtt_delta_ms_avg\" value=\"{}\"/>",
+            edge.rtt_delta_ms_avg
+        );
+        out.push_str("        </attvalues>\n");
+        out.push_str("      </edge>\n");
+    }
+    out.push_str("    </edges>\n");
+    out.push_str("  </graph>\n");
+    out.push_str("</gexf>\n");
+    out
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup_characters() {
+        assert_eq!(escape_xml("fe80::1%<eth0>"), "fe80::1%&lt;eth0&gt;");
+        assert_eq!(escape_xml("a&\"b'"), "a&amp;&quot;b&apos;");
+    }
+}
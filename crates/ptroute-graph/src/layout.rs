@@ -1,56 +1,752 @@
-use ptroute_model::{GraphFile, SceneEdge, SceneFile, SceneNode};
-use std::collections::{HashMap, VecDeque};
+use crate::query::GraphQuery;
+use ptroute_model::{GraphFile, Node, SceneEdge, SceneFile, SceneNode};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+
+const JITTER_SCALE: f32 = 0.5;
+const LANE_SPACING: f32 = 2.0;
+/// Iterations `ptroute layout --algo force3d` runs by default.
+pub const DEFAULT_FORCE_ITERATIONS: usize = 300;
+const FORCE_START_TEMPERATURE: f32 = 1.0;
+const FORCE_END_TEMPERATURE: f32 = 0.01;
+const FORCE_MIN_DISTANCE: f32 = 1e-4;
+/// Default [`LayoutOptions::min_node_separation`].
+pub const DEFAULT_MIN_NODE_SEPARATION: f32 = 0.1;
+// Nodes are pushed slightly past the minimum so rounding cannot leave a
+// pair just inside it.
+const SEPARATION_MARGIN: f32 = 1.01;
+const SEPARATION_MAX_PASSES: usize = 100;
+
+/// Which placement [`layout_graph_with_options`] uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LayoutAlgo {
+    /// X is hop depth, Y the degree bucket (see [`layout_graph`]).
+    #[default]
+    Depth,
+    /// Concentric rings by hop depth (see [`layout_graph_circular`]).
+    Circular,
+    /// Fruchterman-Reingold in 3D (see [`layout_graph_force_3d`]).
+    Force3d,
+}
+
+impl std::str::FromStr for LayoutAlgo {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "depth" => Ok(Self::Depth),
+            "circular" => Ok(Self::Circular),
+            "force3d" => Ok(Self::Force3d),
+            other => Err(format!(
+                "unknown layout algorithm {other:?} (expected depth, circular or force3d)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutOptions {
+    pub algo: LayoutAlgo,
+    /// How far [`layout_graph_incremental`] moves nodes toward a fresh
+    /// layout: 0.0 keeps previous positions, 1.0 matches `layout_graph`.
+    pub blend_factor: f32,
+    /// Pull nodes sharing an IPv4 /24 (IPv6 /48) to within ±0.5 in Y and Z
+    /// of the group member at the group's median depth. Depth layout only.
+    pub subnet_clustering: bool,
+    /// Bucket nodes by incident edges weighted by `seen / max seen` instead
+    /// of by plain edge count, so runs of different lengths lay out alike.
+    pub normalize_weights: bool,
+    /// When set, [`layout_graph_incremental`] treats the blend as a spring
+    /// force and carries momentum between calls, one call per frame:
+    /// `velocity = (velocity + force) * damping`, then
+    /// `position += velocity`. `None` moves nodes by the force alone and
+    /// leaves velocities at rest.
+    pub velocity_damping: Option<f32>,
+    /// Steps [`layout_graph_force_3d`] runs; 0 keeps the initial positions.
+    /// Defaults to [`DEFAULT_FORCE_ITERATIONS`].
+    pub force_iterations: usize,
+    /// Node id to fixed position. Every layout places these nodes exactly
+    /// here; the force layout never moves them, though they still push and
+    /// pull the others. `target_bbox` is applied afterwards and moves them
+    /// with everything else. Ids missing from the graph are ignored.
+    pub pinned_nodes: HashMap<String, [f32; 3]>,
+    /// Nodes closer than this are nudged apart along Z after layout (X and
+    /// Y carry depth and degree). Pinned nodes stay put; 0 disables the
+    /// pass. Defaults to [`DEFAULT_MIN_NODE_SEPARATION`].
+    pub min_node_separation: f32,
+    /// `[width, height, depth]`: after layout, positions are scaled to fit a
+    /// box this size centered on the origin. Runs last, so pinned nodes move
+    /// with everything else and shrinking can undo `min_node_separation`.
+    pub target_bbox: Option<[f32; 3]>,
+    /// Scale `target_bbox` fits by one factor on every axis, keeping the
+    /// layout's proportions; otherwise each axis fills the box on its own.
+    /// Defaults to true.
+    pub uniform_scale: bool,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            algo: LayoutAlgo::default(),
+            blend_factor: 0.0,
+            subnet_clustering: false,
+            normalize_weights: false,
+            velocity_damping: None,
+            force_iterations: DEFAULT_FORCE_ITERATIONS,
+            pinned_nodes: HashMap::new(),
+            min_node_separation: DEFAULT_MIN_NODE_SEPARATION,
+            target_bbox: None,
+            uniform_scale: true,
+        }
+    }
+}
 
 pub fn layout_graph(graph: &GraphFile, seed: u64) -> SceneFile {
+    layout_graph_with_options(graph, seed, &LayoutOptions::default())
+}
+
+pub fn layout_graph_with_options(
+    graph: &GraphFile,
+    seed: u64,
+    options: &LayoutOptions,
+) -> SceneFile {
+    let mut scene = layout_unfitted(graph, seed, options);
+    if let Some(target) = options.target_bbox {
+        fit_to_bbox(&mut scene.nodes, target, options.uniform_scale);
+    }
+    scene
+}
+
+fn layout_unfitted(graph: &GraphFile, seed: u64, options: &LayoutOptions) -> SceneFile {
+    match options.algo {
+        LayoutAlgo::Depth => {}
+        LayoutAlgo::Circular => {
+            let mut scene = layout_graph_circular(graph, seed);
+            pin_nodes(&mut scene.nodes, &options.pinned_nodes);
+            separate_nodes(&mut scene.nodes, options, |_| false);
+            return scene;
+        }
+        LayoutAlgo::Force3d => return layout_graph_force_3d(graph, seed, options),
+    }
     if graph.nodes.is_empty() {
-        return SceneFile {
-            version: 1,
-            nodes: Vec::new(),
-            edges: Vec::new(),
-        };
+        return empty_scene();
     }
 
-    let mut indegree: HashMap<&str, u32> = HashMap::new();
-    let mut outdegree: HashMap<&str, u32> = HashMap::new();
-    let mut adjacency: HashMap<&str, Vec<&str>> = HashMap::new();
+    let shape = GraphShape::new(graph);
+    let weighted_degree = options.normalize_weights.then(|| normalized_degrees(graph));
 
-    for node in &graph.nodes {
-        indegree.insert(node.id.as_str(), 0);
-        outdegree.insert(node.id.as_str(), 0);
+    let mut nodes_sorted: Vec<_> = graph.nodes.iter().collect();
+    nodes_sorted.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut nodes: Vec<SceneNode> = nodes_sorted
+        .into_iter()
+        .map(|node| {
+            let degree = match &weighted_degree {
+                Some(weighted) => weighted.get(node.id.as_str()).copied().unwrap_or(0.0),
+                None => f64::from(
+                    shape.indegree.get(node.id.as_str()).copied().unwrap_or(0)
+                        + shape.outdegree.get(node.id.as_str()).copied().unwrap_or(0),
+                ),
+            };
+            if let Some(&position) = options.pinned_nodes.get(&node.id) {
+                return scene_node(node, position);
+            }
+            let bucket = degree_bucket(degree);
+            let x = shape.depth_of(&node.id) as f32;
+            let y = bucket as f32 * LANE_SPACING;
+            let z = jitter(seed, &node.id) * JITTER_SCALE;
+            scene_node(node, [x, y, z])
+        })
+        .collect();
+
+    if options.subnet_clustering {
+        cluster_subnets(&mut nodes, seed);
+        pin_nodes(&mut nodes, &options.pinned_nodes);
     }
+    separate_nodes(&mut nodes, options, |_| false);
 
-    for edge in &graph.edges {
-        *outdegree.entry(edge.from.as_str()).or_insert(0) += 1;
-        *indegree.entry(edge.to.as_str()).or_insert(0) += 1;
-        adjacency
-            .entry(edge.from.as_str())
+    SceneFile {
+        version: 1,
+        nodes,
+        edges: scene_edges(graph),
+    }
+}
+
+/// Places nodes on concentric rings in the XY plane: the start nodes at the
+/// center (on a half-lane ring when there are several), depth `d` on a ring
+/// of radius `d * lane_spacing`, each ring evenly spaced by id order. Nodes
+/// unreachable from the start nodes share the outermost ring. Z jitter
+/// matches [`layout_graph`].
+pub fn layout_graph_circular(graph: &GraphFile, seed: u64) -> SceneFile {
+    if graph.nodes.is_empty() {
+        return empty_scene();
+    }
+
+    let shape = GraphShape::new(graph);
+    let mut rings: BTreeMap<u32, Vec<&Node>> = BTreeMap::new();
+    for node in &graph.nodes {
+        rings
+            .entry(shape.depth_of(&node.id))
             .or_default()
-            .push(edge.to.as_str());
+            .push(node);
+    }
+
+    let mut nodes = Vec::with_capacity(graph.nodes.len());
+    for (depth, mut ring) in rings {
+        ring.sort_by(|a, b| a.id.cmp(&b.id));
+        let radius = match depth {
+            0 if ring.len() > 1 => LANE_SPACING * 0.5,
+            _ => depth as f32 * LANE_SPACING,
+        };
+        let count = ring.len() as f32;
+        for (index, node) in ring.into_iter().enumerate() {
+            let angle = std::f32::consts::TAU * index as f32 / count;
+            let z = jitter(seed, &node.id) * JITTER_SCALE;
+            nodes.push(scene_node(
+                node,
+                [radius * angle.cos(), radius * angle.sin(), z],
+            ));
+        }
+    }
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+
+    SceneFile {
+        version: 1,
+        nodes,
+        edges: scene_edges(graph),
+    }
+}
+
+/// Fruchterman-Reingold in all three axes. Nodes start in the unit cube
+/// around the origin at their per-axis [`jitter`] positions, then for
+/// `options.force_iterations` steps every pair repels with `k² / d` and every
+/// edge (either direction) attracts with `d² / k`, where `k = ∛(1 / n)`.
+/// The per-step displacement cap cools linearly from 1.0 to 0.01.
+pub fn layout_graph_force_3d(graph: &GraphFile, seed: u64, options: &LayoutOptions) -> SceneFile {
+    if graph.nodes.is_empty() {
+        return empty_scene();
+    }
+
+    let mut sorted: Vec<&Node> = graph.nodes.iter().collect();
+    sorted.sort_by(|a, b| a.id.cmp(&b.id));
+    let index: HashMap<&str, usize> = sorted
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+    let links: Vec<(usize, usize)> = graph
+        .edges
+        .iter()
+        .filter_map(|edge| {
+            let from = *index.get(edge.from.as_str())?;
+            let to = *index.get(edge.to.as_str())?;
+            (from != to).then_some((from, to))
+        })
+        .collect();
+
+    let mut positions: Vec<[f32; 3]> = sorted
+        .iter()
+        .map(|node| {
+            if let Some(&position) = options.pinned_nodes.get(&node.id) {
+                return position;
+            }
+            let axis = |offset: u64| jitter(seed.wrapping_add(offset), &node.id) * 0.5;
+            [axis(0), axis(1), axis(2)]
+        })
+        .collect();
+    let pinned: Vec<bool> = sorted
+        .iter()
+        .map(|node| options.pinned_nodes.contains_key(&node.id))
+        .collect();
+
+    let count = positions.len();
+    let k = (1.0 / count as f32).cbrt();
+    let iterations = options.force_iterations;
+    let mut displacement = vec![[0.0f32; 3]; count];
+    for step in 0..iterations {
+        let progress = if iterations > 1 {
+            step as f32 / (iterations - 1) as f32
+        } else {
+            0.0
+        };
+        let temperature =
+            FORCE_START_TEMPERATURE + (FORCE_END_TEMPERATURE - FORCE_START_TEMPERATURE) * progress;
+
+        displacement.iter_mut().for_each(|d| *d = [0.0; 3]);
+        for i in 0..count {
+            for j in (i + 1)..count {
+                let (delta, distance) = separation(positions[i], positions[j]);
+                let force = k * k / distance;
+                for axis in 0..3 {
+                    let push = delta[axis] / distance * force;
+                    displacement[i][axis] += push;
+                    displacement[j][axis] -= push;
+                }
+            }
+        }
+        for &(from, to) in &links {
+            let (delta, distance) = separation(positions[from], positions[to]);
+            let force = distance * distance / k;
+            for axis in 0..3 {
+                let pull = delta[axis] / distance * force;
+                displacement[from][axis] -= pull;
+                displacement[to][axis] += pull;
+            }
+        }
+
+        for ((position, moved), &pinned) in positions.iter_mut().zip(&displacement).zip(&pinned) {
+            let length = moved.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if pinned || length <= 0.0 {
+                continue;
+            }
+            let scale = length.min(temperature) / length;
+            for axis in 0..3 {
+                position[axis] += moved[axis] * scale;
+            }
+        }
+    }
+
+    let mut nodes: Vec<SceneNode> = sorted
+        .iter()
+        .zip(positions)
+        .map(|(node, position)| scene_node(node, position))
+        .collect();
+    separate_nodes(&mut nodes, options, |_| false);
+
+    SceneFile {
+        version: 1,
+        nodes,
+        edges: scene_edges(graph),
     }
+}
 
-    for neighbors in adjacency.values_mut() {
-        neighbors.sort();
+/// `a - b` and its length, nudged apart along X when the points coincide.
+fn separation(a: [f32; 3], b: [f32; 3]) -> ([f32; 3], f32) {
+    let delta = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let distance = delta.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if distance < FORCE_MIN_DISTANCE {
+        ([FORCE_MIN_DISTANCE, 0.0, 0.0], FORCE_MIN_DISTANCE)
+    } else {
+        (delta, distance)
     }
+}
 
-    let mut starts: Vec<&str> = indegree
+/// Pushes apart, along Z, every pair of nodes closer than
+/// `options.min_node_separation`. Pinned nodes and those `held` selects
+/// never move. The other nodes sharing an exact X and Y (a depth layout lane)
+/// are first spread out in one sweep around their mean Z. Any pairs still
+/// too close then share each push unless one is held, repeating until none
+/// are left (or a pass limit is hit), since a push can crowd a third node.
+fn separate_nodes(
+    nodes: &mut [SceneNode],
+    options: &LayoutOptions,
+    held: impl Fn(&SceneNode) -> bool,
+) {
+    let min = options.min_node_separation;
+    if min <= 0.0 || nodes.len() < 2 {
+        return;
+    }
+    let target = min * SEPARATION_MARGIN;
+    let pinned: Vec<bool> = nodes
         .iter()
-        .filter_map(|(id, deg)| if *deg == 0 { Some(*id) } else { None })
+        .map(|node| options.pinned_nodes.contains_key(&node.id) || held(node))
         .collect();
 
-    if starts.is_empty() {
-        let min_in = indegree.values().min().copied().unwrap_or(0);
-        starts = indegree
+    let mut lanes: BTreeMap<(u32, u32), Vec<usize>> = BTreeMap::new();
+    for (index, node) in nodes.iter().enumerate() {
+        if !pinned[index] {
+            let [x, y, _] = node.position;
+            lanes
+                .entry((x.to_bits(), y.to_bits()))
+                .or_default()
+                .push(index);
+        }
+    }
+    for mut lane in lanes.into_values().filter(|lane| lane.len() > 1) {
+        lane.sort_by(|&a, &b| {
+            nodes[a].position[2]
+                .total_cmp(&nodes[b].position[2])
+                .then_with(|| nodes[a].id.cmp(&nodes[b].id))
+        });
+        let before: Vec<f32> = lane.iter().map(|&i| nodes[i].position[2]).collect();
+        let mut after = before.clone();
+        for k in 1..after.len() {
+            after[k] = after[k].max(after[k - 1] + target);
+        }
+        let shift = (before.iter().sum::<f32>() - after.iter().sum::<f32>()) / lane.len() as f32;
+        for (&i, z) in lane.iter().zip(after) {
+            nodes[i].position[2] = z + shift;
+        }
+    }
+    // Only Z moves, so one sort by X bounds every pass's search.
+    let mut order: Vec<usize> = (0..nodes.len()).collect();
+    order.sort_by(|&a, &b| {
+        nodes[a].position[0]
+            .total_cmp(&nodes[b].position[0])
+            .then_with(|| nodes[a].id.cmp(&nodes[b].id))
+    });
+
+    for _ in 0..SEPARATION_MAX_PASSES {
+        let mut moved = false;
+        for (k, &i) in order.iter().enumerate() {
+            for &j in &order[k + 1..] {
+                let (a, b) = (nodes[i].position, nodes[j].position);
+                if b[0] - a[0] >= min {
+                    break;
+                }
+                let flat = (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2);
+                let dz = b[2] - a[2];
+                if flat + dz * dz >= min * min {
+                    continue;
+                }
+                let (share_i, share_j) = match (pinned[i], pinned[j]) {
+                    (true, true) => continue,
+                    (true, false) => (0.0, 1.0),
+                    (false, true) => (1.0, 0.0),
+                    (false, false) => (0.5, 0.5),
+                };
+                // Coincident nodes split by id so the result is stable.
+                let up = if dz == 0.0 {
+                    nodes[i].id < nodes[j].id
+                } else {
+                    dz > 0.0
+                };
+                let push = ((target * target - flat).max(0.0).sqrt() - dz.abs())
+                    * if up { 1.0 } else { -1.0 };
+                nodes[i].position[2] -= push * share_i;
+                nodes[j].position[2] += push * share_j;
+                moved = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+}
+
+/// Centers the nodes' bounding box on the origin and scales it to fit
+/// `target`. Axes along which every node lines up are only centered; with
+/// `uniform` the tightest axis sets one scale for all three.
+fn fit_to_bbox(nodes: &mut [SceneNode], target: [f32; 3], uniform: bool) {
+    if nodes.is_empty() {
+        return;
+    }
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for node in nodes.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(node.position[axis]);
+            max[axis] = max[axis].max(node.position[axis]);
+        }
+    }
+
+    let mut scale = [1.0_f32; 3];
+    for axis in 0..3 {
+        let extent = max[axis] - min[axis];
+        if extent > f32::EPSILON {
+            scale[axis] = target[axis].max(0.0) / extent;
+        }
+    }
+    if uniform {
+        let common = (0..3)
+            .filter(|&axis| max[axis] - min[axis] > f32::EPSILON)
+            .map(|axis| scale[axis])
+            .fold(f32::INFINITY, f32::min);
+        scale = [if common.is_finite() { common } else { 1.0 }; 3];
+    }
+
+    for node in nodes {
+        for axis in 0..3 {
+            let center = (min[axis] + max[axis]) * 0.5;
+            node.position[axis] = (node.position[axis] - center) * scale[axis];
+        }
+    }
+}
+
+/// Moves every node in `pins` to its pinned position.
+fn pin_nodes(nodes: &mut [SceneNode], pins: &HashMap<String, [f32; 3]>) {
+    if pins.is_empty() {
+        return;
+    }
+    for node in nodes {
+        if let Some(&position) = pins.get(&node.id) {
+            node.position = position;
+        }
+    }
+}
+
+fn empty_scene() -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: Vec::new(),
+        edges: Vec::new(),
+    }
+}
+
+fn scene_node(node: &Node, position: [f32; 3]) -> SceneNode {
+    SceneNode {
+        id: node.id.clone(),
+        position,
+        seen: node.seen,
+        loss_probes: node.loss_probes,
+        sent_probes: node.sent_probes,
+        pagerank: node.pagerank,
+        rtt_ms_avg: node.rtt_ms_avg,
+        asn: node.asn,
+        label: None,
+        velocity: [0.0; 3],
+    }
+}
+
+fn scene_edges(graph: &GraphFile) -> Vec<SceneEdge> {
+    graph
+        .edges
+        .iter()
+        .map(|edge| SceneEdge {
+            from: edge.from.clone(),
+            to: edge.to.clone(),
+            seen: edge.seen,
+            rtt_delta_ms_avg: edge.rtt_delta_ms_avg,
+            depth: edge.depth,
+        })
+        .collect()
+}
+
+/// Degrees and hop depths shared by the layouts. Depth counts from the nodes
+/// nothing points at (or the least pointed-at ones when every node has an
+/// incoming edge).
+struct GraphShape<'a> {
+    indegree: HashMap<&'a str, u32>,
+    outdegree: HashMap<&'a str, u32>,
+    depth: HashMap<&'a str, u32>,
+    fallback_depth: u32,
+}
+
+impl<'a> GraphShape<'a> {
+    fn new(graph: &'a GraphFile) -> Self {
+        let mut indegree: HashMap<&str, u32> = HashMap::new();
+        let mut outdegree: HashMap<&str, u32> = HashMap::new();
+        let mut adjacency: HashMap<&str, Vec<&str>> = HashMap::new();
+
+        for node in &graph.nodes {
+            indegree.insert(node.id.as_str(), 0);
+            outdegree.insert(node.id.as_str(), 0);
+        }
+
+        for edge in &graph.edges {
+            *outdegree.entry(edge.from.as_str()).or_insert(0) += 1;
+            *indegree.entry(edge.to.as_str()).or_insert(0) += 1;
+            adjacency
+                .entry(edge.from.as_str())
+                .or_default()
+                .push(edge.to.as_str());
+        }
+
+        for neighbors in adjacency.values_mut() {
+            neighbors.sort();
+        }
+
+        let mut starts: Vec<&str> = indegree
             .iter()
-            .filter_map(|(id, deg)| if *deg == min_in { Some(*id) } else { None })
+            .filter_map(|(id, deg)| if *deg == 0 { Some(*id) } else { None })
             .collect();
+
+        if starts.is_empty() {
+            let min_in = indegree.values().min().copied().unwrap_or(0);
+            starts = indegree
+                .iter()
+                .filter_map(|(id, deg)| if *deg == min_in { Some(*id) } else { None })
+                .collect();
+        }
+
+        starts.sort();
+
+        let depth = match graph.topological_sort() {
+            Some(order) => topological_depths(&order, &starts, &adjacency),
+            None => bfs_depths(&starts, &adjacency),
+        };
+        let fallback_depth = depth.values().copied().max().unwrap_or(0) + 1;
+
+        Self {
+            indegree,
+            outdegree,
+            depth,
+            fallback_depth,
+        }
+    }
+
+    fn depth_of(&self, id: &str) -> u32 {
+        self.depth.get(id).copied().unwrap_or(self.fallback_depth)
     }
+}
+
+/// Lays out `updated_graph` starting from `previous`: surviving nodes keep
+/// their positions, nodes that disappeared are dropped, and new nodes are
+/// placed one step beyond an already placed neighbor (walking outward from
+/// the surviving nodes). New nodes with no placed neighbor use their
+/// `layout_graph` position. Every position is then blended toward the fresh
+/// layout by `options.blend_factor`, with momentum when
+/// `options.velocity_damping` is set. Pinned nodes sit at their pins and
+/// stay at rest. `options.min_node_separation` only moves new nodes, so with
+/// a zero blend surviving nodes keep their previous positions exactly.
+pub fn layout_graph_incremental(
+    previous: &SceneFile,
+    updated_graph: &GraphFile,
+    seed: u64,
+    options: &LayoutOptions,
+) -> SceneFile {
+    let mut scene = layout_graph_with_options(updated_graph, seed, options);
+    let blend = options.blend_factor.clamp(0.0, 1.0);
 
-    starts.sort();
+    let mut neighbors: HashMap<&str, Vec<(&str, f32)>> = HashMap::new();
+    for edge in &updated_graph.edges {
+        neighbors
+            .entry(edge.from.as_str())
+            .or_default()
+            .push((edge.to.as_str(), 1.0));
+        neighbors
+            .entry(edge.to.as_str())
+            .or_default()
+            .push((edge.from.as_str(), -1.0));
+    }
+    for list in neighbors.values_mut() {
+        list.sort_by(|a, b| a.0.cmp(b.0));
+    }
 
+    let wanted: HashSet<&str> = scene.nodes.iter().map(|node| node.id.as_str()).collect();
+    let mut placed: HashMap<&str, [f32; 3]> = previous
+        .nodes
+        .iter()
+        .filter(|node| wanted.contains(node.id.as_str()))
+        .map(|node| (node.id.as_str(), node.position))
+        .collect();
+    for (id, position) in &options.pinned_nodes {
+        if wanted.contains(id.as_str()) {
+            placed.insert(id.as_str(), *position);
+        }
+    }
+    let velocities: HashMap<&str, [f32; 3]> = previous
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node.velocity))
+        .collect();
+
+    let mut queue: VecDeque<&str> = {
+        let mut kept: Vec<&str> = placed.keys().copied().collect();
+        kept.sort();
+        kept.into()
+    };
+    while let Some(id) = queue.pop_front() {
+        let origin = placed[id];
+        for &(neighbor, dx) in neighbors.get(id).into_iter().flatten() {
+            if placed.contains_key(neighbor) || !wanted.contains(neighbor) {
+                continue;
+            }
+            let z = origin[2] + jitter(seed, neighbor) * JITTER_SCALE;
+            placed.insert(neighbor, [origin[0] + dx, origin[1], z]);
+            queue.push_back(neighbor);
+        }
+    }
+
+    for node in &mut scene.nodes {
+        if options.pinned_nodes.contains_key(&node.id) {
+            continue;
+        }
+        let Some(local) = placed.get(node.id.as_str()) else {
+            continue;
+        };
+        let velocity = velocities
+            .get(node.id.as_str())
+            .copied()
+            .unwrap_or_default();
+        for axis in 0..3 {
+            let fresh = node.position[axis];
+            node.position[axis] = match options.velocity_damping {
+                Some(damping) => {
+                    let force = (fresh - local[axis]) * blend;
+                    node.velocity[axis] = (velocity[axis] + force) * damping;
+                    local[axis] + node.velocity[axis]
+                }
+                None => local[axis] * (1.0 - blend) + fresh * blend,
+            };
+        }
+    }
+    // Surviving nodes already sit where the previous frame put them.
+    let kept: HashSet<&str> = previous.nodes.iter().map(|node| node.id.as_str()).collect();
+    separate_nodes(&mut scene.nodes, options, |node| {
+        kept.contains(node.id.as_str())
+    });
+
+    scene
+}
+
+fn cluster_subnets(nodes: &mut [SceneNode], seed: u64) {
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (index, node) in nodes.iter().enumerate() {
+        if let Some(prefix) = subnet_prefix(&node.id) {
+            groups.entry(prefix).or_default().push(index);
+        }
+    }
+
+    for mut members in groups.into_values().filter(|members| members.len() > 1) {
+        // Nodes are sorted by id, so ties on depth resolve to the lowest id.
+        members.sort_by(|&a, &b| nodes[a].position[0].total_cmp(&nodes[b].position[0]));
+        let anchor = nodes[members[(members.len() - 1) / 2]].position;
+        for index in members {
+            let node = &mut nodes[index];
+            node.position[1] = anchor[1] + jitter(seed.rotate_left(32), &node.id) * JITTER_SCALE;
+            node.position[2] = anchor[2] + jitter(seed, &node.id) * JITTER_SCALE;
+        }
+    }
+}
+
+/// `a.b.c` for IPv4 ids (/24) and the first three groups for IPv6 ids (/48).
+fn subnet_prefix(id: &str) -> Option<String> {
+    let address = id.split_once('%').map_or(id, |(address, _zone)| address);
+    match address.parse::<IpAddr>().ok()? {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            Some(format!("{a}.{b}.{c}"))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            Some(format!(
+                "{:x}:{:x}:{:x}",
+                segments[0], segments[1], segments[2]
+            ))
+        }
+    }
+}
+
+// Shortest hop count from any start node, relaxed in topological order so
+// every predecessor is settled before its successors.
+fn topological_depths<'a>(
+    order: &[String],
+    starts: &[&'a str],
+    adjacency: &HashMap<&'a str, Vec<&'a str>>,
+) -> HashMap<&'a str, u32> {
+    let mut depth: HashMap<&str, u32> = starts.iter().map(|start| (*start, 0)).collect();
+    for node in order {
+        let Some(&current) = depth.get(node.as_str()) else {
+            continue;
+        };
+        for &neighbor in adjacency.get(node.as_str()).into_iter().flatten() {
+            let entry = depth.entry(neighbor).or_insert(current + 1);
+            *entry = (*entry).min(current + 1);
+        }
+    }
+    depth
+}
+
+fn bfs_depths<'a>(
+    starts: &[&'a str],
+    adjacency: &HashMap<&'a str, Vec<&'a str>>,
+) -> HashMap<&'a str, u32> {
     let mut depth: HashMap<&str, u32> = HashMap::new();
     let mut queue: VecDeque<&str> = VecDeque::new();
 
-    for start in starts {
+    for &start in starts {
         depth.insert(start, 0);
         queue.push_back(start);
     }
@@ -66,59 +762,71 @@ pub fn layout_graph(graph: &GraphFile, seed: u64) -> SceneFile {
             }
         }
     }
+    depth
+}
 
-    let max_depth = depth.values().copied().max().unwrap_or(0);
-    let fallback_depth = max_depth + 1;
-    let lane_spacing = 2.0_f32;
-    let jitter_scale = 0.5_f32;
-
-    let mut nodes_sorted: Vec<_> = graph.nodes.iter().collect();
-    nodes_sorted.sort_by(|a, b| a.id.cmp(&b.id));
+/// Recenters the scene on the node centroid and scales it uniformly so every
+/// position component lies within `[-1, 1]`.
+pub fn normalize_scene(scene: &SceneFile) -> SceneFile {
+    let mut normalized = scene.clone();
+    if scene.nodes.is_empty() {
+        return normalized;
+    }
 
-    let nodes: Vec<SceneNode> = nodes_sorted
-        .into_iter()
-        .map(|node| {
-            let degree = indegree.get(node.id.as_str()).copied().unwrap_or(0)
-                + outdegree.get(node.id.as_str()).copied().unwrap_or(0);
-            let bucket = degree_bucket(degree);
-            let x = depth
-                .get(node.id.as_str())
-                .copied()
-                .unwrap_or(fallback_depth) as f32;
-            let y = bucket as f32 * lane_spacing;
-            let z = jitter(seed, &node.id) * jitter_scale;
-            SceneNode {
-                id: node.id.clone(),
-                position: [x, y, z],
-                seen: node.seen,
-                loss_probes: node.loss_probes,
-            }
-        })
-        .collect();
+    let count = scene.nodes.len() as f64;
+    let mut centroid = [0.0_f64; 3];
+    for node in &scene.nodes {
+        for (axis, value) in node.position.iter().enumerate() {
+            centroid[axis] += f64::from(*value) / count;
+        }
+    }
 
-    let edges: Vec<SceneEdge> = graph
-        .edges
+    let max_offset = scene
+        .nodes
         .iter()
-        .map(|edge| SceneEdge {
-            from: edge.from.clone(),
-            to: edge.to.clone(),
-            seen: edge.seen,
-            rtt_delta_ms_avg: edge.rtt_delta_ms_avg,
+        .flat_map(|node| {
+            node.position
+                .iter()
+                .enumerate()
+                .map(|(axis, value)| (f64::from(*value) - centroid[axis]).abs())
+                .collect::<Vec<_>>()
         })
-        .collect();
+        .fold(0.0_f64, f64::max);
+    let scale = if max_offset > 0.0 {
+        1.0 / max_offset
+    } else {
+        1.0
+    };
 
-    SceneFile {
-        version: 1,
-        nodes,
-        edges,
+    for node in &mut normalized.nodes {
+        for (axis, value) in node.position.iter_mut().enumerate() {
+            *value = ((f64::from(*value) - centroid[axis]) * scale) as f32;
+        }
+    }
+
+    normalized
+}
+
+/// Sum of `seen / max seen` over each node's incident edges.
+fn normalized_degrees(graph: &GraphFile) -> HashMap<&str, f64> {
+    let max_seen = graph.edges.iter().map(|edge| edge.seen).max().unwrap_or(0);
+    let mut degrees: HashMap<&str, f64> = HashMap::new();
+    if max_seen == 0 {
+        return degrees;
+    }
+    for edge in &graph.edges {
+        let weight = f64::from(edge.seen) / f64::from(max_seen);
+        *degrees.entry(edge.from.as_str()).or_insert(0.0) += weight;
+        *degrees.entry(edge.to.as_str()).or_insert(0.0) += weight;
     }
+    degrees
 }
 
-fn degree_bucket(degree: u32) -> i32 {
-    if degree == 0 {
+fn degree_bucket(degree: f64) -> i32 {
+    if degree <= 0.0 {
         0
     } else {
-        (f64::from(degree).log2().floor() as i32).max(0)
+        (degree.log2().floor() as i32).max(0)
     }
 }
 
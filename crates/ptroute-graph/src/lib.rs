@@ -1,7 +1,23 @@
 //! Graph building and layout.
 
+pub mod adjacency;
 pub mod build;
+pub mod centrality;
+pub mod geo_export;
+pub mod gexf;
 pub mod layout;
+pub mod pagerank;
+pub mod query;
 
-pub use build::build_graph;
-pub use layout::layout_graph;
+pub use adjacency::to_adjacency_csv;
+pub use build::{build_graph, build_graph_with_options, is_unknown_id, BuildOptions};
+pub use centrality::{betweenness_centrality, betweenness_centrality_weighted};
+pub use geo_export::{to_geojson, GeoDb, GeoPoint};
+pub use gexf::to_gexf;
+pub use layout::{
+    layout_graph, layout_graph_circular, layout_graph_force_3d, layout_graph_incremental,
+    layout_graph_with_options, normalize_scene, LayoutAlgo, LayoutOptions,
+    DEFAULT_FORCE_ITERATIONS, DEFAULT_MIN_NODE_SEPARATION,
+};
+pub use pagerank::{annotate_page_rank, page_rank};
+pub use query::GraphQuery;
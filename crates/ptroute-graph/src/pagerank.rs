@@ -0,0 +1,71 @@
+use ptroute_model::GraphFile;
+use std::collections::HashMap;
+
+/// Damping factor used by `ptroute build --annotate-pagerank`.
+pub const DEFAULT_DAMPING: f64 = 0.85;
+/// Iteration count used by `ptroute build --annotate-pagerank`.
+pub const DEFAULT_ITERATIONS: u32 = 50;
+
+/// Weighted PageRank keyed by node id. Each node passes its rank along its
+/// outgoing edges in proportion to their `seen` counts; nodes without
+/// outgoing edges spread theirs evenly over all nodes, so scores sum to 1.
+/// Edges whose endpoints are missing from the node list are ignored.
+pub fn page_rank(graph: &GraphFile, damping: f64, iterations: u32) -> HashMap<String, f64> {
+    let count = graph.nodes.len();
+    if count == 0 {
+        return HashMap::new();
+    }
+    let index: HashMap<&str, usize> = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+
+    let mut out: Vec<Vec<(usize, f64)>> = vec![Vec::new(); count];
+    for edge in &graph.edges {
+        let (Some(&from), Some(&to)) = (index.get(edge.from.as_str()), index.get(edge.to.as_str()))
+        else {
+            continue;
+        };
+        if edge.seen > 0 {
+            out[from].push((to, f64::from(edge.seen)));
+        }
+    }
+    let out_weight: Vec<f64> = out
+        .iter()
+        .map(|targets| targets.iter().map(|(_, weight)| weight).sum())
+        .collect();
+
+    let n = count as f64;
+    let mut rank = vec![1.0 / n; count];
+    for _ in 0..iterations {
+        let dangling: f64 = (0..count)
+            .filter(|&node| out[node].is_empty())
+            .map(|node| rank[node])
+            .sum();
+        let base = (1.0 - damping) / n + damping * dangling / n;
+        let mut next = vec![base; count];
+        for (from, targets) in out.iter().enumerate() {
+            for &(to, weight) in targets {
+                next[to] += damping * rank[from] * weight / out_weight[from];
+            }
+        }
+        rank = next;
+    }
+
+    graph
+        .nodes
+        .iter()
+        .zip(rank)
+        .map(|(node, score)| (node.id.clone(), score))
+        .collect()
+}
+
+/// Stores [`page_rank`] scores on every node of `graph`.
+pub fn annotate_page_rank(graph: &mut GraphFile, damping: f64, iterations: u32) {
+    let scores = page_rank(graph, damping, iterations);
+    for node in &mut graph.nodes {
+        node.pagerank = scores.get(&node.id).copied();
+    }
+}
@@ -0,0 +1,291 @@
+use ptroute_model::GraphFile;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// Topology metrics over a built graph. Edges whose endpoints are missing
+/// from the node list are ignored.
+pub trait GraphQuery {
+    /// True when every node can reach every other node, ignoring edge direction.
+    fn is_connected(&self) -> bool;
+    /// Longest simple directed path, as node ids from first to last hop.
+    fn longest_path(&self) -> Option<Vec<String>>;
+    /// Largest hop count among the shortest undirected paths between
+    /// reachable node pairs.
+    fn diameter(&self) -> usize;
+    /// Node ids in topological order (Kahn's algorithm, ties broken by
+    /// alphabetical id), or `None` when the graph has a cycle.
+    fn topological_sort(&self) -> Option<Vec<String>>;
+    /// True when some directed path leads back to its start, self-loops included.
+    fn has_cycle(&self) -> bool;
+    /// Every node plus the edges of a minimum spanning forest, ignoring edge
+    /// direction and weighting edges by `rtt_delta_ms_avg` (Kruskal's
+    /// algorithm; ties go to the earlier edge in the file). Kept edges are
+    /// copied unchanged, in file order. Self-loops are dropped.
+    fn minimum_spanning_tree(&self) -> GraphFile;
+}
+
+struct Adjacency<'a> {
+    ids: Vec<&'a str>,
+    out: Vec<Vec<usize>>,
+    undirected: Vec<Vec<usize>>,
+    self_loop: bool,
+}
+
+impl<'a> Adjacency<'a> {
+    fn new(graph: &'a GraphFile) -> Self {
+        let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+        let index: HashMap<&str, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let mut out = vec![Vec::new(); ids.len()];
+        let mut undirected = vec![Vec::new(); ids.len()];
+        let mut self_loop = false;
+        for edge in &graph.edges {
+            let (Some(&from), Some(&to)) =
+                (index.get(edge.from.as_str()), index.get(edge.to.as_str()))
+            else {
+                continue;
+            };
+            if from == to {
+                self_loop = true;
+                continue;
+            }
+            out[from].push(to);
+            undirected[from].push(to);
+            undirected[to].push(from);
+        }
+        Self {
+            ids,
+            out,
+            undirected,
+            self_loop,
+        }
+    }
+
+    fn bfs_distances(&self, start: usize) -> Vec<Option<usize>> {
+        let mut dist = vec![None; self.ids.len()];
+        let mut queue = VecDeque::new();
+        dist[start] = Some(0);
+        queue.push_back(start);
+        while let Some(node) = queue.pop_front() {
+            let next = dist[node].unwrap_or(0) + 1;
+            for &neighbor in &self.undirected[node] {
+                if dist[neighbor].is_none() {
+                    dist[neighbor] = Some(next);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        dist
+    }
+
+    fn topological_order(&self) -> Option<Vec<usize>> {
+        let mut indegree = vec![0usize; self.ids.len()];
+        for targets in &self.out {
+            for &to in targets {
+                indegree[to] += 1;
+            }
+        }
+        let mut ready: BTreeSet<(&str, usize)> = (0..self.ids.len())
+            .filter(|&node| indegree[node] == 0)
+            .map(|node| (self.ids[node], node))
+            .collect();
+        let mut order = Vec::with_capacity(self.ids.len());
+        while let Some((_, node)) = ready.pop_first() {
+            order.push(node);
+            for &to in &self.out[node] {
+                indegree[to] -= 1;
+                if indegree[to] == 0 {
+                    ready.insert((self.ids[to], to));
+                }
+            }
+        }
+        (order.len() == self.ids.len()).then_some(order)
+    }
+
+    fn longest_path_dag(&self, order: &[usize]) -> Vec<usize> {
+        let mut length = vec![1usize; self.ids.len()];
+        let mut parent: Vec<Option<usize>> = vec![None; self.ids.len()];
+        for &node in order {
+            for &to in &self.out[node] {
+                if length[node] + 1 > length[to] {
+                    length[to] = length[node] + 1;
+                    parent[to] = Some(node);
+                }
+            }
+        }
+        let mut end = 0;
+        for node in 1..self.ids.len() {
+            if length[node] > length[end] {
+                end = node;
+            }
+        }
+        let mut path = vec![end];
+        while let Some(prev) = parent[*path.last().unwrap_or(&end)] {
+            path.push(prev);
+        }
+        path.reverse();
+        path
+    }
+
+    fn longest_path_dfs(&self) -> Vec<usize> {
+        let mut best = Vec::new();
+        let mut on_path = vec![false; self.ids.len()];
+        let mut path = Vec::new();
+        for start in 0..self.ids.len() {
+            self.extend_path(start, &mut on_path, &mut path, &mut best);
+        }
+        best
+    }
+
+    fn extend_path(
+        &self,
+        node: usize,
+        on_path: &mut [bool],
+        path: &mut Vec<usize>,
+        best: &mut Vec<usize>,
+    ) {
+        on_path[node] = true;
+        path.push(node);
+        if path.len() > best.len() {
+            best.clone_from(path);
+        }
+        for &to in &self.out[node] {
+            if !on_path[to] {
+                self.extend_path(to, on_path, path, best);
+            }
+        }
+        path.pop();
+        on_path[node] = false;
+    }
+}
+
+impl GraphQuery for GraphFile {
+    fn is_connected(&self) -> bool {
+        let adjacency = Adjacency::new(self);
+        if adjacency.ids.is_empty() {
+            return true;
+        }
+        adjacency.bfs_distances(0).iter().all(Option::is_some)
+    }
+
+    fn longest_path(&self) -> Option<Vec<String>> {
+        let adjacency = Adjacency::new(self);
+        if adjacency.ids.is_empty() {
+            return None;
+        }
+        let path = match adjacency.topological_order() {
+            Some(order) => adjacency.longest_path_dag(&order),
+            None => adjacency.longest_path_dfs(),
+        };
+        Some(
+            path.into_iter()
+                .map(|node| adjacency.ids[node].to_string())
+                .collect(),
+        )
+    }
+
+    fn diameter(&self) -> usize {
+        let adjacency = Adjacency::new(self);
+        (0..adjacency.ids.len())
+            .flat_map(|start| adjacency.bfs_distances(start))
+            .flatten()
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn topological_sort(&self) -> Option<Vec<String>> {
+        let adjacency = Adjacency::new(self);
+        if adjacency.self_loop {
+            return None;
+        }
+        let order = adjacency.topological_order()?;
+        Some(
+            order
+                .into_iter()
+                .map(|node| adjacency.ids[node].to_string())
+                .collect(),
+        )
+    }
+
+    fn has_cycle(&self) -> bool {
+        self.topological_sort().is_none()
+    }
+
+    fn minimum_spanning_tree(&self) -> GraphFile {
+        let index: HashMap<&str, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.as_str(), i))
+            .collect();
+        let mut candidates: Vec<(usize, usize, usize)> = self
+            .edges
+            .iter()
+            .enumerate()
+            .filter_map(|(i, edge)| {
+                let from = *index.get(edge.from.as_str())?;
+                let to = *index.get(edge.to.as_str())?;
+                (from != to).then_some((i, from, to))
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            let weight = |i: usize| self.edges[i].rtt_delta_ms_avg;
+            weight(a.0).total_cmp(&weight(b.0)).then(a.0.cmp(&b.0))
+        });
+
+        let mut sets = DisjointSets::new(self.nodes.len());
+        let mut kept = vec![false; self.edges.len()];
+        for (i, from, to) in candidates {
+            if sets.union(from, to) {
+                kept[i] = true;
+            }
+        }
+
+        GraphFile {
+            version: self.version,
+            nodes: self.nodes.clone(),
+            edges: self
+                .edges
+                .iter()
+                .zip(kept)
+                .filter(|(_, kept)| *kept)
+                .map(|(edge, _)| edge.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Union-find with path halving and union by size.
+struct DisjointSets {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+            size: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut node: usize) -> usize {
+        while self.parent[node] != node {
+            self.parent[node] = self.parent[self.parent[node]];
+            node = self.parent[node];
+        }
+        node
+    }
+
+    /// Joins the sets of `a` and `b`; false when they were already one set.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        true
+    }
+}
@@ -1,11 +1,35 @@
-use ptroute_graph::{build_graph, layout_graph};
-use ptroute_model::{Edge, GraphFile, Hop, Node, TraceFile, TraceRun};
+use ptroute_graph::{
+    annotate_page_rank, build_graph, build_graph_with_options, layout_graph, layout_graph_circular,
+    layout_graph_force_3d, layout_graph_incremental, layout_graph_with_options, normalize_scene,
+    page_rank, to_geojson, to_gexf, BuildOptions, GeoDb, GraphQuery, LayoutAlgo, LayoutOptions,
+    DEFAULT_FORCE_ITERATIONS, DEFAULT_MIN_NODE_SEPARATION,
+};
+use ptroute_model::{Edge, GraphFile, Hop, Node, SceneFile, TraceFile, TraceProtocol, TraceRun};
+use std::collections::HashMap;
+
+fn node(id: &str) -> Node {
+    Node {
+        id: id.to_string(),
+        seen: 1,
+        ..Default::default()
+    }
+}
+
+fn edge(from: &str, to: &str) -> Edge {
+    Edge {
+        from: from.to_string(),
+        to: to.to_string(),
+        seen: 1,
+        ..Default::default()
+    }
+}
 
 fn hop(ttl: u32, ip: Option<&str>, rtt: &[Option<f64>]) -> Hop {
     Hop {
         ttl,
         ip: ip.map(|value| value.to_string()),
         rtt_ms: rtt.to_vec(),
+        mpls_labels: Vec::new(),
     }
 }
 
@@ -22,6 +46,9 @@ fn build_graph_counts_nodes_edges() {
                     hop(2, Some("10.0.0.2"), &[Some(3.0)]),
                     hop(3, None, &[None, None]),
                 ],
+                label: None,
+                source_ip: None,
+                protocol: TraceProtocol::Udp,
             },
             TraceRun {
                 target: "2.2.2.2".to_string(),
@@ -30,6 +57,9 @@ fn build_graph_counts_nodes_edges() {
                     hop(1, Some("10.0.0.1"), &[Some(1.2)]),
                     hop(2, Some("10.0.0.3"), &[Some(4.2)]),
                 ],
+                label: None,
+                source_ip: None,
+                protocol: TraceProtocol::Udp,
             },
         ],
     };
@@ -40,10 +70,14 @@ fn build_graph_counts_nodes_edges() {
     let node = |id: &str| graph.nodes.iter().find(|node| node.id == id).unwrap();
     assert_eq!(node("10.0.0.1").seen, 2);
     assert_eq!(node("10.0.0.1").loss_probes, 1);
+    assert_eq!(node("10.0.0.1").sent_probes, 3);
+    assert!((node("10.0.0.1").loss_ratio() - 1.0 / 3.0).abs() < 1e-9);
     assert_eq!(node("10.0.0.2").seen, 1);
     assert_eq!(node("10.0.0.3").seen, 1);
     assert_eq!(node("unknown").seen, 1);
     assert_eq!(node("unknown").loss_probes, 2);
+    assert!((node("10.0.0.1").rtt_ms_avg.unwrap() - 1.1).abs() < 1e-9);
+    assert_eq!(node("unknown").rtt_ms_avg, None);
 
     let edge = |from: &str, to: &str| {
         graph
@@ -66,39 +100,109 @@ fn build_graph_counts_nodes_edges() {
     assert!((edge_c.rtt_delta_ms_avg - 3.0).abs() < 1e-6);
 }
 
+#[test]
+fn build_graph_records_node_rtt_percentiles_over_every_probe() {
+    let run = |hops: Vec<Hop>| TraceRun {
+        target: "1.1.1.1".to_string(),
+        timestamp_utc: "2026-02-01T12:00:00Z".to_string(),
+        hops,
+        label: None,
+        source_ip: None,
+        protocol: TraceProtocol::Udp,
+    };
+    // 20 probes to 10.0.0.2 (1..=20 ms, shuffled across runs), 3 to
+    // 10.0.0.1, and none answered by 10.0.0.3.
+    let mut runs = vec![
+        run(vec![
+            hop(1, Some("10.0.0.1"), &[Some(3.0), None, Some(1.0)]),
+            hop(2, Some("10.0.0.3"), &[None, None]),
+        ]),
+        run(vec![hop(1, Some("10.0.0.1"), &[Some(2.0)])]),
+    ];
+    for chunk in [
+        [7, 20, 1, 12],
+        [5, 16, 9, 3],
+        [18, 11, 2, 14],
+        [6, 19, 8, 13],
+        [4, 10, 15, 17],
+    ] {
+        let rtts: Vec<Option<f64>> = chunk.iter().map(|&ms| Some(ms as f64)).collect();
+        runs.push(run(vec![hop(1, Some("10.0.0.2"), &rtts)]));
+    }
+    let graph = build_graph(&TraceFile { version: 1, runs });
+    let node = |id: &str| graph.nodes.iter().find(|node| node.id == id).unwrap();
+
+    assert_eq!(node("10.0.0.1").rtt_p50_ms, Some(2.0));
+    assert_eq!(node("10.0.0.1").rtt_p95_ms, Some(2.9));
+    assert_eq!(node("10.0.0.1").rtt_ms_avg, Some(2.0));
+
+    let p95 = node("10.0.0.2").rtt_p95_ms.unwrap();
+    assert!((19.0..=20.0).contains(&p95), "{p95}");
+    assert_eq!(node("10.0.0.2").rtt_p50_ms, Some(10.5));
+
+    assert_eq!(node("10.0.0.3").rtt_p50_ms, None);
+    assert_eq!(node("10.0.0.3").rtt_p95_ms, None);
+    assert_eq!(node("10.0.0.3").loss_probes, 2);
+}
+
+#[test]
+fn build_graph_records_edge_depth_from_source_ttl() {
+    let run = |hops: Vec<Hop>| TraceRun {
+        target: "1.1.1.1".to_string(),
+        timestamp_utc: "2026-02-01T12:00:00Z".to_string(),
+        hops,
+        label: None,
+        source_ip: None,
+        protocol: TraceProtocol::Udp,
+    };
+    let trace = TraceFile {
+        version: 1,
+        runs: vec![
+            run(vec![
+                hop(1, Some("10.0.0.1"), &[Some(1.0)]),
+                hop(2, Some("10.0.0.2"), &[Some(2.0)]),
+                hop(3, Some("10.0.0.3"), &[Some(3.0)]),
+            ]),
+            run(vec![
+                hop(1, Some("10.0.0.9"), &[Some(1.0)]),
+                hop(2, Some("10.0.0.1"), &[Some(2.0)]),
+                hop(3, Some("10.0.0.2"), &[Some(3.0)]),
+            ]),
+        ],
+    };
+
+    let graph = build_graph(&trace);
+    let depth = |from: &str, to: &str| {
+        graph
+            .edges
+            .iter()
+            .find(|edge| edge.from == from && edge.to == to)
+            .unwrap()
+            .depth
+    };
+    assert_eq!(depth("10.0.0.9", "10.0.0.1"), 1);
+    assert_eq!(depth("10.0.0.1", "10.0.0.2"), 1);
+    assert_eq!(depth("10.0.0.2", "10.0.0.3"), 2);
+
+    let scene = layout_graph(&graph, 1);
+    for edge in &scene.edges {
+        assert_eq!(edge.depth, depth(&edge.from, &edge.to));
+    }
+}
+
 #[test]
 fn layout_is_deterministic_for_seed() {
     let graph = GraphFile {
         version: 1,
-        nodes: vec![
-            Node {
-                id: "a".to_string(),
-                seen: 1,
-                loss_probes: 0,
-            },
-            Node {
-                id: "b".to_string(),
-                seen: 1,
-                loss_probes: 0,
-            },
-            Node {
-                id: "c".to_string(),
-                seen: 1,
-                loss_probes: 0,
-            },
-        ],
+        nodes: vec![node("a"), node("b"), node("c")],
         edges: vec![
             Edge {
-                from: "a".to_string(),
-                to: "b".to_string(),
-                seen: 1,
                 rtt_delta_ms_avg: 1.0,
+                ..edge("a", "b")
             },
             Edge {
-                from: "b".to_string(),
-                to: "c".to_string(),
-                seen: 1,
                 rtt_delta_ms_avg: 1.0,
+                ..edge("b", "c")
             },
         ],
     };
@@ -113,23 +217,10 @@ fn layout_is_deterministic_for_seed() {
 fn layout_changes_with_seed() {
     let graph = GraphFile {
         version: 1,
-        nodes: vec![
-            Node {
-                id: "a".to_string(),
-                seen: 1,
-                loss_probes: 0,
-            },
-            Node {
-                id: "b".to_string(),
-                seen: 1,
-                loss_probes: 0,
-            },
-        ],
+        nodes: vec![node("a"), node("b")],
         edges: vec![Edge {
-            from: "a".to_string(),
-            to: "b".to_string(),
-            seen: 1,
             rtt_delta_ms_avg: 1.0,
+            ..edge("a", "b")
         }],
     };
 
@@ -140,3 +231,1053 @@ fn layout_changes_with_seed() {
     let z_b = scene_b.nodes[0].position[2];
     assert_ne!(z_a, z_b);
 }
+
+#[test]
+fn normalize_fits_unit_cube_around_origin() {
+    let nodes = ["a", "b", "c", "d", "e"]
+        .iter()
+        .map(|&id| node(id))
+        .collect();
+    let edges = [("a", "b"), ("b", "c"), ("c", "d"), ("a", "e")]
+        .iter()
+        .map(|(from, to)| Edge {
+            rtt_delta_ms_avg: 1.0,
+            ..edge(from, to)
+        })
+        .collect();
+    let graph = GraphFile {
+        version: 1,
+        nodes,
+        edges,
+    };
+
+    let scene = normalize_scene(&layout_graph(&graph, 7));
+
+    let mut centroid = [0.0_f32; 3];
+    let mut max_abs = 0.0_f32;
+    for node in &scene.nodes {
+        for (sum, &value) in centroid.iter_mut().zip(&node.position) {
+            assert!((-1.0..=1.0).contains(&value), "{value} out of range");
+            *sum += value / scene.nodes.len() as f32;
+            max_abs = max_abs.max(value.abs());
+        }
+    }
+    for value in centroid {
+        assert!(value.abs() < 1e-5);
+    }
+    assert!((max_abs - 1.0).abs() < 1e-5);
+}
+
+fn run(target: &str, hops: Vec<Hop>) -> TraceRun {
+    TraceRun {
+        target: target.to_string(),
+        timestamp_utc: "2026-02-01T12:00:00Z".to_string(),
+        hops,
+        label: None,
+        source_ip: None,
+        protocol: TraceProtocol::Udp,
+    }
+}
+
+#[test]
+fn filter_unknown_reconnects_edges_across_gaps() {
+    let trace = TraceFile {
+        version: 1,
+        runs: vec![run(
+            "10.0.0.9",
+            vec![
+                hop(1, Some("10.0.0.1"), &[Some(1.0)]),
+                hop(2, None, &[None, None]),
+                hop(3, None, &[None]),
+                hop(4, Some("10.0.0.9"), &[Some(6.0)]),
+            ],
+        )],
+    };
+    let options = BuildOptions {
+        filter_unknown: true,
+        ..BuildOptions::default()
+    };
+
+    let graph = build_graph_with_options(&trace, &options);
+
+    let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+    assert_eq!(ids, vec!["10.0.0.1", "10.0.0.9"]);
+    assert_eq!(graph.edges.len(), 1);
+    assert_eq!(graph.edges[0].from, "10.0.0.1");
+    assert_eq!(graph.edges[0].to, "10.0.0.9");
+    assert!((graph.edges[0].rtt_delta_ms_avg - 5.0).abs() < 1e-6);
+}
+
+#[test]
+fn edge_rtt_delta_stddev_is_population_stddev() {
+    let path = |delta: f64| {
+        run(
+            "10.0.0.2",
+            vec![
+                hop(1, Some("10.0.0.1"), &[Some(1.0)]),
+                hop(2, Some("10.0.0.2"), &[Some(1.0 + delta)]),
+            ],
+        )
+    };
+    let single = build_graph(&TraceFile {
+        version: 1,
+        runs: vec![path(1.0)],
+    });
+    assert_eq!(single.edges[0].rtt_delta_ms_stddev, 0.0);
+
+    let graph = build_graph(&TraceFile {
+        version: 1,
+        runs: vec![path(1.0), path(3.0)],
+    });
+    assert_eq!(graph.edges.len(), 1);
+    assert!((graph.edges[0].rtt_delta_ms_avg - 2.0).abs() < 1e-9);
+    assert!((graph.edges[0].rtt_delta_ms_stddev - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn filter_unknown_keeps_target_for_fully_anonymous_path() {
+    let trace = TraceFile {
+        version: 1,
+        runs: vec![run(
+            "203.0.113.1",
+            vec![hop(1, None, &[None]), hop(2, None, &[None])],
+        )],
+    };
+    let options = BuildOptions {
+        filter_unknown: true,
+        ..BuildOptions::default()
+    };
+
+    let graph = build_graph_with_options(&trace, &options);
+
+    assert_eq!(graph.nodes.len(), 1);
+    assert_eq!(graph.nodes[0].id, "203.0.113.1");
+    assert!(graph.edges.is_empty());
+}
+
+#[test]
+fn filter_private_ips_strips_local_hops() {
+    let trace = TraceFile {
+        version: 1,
+        runs: vec![run(
+            "8.8.8.8",
+            vec![
+                hop(1, Some("192.168.1.1"), &[Some(0.5)]),
+                hop(2, Some("100.64.0.1"), &[Some(2.0)]),
+                hop(3, Some("172.16.5.1"), &[Some(4.0)]),
+                hop(4, Some("8.8.8.8"), &[Some(9.0)]),
+            ],
+        )],
+    };
+    let options = BuildOptions {
+        filter_private_ips: true,
+        ..BuildOptions::default()
+    };
+
+    let graph = build_graph_with_options(&trace, &options);
+
+    let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+    assert_eq!(ids, vec!["100.64.0.1", "8.8.8.8"]);
+    assert_eq!(graph.edges.len(), 1);
+    assert_eq!(graph.edges[0].from, "100.64.0.1");
+    assert_eq!(graph.edges[0].to, "8.8.8.8");
+    assert!((graph.edges[0].rtt_delta_ms_avg - 7.0).abs() < 1e-6);
+}
+
+#[test]
+fn build_merges_repeated_hops_unless_disabled() {
+    let trace = TraceFile {
+        version: 1,
+        runs: vec![run(
+            "10.0.0.3",
+            vec![
+                hop(1, Some("10.0.0.1"), &[Some(1.0)]),
+                hop(2, Some("10.0.0.1"), &[Some(1.5)]),
+                hop(3, Some("10.0.0.3"), &[Some(4.0)]),
+            ],
+        )],
+    };
+
+    let graph = build_graph(&trace);
+    assert_eq!(graph.edges.len(), 1);
+    assert_eq!(graph.edges[0].from, "10.0.0.1");
+    assert_eq!(graph.edges[0].to, "10.0.0.3");
+
+    let options = BuildOptions {
+        keep_duplicate_hops: true,
+        ..BuildOptions::default()
+    };
+    let raw = build_graph_with_options(&trace, &options);
+    assert_eq!(raw.edges.len(), 2);
+    assert!(raw
+        .edges
+        .iter()
+        .any(|edge| edge.from == "10.0.0.1" && edge.to == "10.0.0.1"));
+}
+
+#[test]
+fn geojson_export_places_known_nodes_and_flags_missing_ones() {
+    let graph = GraphFile {
+        version: 1,
+        nodes: vec![
+            Node {
+                seen: 2,
+                ..node("10.0.0.1")
+            },
+            Node {
+                loss_probes: 1,
+                ..node("203.0.113.7")
+            },
+        ],
+        edges: vec![Edge {
+            rtt_delta_ms_avg: 4.5,
+            ..edge("10.0.0.1", "203.0.113.7")
+        }],
+    };
+    let mut geo = GeoDb::new();
+    geo.insert("203.0.113.7", 52.5, 13.4);
+
+    let value: serde_json::Value = serde_json::from_str(&to_geojson(&graph, &geo)).unwrap();
+
+    assert_eq!(value["type"], "FeatureCollection");
+    let features = value["features"].as_array().unwrap();
+    assert_eq!(features.len(), 3);
+
+    assert_eq!(features[0]["type"], "Feature");
+    assert_eq!(features[0]["geometry"]["type"], "Point");
+    assert_eq!(
+        features[0]["geometry"]["coordinates"],
+        serde_json::json!([0.0, 0.0])
+    );
+    assert_eq!(features[0]["properties"]["no_geo"], true);
+
+    assert_eq!(
+        features[1]["geometry"]["coordinates"],
+        serde_json::json!([13.4, 52.5])
+    );
+    assert!(features[1]["properties"].get("no_geo").is_none());
+
+    assert_eq!(features[2]["geometry"]["type"], "LineString");
+    assert_eq!(
+        features[2]["geometry"]["coordinates"],
+        serde_json::json!([[0.0, 0.0], [13.4, 52.5]])
+    );
+    assert_eq!(features[2]["properties"]["from"], "10.0.0.1");
+    assert_eq!(features[2]["properties"]["no_geo"], true);
+}
+
+#[test]
+fn gexf_export_contains_nodes_edges_and_attributes() {
+    let graph = GraphFile {
+        version: 1,
+        nodes: vec![
+            Node {
+                seen: 3,
+                loss_probes: 1,
+                ..node("10.0.0.1")
+            },
+            node("host<&>"),
+        ],
+        edges: vec![Edge {
+            seen: 2,
+            rtt_delta_ms_avg: 1.5,
+            ..edge("10.0.0.1", "host<&>")
+        }],
+    };
+
+    let gexf = to_gexf(&graph);
+
+    assert!(gexf.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+    assert!(gexf.contains("<gexf xmlns=\"http://gexf.net/1.3\""));
+    assert!(gexf.contains("version=\"1.3\""));
+    assert!(gexf.contains("<graph mode=\"static\" defaultedgetype=\"directed\">"));
+    assert!(gexf.contains("<node id=\"10.0.0.1\" label=\"10.0.0.1\">"));
+    assert!(gexf.contains("<attvalue for=\"seen\" value=\"3\"/>"));
+    assert!(gexf.contains("<attvalue for=\"loss_probes\" value=\"1\"/>"));
+    assert!(gexf.contains("<node id=\"host&lt;&amp;&gt;\""));
+    assert!(gexf.contains("<edge id=\"0\" source=\"10.0.0.1\" target=\"host&lt;&amp;&gt;\">"));
+    assert!(gexf.contains("<attvalue for=\"rtt_delta_ms_avg\" value=\"1.5\"/>"));
+    assert!(!gexf.contains("host<"));
+    assert!(gexf.trim_end().ends_with("</gexf>"));
+}
+
+fn graph_from_edges(ids: &[&str], edges: &[(&str, &str)]) -> GraphFile {
+    GraphFile {
+        version: 1,
+        nodes: ids.iter().map(|&id| node(id)).collect(),
+        edges: edges.iter().map(|(from, to)| edge(from, to)).collect(),
+    }
+}
+
+#[test]
+fn linear_graph_metrics() {
+    let graph = graph_from_edges(&["a", "b", "c", "d"], &[("a", "b"), ("b", "c"), ("c", "d")]);
+
+    assert!(graph.is_connected());
+    assert_eq!(graph.diameter(), 3);
+    assert_eq!(
+        graph.longest_path(),
+        Some(vec![
+            "a".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+            "d".to_string()
+        ])
+    );
+}
+
+#[test]
+fn star_graph_has_diameter_two() {
+    let graph = graph_from_edges(
+        &["hub", "x", "y", "z"],
+        &[("hub", "x"), ("hub", "y"), ("hub", "z")],
+    );
+
+    assert!(graph.is_connected());
+    assert_eq!(graph.diameter(), 2);
+    assert_eq!(graph.longest_path().map(|path| path.len()), Some(2));
+}
+
+#[test]
+fn disconnected_graph_is_not_connected() {
+    let graph = graph_from_edges(&["a", "b", "c", "d"], &[("a", "b"), ("c", "d")]);
+
+    assert!(!graph.is_connected());
+    assert_eq!(graph.diameter(), 1);
+}
+
+#[test]
+fn longest_path_handles_cycles() {
+    let graph = graph_from_edges(&["a", "b", "c"], &[("a", "b"), ("b", "c"), ("c", "a")]);
+
+    assert_eq!(graph.longest_path().map(|path| path.len()), Some(3));
+    assert_eq!(graph_from_edges(&[], &[]).longest_path(), None);
+}
+
+#[test]
+fn topological_sort_orders_trace_dag() {
+    let trace = TraceFile {
+        version: 1,
+        runs: vec![
+            run(
+                "9.9.9.9",
+                vec![
+                    hop(1, Some("10.0.0.1"), &[Some(1.0)]),
+                    hop(2, Some("10.0.0.3"), &[Some(2.0)]),
+                    hop(3, Some("9.9.9.9"), &[Some(3.0)]),
+                ],
+            ),
+            run(
+                "9.9.9.9",
+                vec![
+                    hop(1, Some("10.0.0.1"), &[Some(1.0)]),
+                    hop(2, Some("10.0.0.2"), &[Some(2.0)]),
+                    hop(3, Some("9.9.9.9"), &[Some(3.0)]),
+                ],
+            ),
+        ],
+    };
+    let graph = build_graph(&trace);
+
+    assert!(!graph.has_cycle());
+    assert_eq!(
+        graph.topological_sort(),
+        Some(vec![
+            "10.0.0.1".to_string(),
+            "10.0.0.2".to_string(),
+            "10.0.0.3".to_string(),
+            "9.9.9.9".to_string(),
+        ])
+    );
+}
+
+#[test]
+fn topological_sort_rejects_cycles() {
+    let graph = graph_from_edges(&["a", "b", "c"], &[("a", "b"), ("b", "c"), ("c", "a")]);
+    assert_eq!(graph.topological_sort(), None);
+    assert!(graph.has_cycle());
+
+    let scene = layout_graph(&graph, 1);
+    assert_eq!(scene.nodes.len(), 3);
+}
+
+#[test]
+fn topological_sort_single_node() {
+    let graph = graph_from_edges(&["a"], &[]);
+    assert_eq!(graph.topological_sort(), Some(vec!["a".to_string()]));
+    assert!(!graph.has_cycle());
+}
+
+fn weighted(graph: &mut GraphFile, weights: &[f64]) {
+    for (edge, &weight) in graph.edges.iter_mut().zip(weights) {
+        edge.rtt_delta_ms_avg = weight;
+        edge.seen = weight as u32 + 1;
+    }
+}
+
+#[test]
+fn minimum_spanning_tree_drops_the_heaviest_triangle_edge() {
+    let mut graph = graph_from_edges(&["a", "b", "c"], &[("a", "b"), ("b", "c"), ("a", "c")]);
+    weighted(&mut graph, &[2.0, 5.0, 3.0]);
+    graph.nodes[1].loss_probes = 4;
+
+    let mst = graph.minimum_spanning_tree();
+    assert_eq!(mst.nodes, graph.nodes);
+    assert_eq!(
+        mst.edges,
+        vec![graph.edges[0].clone(), graph.edges[2].clone()]
+    );
+}
+
+#[test]
+fn minimum_spanning_tree_spans_connected_graphs_with_original_edges() {
+    let mut graph = graph_from_edges(
+        &["a", "b", "c", "d", "e", "f"],
+        &[
+            ("a", "b"),
+            ("a", "c"),
+            ("b", "d"),
+            ("c", "d"),
+            ("d", "e"),
+            ("c", "e"),
+            ("e", "f"),
+            ("b", "f"),
+            ("f", "f"),
+        ],
+    );
+    weighted(&mut graph, &[4.0, 1.0, 7.0, 2.0, 6.0, 3.0, 5.0, 9.0, -1.0]);
+    assert!(graph.is_connected());
+
+    let mst = graph.minimum_spanning_tree();
+    assert_eq!(mst.edges.len(), graph.nodes.len() - 1);
+    assert!(mst.is_connected());
+    assert!(mst.edges.iter().all(|edge| graph.edges.contains(edge)));
+    let total: f64 = mst.edges.iter().map(|edge| edge.rtt_delta_ms_avg).sum();
+    assert_eq!(total, 1.0 + 2.0 + 3.0 + 4.0 + 5.0);
+
+    // Disconnected graphs give a spanning forest.
+    let split = graph_from_edges(&["a", "b", "c", "d"], &[("a", "b"), ("c", "d")]);
+    assert_eq!(split.minimum_spanning_tree().edges, split.edges);
+}
+
+#[test]
+fn circular_layout_puts_each_depth_on_one_ring() {
+    let graph = graph_from_edges(
+        &["a", "b", "c", "d", "e", "f", "x"],
+        &[("a", "b"), ("a", "c"), ("a", "d"), ("b", "e"), ("c", "f")],
+    );
+    let scene = layout_graph_circular(&graph, 3);
+    let radius = |id: &str| {
+        let node = scene.nodes.iter().find(|node| node.id == id).unwrap();
+        node.position[0].hypot(node.position[1])
+    };
+
+    // Two start nodes share the half-lane ring around the center.
+    assert!((radius("a") - 1.0).abs() < 1e-5);
+    assert!((radius("x") - 1.0).abs() < 1e-5);
+    for id in ["b", "c", "d"] {
+        assert!((radius(id) - 2.0).abs() < 1e-5, "{id}");
+    }
+    for id in ["e", "f"] {
+        assert!((radius(id) - 4.0).abs() < 1e-5, "{id}");
+    }
+
+    for (i, a) in scene.nodes.iter().enumerate() {
+        for b in &scene.nodes[i + 1..] {
+            assert_ne!(a.position, b.position, "{} and {}", a.id, b.id);
+        }
+    }
+
+    let options = LayoutOptions {
+        algo: LayoutAlgo::Circular,
+        ..LayoutOptions::default()
+    };
+    assert_eq!(layout_graph_with_options(&graph, 3, &options), scene);
+    assert_eq!("Circular".parse(), Ok(LayoutAlgo::Circular));
+}
+
+#[test]
+fn circular_layout_centers_a_single_root() {
+    let graph = graph_from_edges(&["a", "b", "c"], &[("a", "b"), ("a", "c")]);
+    let scene = layout_graph_circular(&graph, 1);
+    assert_eq!(scene.nodes[0].position[..2], [0.0, 0.0]);
+    assert!((scene.nodes[1].position[0] - 2.0).abs() < 1e-5);
+    assert!((scene.nodes[2].position[0] + 2.0).abs() < 1e-5);
+}
+
+#[test]
+fn min_node_separation_removes_overlaps() {
+    // 40 leaves share a depth and degree bucket, so only Z jitter (±0.25)
+    // tells them apart.
+    let leaves: Vec<String> = (0..40).map(|i| format!("10.1.0.{i}")).collect();
+    let mut ids: Vec<&str> = leaves.iter().map(String::as_str).collect();
+    ids.push("10.0.0.1");
+    let links: Vec<(&str, &str)> = leaves
+        .iter()
+        .map(|leaf| ("10.0.0.1", leaf.as_str()))
+        .collect();
+    let graph = graph_from_edges(&ids, &links);
+    let mut grown_ids = ids.clone();
+    grown_ids.push("10.2.0.1");
+    let mut grown_links = links.clone();
+    grown_links.push(("10.0.0.1", "10.2.0.1"));
+    let grown = graph_from_edges(&grown_ids, &grown_links);
+
+    for algo in [LayoutAlgo::Depth, LayoutAlgo::Circular, LayoutAlgo::Force3d] {
+        let options = LayoutOptions {
+            algo,
+            force_iterations: 5,
+            min_node_separation: 0.0,
+            ..LayoutOptions::default()
+        };
+        let crowded = layout_graph_with_options(&graph, 5, &options);
+        if algo == LayoutAlgo::Depth {
+            assert!(crowded.node_overlap_count(DEFAULT_MIN_NODE_SEPARATION) > 0);
+        }
+
+        let options = LayoutOptions {
+            min_node_separation: DEFAULT_MIN_NODE_SEPARATION,
+            ..options
+        };
+        let scene = layout_graph_with_options(&graph, 5, &options);
+        assert_eq!(scene.node_overlap_count(0.1), 0, "{algo:?}");
+        // Only Z moves.
+        for (before, after) in crowded.nodes.iter().zip(&scene.nodes) {
+            assert_eq!(before.position[..2], after.position[..2]);
+        }
+
+        // Surviving nodes are never pushed, only new ones.
+        let incremental = layout_graph_incremental(&crowded, &grown, 5, &options);
+        for before in &crowded.nodes {
+            let after = incremental
+                .nodes
+                .iter()
+                .find(|node| node.id == before.id)
+                .unwrap();
+            assert_eq!(
+                before.position.map(f32::to_bits),
+                after.position.map(f32::to_bits),
+                "incremental {algo:?} {}",
+                before.id
+            );
+        }
+        let new = incremental
+            .nodes
+            .iter()
+            .find(|node| node.id == "10.2.0.1")
+            .unwrap();
+        for node in incremental.nodes.iter().filter(|node| node.id != new.id) {
+            let distance = (0..3)
+                .map(|axis| (node.position[axis] - new.position[axis]).powi(2))
+                .sum::<f32>()
+                .sqrt();
+            assert!(distance >= 0.1, "incremental {algo:?} {}", node.id);
+        }
+    }
+    assert_eq!(layout_graph(&graph, 5).node_overlap_count(0.1), 0);
+}
+
+#[test]
+fn pinned_nodes_sit_at_their_pins_in_every_layout() {
+    let graph = graph_from_edges(
+        &["10.0.0.1", "10.0.0.2", "10.0.1.1", "10.0.1.2"],
+        &[
+            ("10.0.0.1", "10.0.0.2"),
+            ("10.0.0.2", "10.0.1.1"),
+            ("10.0.1.1", "10.0.1.2"),
+        ],
+    );
+    let pins = [
+        ("10.0.0.2", [5.0, -3.0, 1.25]),
+        ("10.0.1.2", [0.0, 0.0, 0.0]),
+    ];
+    let pinned_nodes: HashMap<String, [f32; 3]> = pins
+        .iter()
+        .map(|&(id, position)| (id.to_string(), position))
+        .chain([("192.0.2.9".to_string(), [9.0; 3])])
+        .collect();
+
+    for algo in [LayoutAlgo::Depth, LayoutAlgo::Circular, LayoutAlgo::Force3d] {
+        for subnet_clustering in [false, true] {
+            let options = LayoutOptions {
+                algo,
+                subnet_clustering,
+                force_iterations: 50,
+                pinned_nodes: pinned_nodes.clone(),
+                ..LayoutOptions::default()
+            };
+            let scene = layout_graph_with_options(&graph, 3, &options);
+            assert_eq!(scene.nodes.len(), 4);
+            for (id, position) in pins {
+                let node = scene.nodes.iter().find(|node| node.id == id).unwrap();
+                assert_eq!(node.position, position, "{algo:?} {id}");
+            }
+
+            // Unpinned nodes still move in the force layout.
+            if algo == LayoutAlgo::Force3d {
+                let free = LayoutOptions {
+                    pinned_nodes: HashMap::new(),
+                    ..options.clone()
+                };
+                let unpinned = layout_graph_with_options(&graph, 3, &free);
+                assert_ne!(unpinned.nodes[0].position, scene.nodes[0].position);
+            }
+
+            let previous = layout_graph(&graph, 11);
+            let options = LayoutOptions {
+                blend_factor: 0.5,
+                velocity_damping: Some(0.8),
+                ..options
+            };
+            let scene = layout_graph_incremental(&previous, &graph, 3, &options);
+            for (id, position) in pins {
+                let node = scene.nodes.iter().find(|node| node.id == id).unwrap();
+                assert_eq!(node.position, position, "incremental {algo:?} {id}");
+                assert_eq!(node.velocity, [0.0; 3]);
+            }
+        }
+    }
+}
+
+#[test]
+fn force_3d_layout_pulls_linked_nodes_together() {
+    let ids = ["a", "b", "c", "d", "e", "f", "g", "h"];
+    let links = [
+        ("a", "b"),
+        ("b", "c"),
+        ("c", "a"),
+        ("d", "e"),
+        ("e", "f"),
+        ("f", "d"),
+        ("c", "g"),
+        ("g", "h"),
+    ];
+    let graph = graph_from_edges(&ids, &links);
+    let options = LayoutOptions {
+        algo: LayoutAlgo::Force3d,
+        force_iterations: 300,
+        ..LayoutOptions::default()
+    };
+    let scene = layout_graph_force_3d(&graph, 7, &options);
+    assert_eq!(layout_graph_with_options(&graph, 7, &options), scene);
+
+    let (mean_edge, mean_other) = mean_edge_and_other_distance(&scene, &ids, &links);
+    assert!(mean_edge < mean_other, "{mean_edge} vs {mean_other}");
+
+    // Every axis is used, not just a plane.
+    for axis in 0..3 {
+        let values: Vec<f32> = scene.nodes.iter().map(|node| node.position[axis]).collect();
+        let spread = values.iter().cloned().fold(f32::MIN, f32::max)
+            - values.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(spread > 0.05, "axis {axis} spread {spread}");
+    }
+}
+
+/// Mean distance between linked node pairs and between every other pair.
+fn mean_edge_and_other_distance(
+    scene: &SceneFile,
+    ids: &[&str],
+    links: &[(&str, &str)],
+) -> (f32, f32) {
+    let position = |id: &str| {
+        scene
+            .nodes
+            .iter()
+            .find(|node| node.id == id)
+            .unwrap()
+            .position
+    };
+    let distance = |a: &str, b: &str| {
+        let (p, q) = (position(a), position(b));
+        ((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2)).sqrt()
+    };
+    let linked = |a: &str, b: &str| {
+        links
+            .iter()
+            .any(|&(from, to)| (from, to) == (a, b) || (from, to) == (b, a))
+    };
+
+    let (mut edge_sum, mut edge_count) = (0.0, 0);
+    let (mut other_sum, mut other_count) = (0.0, 0);
+    for (i, a) in ids.iter().enumerate() {
+        for b in &ids[i + 1..] {
+            if linked(a, b) {
+                edge_sum += distance(a, b);
+                edge_count += 1;
+            } else {
+                other_sum += distance(a, b);
+                other_count += 1;
+            }
+        }
+    }
+    (edge_sum / edge_count as f32, other_sum / other_count as f32)
+}
+
+#[test]
+fn force_3d_layout_runs_default_iterations() {
+    let ids = ["a", "b", "c", "d", "e", "f"];
+    let links = [("a", "b"), ("b", "c"), ("c", "a"), ("d", "e"), ("e", "f")];
+    let graph = graph_from_edges(&ids, &links);
+    assert_eq!(
+        LayoutOptions::default().force_iterations,
+        DEFAULT_FORCE_ITERATIONS
+    );
+    let scene = layout_graph_force_3d(&graph, 3, &LayoutOptions::default());
+    let (mean_edge, mean_other) = mean_edge_and_other_distance(&scene, &ids, &links);
+    assert!(mean_edge < mean_other, "{mean_edge} vs {mean_other}");
+}
+
+#[test]
+fn layout_diff_tracks_seed_changes() {
+    let graph = graph_from_edges(
+        &["a", "b", "c", "d", "e"],
+        &[("a", "b"), ("b", "c"), ("b", "d"), ("d", "e")],
+    );
+    let options = LayoutOptions {
+        algo: LayoutAlgo::Force3d,
+        force_iterations: 50,
+        ..LayoutOptions::default()
+    };
+    for options in [LayoutOptions::default(), options] {
+        let scene = layout_graph_with_options(&graph, 1, &options);
+        let same = layout_graph_with_options(&graph, 1, &options);
+        assert!(scene.layout_diff(&same, 0.5).is_empty());
+
+        let reseeded = layout_graph_with_options(&graph, 99, &options);
+        // The depth layout only reseeds its small Z jitter.
+        let diff = scene.layout_diff(&reseeded, 0.0);
+        assert!(!diff.moved.is_empty());
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+}
+
+#[test]
+fn incremental_layout_keeps_existing_positions() {
+    let before = graph_from_edges(&["a", "b", "c"], &[("a", "b"), ("b", "c")]);
+    let mut previous = layout_graph(&before, 1);
+    for (i, node) in previous.nodes.iter_mut().enumerate() {
+        node.position = [i as f32 * 3.0, 7.0, -2.0];
+    }
+
+    let after = graph_from_edges(&["a", "b", "d"], &[("a", "b"), ("b", "d")]);
+    let scene = layout_graph_incremental(&previous, &after, 1, &LayoutOptions::default());
+
+    let position = |scene: &ptroute_model::SceneFile, id: &str| {
+        scene
+            .nodes
+            .iter()
+            .find(|node| node.id == id)
+            .map(|node| node.position)
+    };
+    assert_eq!(position(&scene, "a"), position(&previous, "a"));
+    assert_eq!(position(&scene, "b"), position(&previous, "b"));
+    assert_eq!(position(&scene, "c"), None);
+
+    let b = position(&previous, "b").unwrap();
+    let d = position(&scene, "d").unwrap();
+    assert_eq!(d[0], b[0] + 1.0);
+    assert_eq!(d[1], b[1]);
+    assert_eq!(scene.edges.len(), 2);
+}
+
+#[test]
+fn incremental_layout_full_blend_matches_fresh_layout() {
+    let before = graph_from_edges(&["a", "b"], &[("a", "b")]);
+    let mut previous = layout_graph(&before, 1);
+    for node in &mut previous.nodes {
+        node.position = [10.0, 10.0, 10.0];
+    }
+    let after = graph_from_edges(&["a", "b", "c"], &[("a", "b"), ("a", "c")]);
+
+    let options = LayoutOptions {
+        blend_factor: 1.0,
+        ..LayoutOptions::default()
+    };
+    let scene = layout_graph_incremental(&previous, &after, 1, &options);
+    assert_eq!(scene, layout_graph(&after, 1));
+}
+
+#[test]
+fn incremental_layout_velocity_decays_to_rest() {
+    let graph = graph_from_edges(&["a", "b", "c"], &[("a", "b"), ("b", "c")]);
+    let fresh = layout_graph(&graph, 1);
+    let mut scene = fresh.clone();
+    scene.nodes[1].position[0] += 5.0;
+
+    let options = LayoutOptions {
+        blend_factor: 0.1,
+        velocity_damping: Some(0.9),
+        ..LayoutOptions::default()
+    };
+    scene = layout_graph_incremental(&scene, &graph, 1, &options);
+    assert!(scene.nodes[1].velocity[0] < 0.0);
+    for _ in 0..300 {
+        scene = layout_graph_incremental(&scene, &graph, 1, &options);
+    }
+
+    for (node, target) in scene.nodes.iter().zip(&fresh.nodes) {
+        for axis in 0..3 {
+            assert!(node.velocity[axis].abs() < 1e-3, "{:?}", node.velocity);
+            assert!((node.position[axis] - target.position[axis]).abs() < 1e-2);
+        }
+    }
+}
+
+#[test]
+fn normalized_weights_make_layout_scale_invariant() {
+    let hub = |scale: u32| {
+        let mut graph = graph_from_edges(
+            &["a", "b", "c", "d", "h"],
+            &[("h", "a"), ("h", "b"), ("h", "c"), ("h", "d")],
+        );
+        for (edge, seen) in graph.edges.iter_mut().zip([1000, 1, 1, 1]) {
+            edge.seen = seen * scale;
+        }
+        graph
+    };
+    let options = LayoutOptions {
+        normalize_weights: true,
+        ..LayoutOptions::default()
+    };
+
+    let short = layout_graph_with_options(&hub(1), 1, &options);
+    let long = layout_graph_with_options(&hub(100), 1, &options);
+    assert_eq!(short.nodes, long.nodes);
+
+    // Three near-idle spokes weigh less than the plain edge count.
+    let lane = |scene: &ptroute_model::SceneFile| {
+        scene
+            .nodes
+            .iter()
+            .find(|node| node.id == "h")
+            .map(|node| node.position[1])
+            .unwrap()
+    };
+    assert!(lane(&short) < lane(&layout_graph(&hub(1), 1)));
+
+    // A weighted degree of 3.5 stays in the lane for 2-3, not the one for 4.
+    let mut graph = hub(1);
+    for (edge, seen) in graph.edges.iter_mut().zip([1000, 1000, 1000, 500]) {
+        edge.seen = seen;
+    }
+    assert_eq!(lane(&layout_graph_with_options(&graph, 1, &options)), 2.0);
+    assert_eq!(lane(&layout_graph(&graph, 1)), 4.0);
+}
+
+#[test]
+fn subnet_clustering_groups_same_prefix() {
+    let graph = graph_from_edges(
+        &[
+            "10.0.0.1",
+            "10.0.0.2",
+            "172.16.0.1",
+            "172.16.1.1",
+            "192.168.0.1",
+        ],
+        &[
+            ("10.0.0.1", "10.0.0.2"),
+            ("10.0.0.1", "172.16.0.1"),
+            ("10.0.0.1", "172.16.1.1"),
+            ("10.0.0.1", "192.168.0.1"),
+        ],
+    );
+    let y = |scene: &ptroute_model::SceneFile, id: &str| {
+        scene
+            .nodes
+            .iter()
+            .find(|node| node.id == id)
+            .map(|node| node.position[1])
+            .unwrap()
+    };
+
+    let plain = layout_graph(&graph, 1);
+    assert!((y(&plain, "10.0.0.1") - y(&plain, "10.0.0.2")).abs() > 1.0);
+
+    let options = LayoutOptions {
+        subnet_clustering: true,
+        ..LayoutOptions::default()
+    };
+    let clustered = layout_graph_with_options(&graph, 1, &options);
+    let same = (y(&clustered, "10.0.0.1") - y(&clustered, "10.0.0.2")).abs();
+    let different = (y(&clustered, "10.0.0.2") - y(&clustered, "192.168.0.1")).abs();
+    assert!(same <= 1.0);
+    assert!(same < different);
+    assert_eq!(
+        clustered
+            .nodes
+            .iter()
+            .map(|node| node.position[0])
+            .collect::<Vec<_>>(),
+        plain
+            .nodes
+            .iter()
+            .map(|node| node.position[0])
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn target_bbox_fits_every_algo_into_the_box() {
+    let graph = graph_from_edges(
+        &["a", "b", "c", "d", "e"],
+        &[("a", "b"), ("b", "c"), ("c", "d"), ("b", "e")],
+    );
+    // Per-axis (min, max) of the node positions.
+    let bounds = |scene: &ptroute_model::SceneFile| {
+        let mut bounds = [(f32::INFINITY, f32::NEG_INFINITY); 3];
+        for node in &scene.nodes {
+            for (axis, value) in node.position.iter().enumerate() {
+                bounds[axis].0 = bounds[axis].0.min(*value);
+                bounds[axis].1 = bounds[axis].1.max(*value);
+            }
+        }
+        bounds
+    };
+    let extents = |scene: &ptroute_model::SceneFile| bounds(scene).map(|(min, max)| max - min);
+    let centered = |scene: &ptroute_model::SceneFile| {
+        bounds(scene)
+            .iter()
+            .all(|(min, max)| (min + max).abs() < 1e-4)
+    };
+
+    for algo in [LayoutAlgo::Depth, LayoutAlgo::Circular, LayoutAlgo::Force3d] {
+        let options = LayoutOptions {
+            algo,
+            force_iterations: 50,
+            target_bbox: Some([2.0, 2.0, 2.0]),
+            uniform_scale: true,
+            ..LayoutOptions::default()
+        };
+        let scene = layout_graph_with_options(&graph, 7, &options);
+        for node in &scene.nodes {
+            assert!(
+                node.position.iter().all(|value| value.abs() <= 1.0 + 1e-5),
+                "{algo:?}: {} at {:?}",
+                node.id,
+                node.position
+            );
+        }
+        assert!(centered(&scene), "{algo:?}");
+        let fitted = extents(&scene);
+        let largest = fitted.iter().copied().fold(0.0_f32, f32::max);
+        assert!((largest - 2.0).abs() < 1e-4, "{algo:?}: {fitted:?}");
+
+        // Uniform scaling keeps the layout's proportions.
+        let plain = extents(&layout_graph_with_options(
+            &graph,
+            7,
+            &LayoutOptions {
+                target_bbox: None,
+                ..options.clone()
+            },
+        ));
+        let ratio = fitted[0] / plain[0];
+        for axis in 1..3 {
+            assert!((fitted[axis] - plain[axis] * ratio).abs() < 1e-4);
+        }
+    }
+
+    let stretched = layout_graph_with_options(
+        &graph,
+        7,
+        &LayoutOptions {
+            target_bbox: Some([4.0, 2.0, 1.0]),
+            uniform_scale: false,
+            ..LayoutOptions::default()
+        },
+    );
+    assert!(centered(&stretched));
+    let fitted = extents(&stretched);
+    for (axis, want) in [4.0, 2.0, 1.0].into_iter().enumerate() {
+        assert!((fitted[axis] - want).abs() < 1e-4, "{fitted:?}");
+    }
+}
+
+#[test]
+fn page_rank_sums_to_one_and_favours_downstream_hops() {
+    let graph = graph_from_edges(&["a", "b", "c", "d"], &[("a", "b"), ("b", "c"), ("c", "d")]);
+    let scores = page_rank(&graph, 0.85, 50);
+
+    let total: f64 = scores.values().sum();
+    assert!((total - 1.0).abs() < 1e-9, "sum was {total}");
+    assert!(scores["a"] < scores["b"]);
+    assert!(scores["b"] < scores["c"]);
+    assert!(scores["c"] < scores["d"]);
+}
+
+#[test]
+fn page_rank_ranks_shared_hop_highest() {
+    let graph = graph_from_edges(
+        &["s1", "s2", "s3", "core", "t"],
+        &[
+            ("s1", "core"),
+            ("s2", "core"),
+            ("s3", "core"),
+            ("core", "t"),
+        ],
+    );
+    let mut annotated = graph.clone();
+    annotate_page_rank(&mut annotated, 0.85, 50);
+    let scores = page_rank(&graph, 0.85, 50);
+
+    for source in ["s1", "s2", "s3"] {
+        assert!(scores["core"] > scores[source]);
+    }
+    for node in &annotated.nodes {
+        assert_eq!(node.pagerank, Some(scores[&node.id]));
+    }
+}
+
+#[test]
+fn page_rank_of_empty_graph_is_empty() {
+    let graph = graph_from_edges(&[], &[]);
+    assert!(page_rank(&graph, 0.85, 50).is_empty());
+}
+
+#[test]
+fn max_runs_per_target_keeps_latest_run() {
+    let timed = |target: &str, timestamp: &str, ips: &[&str]| TraceRun {
+        timestamp_utc: timestamp.to_string(),
+        ..run(
+            target,
+            ips.iter()
+                .enumerate()
+                .map(|(i, ip)| hop(i as u32 + 1, Some(ip), &[Some(1.0 + i as f64)]))
+                .collect(),
+        )
+    };
+    let trace = TraceFile {
+        version: 2,
+        runs: vec![
+            timed("1.1.1.1", "2026-03-01T12:00:00Z", &["10.0.0.1", "10.0.0.9"]),
+            timed("1.1.1.1", "2026-01-01T12:00:00Z", &["10.0.0.1", "10.0.0.2"]),
+            timed("8.8.8.8", "2026-02-01T12:00:00Z", &["10.0.0.1", "10.0.0.3"]),
+            timed(
+                "1.1.1.1",
+                "2026-02-01T12:00:00+02:00",
+                &["10.0.0.1", "10.0.0.4"],
+            ),
+        ],
+    };
+    let options = BuildOptions {
+        max_runs_per_target: Some(1),
+        ..BuildOptions::default()
+    };
+
+    let graph = build_graph_with_options(&trace, &options);
+
+    let seen: Vec<(&str, u32)> = graph
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node.seen))
+        .collect();
+    assert_eq!(
+        seen,
+        vec![("10.0.0.1", 2), ("10.0.0.3", 1), ("10.0.0.9", 1)]
+    );
+    assert!(!graph.edges.iter().any(|edge| edge.to == "10.0.0.2"));
+    assert!(!graph.edges.iter().any(|edge| edge.to == "10.0.0.4"));
+
+    let all = build_graph_with_options(&trace, &BuildOptions::default());
+    assert_eq!(all.nodes.len(), 5);
+}
@@ -0,0 +1,64 @@
+//! Address classification helpers shared by the graph builder and renderers.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// True for RFC1918 (`10/8`, `172.16/12`, `192.168/16`) and link-local
+/// (`169.254/16`) IPv4 addresses, and RFC4193 unique-local IPv6 (`fc00::/7`).
+/// Strings that do not parse as an address are never private.
+pub fn is_private_ip(id: &str) -> bool {
+    match id.parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) => is_private_v4(addr),
+        Ok(IpAddr::V6(addr)) => is_unique_local_v6(addr),
+        Err(_) => false,
+    }
+}
+
+pub fn is_private_v4(addr: Ipv4Addr) -> bool {
+    addr.is_private() || addr.is_link_local()
+}
+
+pub fn is_unique_local_v6(addr: Ipv6Addr) -> bool {
+    (addr.segments()[0] & 0xfe00) == 0xfc00
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_rfc1918_ranges() {
+        for ip in [
+            "10.0.0.1",
+            "10.255.255.255",
+            "172.16.0.1",
+            "172.31.255.254",
+            "192.168.0.1",
+            "192.168.255.255",
+        ] {
+            assert!(is_private_ip(ip), "{ip} should be private");
+        }
+    }
+
+    #[test]
+    fn detects_link_local_and_unique_local() {
+        assert!(is_private_ip("169.254.10.20"));
+        assert!(is_private_ip("fc00::1"));
+        assert!(is_private_ip("fd12:3456:789a::1"));
+    }
+
+    #[test]
+    fn public_addresses_pass_through() {
+        for ip in [
+            "8.8.8.8",
+            "172.15.255.255",
+            "172.32.0.1",
+            "192.169.0.1",
+            "11.0.0.1",
+            "2001:4860:4860::8888",
+            "fe80::1",
+            "unknown",
+        ] {
+            assert!(!is_private_ip(ip), "{ip} should not be private");
+        }
+    }
+}
@@ -1,6 +1,17 @@
 //! Shared data structures for PathTraceRoute.
 
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+
+pub mod ip_class;
+#[cfg(feature = "yaml")]
+pub mod yaml;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TraceFile {
@@ -13,13 +24,34 @@ pub struct TraceRun {
     pub target: String,
     pub timestamp_utc: String,
     pub hops: Vec<Hop>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub source_ip: Option<String>,
+    #[serde(default)]
+    pub protocol: TraceProtocol,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Probe protocol a run was traced with. Plain `traceroute` (what ptroute
+/// runs outside Windows) sends UDP probes, hence the default; `pathping`
+/// sends ICMP echo requests.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum TraceProtocol {
+    Icmp,
+    #[default]
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Hop {
     pub ttl: u32,
     pub ip: Option<String>,
     pub rtt_ms: Vec<Option<f64>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mpls_labels: Vec<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -29,19 +61,47 @@ pub struct GraphFile {
     pub edges: Vec<Edge>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Node {
     pub id: String,
     pub seen: u32,
     pub loss_probes: u32,
+    /// Probes sent to this hop across every run, answered or not; 0 in
+    /// graphs built before it was recorded. See [`Node::loss_ratio`].
+    #[serde(default)]
+    pub sent_probes: u32,
+    /// PageRank score, filled in by `ptroute build --annotate-pagerank`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagerank: Option<f64>,
+    /// Mean of every answered probe to this node; `None` when none answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_ms_avg: Option<f64>,
+    /// Median of every answered probe, interpolated between the closest
+    /// ranks; `None` when none answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_p50_ms: Option<f64>,
+    /// 95th percentile of every answered probe, like `rtt_p50_ms`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_p95_ms: Option<f64>,
+    /// Autonomous system the hop belongs to. `build` cannot learn it from
+    /// traceroute output, so it is only present in graphs annotated with it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct Edge {
     pub from: String,
     pub to: String,
     pub seen: u32,
     pub rtt_delta_ms_avg: f64,
+    /// Population standard deviation of the per-run RTT deltas (jitter).
+    #[serde(default)]
+    pub rtt_delta_ms_stddev: f64,
+    /// Smallest TTL the `from` hop was seen at, i.e. how far along the path
+    /// the edge starts. 0 in graphs built before edges carried it.
+    #[serde(default)]
+    pub depth: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -57,6 +117,51 @@ pub struct SceneNode {
     pub position: [f32; 3],
     pub seen: u32,
     pub loss_probes: u32,
+    /// Copied from [`Node::sent_probes`] by layout.
+    #[serde(default)]
+    pub sent_probes: u32,
+    /// Copied from [`Node::pagerank`] by layout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagerank: Option<f64>,
+    /// Copied from [`Node::rtt_ms_avg`] by layout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_ms_avg: Option<f64>,
+    /// Copied from [`Node::asn`] by layout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// Text drawn next to the node when labels are rendered; the id is used when absent.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Per-frame motion carried between `layout_graph_incremental` calls;
+    /// omitted from the JSON while the node is at rest.
+    #[serde(default, skip_serializing_if = "is_at_rest")]
+    pub velocity: [f32; 3],
+}
+
+impl Node {
+    /// Share of the sent probes that went unanswered, in `[0, 1]`. Without
+    /// a recorded `sent_probes` (older graphs) any loss counts as total.
+    pub fn loss_ratio(&self) -> f64 {
+        loss_ratio(self.loss_probes, self.sent_probes)
+    }
+}
+
+impl SceneNode {
+    /// Same as [`Node::loss_ratio`].
+    pub fn loss_ratio(&self) -> f64 {
+        loss_ratio(self.loss_probes, self.sent_probes)
+    }
+}
+
+fn loss_ratio(loss_probes: u32, sent_probes: u32) -> f64 {
+    match sent_probes.max(loss_probes) {
+        0 => 0.0,
+        sent => f64::from(loss_probes) / f64::from(sent),
+    }
+}
+
+fn is_at_rest(velocity: &[f32; 3]) -> bool {
+    *velocity == [0.0; 3]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -65,12 +170,726 @@ pub struct SceneEdge {
     pub to: String,
     pub seen: u32,
     pub rtt_delta_ms_avg: f64,
+    /// Copied from [`Edge::depth`] by layout.
+    #[serde(default)]
+    pub depth: u32,
+}
+
+// Float fields hash by bit pattern, with -0.0 folded into 0.0 so values that
+// compare equal hash equally. Folding is harmless for `Node` and `Hop`, whose
+// `==` compares floats by `total_cmp` and so only ever equates identical bits.
+fn hash_f64<H: Hasher>(value: f64, state: &mut H) {
+    let value = if value == 0.0 { 0.0 } else { value };
+    value.to_bits().hash(state);
+}
+
+fn hash_f32<H: Hasher>(value: f32, state: &mut H) {
+    let value = if value == 0.0 { 0.0 } else { value };
+    value.to_bits().hash(state);
+}
+
+// IEEE total order: -0.0 sorts before 0.0 and NaN equals a NaN with the same
+// bits, so `Node` and `Hop` can base both `cmp` and `==` on it.
+fn cmp_f64(a: f64, b: f64) -> Ordering {
+    a.total_cmp(&b)
+}
+
+fn cmp_opt_f64(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp_f64(a, b),
+        _ => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+fn hash_opt_f64<H: Hasher>(value: Option<f64>, state: &mut H) {
+    value.is_some().hash(state);
+    if let Some(value) = value {
+        hash_f64(value, state);
+    }
+}
+
+/// Float fields compare by [`f64::total_cmp`], so a NaN equals itself and
+/// `==` agrees with `cmp`.
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Node {}
+
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.seen.hash(state);
+        self.loss_probes.hash(state);
+        self.sent_probes.hash(state);
+        hash_opt_f64(self.pagerank, state);
+        hash_opt_f64(self.rtt_ms_avg, state);
+        hash_opt_f64(self.rtt_p50_ms, state);
+        hash_opt_f64(self.rtt_p95_ms, state);
+        self.asn.hash(state);
+    }
+}
+
+/// Orders by `id`, then by the remaining fields so that `cmp` agrees with
+/// `==`: a `BTreeSet<Node>` iterates in id order, but unlike an id-only
+/// order it keeps nodes that share an id and differ elsewhere, merging only
+/// exact duplicates. Deduplicate by id with a map keyed on `id` instead.
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id
+            .cmp(&other.id)
+            .then(self.seen.cmp(&other.seen))
+            .then(self.loss_probes.cmp(&other.loss_probes))
+            .then(self.sent_probes.cmp(&other.sent_probes))
+            .then(cmp_opt_f64(self.pagerank, other.pagerank))
+            .then(cmp_opt_f64(self.rtt_ms_avg, other.rtt_ms_avg))
+            .then(cmp_opt_f64(self.rtt_p50_ms, other.rtt_p50_ms))
+            .then(cmp_opt_f64(self.rtt_p95_ms, other.rtt_p95_ms))
+            .then(self.asn.cmp(&other.asn))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// RTTs compare by [`f64::total_cmp`], so a NaN equals itself and `==`
+/// agrees with `cmp`.
+impl PartialEq for Hop {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Hop {}
+
+impl Hash for Hop {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ttl.hash(state);
+        self.ip.hash(state);
+        self.rtt_ms.len().hash(state);
+        for rtt in &self.rtt_ms {
+            hash_opt_f64(*rtt, state);
+        }
+        self.mpls_labels.hash(state);
+    }
+}
+
+/// Orders by `ttl`, so `hops.sort()` puts a run in path order. Hops at one
+/// ttl are ordered by address, RTTs and labels, keeping `cmp` in line with
+/// `==`.
+impl Ord for Hop {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.ttl
+            .cmp(&other.ttl)
+            .then_with(|| self.ip.cmp(&other.ip))
+            .then_with(|| {
+                self.rtt_ms
+                    .iter()
+                    .zip(&other.rtt_ms)
+                    .map(|(a, b)| cmp_opt_f64(*a, *b))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or_else(|| self.rtt_ms.len().cmp(&other.rtt_ms.len()))
+            })
+            .then_with(|| self.mpls_labels.cmp(&other.mpls_labels))
+    }
+}
+
+impl PartialOrd for Hop {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for Edge {}
+
+impl Hash for Edge {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.from.hash(state);
+        self.to.hash(state);
+        self.seen.hash(state);
+        hash_f64(self.rtt_delta_ms_avg, state);
+        hash_f64(self.rtt_delta_ms_stddev, state);
+        self.depth.hash(state);
+    }
+}
+
+impl Eq for SceneNode {}
+
+impl Hash for SceneNode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        for axis in self.position {
+            hash_f32(axis, state);
+        }
+        self.seen.hash(state);
+        self.loss_probes.hash(state);
+        self.sent_probes.hash(state);
+        hash_opt_f64(self.pagerank, state);
+        hash_opt_f64(self.rtt_ms_avg, state);
+        self.label.hash(state);
+    }
+}
+
+impl Eq for SceneEdge {}
+
+impl Hash for SceneEdge {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.from.hash(state);
+        self.to.hash(state);
+        self.seen.hash(state);
+        hash_f64(self.rtt_delta_ms_avg, state);
+        self.depth.hash(state);
+    }
+}
+
+macro_rules! impl_json_io {
+    ($($ty:ty),*) => {
+        $(
+            impl $ty {
+                /// Deserializes from a JSON stream without buffering it into a `String`.
+                pub fn from_reader<R: Read>(reader: R) -> Result<Self, serde_json::Error> {
+                    serde_json::from_reader(reader)
+                }
+
+                /// Like `serde_json::from_str`, but fails on keys the model does not
+                /// know instead of ignoring them.
+                pub fn from_str_strict(text: &str) -> Result<Self, serde_json::Error> {
+                    from_str_strict(text)
+                }
+
+                /// Serializes as pretty-printed JSON.
+                pub fn to_writer<W: Write>(&self, writer: W) -> Result<(), serde_json::Error> {
+                    serde_json::to_writer_pretty(writer, self)
+                }
+
+                #[cfg(feature = "yaml")]
+                pub fn from_yaml_str(text: &str) -> Result<Self, yaml::Error> {
+                    yaml::from_str(text)
+                }
+
+                #[cfg(feature = "yaml")]
+                pub fn to_yaml_str(&self) -> Result<String, yaml::Error> {
+                    yaml::to_string(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_json_io!(TraceFile, GraphFile, SceneFile);
+
+fn from_str_strict<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    let mut unknown = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_str(text);
+    let value =
+        serde_ignored::deserialize(&mut deserializer, |path| unknown.push(path.to_string()))?;
+    deserializer.end()?;
+    match unknown.first() {
+        Some(path) => Err(serde::de::Error::custom(format!("unknown field `{path}`"))),
+        None => Ok(value),
+    }
+}
+
+/// Semantic problem found by [`TraceFile::validate`]. Indices point into
+/// `runs`, then that run's `hops`, then the hop's `rtt_ms`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    ZeroTtl {
+        run_index: usize,
+        hop_index: usize,
+    },
+    /// `hop2_idx` follows `hop1_idx` but does not have a larger TTL.
+    NonMonotonicTtl {
+        run_index: usize,
+        hop1_idx: usize,
+        hop2_idx: usize,
+    },
+    NegativeRtt {
+        run_index: usize,
+        hop_index: usize,
+        probe_index: usize,
+    },
+    EmptyTarget {
+        run_index: usize,
+    },
+    FutureTimestamp {
+        run_index: usize,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ValidationError::ZeroTtl {
+                run_index,
+                hop_index,
+            } => write!(f, "run {run_index} hop {hop_index}: ttl is 0"),
+            ValidationError::NonMonotonicTtl {
+                run_index,
+                hop1_idx,
+                hop2_idx,
+            } => write!(
+                f,
+                "run {run_index}: hop {hop2_idx} does not have a larger ttl than hop {hop1_idx}"
+            ),
+            ValidationError::NegativeRtt {
+                run_index,
+                hop_index,
+                probe_index,
+            } => write!(
+                f,
+                "run {run_index} hop {hop_index} probe {probe_index}: negative rtt"
+            ),
+            ValidationError::EmptyTarget { run_index } => {
+                write!(f, "run {run_index}: empty target")
+            }
+            ValidationError::FutureTimestamp { run_index } => {
+                write!(f, "run {run_index}: timestamp is in the future")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Hop {
+    /// Combines the probes of two sightings of the same hop, e.g. from
+    /// repeated runs: `None` unless the TTLs match and the addresses match
+    /// or one of them is anonymous. The merged hop keeps the known address,
+    /// `self`'s probes followed by `other`'s, and the first non-empty MPLS
+    /// label stack.
+    pub fn merge(&self, other: &Hop) -> Option<Hop> {
+        if self.ttl != other.ttl {
+            return None;
+        }
+        if let (Some(a), Some(b)) = (&self.ip, &other.ip) {
+            if a != b {
+                return None;
+            }
+        }
+        let mpls_labels = if self.mpls_labels.is_empty() {
+            &other.mpls_labels
+        } else {
+            &self.mpls_labels
+        };
+        Some(Hop {
+            ttl: self.ttl,
+            ip: self.ip.clone().or_else(|| other.ip.clone()),
+            rtt_ms: self.rtt_ms.iter().chain(&other.rtt_ms).copied().collect(),
+            mpls_labels: mpls_labels.clone(),
+        })
+    }
+}
+
+impl TraceRun {
+    /// Folds repeated runs into one, merging each TTL's hops with
+    /// [`Hop::merge`] in run order. Hops at one TTL that answered from
+    /// different addresses stay separate. Target, timestamp, label, source
+    /// and protocol come from the first run; `None` when `runs` is empty.
+    pub fn merge_repeated(runs: &[TraceRun]) -> Option<TraceRun> {
+        let first = runs.first()?;
+        let mut by_ttl: BTreeMap<u32, Vec<Hop>> = BTreeMap::new();
+        for hop in runs.iter().flat_map(|run| &run.hops) {
+            let group = by_ttl.entry(hop.ttl).or_default();
+            match group
+                .iter_mut()
+                .find_map(|merged| merged.merge(hop).map(|combined| (merged, combined)))
+            {
+                Some((merged, combined)) => *merged = combined,
+                None => group.push(hop.clone()),
+            }
+        }
+        Some(TraceRun {
+            hops: by_ttl.into_values().flatten().collect(),
+            ..first.clone()
+        })
+    }
+
+    /// Merges consecutive hops that answered from the same address into the
+    /// first of them, concatenating their probes. Anonymous hops are never merged.
+    pub fn deduplicate_hops(&mut self) {
+        let mut merged: Vec<Hop> = Vec::with_capacity(self.hops.len());
+        for hop in self.hops.drain(..) {
+            match merged.last_mut() {
+                Some(prev) if prev.ip.is_some() && prev.ip == hop.ip => {
+                    prev.rtt_ms.extend(hop.rtt_ms);
+                    if prev.mpls_labels.is_empty() {
+                        prev.mpls_labels = hop.mpls_labels;
+                    }
+                }
+                _ => merged.push(hop),
+            }
+        }
+        self.hops = merged;
+    }
+}
+
+impl TraceFile {
+    /// Version written by this build. 2 added [`TraceRun::protocol`].
+    pub const VERSION: u32 = 2;
+
+    /// Upgrades a file written by an older ptroute in place and sets
+    /// `version` to [`TraceFile::VERSION`]. Files from a newer ptroute are
+    /// left untouched.
+    pub fn migrate_to_current(&mut self) {
+        if self.version >= Self::VERSION {
+            return;
+        }
+        if self.version < 2 {
+            for run in &mut self.runs {
+                run.protocol = TraceProtocol::Icmp;
+            }
+        }
+        self.version = Self::VERSION;
+    }
+
+    pub fn deduplicate_all(&mut self) {
+        for run in &mut self.runs {
+            run.deduplicate_hops();
+        }
+    }
+
+    /// Copy holding only the runs whose target is one of `targets`.
+    pub fn filter_targets(&self, targets: &[&str]) -> TraceFile {
+        TraceFile {
+            version: self.version,
+            runs: self
+                .runs
+                .iter()
+                .filter(|run| targets.contains(&run.target.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Runs keyed by target, each list in file order.
+    pub fn group_by_target(&self) -> HashMap<&str, Vec<&TraceRun>> {
+        let mut groups: HashMap<&str, Vec<&TraceRun>> = HashMap::new();
+        for run in &self.runs {
+            groups.entry(run.target.as_str()).or_default().push(run);
+        }
+        groups
+    }
+
+    /// Every target traced, sorted and deduplicated.
+    pub fn unique_targets(&self) -> Vec<&str> {
+        let targets: BTreeSet<&str> = self.runs.iter().map(|run| run.target.as_str()).collect();
+        targets.into_iter().collect()
+    }
+
+    /// Runs keyed by their UTC date (`YYYY-MM-DD`), each list in file order.
+    /// Runs whose `timestamp_utc` does not parse as RFC 3339 are left out.
+    pub fn group_by_date(&self) -> HashMap<String, Vec<&TraceRun>> {
+        let mut groups: HashMap<String, Vec<&TraceRun>> = HashMap::new();
+        for run in &self.runs {
+            let Ok(time) = DateTime::parse_from_rfc3339(&run.timestamp_utc) else {
+                continue;
+            };
+            let date = time.with_timezone(&Utc).format("%Y-%m-%d").to_string();
+            groups.entry(date).or_default().push(run);
+        }
+        groups
+    }
+
+    /// Earliest and latest run timestamps, recomputed on each call. Runs whose
+    /// `timestamp_utc` does not parse as RFC 3339 are ignored; `None` when no
+    /// run has a usable timestamp.
+    pub fn time_range(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.runs
+            .iter()
+            .filter_map(|run| DateTime::parse_from_rfc3339(&run.timestamp_utc).ok())
+            .map(|time| time.with_timezone(&Utc))
+            .fold(None, |range, time| match range {
+                None => Some((time, time)),
+                Some((earliest, latest)) => Some((earliest.min(time), latest.max(time))),
+            })
+    }
+
+    /// Span between the earliest and latest runs; see [`TraceFile::time_range`].
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        self.time_range()
+            .map(|(earliest, latest)| latest - earliest)
+    }
+
+    /// Every semantic problem in the file, in run order; empty when the file
+    /// is fine. Timestamps that do not parse are not reported.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let now = DateTime::<Utc>::from(std::time::SystemTime::now());
+        let mut errors = Vec::new();
+        for (run_index, run) in self.runs.iter().enumerate() {
+            if run.target.trim().is_empty() {
+                errors.push(ValidationError::EmptyTarget { run_index });
+            }
+            if DateTime::parse_from_rfc3339(&run.timestamp_utc)
+                .is_ok_and(|time| time.with_timezone(&Utc) > now)
+            {
+                errors.push(ValidationError::FutureTimestamp { run_index });
+            }
+            for (hop_index, hop) in run.hops.iter().enumerate() {
+                if hop.ttl == 0 {
+                    errors.push(ValidationError::ZeroTtl {
+                        run_index,
+                        hop_index,
+                    });
+                }
+                if hop_index > 0 && hop.ttl <= run.hops[hop_index - 1].ttl {
+                    errors.push(ValidationError::NonMonotonicTtl {
+                        run_index,
+                        hop1_idx: hop_index - 1,
+                        hop2_idx: hop_index,
+                    });
+                }
+                for (probe_index, rtt) in hop.rtt_ms.iter().enumerate() {
+                    if rtt.is_some_and(|rtt| rtt < 0.0) {
+                        errors.push(ValidationError::NegativeRtt {
+                            run_index,
+                            hop_index,
+                            probe_index,
+                        });
+                    }
+                }
+            }
+        }
+        errors
+    }
+}
+
+impl GraphFile {
+    /// Copy holding only the nodes in `ids` and the edges between them.
+    pub fn filter_nodes(&self, ids: &[&str]) -> GraphFile {
+        let ids: HashSet<&str> = ids.iter().copied().collect();
+        let keep = |id: &str| ids.contains(id);
+        GraphFile {
+            version: self.version,
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|node| keep(&node.id))
+                .cloned()
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|edge| keep(&edge.from) && keep(&edge.to))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Nodes within `depth` hops of `center`, following edges in either
+    /// direction, and the edges between them. Empty when `center` is absent.
+    pub fn subgraph_around(&self, center: &str, depth: u32) -> GraphFile {
+        if !self.nodes.iter().any(|node| node.id == center) {
+            return self.filter_nodes(&[]);
+        }
+        let mut neighbors: HashMap<&str, Vec<&str>> = HashMap::new();
+        for edge in &self.edges {
+            neighbors.entry(&edge.from).or_default().push(&edge.to);
+            neighbors.entry(&edge.to).or_default().push(&edge.from);
+        }
+
+        let mut reached: HashSet<&str> = HashSet::from([center]);
+        let mut frontier = vec![center];
+        for _ in 0..depth {
+            frontier = frontier
+                .iter()
+                .flat_map(|id| neighbors.get(id).into_iter().flatten().copied())
+                .filter(|id| reached.insert(id))
+                .collect();
+            if frontier.is_empty() {
+                break;
+            }
+        }
+
+        let ids: Vec<&str> = reached.into_iter().collect();
+        self.filter_nodes(&ids)
+    }
+
+    /// Drops nodes and edges with `seen < min_seen`, plus every edge touching
+    /// a dropped node.
+    pub fn prune_cold_nodes(&self, min_seen: u32) -> GraphFile {
+        let ids: Vec<&str> = self
+            .nodes
+            .iter()
+            .filter(|node| node.seen >= min_seen)
+            .map(|node| node.id.as_str())
+            .collect();
+        let mut graph = self.filter_nodes(&ids);
+        graph.edges.retain(|edge| edge.seen >= min_seen);
+        graph
+    }
+
+    /// Drops edges (with `seen > 0`) whose endpoints together lost more than
+    /// `max_loss_ratio` of the probes sent to them, counted as in
+    /// [`Node::loss_ratio`]. Nodes are kept.
+    pub fn prune_lossy_edges(&self, max_loss_ratio: f64) -> GraphFile {
+        let nodes: HashMap<&str, &Node> = self
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node))
+            .collect();
+        let loss_ratio = |edge: &Edge| {
+            let (lost, probes) = [&edge.from, &edge.to]
+                .into_iter()
+                .filter_map(|id| nodes.get(id.as_str()))
+                .fold((0u64, 0u64), |(lost, probes), node| {
+                    (
+                        lost + u64::from(node.loss_probes),
+                        probes + u64::from(node.sent_probes.max(node.loss_probes)),
+                    )
+                });
+            if probes == 0 {
+                0.0
+            } else {
+                lost as f64 / probes as f64
+            }
+        };
+
+        let mut graph = self.clone();
+        graph
+            .edges
+            .retain(|edge| edge.seen == 0 || loss_ratio(edge) <= max_loss_ratio);
+        graph
+    }
+
+    /// Node ids in file order and a square matrix with
+    /// `matrix[i][j] = rtt_delta_ms_avg` of the edge from node `i` to node
+    /// `j`, or 0.0 without one. Edges to unknown nodes are skipped.
+    pub fn to_adjacency_matrix(&self) -> (Vec<String>, Vec<Vec<f64>>) {
+        self.adjacency_matrix(|edge| edge.rtt_delta_ms_avg)
+    }
+
+    /// Like [`GraphFile::to_adjacency_matrix`], weighted by `seen`.
+    pub fn to_weighted_adjacency_matrix(&self) -> (Vec<String>, Vec<Vec<f64>>) {
+        self.adjacency_matrix(|edge| f64::from(edge.seen))
+    }
+
+    fn adjacency_matrix(&self, weight: impl Fn(&Edge) -> f64) -> (Vec<String>, Vec<Vec<f64>>) {
+        let ids: Vec<String> = self.nodes.iter().map(|node| node.id.clone()).collect();
+        let index: HashMap<&str, usize> = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.as_str(), i))
+            .collect();
+        let mut matrix = vec![vec![0.0; ids.len()]; ids.len()];
+        for edge in &self.edges {
+            if let (Some(&from), Some(&to)) =
+                (index.get(edge.from.as_str()), index.get(edge.to.as_str()))
+            {
+                matrix[from][to] = weight(edge);
+            }
+        }
+        (ids, matrix)
+    }
+}
+
+/// What changed between two layouts of the same graph, from
+/// [`SceneFile::layout_diff`]. Each list is sorted by node id.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneLayoutDiff {
+    /// `(id, distance, old position, new position)`.
+    pub moved: Vec<(String, f32, [f32; 3], [f32; 3])>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl SceneLayoutDiff {
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl SceneFile {
+    /// Compares `self` (the old layout) with `updated`. Nodes in both count
+    /// as moved when their positions are more than `min_distance` apart.
+    pub fn layout_diff(&self, updated: &SceneFile, min_distance: f32) -> SceneLayoutDiff {
+        let old: HashMap<&str, [f32; 3]> = self
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.position))
+            .collect();
+        let new: HashMap<&str, [f32; 3]> = updated
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.position))
+            .collect();
+
+        let mut diff = SceneLayoutDiff::default();
+        for (&id, &after) in &new {
+            let Some(&before) = old.get(id) else {
+                diff.added.push(id.to_string());
+                continue;
+            };
+            let distance = (0..3)
+                .map(|axis| (after[axis] - before[axis]).powi(2))
+                .sum::<f32>()
+                .sqrt();
+            if distance > min_distance {
+                diff.moved.push((id.to_string(), distance, before, after));
+            }
+        }
+        diff.removed = old
+            .keys()
+            .filter(|id| !new.contains_key(*id))
+            .map(|id| id.to_string())
+            .collect();
+
+        diff.moved.sort_by(|a, b| a.0.cmp(&b.0));
+        diff.added.sort();
+        diff.removed.sort();
+        diff
+    }
+
+    /// Pairs of nodes closer together than `min_distance`, which render as
+    /// one overlapping blob.
+    pub fn node_overlap_count(&self, min_distance: f32) -> usize {
+        let mut positions: Vec<[f32; 3]> = self.nodes.iter().map(|node| node.position).collect();
+        positions.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        let mut count = 0;
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                // Sorted by X, so no later node can be within range either.
+                if b[0] - a[0] >= min_distance {
+                    break;
+                }
+                let distance = (0..3)
+                    .map(|axis| (a[axis] - b[axis]).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                if distance < min_distance {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn node(id: &str) -> Node {
+        Node {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn edge(from: &str, to: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn trace_file_round_trip_is_stable() {
         let trace = TraceFile {
@@ -78,21 +897,27 @@ mod tests {
             runs: vec![TraceRun {
                 target: "1.1.1.1".to_string(),
                 timestamp_utc: "2026-02-01T12:34:56Z".to_string(),
+                label: Some("before-maintenance".to_string()),
+                source_ip: Some("192.168.1.20".to_string()),
+                protocol: TraceProtocol::Udp,
                 hops: vec![
                     Hop {
                         ttl: 1,
                         ip: Some("192.168.1.1".to_string()),
                         rtt_ms: vec![Some(1.2), Some(1.1), Some(1.3)],
+                        mpls_labels: Vec::new(),
                     },
                     Hop {
                         ttl: 2,
                         ip: Some("10.0.0.1".to_string()),
                         rtt_ms: vec![Some(5.2), None, Some(5.1)],
+                        mpls_labels: vec![24006, 16],
                     },
                     Hop {
                         ttl: 3,
                         ip: None,
                         rtt_ms: vec![None, None, None],
+                        mpls_labels: Vec::new(),
                     },
                 ],
             }],
@@ -106,27 +931,297 @@ mod tests {
         assert_eq!(json, json2);
     }
 
+    fn dedupe_run(ips: &[Option<&str>]) -> TraceRun {
+        TraceRun {
+            target: "8.8.8.8".to_string(),
+            timestamp_utc: "2026-02-01T12:34:56Z".to_string(),
+            label: None,
+            source_ip: None,
+            protocol: TraceProtocol::Udp,
+            hops: ips
+                .iter()
+                .enumerate()
+                .map(|(idx, ip)| Hop {
+                    ttl: idx as u32 + 1,
+                    ip: ip.map(str::to_string),
+                    rtt_ms: vec![Some(idx as f64 + 1.0)],
+                    mpls_labels: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn deduplicate_merges_consecutive_identical_hops() {
+        let mut run = dedupe_run(&[Some("10.0.0.1"), Some("10.0.0.2"), Some("10.0.0.2")]);
+        run.deduplicate_hops();
+
+        assert_eq!(run.hops.len(), 2);
+        assert_eq!(run.hops[1].ttl, 2);
+        assert_eq!(run.hops[1].ip.as_deref(), Some("10.0.0.2"));
+        assert_eq!(run.hops[1].rtt_ms, vec![Some(2.0), Some(3.0)]);
+    }
+
+    #[test]
+    fn deduplicate_keeps_alternating_hops() {
+        let mut run = dedupe_run(&[Some("10.0.0.1"), Some("10.0.0.2"), Some("10.0.0.1")]);
+        let before = run.clone();
+        run.deduplicate_hops();
+        assert_eq!(run, before);
+    }
+
+    #[test]
+    fn deduplicate_does_not_merge_across_anonymous_hop() {
+        let mut trace = TraceFile {
+            version: 1,
+            runs: vec![dedupe_run(&[Some("10.0.0.1"), None, Some("10.0.0.1")])],
+        };
+        let before = trace.clone();
+        trace.deduplicate_all();
+        assert_eq!(trace, before);
+    }
+
+    fn probe_hop(ttl: u32, ip: Option<&str>, rtt_ms: Vec<Option<f64>>) -> Hop {
+        Hop {
+            ttl,
+            ip: ip.map(str::to_string),
+            rtt_ms,
+            mpls_labels: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hop_merge_concatenates_probes_of_matching_hops() {
+        let a = probe_hop(3, Some("10.0.0.3"), vec![Some(1.0), None]);
+        let b = probe_hop(3, None, vec![None, Some(2.0)]);
+
+        let merged = a.merge(&b).unwrap();
+        assert_eq!(merged.ip.as_deref(), Some("10.0.0.3"));
+        assert_eq!(merged.rtt_ms, vec![Some(1.0), None, None, Some(2.0)]);
+        assert_eq!(b.merge(&a).unwrap().ip.as_deref(), Some("10.0.0.3"));
+
+        assert_eq!(a.merge(&probe_hop(4, Some("10.0.0.3"), vec![])), None);
+        assert_eq!(a.merge(&probe_hop(3, Some("10.0.0.9"), vec![])), None);
+    }
+
+    #[test]
+    fn merge_repeated_groups_hops_by_ttl() {
+        let mut first = dedupe_run(&[Some("10.0.0.1"), None]);
+        first.hops[1].rtt_ms = vec![None];
+        let mut second = dedupe_run(&[Some("10.0.0.1"), Some("10.0.0.2"), Some("8.8.8.8")]);
+        second.timestamp_utc = "2026-02-01T12:35:56Z".to_string();
+        let mut third = dedupe_run(&[Some("10.0.0.9")]);
+        third.hops[0].rtt_ms = vec![Some(9.0)];
+
+        let merged = TraceRun::merge_repeated(&[first.clone(), second, third]).unwrap();
+        let summary: Vec<_> = merged
+            .hops
+            .iter()
+            .map(|hop| (hop.ttl, hop.ip.as_deref(), hop.rtt_ms.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, Some("10.0.0.1"), vec![Some(1.0), Some(1.0)]),
+                (1, Some("10.0.0.9"), vec![Some(9.0)]),
+                (2, Some("10.0.0.2"), vec![None, Some(2.0)]),
+                (3, Some("8.8.8.8"), vec![Some(3.0)]),
+            ]
+        );
+        assert_eq!(merged.timestamp_utc, first.timestamp_utc);
+        assert_eq!(TraceRun::merge_repeated(&[]), None);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn json_yaml_json_round_trip_is_identical() {
+        let mut trace = TraceFile {
+            version: 1,
+            runs: vec![dedupe_run(&[Some("10.0.0.1"), None, Some("10.0.0.2")])],
+        };
+        trace.runs[0].label = Some("nightly".to_string());
+        trace.runs[0].hops[2].mpls_labels = vec![16];
+        let graph = GraphFile {
+            version: 1,
+            nodes: vec![Node {
+                seen: 2,
+                loss_probes: 1,
+                ..node("10.0.0.1")
+            }],
+            edges: vec![Edge {
+                seen: 2,
+                rtt_delta_ms_avg: 0.25,
+                ..edge("10.0.0.1", "10.0.0.2")
+            }],
+        };
+        let scene = SceneFile {
+            version: 1,
+            nodes: vec![SceneNode {
+                id: "10.0.0.1".to_string(),
+                position: [0.5, -1.0, 2.0],
+                seen: 2,
+                loss_probes: 1,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            }],
+            edges: Vec::new(),
+        };
+
+        let json = serde_json::to_string(&trace).unwrap();
+        let yaml = TraceFile::from_yaml_str(&TraceFile::to_yaml_str(&trace).unwrap()).unwrap();
+        assert_eq!(serde_json::to_string(&yaml).unwrap(), json);
+
+        let decoded = GraphFile::from_yaml_str(&graph.to_yaml_str().unwrap()).unwrap();
+        assert_eq!(decoded, graph);
+        let decoded = SceneFile::from_yaml_str(&scene.to_yaml_str().unwrap()).unwrap();
+        assert_eq!(decoded, scene);
+    }
+
+    #[test]
+    fn strict_parsing_rejects_unknown_fields() {
+        let json = r#"{
+            "version": 1,
+            "runs": [{
+                "target": "1.1.1.1",
+                "timestamp_utc": "2026-02-01T12:34:56Z",
+                "hops": [{"ttl": 1, "ip": null, "rtt_ms": [null], "extra_field": 42}]
+            }]
+        }"#;
+
+        assert!(serde_json::from_str::<TraceFile>(json).is_ok());
+        let err = TraceFile::from_str_strict(json).unwrap_err();
+        assert!(
+            err.to_string().contains("runs.0.hops.0.extra_field"),
+            "{err}"
+        );
+
+        let graph = r#"{"version": 1, "nodes": [], "edges": [], "extra_field": 42}"#;
+        assert!(serde_json::from_str::<GraphFile>(graph).is_ok());
+        assert!(GraphFile::from_str_strict(graph).is_err());
+    }
+
+    #[test]
+    fn strict_parsing_accepts_known_fields() {
+        let scene = r#"{"version": 1, "nodes": [{"id": "a", "position": [0, 0, 0], "seen": 1, "loss_probes": 0}], "edges": []}"#;
+        assert_eq!(SceneFile::from_str_strict(scene).unwrap().nodes.len(), 1);
+    }
+
+    #[test]
+    fn trace_run_label_defaults_to_none() {
+        let json = r#"{"target":"1.1.1.1","timestamp_utc":"2026-02-01T12:34:56Z","hops":[]}"#;
+        let run: TraceRun = serde_json::from_str(json).unwrap();
+        assert_eq!(run.label, None);
+        assert_eq!(run.source_ip, None);
+    }
+
+    #[test]
+    fn trace_file_reader_writer_round_trip() {
+        let trace = TraceFile {
+            version: 1,
+            runs: vec![TraceRun {
+                target: "1.1.1.1".to_string(),
+                timestamp_utc: "2026-02-01T12:34:56Z".to_string(),
+                hops: vec![Hop {
+                    ttl: 1,
+                    ip: Some("192.168.1.1".to_string()),
+                    rtt_ms: vec![Some(1.2), None],
+                    mpls_labels: Vec::new(),
+                }],
+                label: None,
+                source_ip: None,
+                protocol: TraceProtocol::Udp,
+            }],
+        };
+
+        let mut buf = Vec::new();
+        trace.to_writer(&mut buf).unwrap();
+        assert_eq!(buf, serde_json::to_vec_pretty(&trace).unwrap());
+
+        let decoded = TraceFile::from_reader(buf.as_slice()).unwrap();
+        assert_eq!(trace, decoded);
+    }
+
+    #[test]
+    fn trace_file_from_reader_streams_large_input() {
+        // Emits ~10 MB of JSON chunk by chunk so the document never exists
+        // as a single `String`.
+        const RUN: &[u8] = br#"{"target":"1.1.1.1","timestamp_utc":"2026-02-01T12:34:56Z","hops":[{"ttl":1,"ip":"10.0.0.1","rtt_ms":[1.0,null,2.5]},{"ttl":2,"ip":null,"rtt_ms":[null,null,null]}]}"#;
+        const RUNS: usize = 10 * 1024 * 1024 / RUN.len();
+
+        struct GeneratedTrace {
+            emitted: usize,
+            pending: &'static [u8],
+            done: bool,
+        }
+
+        impl GeneratedTrace {
+            fn next_chunk(&mut self) -> Option<&'static [u8]> {
+                if self.done {
+                    return None;
+                }
+                let chunk: &'static [u8] = if self.emitted == 0 {
+                    br#"{"version":1,"runs":["#
+                } else if self.emitted % 2 == 1 {
+                    RUN
+                } else if self.emitted / 2 < RUNS {
+                    b","
+                } else {
+                    self.done = true;
+                    b"]}"
+                };
+                self.emitted += 1;
+                Some(chunk)
+            }
+        }
+
+        impl Read for GeneratedTrace {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                while self.pending.is_empty() {
+                    match self.next_chunk() {
+                        Some(chunk) => self.pending = chunk,
+                        None => return Ok(0),
+                    }
+                }
+                let n = buf.len().min(self.pending.len());
+                buf[..n].copy_from_slice(&self.pending[..n]);
+                self.pending = &self.pending[n..];
+                Ok(n)
+            }
+        }
+
+        let reader = GeneratedTrace {
+            emitted: 0,
+            pending: &[],
+            done: false,
+        };
+        let trace = TraceFile::from_reader(std::io::BufReader::new(reader)).unwrap();
+        assert_eq!(trace.runs.len(), RUNS);
+        assert_eq!(trace.runs[RUNS - 1].hops[1].ip, None);
+    }
+
     #[test]
     fn graph_file_round_trip_is_stable() {
         let graph = GraphFile {
             version: 1,
             nodes: vec![
                 Node {
-                    id: "192.168.1.1".to_string(),
                     seen: 10,
-                    loss_probes: 0,
+                    ..node("192.168.1.1")
                 },
                 Node {
-                    id: "10.0.0.1".to_string(),
                     seen: 10,
                     loss_probes: 2,
+                    ..node("10.0.0.1")
                 },
             ],
             edges: vec![Edge {
-                from: "192.168.1.1".to_string(),
-                to: "10.0.0.1".to_string(),
                 seen: 10,
                 rtt_delta_ms_avg: 4.0,
+                ..edge("192.168.1.1", "10.0.0.1")
             }],
         };
 
@@ -147,12 +1242,19 @@ mod tests {
                 position: [0.0, 0.5, -0.25],
                 seen: 10,
                 loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
             }],
             edges: vec![SceneEdge {
                 from: "192.168.1.1".to_string(),
                 to: "10.0.0.1".to_string(),
                 seen: 10,
                 rtt_delta_ms_avg: 4.0,
+                depth: 0,
             }],
         };
 
@@ -163,4 +1265,553 @@ mod tests {
         assert_eq!(scene, decoded);
         assert_eq!(json, json2);
     }
+
+    #[test]
+    fn scene_node_velocity_is_optional() {
+        let json = r#"{"id":"a","position":[1.0,2.0,3.0],"seen":1,"loss_probes":0}"#;
+        let mut node: SceneNode = serde_json::from_str(json).unwrap();
+        assert_eq!(node.velocity, [0.0; 3]);
+        assert!(!serde_json::to_string(&node).unwrap().contains("velocity"));
+
+        node.velocity = [0.5, 0.0, -0.25];
+        let moving = serde_json::to_string(&node).unwrap();
+        assert_eq!(serde_json::from_str::<SceneNode>(&moving).unwrap(), node);
+    }
+
+    #[test]
+    fn filter_targets_keeps_matching_runs_with_hops() {
+        let run = |target: &str, ip: &str| TraceRun {
+            target: target.to_string(),
+            timestamp_utc: "2026-02-01T12:00:00Z".to_string(),
+            hops: vec![
+                Hop {
+                    ttl: 1,
+                    ip: Some("192.168.1.1".to_string()),
+                    rtt_ms: vec![Some(1.0)],
+                    mpls_labels: Vec::new(),
+                },
+                Hop {
+                    ttl: 2,
+                    ip: Some(ip.to_string()),
+                    rtt_ms: vec![Some(9.0)],
+                    mpls_labels: Vec::new(),
+                },
+            ],
+            label: None,
+            source_ip: None,
+            protocol: TraceProtocol::Udp,
+        };
+        let trace = TraceFile {
+            version: 3,
+            runs: vec![
+                run("1.1.1.1", "1.1.1.1"),
+                run("8.8.8.8", "8.8.8.8"),
+                run("9.9.9.9", "9.9.9.9"),
+                run("1.1.1.1", "1.0.0.1"),
+            ],
+        };
+
+        let filtered = trace.filter_targets(&["1.1.1.1", "9.9.9.9"]);
+        assert_eq!(filtered.version, 3);
+        assert_eq!(
+            filtered.runs,
+            vec![
+                trace.runs[0].clone(),
+                trace.runs[2].clone(),
+                trace.runs[3].clone()
+            ]
+        );
+        assert!(trace.filter_targets(&[]).runs.is_empty());
+    }
+
+    #[test]
+    fn node_overlap_count_counts_close_pairs() {
+        let node = |id: &str, position: [f32; 3]| SceneNode {
+            id: id.to_string(),
+            position,
+            seen: 1,
+            loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: None,
+            velocity: [0.0; 3],
+        };
+        let scene = SceneFile {
+            version: 1,
+            nodes: vec![
+                node("a", [0.0, 0.0, 0.0]),
+                node("b", [0.0, 0.0, 0.05]),
+                node("c", [0.06, 0.0, 0.0]),
+                node("d", [1.0, 2.0, 0.0]),
+                node("e", [1.0, 2.0, 0.0]),
+                node("f", [5.0, 0.0, 0.0]),
+            ],
+            edges: Vec::new(),
+        };
+        // a-b, a-c, b-c (0.078 apart) and the coincident d-e.
+        assert_eq!(scene.node_overlap_count(0.1), 4);
+        assert_eq!(scene.node_overlap_count(0.055), 2);
+        assert_eq!(scene.node_overlap_count(0.0), 0);
+        assert_eq!(scene.node_overlap_count(10.0), 15);
+    }
+
+    #[test]
+    fn hops_order_by_ttl_and_nodes_by_id() {
+        let hop = |ttl: u32, ip: Option<&str>, rtt: f64| Hop {
+            ttl,
+            ip: ip.map(str::to_string),
+            rtt_ms: vec![Some(rtt)],
+            mpls_labels: Vec::new(),
+        };
+        assert!(hop(1, Some("10.0.0.9"), 50.0) < hop(2, Some("10.0.0.1"), 1.0));
+        // Same ttl: anonymous first, then by address and RTTs.
+        assert!(hop(3, None, 9.0) < hop(3, Some("10.0.0.3"), 2.0));
+        assert!(hop(3, Some("10.0.0.3"), 1.0) < hop(3, Some("10.0.0.3"), 2.0));
+        // Floats compare by total order, so `cmp` and `==` never disagree.
+        for (a, b) in [(0.0, -0.0), (f64::NAN, f64::NAN), (1.0, f64::NAN)] {
+            let (a, b) = (hop(3, Some("10.0.0.3"), a), hop(3, Some("10.0.0.3"), b));
+            assert_eq!(a.cmp(&b) == Ordering::Equal, a == b);
+        }
+        assert_eq!(hop(5, None, f64::NAN), hop(5, None, f64::NAN));
+        assert_ne!(hop(5, None, 0.0), hop(5, None, -0.0));
+
+        let mut hops = [
+            hop(3, Some("10.0.0.3"), 3.0),
+            hop(1, Some("10.0.0.1"), 1.0),
+            hop(4, None, 0.0),
+            hop(2, Some("10.0.0.2"), 2.0),
+        ];
+        assert!(!hops.windows(2).all(|w| w[0] < w[1]));
+        hops.sort();
+        assert_eq!(
+            hops.iter().map(|hop| hop.ttl).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(hops.windows(2).all(|w| w[0] < w[1]));
+
+        let seen_node = |id: &str, seen: u32| Node { seen, ..node(id) };
+        let nodes: BTreeSet<Node> = [
+            seen_node("b", 5),
+            seen_node("a", 2),
+            seen_node("b", 1),
+            seen_node("c", 1),
+            seen_node("a", 2),
+        ]
+        .into_iter()
+        .collect();
+        let ids: Vec<(&str, u32)> = nodes.iter().map(|n| (n.id.as_str(), n.seen)).collect();
+        // Only the exact duplicate is dropped; `collect` agrees with `insert`.
+        assert_eq!(ids, vec![("a", 2), ("b", 1), ("b", 5), ("c", 1)]);
+
+        let nan = Node {
+            rtt_ms_avg: Some(f64::NAN),
+            ..seen_node("a", 2)
+        };
+        assert_eq!(nan, nan.clone());
+        let set: BTreeSet<Node> = [nan.clone(), nan.clone(), seen_node("a", 2)].into();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn group_runs_by_target_and_date() {
+        let run = |target: &str, timestamp: &str| TraceRun {
+            target: target.to_string(),
+            timestamp_utc: timestamp.to_string(),
+            hops: Vec::new(),
+            label: None,
+            source_ip: None,
+            protocol: TraceProtocol::Udp,
+        };
+        let trace = TraceFile {
+            version: TraceFile::VERSION,
+            runs: vec![
+                run("B", "2026-02-01T23:30:00Z"),
+                run("A", "2026-02-01T08:00:00Z"),
+                run("A", "2026-02-02T01:30:00+03:00"),
+                run("B", "2026-02-02T00:10:00Z"),
+                run("A", "yesterday"),
+            ],
+        };
+
+        let by_target = trace.group_by_target();
+        assert_eq!(by_target.len(), 2);
+        assert_eq!(
+            by_target["A"],
+            vec![&trace.runs[1], &trace.runs[2], &trace.runs[4]]
+        );
+        assert_eq!(by_target["B"], vec![&trace.runs[0], &trace.runs[3]]);
+        assert_eq!(trace.unique_targets(), vec!["A", "B"]);
+
+        let by_date = trace.group_by_date();
+        assert_eq!(by_date.len(), 2);
+        assert_eq!(
+            by_date["2026-02-01"],
+            vec![&trace.runs[0], &trace.runs[1], &trace.runs[2]]
+        );
+        assert_eq!(by_date["2026-02-02"], vec![&trace.runs[3]]);
+
+        let empty = TraceFile {
+            version: TraceFile::VERSION,
+            runs: Vec::new(),
+        };
+        assert!(empty.group_by_target().is_empty());
+        assert!(empty.unique_targets().is_empty());
+        assert!(empty.group_by_date().is_empty());
+    }
+
+    #[test]
+    fn time_range_spans_earliest_to_latest_run() {
+        let run = |timestamp: &str| TraceRun {
+            target: "1.1.1.1".to_string(),
+            timestamp_utc: timestamp.to_string(),
+            hops: Vec::new(),
+            label: None,
+            source_ip: None,
+            protocol: TraceProtocol::Udp,
+        };
+        let mut trace = TraceFile {
+            version: TraceFile::VERSION,
+            runs: Vec::new(),
+        };
+        assert_eq!(trace.time_range(), None);
+        assert_eq!(trace.duration(), None);
+
+        trace.runs = vec![
+            run("2026-02-01T13:00:00Z"),
+            run("not a time"),
+            run("2026-02-01T12:00:00Z"),
+        ];
+        let (earliest, latest) = trace.time_range().unwrap();
+        assert_eq!(earliest.to_rfc3339(), "2026-02-01T12:00:00+00:00");
+        assert_eq!(latest.to_rfc3339(), "2026-02-01T13:00:00+00:00");
+        assert_eq!(trace.duration(), Some(chrono::Duration::hours(1)));
+    }
+
+    #[test]
+    fn validate_reports_each_semantic_error() {
+        let hop = |ttl: u32, rtt_ms: Vec<Option<f64>>| Hop {
+            ttl,
+            ip: Some("10.0.0.1".to_string()),
+            rtt_ms,
+            mpls_labels: Vec::new(),
+        };
+        let run = |target: &str, timestamp: &str, hops: Vec<Hop>| TraceRun {
+            target: target.to_string(),
+            timestamp_utc: timestamp.to_string(),
+            hops,
+            label: None,
+            source_ip: None,
+            protocol: TraceProtocol::Udp,
+        };
+        let clean = run(
+            "1.1.1.1",
+            "2026-02-01T12:00:00Z",
+            vec![hop(1, vec![Some(1.0), None]), hop(3, vec![Some(2.0)])],
+        );
+        let mut trace = TraceFile {
+            version: TraceFile::VERSION,
+            runs: vec![clean.clone()],
+        };
+        assert!(trace.validate().is_empty());
+
+        trace.runs = vec![
+            clean,
+            run(
+                "1.1.1.1",
+                "2026-02-01T12:00:00Z",
+                vec![hop(0, vec![Some(1.0)])],
+            ),
+            run(
+                "1.1.1.1",
+                "2026-02-01T12:00:00Z",
+                vec![hop(1, vec![]), hop(2, vec![]), hop(2, vec![])],
+            ),
+            run(
+                "1.1.1.1",
+                "2026-02-01T12:00:00Z",
+                vec![hop(1, vec![Some(1.0), None, Some(-0.5)])],
+            ),
+            run("  ", "2026-02-01T12:00:00Z", Vec::new()),
+            run("1.1.1.1", "2999-01-01T00:00:00Z", Vec::new()),
+            run("1.1.1.1", "not a time", Vec::new()),
+        ];
+        assert_eq!(
+            trace.validate(),
+            vec![
+                ValidationError::ZeroTtl {
+                    run_index: 1,
+                    hop_index: 0
+                },
+                ValidationError::NonMonotonicTtl {
+                    run_index: 2,
+                    hop1_idx: 1,
+                    hop2_idx: 2
+                },
+                ValidationError::NegativeRtt {
+                    run_index: 3,
+                    hop_index: 0,
+                    probe_index: 2
+                },
+                ValidationError::EmptyTarget { run_index: 4 },
+                ValidationError::FutureTimestamp { run_index: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_nodes_drops_orphan_edges() {
+        let graph = GraphFile {
+            version: 1,
+            nodes: vec![node("a"), node("b"), node("c")],
+            edges: vec![edge("a", "b"), edge("b", "c")],
+        };
+
+        let filtered = graph.filter_nodes(&["a", "b"]);
+        assert_eq!(filtered.nodes, vec![node("a"), node("b")]);
+        assert_eq!(filtered.edges, vec![edge("a", "b")]);
+    }
+
+    fn hash_of<T: Hash>(value: &T) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn node_set_deduplicates_identical_nodes() {
+        let seen_node = |id: &str, seen: u32| Node { seen, ..node(id) };
+        assert_eq!(hash_of(&seen_node("a", 1)), hash_of(&seen_node("a", 1)));
+
+        let set: std::collections::HashSet<Node> = [
+            seen_node("a", 1),
+            seen_node("a", 1),
+            seen_node("a", 2),
+            seen_node("b", 1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn float_fields_hash_consistently_with_eq() {
+        let hop = |rtt: f64| Hop {
+            ttl: 1,
+            ip: Some("10.0.0.1".to_string()),
+            rtt_ms: vec![Some(rtt), None],
+            mpls_labels: Vec::new(),
+        };
+        assert_eq!(hash_of(&hop(1.5)), hash_of(&hop(1.5)));
+        assert_ne!(hash_of(&hop(1.5)), hash_of(&hop(2.5)));
+        // Hops compare floats by total order: -0.0 is its own value, NaN
+        // equals itself.
+        assert_ne!(hop(0.0), hop(-0.0));
+        assert_eq!(hop(f64::NAN), hop(f64::NAN));
+        assert_eq!(hash_of(&hop(f64::NAN)), hash_of(&hop(f64::NAN)));
+
+        let rtt_edge = |rtt: f64| Edge {
+            seen: 1,
+            rtt_delta_ms_avg: rtt,
+            ..edge("a", "b")
+        };
+        let set: std::collections::HashSet<Edge> = [rtt_edge(1.0), rtt_edge(1.0), rtt_edge(2.0)]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 2);
+        assert_eq!(rtt_edge(0.0), rtt_edge(-0.0));
+        assert_eq!(hash_of(&rtt_edge(0.0)), hash_of(&rtt_edge(-0.0)));
+    }
+
+    #[test]
+    fn subgraph_around_limits_depth_both_ways() {
+        let graph = GraphFile {
+            version: 1,
+            nodes: vec![node("a"), node("b"), node("c"), node("d")],
+            edges: vec![edge("a", "b"), edge("b", "c"), edge("c", "d")],
+        };
+
+        let only_center = graph.subgraph_around("b", 0);
+        assert_eq!(only_center.nodes, vec![node("b")]);
+        assert!(only_center.edges.is_empty());
+
+        let neighbors = graph.subgraph_around("b", 1);
+        assert_eq!(neighbors.nodes, vec![node("a"), node("b"), node("c")]);
+        assert_eq!(neighbors.edges, vec![edge("a", "b"), edge("b", "c")]);
+
+        assert_eq!(graph.subgraph_around("a", 10), graph);
+        assert!(graph.subgraph_around("zz", 1).nodes.is_empty());
+    }
+
+    #[test]
+    fn prune_cold_nodes_drops_rare_nodes_and_edges() {
+        let seen_node = |id: &str, seen: u32| Node { seen, ..node(id) };
+        let seen_edge = |from: &str, to: &str, seen: u32| Edge {
+            seen,
+            rtt_delta_ms_avg: 1.0,
+            ..edge(from, to)
+        };
+        let graph = GraphFile {
+            version: 1,
+            nodes: vec![
+                seen_node("a", 5),
+                seen_node("b", 5),
+                seen_node("c", 1),
+                seen_node("d", 4),
+            ],
+            edges: vec![
+                seen_edge("a", "b", 5),
+                seen_edge("b", "c", 1),
+                seen_edge("c", "d", 1),
+                seen_edge("b", "d", 2),
+            ],
+        };
+
+        let pruned = graph.prune_cold_nodes(3);
+        assert_eq!(pruned.nodes.len(), 3);
+        assert_eq!(pruned.edges, vec![seen_edge("a", "b", 5)]);
+        assert_eq!(graph.prune_cold_nodes(0), graph);
+    }
+
+    #[test]
+    fn prune_lossy_edges_uses_endpoint_loss() {
+        let probed_node = |id: &str, sent_probes: u32, loss_probes: u32| Node {
+            seen: 2,
+            loss_probes,
+            sent_probes,
+            ..node(id)
+        };
+        let seen_edge = |from: &str, to: &str, seen: u32| Edge {
+            seen,
+            rtt_delta_ms_avg: 1.0,
+            ..edge(from, to)
+        };
+        let graph = GraphFile {
+            version: 1,
+            // 4 probes per sighting, not the trace default of 3.
+            nodes: vec![
+                probed_node("a", 8, 0),
+                probed_node("b", 8, 1),
+                probed_node("c", 8, 6),
+            ],
+            edges: vec![
+                seen_edge("a", "b", 2),
+                seen_edge("b", "c", 2),
+                seen_edge("c", "a", 0),
+            ],
+        };
+
+        let pruned = graph.prune_lossy_edges(0.25);
+        assert_eq!(pruned.nodes.len(), 3);
+        // a-b loses 1/16, b-c 7/16; c-a is never pruned since it was not seen.
+        assert_eq!(
+            pruned.edges,
+            vec![seen_edge("a", "b", 2), seen_edge("c", "a", 0)]
+        );
+        assert_eq!(graph.prune_lossy_edges(1.0), graph);
+    }
+
+    #[test]
+    fn adjacency_matrix_is_square_with_zero_for_missing_edges() {
+        let weighted_edge = |from: &str, to: &str, seen: u32, rtt: f64| Edge {
+            seen,
+            rtt_delta_ms_avg: rtt,
+            ..edge(from, to)
+        };
+        let graph = GraphFile {
+            version: 1,
+            nodes: vec![node("a"), node("b"), node("c")],
+            edges: vec![
+                weighted_edge("a", "b", 4, 2.5),
+                weighted_edge("b", "c", 1, 7.0),
+                weighted_edge("c", "missing", 1, 1.0),
+            ],
+        };
+
+        let (ids, matrix) = graph.to_adjacency_matrix();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(matrix.len(), 3);
+        assert!(matrix.iter().all(|row| row.len() == 3));
+        assert_eq!(matrix[0][1], 2.5);
+        assert_eq!(matrix[1][2], 7.0);
+        assert_eq!(matrix[1][0], 0.0);
+        assert_eq!(matrix[2].iter().sum::<f64>(), 0.0);
+
+        let (_, weighted) = graph.to_weighted_adjacency_matrix();
+        assert_eq!(weighted[0][1], 4.0);
+        assert_eq!(weighted[1][2], 1.0);
+        assert_eq!(weighted[0][2], 0.0);
+    }
+
+    #[test]
+    fn layout_diff_reports_moved_added_and_removed_nodes() {
+        let scene = |nodes: &[(&str, [f32; 3])]| SceneFile {
+            version: 1,
+            nodes: nodes
+                .iter()
+                .map(|&(id, position)| SceneNode {
+                    id: id.to_string(),
+                    position,
+                    seen: 1,
+                    loss_probes: 0,
+                    sent_probes: 0,
+                    pagerank: None,
+                    rtt_ms_avg: None,
+                    asn: None,
+                    label: None,
+                    velocity: [0.0; 3],
+                })
+                .collect(),
+            edges: Vec::new(),
+        };
+        let before = scene(&[
+            ("a", [0.0; 3]),
+            ("b", [1.0, 0.0, 0.0]),
+            ("gone", [2.0, 0.0, 0.0]),
+        ]);
+        let after = scene(&[
+            ("new", [5.0, 0.0, 0.0]),
+            ("b", [1.0, 0.4, 0.0]),
+            ("a", [0.0, 0.0, 3.0]),
+        ]);
+
+        let diff = before.layout_diff(&after, 0.5);
+        assert_eq!(
+            diff.moved,
+            vec![("a".to_string(), 3.0, [0.0; 3], [0.0, 0.0, 3.0])]
+        );
+        assert_eq!(diff.added, vec!["new".to_string()]);
+        assert_eq!(diff.removed, vec!["gone".to_string()]);
+        assert!(before.layout_diff(&before, 0.5).is_empty());
+    }
+
+    #[test]
+    fn v1_trace_file_migrates_to_current() {
+        let json = r#"{"version": 1, "runs": [{"target": "1.1.1.1", "timestamp_utc": "2026-02-01T12:00:00Z", "hops": []}]}"#;
+        let mut trace: TraceFile = serde_json::from_str(json).unwrap();
+        trace.migrate_to_current();
+
+        assert_eq!(trace.version, 2);
+        assert_eq!(trace.version, TraceFile::VERSION);
+        assert_eq!(trace.runs[0].protocol, TraceProtocol::Icmp);
+    }
+
+    #[test]
+    fn migration_leaves_current_and_newer_files_alone() {
+        let json = r#"{"version": 2, "runs": [{"target": "1.1.1.1", "timestamp_utc": "2026-02-01T12:00:00Z", "hops": [], "protocol": "icmp"}]}"#;
+        let mut current: TraceFile = serde_json::from_str(json).unwrap();
+        let before = current.clone();
+        current.migrate_to_current();
+        assert_eq!(current, before);
+        assert_eq!(current.runs[0].protocol, TraceProtocol::Icmp);
+
+        let mut newer = TraceFile {
+            version: 9,
+            runs: Vec::new(),
+        };
+        newer.migrate_to_current();
+        assert_eq!(newer.version, 9);
+    }
 }
@@ -0,0 +1,14 @@
+//! YAML counterparts of the JSON helpers, enabled by the `yaml` feature.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub use serde_yaml::Error;
+
+pub fn from_str<T: DeserializeOwned>(text: &str) -> Result<T, Error> {
+    serde_yaml::from_str(text)
+}
+
+pub fn to_string<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_yaml::to_string(value)
+}
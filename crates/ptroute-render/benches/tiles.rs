@@ -0,0 +1,133 @@
+//! Row-based vs tile-based rendering of a 1920x1080 frame, and scanline vs
+//! Morton vs Hilbert tile order. Each order is also replayed against a small
+//! LRU cache in which every tile touches its 3x3 neighbourhood of tiles, a
+//! stand-in for the BVH nodes and pixels shared between adjacent tiles.
+//!
+//! Run with `cargo bench -p ptroute-render --bench tiles`.
+
+use ptroute_model::{SceneEdge, SceneFile, SceneNode};
+use ptroute_render::{render_scene, RenderSettings, TileOrder};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 3;
+const LRU_CAPACITY: usize = 24;
+
+fn scene() -> SceneFile {
+    let nodes: Vec<SceneNode> = (0..48)
+        .map(|i| SceneNode {
+            id: format!("10.0.{}.{}", i / 8, i % 8),
+            position: [
+                (i / 8) as f32 * 1.5,
+                (i % 8) as f32,
+                ((i * 7) % 5) as f32 * 0.4,
+            ],
+            seen: 1 + i % 4,
+            loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: None,
+            velocity: [0.0; 3],
+        })
+        .collect();
+    let edges = nodes
+        .windows(2)
+        .map(|pair| SceneEdge {
+            from: pair[0].id.clone(),
+            to: pair[1].id.clone(),
+            seen: 2,
+            rtt_delta_ms_avg: 4.0,
+            depth: 0,
+        })
+        .collect();
+    SceneFile {
+        version: 1,
+        nodes,
+        edges,
+    }
+}
+
+fn time(scene: &SceneFile, settings: &RenderSettings) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        render_scene(scene, settings);
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+/// Misses when each tile of `order` touches its 3x3 neighbourhood through
+/// an LRU cache of [`LRU_CAPACITY`] tiles.
+fn lru_misses(order: &[(u32, u32)]) -> usize {
+    let mut cache: VecDeque<(u32, u32)> = VecDeque::with_capacity(LRU_CAPACITY);
+    let mut misses = 0;
+    for &(tx, ty) in order {
+        for ny in ty.saturating_sub(1)..=ty + 1 {
+            for nx in tx.saturating_sub(1)..=tx + 1 {
+                match cache.iter().position(|&key| key == (nx, ny)) {
+                    Some(index) => {
+                        cache.remove(index);
+                    }
+                    None => {
+                        misses += 1;
+                        if cache.len() == LRU_CAPACITY {
+                            cache.pop_front();
+                        }
+                    }
+                }
+                cache.push_back((nx, ny));
+            }
+        }
+    }
+    misses
+}
+
+fn main() {
+    let scene = scene();
+    let base = RenderSettings {
+        width: 1920,
+        height: 1080,
+        spp: 4,
+        bounces: 3,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+
+    for tile_size in [0, 16, 32, 64, 128] {
+        let settings = RenderSettings {
+            tile_size,
+            ..base.clone()
+        };
+        let label = if tile_size == 0 {
+            "rows".to_string()
+        } else {
+            format!("tiles {tile_size}x{tile_size}")
+        };
+        println!(
+            "{label:>16}: {:?} (best of {RUNS})",
+            time(&scene, &settings)
+        );
+    }
+
+    let tile_size = 32;
+    let (cols, rows) = (
+        base.width.div_ceil(tile_size),
+        base.height.div_ceil(tile_size),
+    );
+    for tile_order in [TileOrder::Scanline, TileOrder::Morton, TileOrder::Hilbert] {
+        let settings = RenderSettings {
+            tile_size,
+            tile_order,
+            ..base.clone()
+        };
+        println!(
+            "{:>16}: {:?} (best of {RUNS}), {} LRU misses",
+            format!("{tile_order:?}"),
+            time(&scene, &settings),
+            lru_misses(&tile_order.tiles(cols, rows))
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Tokio wrappers around the blocking render and PNG output, enabled by the
+//! `tokio` feature.
+
+use crate::image_out::temp_path;
+use crate::integrator::{render_scene, RenderSettings};
+use image::{ImageError, ImageFormat, RgbImage};
+use ptroute_model::SceneFile;
+use std::io::Cursor;
+use std::path::Path;
+use tokio::task::JoinHandle;
+
+/// Runs [`render_scene`] on Tokio's blocking pool so the render does not
+/// stall the async runtime. Must be called from within a runtime.
+pub fn render_scene_async(scene: SceneFile, settings: RenderSettings) -> JoinHandle<RgbImage> {
+    tokio::task::spawn_blocking(move || render_scene(&scene, &settings))
+}
+
+/// Async [`crate::write_png`]: encodes in memory, then writes a temp file
+/// next to `path` with `tokio::fs` and renames it into place.
+pub async fn write_png_async(path: &Path, image: &RgbImage) -> Result<(), ImageError> {
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+
+    let tmp_path = temp_path(path);
+    tokio::fs::write(&tmp_path, &data).await?;
+    if let Err(err) = tokio::fs::rename(&tmp_path, path).await {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(ImageError::IoError(err));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ptroute_model::SceneNode;
+
+    #[tokio::test]
+    async fn renders_and_writes_small_scene() {
+        let scene = SceneFile {
+            version: 1,
+            nodes: vec![SceneNode {
+                id: "10.0.0.1".to_string(),
+                position: [0.0, 0.0, 0.0],
+                seen: 1,
+                loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            }],
+            edges: Vec::new(),
+        };
+        let settings = RenderSettings {
+            width: 16,
+            height: 12,
+            spp: 1,
+            bounces: 1,
+            progress_every: 0,
+            ..RenderSettings::default()
+        };
+
+        let image = render_scene_async(scene.clone(), settings.clone())
+            .await
+            .unwrap();
+        assert_eq!(image, render_scene(&scene, &settings));
+
+        let path = std::env::temp_dir().join(format!("ptroute-async-{}.png", std::process::id()));
+        write_png_async(&path, &image).await.unwrap();
+        let written = image::open(&path).unwrap().to_rgb8();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(written, image);
+    }
+}
@@ -1,7 +1,9 @@
-use crate::geometry::{Hit, Sphere};
+use crate::geometry::{Hit, Hittable, Sphere, Triangle};
 use crate::math::{Ray, Vec3};
+use std::fmt;
 
-const LEAF_SIZE: usize = 4;
+/// Most primitives a leaf holds before the builder splits it.
+pub const LEAF_SIZE: usize = 4;
 
 #[derive(Debug, Clone, Copy)]
 struct Aabb {
@@ -17,12 +19,9 @@ impl Aabb {
         }
     }
 
-    fn from_sphere(sphere: &Sphere) -> Self {
-        let r = Vec3::new(sphere.radius, sphere.radius, sphere.radius);
-        Self {
-            min: sphere.center - r,
-            max: sphere.center + r,
-        }
+    fn of(primitive: &impl Hittable) -> Self {
+        let (min, max) = primitive.bounds();
+        Self { min, max }
     }
 
     fn union(self, other: Self) -> Self {
@@ -36,6 +35,11 @@ impl Aabb {
         self.max - self.min
     }
 
+    fn surface_area(&self) -> f32 {
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
     fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
         if !hit_axis(self.min.x, self.max.x, ray.origin.x, ray.direction.x, &mut t_min, &mut t_max)
         {
@@ -71,6 +75,41 @@ fn hit_axis(min: f32, max: f32, origin: f32, direction: f32, t_min: &mut f32, t_
     *t_max > *t_min
 }
 
+/// The spheres followed by the triangles, addressed by one index.
+#[derive(Clone, Copy)]
+struct Primitives<'a> {
+    spheres: &'a [Sphere],
+    triangles: &'a [Triangle],
+}
+
+impl Primitives<'_> {
+    fn bbox(&self, idx: usize) -> Aabb {
+        match self.spheres.get(idx) {
+            Some(sphere) => Aabb::of(sphere),
+            None => Aabb::of(&self.triangles[idx - self.spheres.len()]),
+        }
+    }
+
+    fn centroid_axis(&self, idx: usize, axis: u8) -> f32 {
+        let centroid = match self.spheres.get(idx) {
+            Some(sphere) => sphere.centroid(),
+            None => self.triangles[idx - self.spheres.len()].centroid(),
+        };
+        match axis {
+            0 => centroid.x,
+            1 => centroid.y,
+            _ => centroid.z,
+        }
+    }
+
+    fn hit(&self, idx: usize, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        match self.spheres.get(idx) {
+            Some(sphere) => sphere.hit(ray, t_min, t_max),
+            None => self.triangles[idx - self.spheres.len()].hit(ray, t_min, t_max),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct BvhNode {
     bbox: Aabb,
@@ -81,10 +120,10 @@ struct BvhNode {
 }
 
 impl BvhNode {
-    fn build(indices: &mut [usize], spheres: &[Sphere], offset: usize) -> Self {
+    fn build(indices: &mut [usize], primitives: Primitives, offset: usize) -> Self {
         let mut bbox = Aabb::empty();
         for &idx in indices.iter() {
-            bbox = bbox.union(Aabb::from_sphere(&spheres[idx]));
+            bbox = bbox.union(primitives.bbox(idx));
         }
 
         if indices.len() <= LEAF_SIZE {
@@ -107,15 +146,15 @@ impl BvhNode {
         };
 
         indices.sort_by(|&a, &b| {
-            let ca = sphere_center_axis(&spheres[a], axis);
-            let cb = sphere_center_axis(&spheres[b], axis);
+            let ca = primitives.centroid_axis(a, axis);
+            let cb = primitives.centroid_axis(b, axis);
             ca.partial_cmp(&cb).unwrap_or(std::cmp::Ordering::Equal)
         });
 
         let mid = indices.len() / 2;
         let (left_indices, right_indices) = indices.split_at_mut(mid);
-        let left = Box::new(BvhNode::build(left_indices, spheres, offset));
-        let right = Box::new(BvhNode::build(right_indices, spheres, offset + mid));
+        let left = Box::new(BvhNode::build(left_indices, primitives, offset));
+        let right = Box::new(BvhNode::build(right_indices, primitives, offset + mid));
 
         let bbox = left.bbox.union(right.bbox);
 
@@ -128,7 +167,7 @@ impl BvhNode {
         }
     }
 
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, spheres: &[Sphere], indices: &[usize]) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, primitives: Primitives, indices: &[usize]) -> Option<Hit> {
         if !self.bbox.hit(ray, t_min, t_max) {
             return None;
         }
@@ -137,7 +176,7 @@ impl BvhNode {
             let mut closest = None;
             let mut closest_t = t_max;
             for &idx in &indices[self.start..self.end] {
-                if let Some(hit) = spheres[idx].hit(ray, t_min, closest_t) {
+                if let Some(hit) = primitives.hit(idx, ray, t_min, closest_t) {
                     closest_t = hit.t;
                     closest = Some(hit);
                 }
@@ -150,39 +189,171 @@ impl BvhNode {
         let mut closest_t = t_max;
 
         if let Some(left) = &self.left {
-            if let Some(hit) = left.hit(ray, t_min, closest_t, spheres, indices) {
+            if let Some(hit) = left.hit(ray, t_min, closest_t, primitives, indices) {
                 closest_t = hit.t;
                 hit_left = Some(hit);
             }
         }
 
         if let Some(right) = &self.right {
-            if let Some(hit) = right.hit(ray, t_min, closest_t, spheres, indices) {
+            if let Some(hit) = right.hit(ray, t_min, closest_t, primitives, indices) {
                 hit_right = Some(hit);
             }
         }
 
         hit_right.or(hit_left)
     }
+
+    fn refit(&mut self, primitives: Primitives, indices: &[usize]) {
+        self.bbox = match (&mut self.left, &mut self.right) {
+            (Some(left), Some(right)) => {
+                left.refit(primitives, indices);
+                right.refit(primitives, indices);
+                left.bbox.union(right.bbox)
+            }
+            _ => indices[self.start..self.end]
+                .iter()
+                .fold(Aabb::empty(), |bbox, &idx| bbox.union(primitives.bbox(idx))),
+        };
+    }
+}
+
+/// Shape of a built [`Bvh`], from [`Bvh::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BvhStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Edges from the root to the deepest leaf; a lone leaf has depth 0.
+    pub max_depth: usize,
+    pub avg_leaf_size: f32,
+    pub total_primitives: usize,
 }
 
-fn sphere_center_axis(sphere: &Sphere, axis: u8) -> f32 {
-    match axis {
-        0 => sphere.center.x,
-        1 => sphere.center.y,
-        _ => sphere.center.z,
+impl BvhStats {
+    /// 1.0 for a shallow tree, falling toward 0.0 as the depth approaches
+    /// the primitive count (a degenerate, list-like tree).
+    pub fn quality_score(&self) -> f32 {
+        1.0 - (self.max_depth as f32 / self.total_primitives.max(1) as f32).min(1.0)
+    }
+
+    fn visit(&mut self, node: &BvhNode, depth: usize) {
+        self.node_count += 1;
+        self.max_depth = self.max_depth.max(depth);
+        match (&node.left, &node.right) {
+            (Some(left), Some(right)) => {
+                self.visit(left, depth + 1);
+                self.visit(right, depth + 1);
+            }
+            _ => {
+                self.leaf_count += 1;
+                self.total_primitives += node.end - node.start;
+            }
+        }
+    }
+}
+
+/// Surface area heuristic view of a built [`Bvh`], from
+/// [`Bvh::quality_report`]. Prints as one line for `render --bvh-report`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BvhQualityReport {
+    /// Sum over leaves of `area(leaf) / area(root) * primitives`: roughly the
+    /// sphere tests a random ray through the scene pays. Lower is better.
+    pub sah_cost: f32,
+    /// Mean root-to-leaf edge count over all leaves.
+    pub average_depth: f32,
+    pub empty_leaves: usize,
+    pub leaf_count: usize,
+    pub total_primitives: usize,
+    pub average_leaf_size: f32,
+}
+
+impl BvhQualityReport {
+    fn visit(&mut self, node: &BvhNode, depth: usize, root_area: f32) {
+        match (&node.left, &node.right) {
+            (Some(left), Some(right)) => {
+                self.visit(left, depth + 1, root_area);
+                self.visit(right, depth + 1, root_area);
+            }
+            _ => {
+                let count = node.end - node.start;
+                self.leaf_count += 1;
+                self.total_primitives += count;
+                self.average_depth += depth as f32;
+                if count == 0 {
+                    self.empty_leaves += 1;
+                }
+                let area_ratio = if root_area > 0.0 {
+                    node.bbox.surface_area() / root_area
+                } else {
+                    1.0
+                };
+                self.sah_cost += area_ratio * count as f32;
+            }
+        }
+    }
+}
+
+impl fmt::Display for BvhQualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bvh sah cost {:.2} ({:.2} per sphere), {} leaves (avg {:.2} spheres, {} empty), avg depth {:.2}, {} spheres",
+            self.sah_cost,
+            self.sah_cost / self.total_primitives.max(1) as f32,
+            self.leaf_count,
+            self.average_leaf_size,
+            self.empty_leaves,
+            self.average_depth,
+            self.total_primitives
+        )
+    }
+}
+
+/// One [`Bvh`] node in [`Bvh::flatten`] order. Leaves (`count > 0`) cover
+/// spheres `first..first + count`; an inner node's left child follows it and
+/// its right child is at `first`.
+#[cfg(feature = "gpu")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FlatNode {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub first: u32,
+    pub count: u32,
+}
+
+#[cfg(feature = "gpu")]
+impl BvhNode {
+    fn flatten(&self, nodes: &mut Vec<FlatNode>) {
+        let at = nodes.len();
+        nodes.push(FlatNode {
+            min: self.bbox.min,
+            max: self.bbox.max,
+            first: self.start as u32,
+            count: (self.end - self.start) as u32,
+        });
+        if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            left.flatten(nodes);
+            nodes[at].first = nodes.len() as u32;
+            right.flatten(nodes);
+        }
     }
 }
 
 pub struct Bvh {
     spheres: Vec<Sphere>,
+    triangles: Vec<Triangle>,
     indices: Vec<usize>,
     root: BvhNode,
 }
 
 impl Bvh {
     pub fn new(spheres: Vec<Sphere>) -> Self {
-        let mut indices: Vec<usize> = (0..spheres.len()).collect();
+        Self::with_triangles(spheres, Vec::new())
+    }
+
+    /// A tree over both spheres and triangles.
+    pub fn with_triangles(spheres: Vec<Sphere>, triangles: Vec<Triangle>) -> Self {
+        let mut indices: Vec<usize> = (0..spheres.len() + triangles.len()).collect();
         let root = if indices.is_empty() {
             BvhNode {
                 bbox: Aabb::empty(),
@@ -192,24 +363,107 @@ impl Bvh {
                 end: 0,
             }
         } else {
-            BvhNode::build(&mut indices, &spheres, 0)
+            let primitives = Primitives {
+                spheres: &spheres,
+                triangles: &triangles,
+            };
+            BvhNode::build(&mut indices, primitives, 0)
         };
 
         Self {
             spheres,
+            triangles,
             indices,
             root,
         }
     }
 
+    fn primitives(&self) -> Primitives<'_> {
+        Primitives {
+            spheres: &self.spheres,
+            triangles: &self.triangles,
+        }
+    }
+
     pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
         if self.indices.is_empty() {
             return None;
         }
-        self.root.hit(ray, t_min, t_max, &self.spheres, &self.indices)
+        self.root.hit(ray, t_min, t_max, self.primitives(), &self.indices)
     }
 
     pub fn spheres(&self) -> &[Sphere] {
         &self.spheres
     }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    /// Mutable access to the spheres. Call [`Bvh::refit`] after moving or
+    /// resizing any of them.
+    pub fn spheres_mut(&mut self) -> &mut [Sphere] {
+        &mut self.spheres
+    }
+
+    /// Recomputes every node's bounds bottom-up from the current primitives,
+    /// keeping the tree shape. Cheaper than [`Bvh::new`], but the tree gets
+    /// less efficient the further spheres drift from where they were built.
+    pub fn refit(&mut self) {
+        if self.indices.is_empty() {
+            return;
+        }
+        let primitives = Primitives {
+            spheres: &self.spheres,
+            triangles: &self.triangles,
+        };
+        self.root.refit(primitives, &self.indices);
+    }
+
+    /// Node counts and depth of the tree; all zeros when it is empty.
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats::default();
+        if self.indices.is_empty() {
+            return stats;
+        }
+        stats.visit(&self.root, 0);
+        stats.avg_leaf_size = stats.total_primitives as f32 / stats.leaf_count as f32;
+        stats
+    }
+
+    /// SAH cost, average leaf depth and empty leaves of the tree; all zeros
+    /// when it is empty.
+    pub fn quality_report(&self) -> BvhQualityReport {
+        let mut report = BvhQualityReport::default();
+        if self.indices.is_empty() {
+            return report;
+        }
+        report.visit(&self.root, 0, self.root.bbox.surface_area());
+        report.average_depth /= report.leaf_count as f32;
+        report.average_leaf_size = report.total_primitives as f32 / report.leaf_count as f32;
+        report
+    }
+
+    /// Depth-first copy of the tree for the GPU tracer, with the spheres
+    /// reordered so every leaf covers a contiguous range of them. The shader
+    /// only knows spheres, so the tree must not hold triangles.
+    #[cfg(feature = "gpu")]
+    pub(crate) fn flatten(&self) -> (Vec<FlatNode>, Vec<Sphere>) {
+        debug_assert!(self.triangles.is_empty());
+        let mut nodes = Vec::new();
+        if !self.indices.is_empty() {
+            self.root.flatten(&mut nodes);
+        }
+        let spheres = self
+            .indices
+            .iter()
+            .map(|&idx| self.spheres[idx].clone())
+            .collect();
+        (nodes, spheres)
+    }
+
+    /// World-space bounds `(min, max)` of everything in the tree.
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        (self.root.bbox.min, self.root.bbox.max)
+    }
 }
@@ -1,13 +1,151 @@
-use crate::math::{Ray, Vec3};
+use crate::math::{Ray, Vec3, Vec4};
+use ptroute_model::SceneFile;
+
+/// Where [`Camera::new_from_scene`] puts the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum CameraStyle {
+    /// Looks at the scene center from above one corner of its bounds,
+    /// `distance_scale` times the bounds' diagonal away.
+    Perspective { vfov_deg: f32, distance_scale: f32 },
+    /// Orthographic, straight down the Y axis onto the XZ plane.
+    TopDown,
+    /// Orthographic, from 45 degrees above the XZ plane and 45 degrees
+    /// around the Y axis.
+    Isometric,
+}
+
+impl Default for CameraStyle {
+    fn default() -> Self {
+        Self::Perspective {
+            vfov_deg: 35.0,
+            distance_scale: 1.6,
+        }
+    }
+}
+
+impl CameraStyle {
+    /// The name accepted by `FromStr` (`perspective`, `topdown`, `isometric`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Perspective { .. } => "perspective",
+            Self::TopDown => "topdown",
+            Self::Isometric => "isometric",
+        }
+    }
+}
+
+impl std::str::FromStr for CameraStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "perspective" => Ok(Self::default()),
+            "topdown" => Ok(Self::TopDown),
+            "isometric" => Ok(Self::Isometric),
+            other => Err(format!(
+                "unknown camera style {other:?} (expected perspective, topdown or isometric)"
+            )),
+        }
+    }
+}
+
+/// Fraction of the orthographic view left around the scene bounds.
+const ORTHO_MARGIN: f32 = 1.2;
 
 pub struct Camera {
-    origin: Vec3,
-    lower_left: Vec3,
-    horizontal: Vec3,
-    vertical: Vec3,
+    pub(crate) origin: Vec3,
+    pub(crate) lower_left: Vec3,
+    pub(crate) horizontal: Vec3,
+    pub(crate) vertical: Vec3,
+    /// Shared ray direction of an orthographic camera; `None` for perspective.
+    pub(crate) ortho_direction: Option<Vec3>,
 }
 
 impl Camera {
+    /// Frames every node of `scene` in an image `aspect` (width / height)
+    /// wide, as `style` describes.
+    pub fn new_from_scene(scene: &SceneFile, aspect: f32, style: CameraStyle) -> Self {
+        let mut min = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for node in &scene.nodes {
+            let pos = Vec3::new(node.position[0], node.position[1], node.position[2]);
+            min = min.min(pos);
+            max = max.max(pos);
+        }
+        if scene.nodes.is_empty() {
+            min = Vec3::new(0.0, 0.0, 0.0);
+            max = min;
+        }
+
+        let center = (min + max) * 0.5;
+        let size = max - min;
+        let extent = size.length().max(1.0);
+
+        match style {
+            CameraStyle::Perspective {
+                vfov_deg,
+                distance_scale,
+            } => {
+                let distance = extent * distance_scale;
+                let look_from = center + Vec3::new(distance, distance * 0.6, distance);
+                let vup = Vec3::new(0.0, 1.0, 0.0);
+                Self::new(look_from, center, vup, vfov_deg, aspect)
+            }
+            CameraStyle::TopDown => {
+                let view_height = (size.z.max(size.x / aspect) * ORTHO_MARGIN).max(1.0);
+                Self::new_orthographic(
+                    center + Vec3::new(0.0, extent * 2.0, 0.0),
+                    center,
+                    Vec3::new(0.0, 0.0, -1.0),
+                    view_height,
+                    aspect,
+                )
+            }
+            CameraStyle::Isometric => {
+                // Any direction fits inside the bounds' diagonal.
+                let view_height = extent * ORTHO_MARGIN / aspect.min(1.0);
+                let angle = std::f32::consts::FRAC_PI_4;
+                let direction = Vec3::new(
+                    angle.cos() * angle.sin(),
+                    angle.sin(),
+                    angle.cos() * angle.cos(),
+                );
+                Self::new_orthographic(
+                    center + direction * (extent * 2.0),
+                    center,
+                    Vec3::new(0.0, 1.0, 0.0),
+                    view_height,
+                    aspect,
+                )
+            }
+        }
+    }
+
+    /// Parallel rays toward `look_at` over a `view_height` tall film centered
+    /// on `look_from`.
+    pub fn new_orthographic(
+        look_from: Vec3,
+        look_at: Vec3,
+        vup: Vec3,
+        view_height: f32,
+        aspect: f32,
+    ) -> Self {
+        let w = (look_from - look_at).normalized();
+        let u = vup.cross(w).normalized();
+        let v = w.cross(u);
+
+        let horizontal = u * (view_height * aspect);
+        let vertical = v * view_height;
+        Self {
+            origin: look_from,
+            lower_left: look_from - horizontal * 0.5 - vertical * 0.5,
+            horizontal,
+            vertical,
+            ortho_direction: Some(w * -1.0),
+        }
+    }
+
     pub fn new(look_from: Vec3, look_at: Vec3, vup: Vec3, vfov_deg: f32, aspect: f32) -> Self {
         let theta = vfov_deg.to_radians();
         let h = (theta * 0.5).tan();
@@ -28,14 +166,97 @@ impl Camera {
             lower_left,
             horizontal,
             vertical,
+            ortho_direction: None,
         }
     }
 
     pub fn ray(&self, u: f32, v: f32) -> Ray {
+        if let Some(direction) = self.ortho_direction {
+            return Ray {
+                origin: self.lower_left + self.horizontal * u + self.vertical * v,
+                direction,
+            };
+        }
         Ray {
             origin: self.origin,
             direction: (self.lower_left + self.horizontal * u + self.vertical * v - self.origin)
                 .normalized(),
         }
     }
+
+    /// Maps a world point to the `(u, v)` coordinates that [`Camera::ray`]
+    /// accepts, or `None` when the point is behind the camera.
+    pub fn project(&self, point: Vec3) -> Option<(f32, f32)> {
+        if let Some(direction) = self.ortho_direction {
+            if (point - self.origin).dot(direction) <= 0.0 {
+                return None;
+            }
+            let offset = point - self.lower_left;
+            let u = offset.dot(self.horizontal) / self.horizontal.dot(self.horizontal);
+            let v = offset.dot(self.vertical) / self.vertical.dot(self.vertical);
+            return Some((u, v));
+        }
+        let forward = self.lower_left + self.horizontal * 0.5 + self.vertical * 0.5 - self.origin;
+        let direction = point - self.origin;
+        let depth = direction.dot(forward) / forward.dot(forward);
+        if depth <= 0.0 {
+            return None;
+        }
+        let offset = self.origin + direction * (1.0 / depth) - self.lower_left;
+        let u = offset.dot(self.horizontal) / self.horizontal.dot(self.horizontal);
+        let v = offset.dot(self.vertical) / self.vertical.dot(self.vertical);
+        Some((u, v))
+    }
+
+    /// The left, right, bottom, top, near and far planes of the view, with
+    /// normals pointing inside. The near plane passes through the camera
+    /// (the film for orthographic cameras); rays are unbounded, so the far
+    /// plane sits at `f32::MAX`.
+    pub fn frustum_planes(&self) -> [Vec4; 6] {
+        let right = self.lower_left + self.horizontal;
+        let top = self.lower_left + self.vertical;
+        let (sides, forward) = match self.ortho_direction {
+            Some(direction) => {
+                let across = self.horizontal.normalized();
+                let up = self.vertical.normalized();
+                (
+                    [
+                        Vec4::plane(across, self.lower_left),
+                        Vec4::plane(across * -1.0, right),
+                        Vec4::plane(up, self.lower_left),
+                        Vec4::plane(up * -1.0, top),
+                    ],
+                    direction,
+                )
+            }
+            None => {
+                let forward = (self.lower_left + self.horizontal * 0.5 + self.vertical * 0.5
+                    - self.origin)
+                    .normalized();
+                // Planes through the eye and two film corners, flipped to
+                // face the view direction.
+                let side = |a: Vec3, b: Vec3| {
+                    let mut normal = (a - self.origin).cross(b - self.origin).normalized();
+                    if normal.dot(forward) < 0.0 {
+                        normal = normal * -1.0;
+                    }
+                    Vec4::plane(normal, self.origin)
+                };
+                let top_right = right + self.vertical;
+                (
+                    [
+                        side(self.lower_left, top),
+                        side(right, top_right),
+                        side(self.lower_left, right),
+                        side(top, top_right),
+                    ],
+                    forward,
+                )
+            }
+        };
+        let [left, right, bottom, top] = sides;
+        let near = Vec4::plane(forward, self.origin);
+        let far = Vec4::new(-forward.x, -forward.y, -forward.z, f32::MAX);
+        [left, right, bottom, top, near, far]
+    }
 }
@@ -0,0 +1,64 @@
+use crate::integrator::{render_scene, RenderSettings};
+use image::{Rgb, RgbImage};
+use ptroute_model::SceneFile;
+
+const DIFF_GAIN: u32 = 4;
+const SEPARATOR: Rgb<u8> = Rgb([96, 96, 96]);
+
+/// Renders both scenes with the same settings and lays them out as
+/// `before | after | diff`, producing a `3 * width` by `height` image.
+pub fn render_scene_comparison(
+    before: &SceneFile,
+    after: &SceneFile,
+    settings: &RenderSettings,
+) -> RgbImage {
+    let before = render_scene(before, settings);
+    let after = render_scene(after, settings);
+    compose_comparison(&before, &after)
+}
+
+/// Places two images side by side followed by their absolute per-channel
+/// difference (amplified 4x). Images of different sizes are padded with
+/// black to the larger width and height, so the padding shows up in the
+/// diff. The last column of the first two sections is drawn as a separator
+/// so the diff section stays untouched.
+pub fn compose_comparison(before: &RgbImage, after: &RgbImage) -> RgbImage {
+    let width = before.width().max(after.width());
+    let height = before.height().max(after.height());
+    let mut image = RgbImage::new(width * 3, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = padded_pixel(before, x, y);
+            let other = padded_pixel(after, x, y);
+            image.put_pixel(x, y, pixel);
+            image.put_pixel(x + width, y, other);
+            image.put_pixel(x + width * 2, y, diff_pixel(&pixel, &other));
+        }
+    }
+
+    if width > 0 {
+        for y in 0..height {
+            image.put_pixel(width - 1, y, SEPARATOR);
+            image.put_pixel(width * 2 - 1, y, SEPARATOR);
+        }
+    }
+
+    image
+}
+
+fn padded_pixel(image: &RgbImage, x: u32, y: u32) -> Rgb<u8> {
+    if x < image.width() && y < image.height() {
+        *image.get_pixel(x, y)
+    } else {
+        Rgb([0, 0, 0])
+    }
+}
+
+fn diff_pixel(a: &Rgb<u8>, b: &Rgb<u8>) -> Rgb<u8> {
+    let channel = |idx: usize| {
+        let delta = a[idx].abs_diff(b[idx]) as u32 * DIFF_GAIN;
+        delta.min(255) as u8
+    };
+    Rgb([channel(0), channel(1), channel(2)])
+}
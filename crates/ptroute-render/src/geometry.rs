@@ -5,19 +5,82 @@ pub struct Hit {
     pub t: f32,
     pub point: Vec3,
     pub normal: Vec3,
-    pub albedo: Vec3,
-    pub emission: Vec3,
+    pub material: Material,
+}
+
+/// How a sphere scatters light. Metal and dielectric scattering is only
+/// traced with [`crate::RenderSettings::high_quality_materials`]; otherwise
+/// every material bounces diffusely with its [`Material::albedo`]. Node
+/// spheres use the one picked by [`crate::NodeMaterial`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Material {
+    Lambertian {
+        albedo: Vec3,
+    },
+    /// `fuzz` in `[0, 1]` jitters reflections; 0 is a perfect mirror.
+    Metal {
+        albedo: Vec3,
+        fuzz: f32,
+    },
+    /// Clear glass-like refraction with index of refraction `ior`.
+    Dielectric {
+        ior: f32,
+    },
+    Emissive {
+        color: Vec3,
+        intensity: f32,
+    },
+}
+
+/// Diffuse reflectance of emissive spheres, so lit beads still pick up a
+/// little light from their neighbours.
+pub const EMISSIVE_ALBEDO: Vec3 = Vec3::new(0.08, 0.08, 0.08);
+
+impl Material {
+    pub fn albedo(&self) -> Vec3 {
+        match *self {
+            Material::Lambertian { albedo } | Material::Metal { albedo, .. } => albedo,
+            Material::Dielectric { .. } => Vec3::new(1.0, 1.0, 1.0),
+            Material::Emissive { .. } => EMISSIVE_ALBEDO,
+        }
+    }
+
+    pub fn emission(&self) -> Vec3 {
+        match *self {
+            Material::Emissive { color, intensity } => color * intensity,
+            _ => Vec3::zero(),
+        }
+    }
+}
+
+/// A primitive the [`crate::bvh::Bvh`] can hold.
+pub trait Hittable {
+    /// The closest intersection with `t` in `[t_min, t_max]`.
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit>;
+
+    /// Axis-aligned bounds `(min, max)`.
+    fn bounds(&self) -> (Vec3, Vec3);
+
+    /// The point the BVH builder sorts primitives by.
+    fn centroid(&self) -> Vec3;
 }
 
 #[derive(Debug, Clone)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
-    pub albedo: Vec3,
-    pub emission: Vec3,
+    pub material: Material,
 }
 
 impl Sphere {
+    /// Axis-aligned bounds `(min, max)`.
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        (self.center - r, self.center + r)
+    }
+
+    /// The closest intersection with `t` in `[t_min, t_max]`.
     pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
         let oc = ray.origin - self.center;
         let a = ray.direction.dot(ray.direction);
@@ -43,8 +106,102 @@ impl Sphere {
             t: root,
             point,
             normal,
-            albedo: self.albedo,
-            emission: self.emission,
+            material: self.material,
+        })
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        Sphere::hit(self, ray, t_min, t_max)
+    }
+
+    fn bounds(&self) -> (Vec3, Vec3) {
+        Sphere::bounds(self)
+    }
+
+    fn centroid(&self) -> Vec3 {
+        self.center
+    }
+}
+
+/// Half the thickness given to [`Triangle::bounds`], so triangles lying in
+/// an axis plane still have a box rays can enter.
+const TRIANGLE_BOUNDS_PAD: f32 = 1e-4;
+
+/// A flat, two-sided triangle: the hit normal always faces the ray.
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+    pub material: Material,
+}
+
+impl Triangle {
+    /// Axis-aligned bounds `(min, max)`, padded by [`TRIANGLE_BOUNDS_PAD`].
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        let pad = Vec3::new(
+            TRIANGLE_BOUNDS_PAD,
+            TRIANGLE_BOUNDS_PAD,
+            TRIANGLE_BOUNDS_PAD,
+        );
+        (
+            self.v0.min(self.v1).min(self.v2) - pad,
+            self.v0.max(self.v1).max(self.v2) + pad,
+        )
+    }
+
+    /// The closest intersection with `t` in `[t_min, t_max]`, by
+    /// Möller-Trumbore.
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let edge1 = self.v1 - self.v0;
+        let edge2 = self.v2 - self.v0;
+        let p = ray.direction.cross(edge2);
+        let det = edge1.dot(p);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let s = ray.origin - self.v0;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(edge1);
+        let v = ray.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(q) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let mut normal = edge1.cross(edge2).normalized();
+        if normal.dot(ray.direction) > 0.0 {
+            normal = normal * -1.0;
+        }
+        Some(Hit {
+            t,
+            point: ray.at(t),
+            normal,
+            material: self.material,
         })
     }
 }
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        Triangle::hit(self, ray, t_min, t_max)
+    }
+
+    fn bounds(&self) -> (Vec3, Vec3) {
+        Triangle::bounds(self)
+    }
+
+    fn centroid(&self) -> Vec3 {
+        (self.v0 + self.v1 + self.v2) / 3.0
+    }
+}
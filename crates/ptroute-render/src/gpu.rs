@@ -0,0 +1,374 @@
+use crate::camera::Camera;
+use crate::geometry::Sphere;
+use crate::integrator::{build_camera, finish_image, scene_bvh, RenderSettings};
+use crate::math::Vec3;
+use image::RgbImage;
+use ptroute_model::SceneFile;
+use std::fmt;
+use std::time::Instant;
+use wgpu::util::DeviceExt;
+
+const SHADER: &str = include_str!("gpu.wgsl");
+const WORKGROUP_SIZE: u32 = 8;
+// Samples per dispatch, so one submission never runs long enough to trip a
+// driver watchdog on large renders.
+const SAMPLES_PER_DISPATCH: u32 = 8;
+const SPHERE_BYTES: usize = 48;
+const NODE_BYTES: usize = 32;
+const PARAMS_BYTES: u64 = 80;
+// Entries in the shader's BVH traversal stack (`STACK_SIZE` in gpu.wgsl).
+const STACK_SIZE: usize = 64;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GpuError {
+    /// No adapter could be found; callers usually fall back to [`crate::render_scene`].
+    NoAdapter(String),
+    Device(String),
+    Readback(String),
+    /// The settings ask for something the shader does not implement, or the
+    /// scene is too large for it (BVH depth or buffer sizes).
+    Unsupported(String),
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuError::NoAdapter(err) => write!(f, "no compatible GPU adapter: {err}"),
+            GpuError::Device(err) => write!(f, "failed to open GPU device: {err}"),
+            GpuError::Readback(err) => write!(f, "failed to read back GPU render: {err}"),
+            GpuError::Unsupported(what) => write!(f, "not supported on the GPU: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+/// Renders `scene` like [`crate::render_scene`], but traces the samples in a
+/// wgpu compute shader. The result matches the CPU render statistically, not
+/// bit for bit, since the shader uses its own random number generator.
+///
+/// `settings.progress_fn` is called after each batch of samples.
+pub fn render_scene_gpu(
+    scene: &SceneFile,
+    settings: &RenderSettings,
+) -> Result<RgbImage, GpuError> {
+    pollster::block_on(render(scene, settings))
+}
+
+async fn render(scene: &SceneFile, settings: &RenderSettings) -> Result<RgbImage, GpuError> {
+    let camera = build_camera(scene, settings);
+    if camera.ortho_direction.is_some() {
+        return Err(GpuError::Unsupported(format!(
+            "{} camera",
+            settings.camera_style.name()
+        )));
+    }
+    if settings.ground_plane {
+        return Err(GpuError::Unsupported("ground plane".to_string()));
+    }
+    if settings.high_quality_materials {
+        return Err(GpuError::Unsupported("high quality materials".to_string()));
+    }
+    let bvh = scene_bvh(scene, settings);
+    check_bvh_depth(bvh.stats().max_depth)?;
+    let (nodes, spheres) = bvh.flatten();
+    let pixels = (settings.width * settings.height) as usize;
+    let spp = settings.spp.max(1);
+
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        })
+        .await
+        .map_err(|err| GpuError::NoAdapter(err.to_string()))?;
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            label: Some("ptroute-render"),
+            ..Default::default()
+        })
+        .await
+        .map_err(|err| GpuError::Device(err.to_string()))?;
+
+    // Storage buffers may not be empty, so an empty scene binds one unused
+    // element of each.
+    let sphere_bytes = sphere_bytes(&spheres);
+    let node_bytes = node_bytes(&nodes);
+    let accum_size = (pixels.max(1) * 16) as u64;
+    let limits = device.limits();
+    check_buffer_size("spheres", sphere_bytes.len() as u64, &limits)?;
+    check_buffer_size("BVH nodes", node_bytes.len() as u64, &limits)?;
+    check_buffer_size("accumulation buffer", accum_size, &limits)?;
+    let sphere_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("spheres"),
+        contents: &sphere_bytes,
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let node_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("bvh nodes"),
+        contents: &node_bytes,
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let accum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("accum"),
+        size: accum_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: accum_size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("params"),
+        size: PARAMS_BYTES,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("path tracer"),
+        source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("path tracer"),
+        layout: None,
+        module: &module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("path tracer"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: sphere_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: node_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: accum_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let start = Instant::now();
+    let mut done = 0;
+    while done < spp {
+        let samples = SAMPLES_PER_DISPATCH.min(spp - done);
+        let params = params_bytes(&camera, settings, nodes.len() as u32, done, samples);
+        queue.write_buffer(&params_buffer, 0, &params);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("path tracer"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("path tracer"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                settings.width.div_ceil(WORKGROUP_SIZE),
+                settings.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|err| GpuError::Device(err.to_string()))?;
+
+        done += samples;
+        if let Some(callback) = &settings.progress_fn {
+            callback.call(done, spp, start.elapsed().as_secs_f64());
+        }
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("readback"),
+    });
+    encoder.copy_buffer_to_buffer(&accum_buffer, 0, &readback_buffer, 0, accum_size);
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    readback_buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+        let _ = sender.send(result);
+    });
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .map_err(|err| GpuError::Readback(err.to_string()))?;
+    receiver
+        .recv()
+        .map_err(|err| GpuError::Readback(err.to_string()))?
+        .map_err(|err| GpuError::Readback(err.to_string()))?;
+
+    let accum: Vec<Vec3> = {
+        let view = readback_buffer.get_mapped_range(..);
+        view.chunks_exact(16)
+            .take(pixels)
+            .map(|texel| Vec3::new(f32_at(texel, 0), f32_at(texel, 4), f32_at(texel, 8)))
+            .collect()
+    };
+    readback_buffer.unmap();
+
+    let settings = RenderSettings {
+        spp,
+        progress_fn: None,
+        ..settings.clone()
+    };
+    Ok(finish_image(scene, &settings, &camera, &accum))
+}
+
+/// The shader keeps at most one pending sibling per level on its stack, plus
+/// the two children it pushes, so a tree `max_depth` deep needs
+/// `max_depth + 1` entries. Deeper trees would silently lose subtrees.
+fn check_bvh_depth(max_depth: usize) -> Result<(), GpuError> {
+    if max_depth + 1 > STACK_SIZE {
+        return Err(GpuError::Unsupported(format!(
+            "BVH depth {max_depth} (the shader's traversal stack holds {STACK_SIZE} entries)"
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects buffers the device cannot bind, which wgpu would otherwise
+/// report by panicking.
+fn check_buffer_size(what: &str, size: u64, limits: &wgpu::Limits) -> Result<(), GpuError> {
+    let limit = limits
+        .max_storage_buffer_binding_size
+        .min(limits.max_buffer_size);
+    if size > limit {
+        return Err(GpuError::Unsupported(format!(
+            "{what} of {size} bytes (the device binds at most {limit})"
+        )));
+    }
+    Ok(())
+}
+
+fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn push_vec3(out: &mut Vec<u8>, v: Vec3) {
+    for value in [v.x, v.y, v.z] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn sphere_bytes(spheres: &[Sphere]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(spheres.len().max(1) * SPHERE_BYTES);
+    for sphere in spheres {
+        push_vec3(&mut out, sphere.center);
+        out.extend_from_slice(&sphere.radius.to_le_bytes());
+        push_vec3(&mut out, sphere.material.albedo());
+        out.extend_from_slice(&0f32.to_le_bytes());
+        push_vec3(&mut out, sphere.material.emission());
+        out.extend_from_slice(&0f32.to_le_bytes());
+    }
+    out.resize(out.len().max(SPHERE_BYTES), 0);
+    out
+}
+
+fn node_bytes(nodes: &[crate::bvh::FlatNode]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nodes.len().max(1) * NODE_BYTES);
+    for node in nodes {
+        push_vec3(&mut out, node.min);
+        out.extend_from_slice(&node.first.to_le_bytes());
+        push_vec3(&mut out, node.max);
+        out.extend_from_slice(&node.count.to_le_bytes());
+    }
+    out.resize(out.len().max(NODE_BYTES), 0);
+    out
+}
+
+fn params_bytes(
+    camera: &Camera,
+    settings: &RenderSettings,
+    node_count: u32,
+    sample_offset: u32,
+    samples: u32,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(PARAMS_BYTES as usize);
+    push_vec3(&mut out, camera.origin);
+    out.extend_from_slice(&settings.width.to_le_bytes());
+    push_vec3(&mut out, camera.lower_left);
+    out.extend_from_slice(&settings.height.to_le_bytes());
+    push_vec3(&mut out, camera.horizontal);
+    out.extend_from_slice(&samples.to_le_bytes());
+    push_vec3(&mut out, camera.vertical);
+    out.extend_from_slice(&settings.bounces.max(1).to_le_bytes());
+    let seed = (settings.seed ^ (settings.seed >> 32)) as u32;
+    for value in [seed, sample_offset, node_count, 0] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_passes_validation() {
+        let module = wgpu::naga::front::wgsl::parse_str(SHADER).expect("WGSL parses");
+        wgpu::naga::valid::Validator::new(
+            wgpu::naga::valid::ValidationFlags::all(),
+            wgpu::naga::valid::Capabilities::default(),
+        )
+        .validate(&module)
+        .expect("WGSL validates");
+    }
+
+    #[test]
+    fn host_layouts_match_shader() {
+        let settings = RenderSettings::default();
+        let scene = SceneFile {
+            version: 1,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        let camera = build_camera(&scene, &settings);
+        assert_eq!(
+            params_bytes(&camera, &settings, 0, 0, 1).len() as u64,
+            PARAMS_BYTES
+        );
+        assert_eq!(sphere_bytes(&[]).len(), SPHERE_BYTES);
+        assert_eq!(node_bytes(&[]).len(), NODE_BYTES);
+        assert!(SHADER.contains(&format!("const STACK_SIZE: u32 = {STACK_SIZE}u;")));
+        assert!(SHADER.contains(&format!("array<u32, {STACK_SIZE}>")));
+    }
+
+    #[test]
+    fn oversized_scenes_are_unsupported() {
+        assert!(check_bvh_depth(0).is_ok());
+        assert!(check_bvh_depth(STACK_SIZE - 1).is_ok());
+        assert!(matches!(
+            check_bvh_depth(STACK_SIZE),
+            Err(GpuError::Unsupported(_))
+        ));
+
+        let limits = wgpu::Limits::default();
+        let limit = limits.max_storage_buffer_binding_size;
+        assert!(check_buffer_size("accumulation buffer", limit, &limits).is_ok());
+        assert!(matches!(
+            check_buffer_size("accumulation buffer", limit + 1, &limits),
+            Err(GpuError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+// Compute-shader port of the CPU path tracer in integrator.rs. Each
+// invocation traces `params.samples` samples for one pixel and adds their
+// sum to `accum`; the host divides by the total sample count.
+
+struct Sphere {
+    center: vec3<f32>,
+    radius: f32,
+    albedo: vec3<f32>,
+    _pad0: f32,
+    emission: vec3<f32>,
+    _pad1: f32,
+};
+
+// Leaves (count > 0) cover spheres first..first + count; an inner node's left
+// child follows it and its right child is at `first`.
+struct Node {
+    bmin: vec3<f32>,
+    first: u32,
+    bmax: vec3<f32>,
+    count: u32,
+};
+
+struct Params {
+    origin: vec3<f32>,
+    width: u32,
+    lower_left: vec3<f32>,
+    height: u32,
+    horizontal: vec3<f32>,
+    samples: u32,
+    vertical: vec3<f32>,
+    bounces: u32,
+    seed: u32,
+    sample_offset: u32,
+    node_count: u32,
+    _pad: u32,
+};
+
+struct Hit {
+    found: bool,
+    t: f32,
+    point: vec3<f32>,
+    normal: vec3<f32>,
+    albedo: vec3<f32>,
+    emission: vec3<f32>,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> spheres: array<Sphere>;
+@group(0) @binding(2) var<storage, read> nodes: array<Node>;
+@group(0) @binding(3) var<storage, read_write> accum: array<vec4<f32>>;
+
+const PI: f32 = 3.14159265;
+const T_MIN: f32 = 0.001;
+const T_MAX: f32 = 1e30;
+const STACK_SIZE: u32 = 64u;
+
+var<private> rng_state: u32;
+
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn next_f32() -> f32 {
+    rng_state = pcg(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+fn random_unit_vector() -> vec3<f32> {
+    let z = 1.0 - 2.0 * next_f32();
+    let r = sqrt(max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * next_f32();
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+fn random_in_hemisphere(normal: vec3<f32>) -> vec3<f32> {
+    let v = random_unit_vector();
+    if dot(v, normal) > 0.0 {
+        return v;
+    }
+    return -v;
+}
+
+fn background(dir: vec3<f32>) -> vec3<f32> {
+    let t = 0.5 * (dir.y + 1.0);
+    return mix(vec3<f32>(0.05, 0.05, 0.07), vec3<f32>(0.6, 0.8, 1.0), t);
+}
+
+fn hit_aabb(node: Node, origin: vec3<f32>, inv_dir: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (node.bmin - origin) * inv_dir;
+    let t1 = (node.bmax - origin) * inv_dir;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), T_MIN));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), min(max(t0.z, t1.z), t_max));
+    return near <= far;
+}
+
+fn hit_sphere(index: u32, origin: vec3<f32>, dir: vec3<f32>, t_max: f32, hit: ptr<function, Hit>) {
+    let sphere = spheres[index];
+    let oc = origin - sphere.center;
+    let a = dot(dir, dir);
+    let half_b = dot(oc, dir);
+    let c = dot(oc, oc) - sphere.radius * sphere.radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return;
+    }
+    let sqrtd = sqrt(discriminant);
+    var root = (-half_b - sqrtd) / a;
+    if root < T_MIN || root > t_max {
+        root = (-half_b + sqrtd) / a;
+        if root < T_MIN || root > t_max {
+            return;
+        }
+    }
+    let point = origin + dir * root;
+    (*hit).found = true;
+    (*hit).t = root;
+    (*hit).point = point;
+    (*hit).normal = (point - sphere.center) / sphere.radius;
+    (*hit).albedo = sphere.albedo;
+    (*hit).emission = sphere.emission;
+}
+
+fn hit_scene(origin: vec3<f32>, dir: vec3<f32>) -> Hit {
+    var hit: Hit;
+    hit.found = false;
+    hit.t = T_MAX;
+    if params.node_count == 0u {
+        return hit;
+    }
+
+    // Avoid dividing by zero; a tiny positive stand-in still gives a slab
+    // test that is unbounded along that axis.
+    let safe_dir = select(dir, vec3<f32>(1e-20), abs(dir) < vec3<f32>(1e-20));
+    let inv_dir = 1.0 / safe_dir;
+
+    var stack: array<u32, 64>;
+    var sp = 1u;
+    stack[0] = 0u;
+    while sp > 0u {
+        sp -= 1u;
+        let index = stack[sp];
+        let node = nodes[index];
+        if !hit_aabb(node, origin, inv_dir, hit.t) {
+            continue;
+        }
+        if node.count > 0u {
+            for (var i = node.first; i < node.first + node.count; i += 1u) {
+                hit_sphere(i, origin, dir, hit.t, &hit);
+            }
+        } else if sp + 2u <= STACK_SIZE {
+            stack[sp] = node.first;
+            stack[sp + 1u] = index + 1u;
+            sp += 2u;
+        }
+    }
+    return hit;
+}
+
+fn trace(start_origin: vec3<f32>, start_dir: vec3<f32>) -> vec3<f32> {
+    var origin = start_origin;
+    var dir = start_dir;
+    var throughput = vec3<f32>(1.0);
+    var radiance = vec3<f32>(0.0);
+    for (var bounce = 0u; bounce < params.bounces; bounce += 1u) {
+        let hit = hit_scene(origin, dir);
+        if !hit.found {
+            radiance += throughput * background(dir);
+            break;
+        }
+        radiance += throughput * hit.emission;
+        origin = hit.point + hit.normal * 0.001;
+        dir = normalize(hit.normal + random_in_hemisphere(hit.normal));
+        throughput *= hit.albedo;
+    }
+    return radiance;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+    let pixel = id.y * params.width + id.x;
+    var color = vec3<f32>(0.0);
+    for (var s = 0u; s < params.samples; s += 1u) {
+        rng_state = pcg(params.seed ^ pcg(pixel ^ pcg(params.sample_offset + s)));
+        let u = (f32(id.x) + next_f32()) / f32(params.width);
+        let v = (f32(id.y) + next_f32()) / f32(params.height);
+        let dir = normalize(
+            params.lower_left + params.horizontal * u + params.vertical * (1.0 - v) - params.origin
+        );
+        color += trace(params.origin, dir);
+    }
+    accum[pixel] = accum[pixel] + vec4<f32>(color, 0.0);
+}
@@ -1,5 +1,7 @@
 use image::{ImageError, RgbImage};
+use std::error::Error;
 use std::fs;
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -25,7 +27,56 @@ pub fn write_png(path: &Path, image: &RgbImage) -> Result<(), ImageError> {
     Ok(())
 }
 
-fn temp_path(path: &Path) -> PathBuf {
+/// Writes `frames` as one looping APNG shown at `fps` frames per second,
+/// through a temp file like [`write_png`]. Every frame must have the size of
+/// the first.
+pub fn write_animated_png(
+    path: &Path,
+    frames: &[RgbImage],
+    fps: u32,
+) -> Result<(), Box<dyn Error>> {
+    let Some(first) = frames.first() else {
+        return Err("an animated png needs at least one frame".into());
+    };
+    if fps == 0 {
+        return Err("fps must be positive".into());
+    }
+    let (width, height) = first.dimensions();
+    if let Some(frame) = frames
+        .iter()
+        .find(|frame| frame.dimensions() != (width, height))
+    {
+        return Err(format!(
+            "frame is {}x{}, expected {width}x{height}",
+            frame.width(),
+            frame.height()
+        )
+        .into());
+    }
+
+    let tmp_path = temp_path(path);
+    let written = (|| -> Result<(), Box<dyn Error>> {
+        let file = fs::File::create(&tmp_path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0)?;
+        encoder.set_frame_delay(1, fps.min(u16::MAX as u32) as u16)?;
+        let mut writer = encoder.write_header()?;
+        for frame in frames {
+            writer.write_image_data(frame.as_raw())?;
+        }
+        writer.finish()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
     let parent = path.parent().unwrap_or_else(|| Path::new("."));
     let stem = path
         .file_stem()
@@ -1,29 +1,540 @@
 use crate::bvh::Bvh;
-use crate::camera::Camera;
-use crate::geometry::Sphere;
-use crate::math::{Ray, Vec3};
+use crate::camera::{Camera, CameraStyle};
+use crate::geometry::{Hit, Material, Sphere, Triangle};
+use crate::label::draw_labels;
+use crate::math::{hilbert_encode, morton_encode, Ray, Vec3};
 use image::{Rgb, RgbImage};
-use ptroute_model::SceneFile;
+use ptroute_model::{SceneEdge, SceneFile, SceneNode};
 use rayon::prelude::*;
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
 use std::time::Instant;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct RenderSettings {
     pub width: u32,
     pub height: u32,
     pub spp: u32,
     pub bounces: u32,
     pub seed: u64,
+    /// Log progress every N finished scanlines (tiles when `tile_size` is set);
+    /// 0 disables logging. Defaults to 32.
     pub progress_every: u32,
+    /// Worker threads; 0 (the default) picks a count from the image size with
+    /// [`threads_for_settings`], capped at Rayon's global pool size.
     pub threads: usize,
+    /// Side of the square tiles; 0 renders whole rows. Defaults to 64.
+    pub tile_size: u32,
+    /// The order tiles are handed to workers in; see [`TileOrder`].
+    pub tile_order: TileOrder,
+    /// Every this many samples per pixel the running `f32` sums are
+    /// quantized into a `u16` pass buffer, added to exact integer totals and
+    /// zeroed, which bounds `f32` rounding in very long renders. Pass means
+    /// are clamped to `[0, 4]`. 0 (the default) accumulates directly.
+    pub max_accum_depth: u32,
+    /// Trace metal reflections and dielectric refraction instead of treating
+    /// every [`Material`] as diffuse. CPU only. Defaults to false.
+    pub high_quality_materials: bool,
+    /// What node spheres are made of; see [`NodeMaterial`].
+    pub node_material: NodeMaterial,
+    /// How each sample's random stream is seeded; see [`SeedStrategy`].
+    pub seed_strategy: SeedStrategy,
+    /// Where the camera looks from; see [`CameraStyle`].
+    pub camera_style: CameraStyle,
+    /// `[x_min, y_min, x_max, y_max]` in pixels, max exclusive: only pixels
+    /// inside are traced, and everything outside (bloom and labels too)
+    /// stays black in the full-size image. `None` renders everything.
+    pub crop_region: Option<[u32; 4]>,
+    /// Leave spheres entirely outside the camera view out of the BVH. Faster
+    /// on large scenes, but off-screen links then stop lighting what is in
+    /// view. Defaults to false.
+    pub frustum_cull: bool,
+    /// Put a grey floor (two triangles) under the scene. CPU only. Defaults
+    /// to false.
+    pub ground_plane: bool,
+    pub emission_scale: f32,
+    pub emission_rtt_knee_ms: f32,
+    pub emission_min: f32,
+    /// Links dim by `exp(-depth * (emission_depth_decay - 1))` with their
+    /// [`SceneEdge::depth`], so links near the source glow brightest.
+    /// Defaults to 1.0 (no decay); smaller values, which would brighten
+    /// deeper links without bound, are treated as 1.0.
+    pub emission_depth_decay: f32,
+    pub node_radius_base: f32,
+    pub node_radius_seen_scale: f32,
+    pub node_radius_seen_exponent: f32,
+    pub link_radius_base: f32,
+    pub link_radius_seen_scale: f32,
+    pub link_radius_seen_exponent: f32,
+    pub bloom_threshold: f32,
+    pub bloom_radius: u32,
+    pub bloom_intensity: f32,
+    pub node_color_scheme: NodeColorScheme,
+    /// Node RTT at which [`NodeColorScheme::ByRtt`] reaches full red.
+    pub rtt_color_scale_ms: f64,
+    /// Draw each node's label (or id) as white text next to it.
+    pub show_labels: bool,
+    /// Receives progress reports instead of stderr; see [`ProgressFn`].
+    pub progress_fn: Option<ProgressFn>,
+}
+
+/// Progress callback taking `(done, total, elapsed_secs)`.
+///
+/// It is called every `progress_every` finished scanlines (tiles when
+/// `tile_size` is set) in place of the stderr line. Progressive renders also
+/// call it once per pass with the samples done and the total `spp`,
+/// regardless of `progress_every`. Settings compare equal when they share the
+/// same callback allocation.
+#[derive(Clone)]
+pub struct ProgressFn(pub Arc<dyn Fn(u32, u32, f64) + Send + Sync>);
+
+impl ProgressFn {
+    pub fn new(f: impl Fn(u32, u32, f64) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, done: u32, total: u32, elapsed_secs: f64) {
+        (self.0)(done, total, elapsed_secs)
+    }
+}
+
+impl fmt::Debug for ProgressFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressFn(..)")
+    }
+}
+
+impl PartialEq for ProgressFn {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// How node albedo is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum NodeColorScheme {
+    /// Stable hash of the node id (see [`color_from_id`]).
+    #[default]
+    ById,
+    /// Green for clean nodes through red for nodes that lost every probe,
+    /// by [`SceneNode::loss_ratio`].
+    ByLoss,
+    /// Blue at 0 ms through red at `rtt_color_scale_ms` of the node's own
+    /// average RTT. Nodes without one (older scenes) use the average RTT
+    /// delta of their incoming links on the same scale.
+    ByRtt,
+    /// Shared color per autonomous system, hashed from `AS<asn>` like
+    /// [`color_from_id`]. Nodes without an `asn` use the `ById` color.
+    ByAsn,
+    /// The `ById` color, dimmed for nodes with a low PageRank relative to the
+    /// scene's highest score. Nodes without a score are drawn dimmest.
+    ByPageRank,
+}
+
+impl NodeColorScheme {
+    /// The name accepted by `FromStr` (`id`, `loss`, `rtt`, `asn`, `pagerank`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ById => "id",
+            Self::ByLoss => "loss",
+            Self::ByRtt => "rtt",
+            Self::ByAsn => "asn",
+            Self::ByPageRank => "pagerank",
+        }
+    }
+}
+
+/// What node spheres are made of. Link beads are always emissive. Metal and
+/// glass only render as such with [`RenderSettings::high_quality_materials`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum NodeMaterial {
+    /// [`Material::Lambertian`] in the node's color.
+    #[default]
+    Diffuse,
+    /// Slightly rough [`Material::Metal`] tinted with the node's color.
+    Metal,
+    /// Clear [`Material::Dielectric`]; the node color is lost.
+    Glass,
+}
+
+impl NodeMaterial {
+    /// The name accepted by `FromStr` (`diffuse`, `metal`, `glass`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Diffuse => "diffuse",
+            Self::Metal => "metal",
+            Self::Glass => "glass",
+        }
+    }
+}
+
+/// How the random stream for one sample of one pixel is seeded. Every
+/// strategy seeds each sample independently, so progressive and flushed
+/// renders still match a single pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SeedStrategy {
+    /// Hash of `(seed, x, y, sample)` (see [`hash_seed`]).
+    #[default]
+    GlobalProgression,
+    /// One stream per pixel seeded from `(seed, x, y)`; sample `n` starts
+    /// [`SAMPLE_STREAM_STRIDE`] * n draws into it.
+    PixelIndependent,
+    /// Sample indices are offset by each pixel's rank in a 64x64 blue-noise
+    /// table, tiled over the image, so neighbouring pixels draw from distant
+    /// streams.
+    BlueNoise,
+}
+
+/// The order tiles are queued for rendering. Output is identical for every
+/// order; only cache locality between neighbouring work items changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TileOrder {
+    /// Row by row, left to right.
+    Scanline,
+    /// Z-order (see [`morton_encode`]).
+    #[default]
+    Morton,
+    /// Along a Hilbert curve (see [`hilbert_encode`]), which never jumps
+    /// between distant tiles the way Z-order does at quadrant boundaries.
+    Hilbert,
+}
+
+impl TileOrder {
+    /// Every `(column, row)` of a `cols`x`rows` tile grid, in this order.
+    pub fn tiles(self, cols: u32, rows: u32) -> Vec<(u32, u32)> {
+        let mut tiles: Vec<(u32, u32)> = (0..rows)
+            .flat_map(|ty| (0..cols).map(move |tx| (tx, ty)))
+            .collect();
+        match self {
+            Self::Scanline => {}
+            Self::Morton => tiles.sort_by_key(|&(tx, ty)| morton_encode(tx, ty)),
+            Self::Hilbert => {
+                let n = cols.max(rows).next_power_of_two();
+                tiles.sort_by_key(|&(tx, ty)| hilbert_encode(n, tx, ty));
+            }
+        }
+        tiles
+    }
+}
+
+/// Draws reserved per sample by [`SeedStrategy::PixelIndependent`]; far more
+/// than a path of any practical bounce count uses.
+pub const SAMPLE_STREAM_STRIDE: u64 = 1 << 20;
+
+impl std::str::FromStr for NodeColorScheme {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "id" => Ok(Self::ById),
+            "loss" => Ok(Self::ByLoss),
+            "rtt" => Ok(Self::ByRtt),
+            "asn" => Ok(Self::ByAsn),
+            "pagerank" => Ok(Self::ByPageRank),
+            other => Err(format!(
+                "unknown node color scheme {other:?} (expected id, loss, rtt, asn or pagerank)"
+            )),
+        }
+    }
+}
+
+impl std::str::FromStr for NodeMaterial {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "diffuse" => Ok(Self::Diffuse),
+            "metal" => Ok(Self::Metal),
+            "glass" => Ok(Self::Glass),
+            other => Err(format!(
+                "unknown node material {other:?} (expected diffuse, metal or glass)"
+            )),
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 1600,
+            height: 900,
+            spp: 64,
+            bounces: 6,
+            seed: 1,
+            progress_every: 32,
+            threads: 0,
+            tile_size: 64,
+            tile_order: TileOrder::Morton,
+            max_accum_depth: 0,
+            high_quality_materials: false,
+            node_material: NodeMaterial::Diffuse,
+            seed_strategy: SeedStrategy::GlobalProgression,
+            camera_style: CameraStyle::default(),
+            crop_region: None,
+            frustum_cull: false,
+            ground_plane: false,
+            emission_scale: 1.0,
+            emission_rtt_knee_ms: 50.0,
+            emission_min: 0.0,
+            emission_depth_decay: 1.0,
+            node_radius_base: NODE_RADIUS_BASE,
+            node_radius_seen_scale: NODE_RADIUS_SEEN_SCALE,
+            node_radius_seen_exponent: 1.0,
+            link_radius_base: LINK_RADIUS_BASE,
+            link_radius_seen_scale: LINK_RADIUS_SEEN_SCALE,
+            link_radius_seen_exponent: 1.0,
+            bloom_threshold: f32::MAX,
+            bloom_radius: 8,
+            bloom_intensity: 0.6,
+            node_color_scheme: NodeColorScheme::ById,
+            rtt_color_scale_ms: 200.0,
+            show_labels: false,
+            progress_fn: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn builder() -> RenderSettingsBuilder {
+        RenderSettingsBuilder::default()
+    }
+
+    /// Whether pixel `(x, y)` is inside [`RenderSettings::crop_region`].
+    fn in_crop(&self, x: u32, y: u32) -> bool {
+        self.crop_region.is_none_or(|[x_min, y_min, x_max, y_max]| {
+            (x_min..x_max).contains(&x) && (y_min..y_max).contains(&y)
+        })
+    }
+}
+
+/// Chained construction of [`RenderSettings`]; unset fields keep their
+/// `RenderSettings::default()` values.
+#[derive(Debug, Clone, Default)]
+pub struct RenderSettingsBuilder {
+    settings: RenderSettings,
+}
+
+impl RenderSettingsBuilder {
+    pub fn width(mut self, width: u32) -> Self {
+        self.settings.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.settings.height = height;
+        self
+    }
+
+    pub fn spp(mut self, spp: u32) -> Self {
+        self.settings.spp = spp;
+        self
+    }
+
+    pub fn bounces(mut self, bounces: u32) -> Self {
+        self.settings.bounces = bounces;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.settings.seed = seed;
+        self
+    }
+
+    pub fn progress_every(mut self, progress_every: u32) -> Self {
+        self.settings.progress_every = progress_every;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.settings.threads = threads;
+        self
+    }
+
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.settings.tile_size = tile_size;
+        self
+    }
+
+    pub fn tile_order(mut self, tile_order: TileOrder) -> Self {
+        self.settings.tile_order = tile_order;
+        self
+    }
+
+    pub fn max_accum_depth(mut self, max_accum_depth: u32) -> Self {
+        self.settings.max_accum_depth = max_accum_depth;
+        self
+    }
+
+    pub fn high_quality_materials(mut self, high_quality_materials: bool) -> Self {
+        self.settings.high_quality_materials = high_quality_materials;
+        self
+    }
+
+    pub fn node_material(mut self, node_material: NodeMaterial) -> Self {
+        self.settings.node_material = node_material;
+        self
+    }
+
+    pub fn seed_strategy(mut self, seed_strategy: SeedStrategy) -> Self {
+        self.settings.seed_strategy = seed_strategy;
+        self
+    }
+
+    pub fn camera_style(mut self, camera_style: CameraStyle) -> Self {
+        self.settings.camera_style = camera_style;
+        self
+    }
+
+    pub fn crop_region(mut self, crop_region: [u32; 4]) -> Self {
+        self.settings.crop_region = Some(crop_region);
+        self
+    }
+
+    pub fn frustum_cull(mut self, frustum_cull: bool) -> Self {
+        self.settings.frustum_cull = frustum_cull;
+        self
+    }
+
+    pub fn ground_plane(mut self, ground_plane: bool) -> Self {
+        self.settings.ground_plane = ground_plane;
+        self
+    }
+
+    pub fn show_labels(mut self, show_labels: bool) -> Self {
+        self.settings.show_labels = show_labels;
+        self
+    }
+
+    pub fn progress_fn(
+        mut self,
+        progress_fn: impl Fn(u32, u32, f64) + Send + Sync + 'static,
+    ) -> Self {
+        self.settings.progress_fn = Some(ProgressFn::new(progress_fn));
+        self
+    }
+
+    pub fn build(self) -> RenderSettings {
+        self.settings
+    }
+}
+
+const PIXELS_PER_THREAD: u64 = 256;
+const NODE_RADIUS_BASE: f32 = 0.15;
+const NODE_RADIUS_SEEN_SCALE: f32 = 0.05;
+const LINK_RADIUS_BASE: f32 = 0.04;
+const LINK_RADIUS_SEEN_SCALE: f32 = 0.01;
+const LOSS_CLEAN: [f32; 3] = [0.2, 0.8, 0.2];
+const LOSS_LOST: [f32; 3] = [0.8, 0.2, 0.2];
+const RTT_FAST: [f32; 3] = [0.2, 0.3, 0.9];
+const RTT_SLOW: [f32; 3] = [0.9, 0.2, 0.2];
+const RTT_SCALE_LOW: [f32; 3] = [0.1, 0.1, 0.8];
+const RTT_SCALE_HIGH: [f32; 3] = [0.8, 0.1, 0.1];
+const PAGERANK_MIN_BRIGHTNESS: f32 = 0.15;
+const NODE_METAL_FUZZ: f32 = 0.1;
+const NODE_GLASS_IOR: f32 = 1.5;
+const GROUND_ALBEDO: [f32; 3] = [0.3, 0.3, 0.32];
+/// Half the side of the ground square, in multiples of the scene's larger
+/// horizontal extent.
+const GROUND_MARGIN: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy)]
+struct SphereBuildOptions {
+    emission_scale: f32,
+    emission_rtt_knee_ms: f32,
+    emission_min: f32,
+    emission_depth_decay: f32,
+    node_radius: RadiusScale,
+    link_radius: RadiusScale,
+    node_color_scheme: NodeColorScheme,
+    node_material: NodeMaterial,
+    rtt_color_scale_ms: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RadiusScale {
+    base: f32,
+    seen_scale: f32,
+    seen_exponent: f32,
+}
+
+impl RadiusScale {
+    fn radius(&self, seen: u32) -> f32 {
+        self.base + self.seen_scale * (seen.max(1) as f32).powf(self.seen_exponent).ln()
+    }
+}
+
+impl SphereBuildOptions {
+    fn from_settings(settings: &RenderSettings) -> Self {
+        Self {
+            emission_scale: settings.emission_scale,
+            emission_rtt_knee_ms: settings.emission_rtt_knee_ms,
+            emission_min: settings.emission_min,
+            emission_depth_decay: settings.emission_depth_decay.max(1.0),
+            node_radius: RadiusScale {
+                base: settings.node_radius_base,
+                seen_scale: settings.node_radius_seen_scale,
+                seen_exponent: settings.node_radius_seen_exponent,
+            },
+            link_radius: RadiusScale {
+                base: settings.link_radius_base,
+                seen_scale: settings.link_radius_seen_scale,
+                seen_exponent: settings.link_radius_seen_exponent,
+            },
+            node_color_scheme: settings.node_color_scheme,
+            node_material: settings.node_material,
+            rtt_color_scale_ms: settings.rtt_color_scale_ms,
+        }
+    }
 }
 
 pub fn render_scene(scene: &SceneFile, settings: &RenderSettings) -> RgbImage {
     let context = RenderContext::new(scene, settings);
-    let mut accum = vec![Vec3::zero(); (settings.width * settings.height) as usize];
-    render_scene_accum(&context, settings, &mut accum, 0, settings.spp);
-    image_from_accum(&accum, settings.width, settings.height, settings.spp)
+    let mut accum = Accumulator::new(settings);
+    accum.render(&context, settings, 0, settings.spp);
+    finish_image(scene, settings, &context.camera, &accum.sums())
+}
+
+/// Turns `settings.spp` summed samples per pixel into the final image,
+/// applying bloom and labels as configured.
+pub(crate) fn finish_image(
+    scene: &SceneFile,
+    settings: &RenderSettings,
+    camera: &Camera,
+    accum: &[Vec3],
+) -> RgbImage {
+    let bloom = Bloom::from_settings(settings);
+    let mut image = image_from_accum(
+        accum,
+        settings.width,
+        settings.height,
+        settings.spp,
+        bloom.as_ref(),
+    );
+    if settings.show_labels {
+        draw_labels(&mut image, scene, camera);
+    }
+    mask_crop(&mut image, settings);
+    image
+}
+
+/// Blacks out everything outside the crop region, including bloom and
+/// labels that spilled past it.
+fn mask_crop(image: &mut RgbImage, settings: &RenderSettings) {
+    if settings.crop_region.is_none() {
+        return;
+    }
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if !settings.in_crop(x, y) {
+            *pixel = Rgb([0, 0, 0]);
+        }
+    }
 }
 
 pub fn render_scene_progressive<F>(
@@ -33,20 +544,159 @@ pub fn render_scene_progressive<F>(
     mut on_pass: F,
 ) where
     F: FnMut(&RgbImage, u32),
+{
+    render_passes(scene, settings, progressive_every, |image, done| {
+        on_pass(&image, done);
+        true
+    });
+}
+
+/// Renders progressively on a background thread, sending each pass of
+/// `pass_size` samples per pixel as `(image, spp_done)`. At most one image
+/// waits in the channel; dropping the receiver stops the render after the
+/// pass in flight.
+pub fn render_scene_streaming(
+    scene: SceneFile,
+    settings: RenderSettings,
+    pass_size: u32,
+) -> mpsc::Receiver<(RgbImage, u32)> {
+    let (sender, receiver) = mpsc::sync_channel(1);
+    thread::spawn(move || {
+        render_passes(&scene, &settings, pass_size, |image, done| {
+            sender.send((image, done)).is_ok()
+        });
+    });
+    receiver
+}
+
+/// Shared loop of [`render_scene_progressive`] and
+/// [`render_scene_streaming`]; stops early once `on_pass` returns false.
+fn render_passes<F>(
+    scene: &SceneFile,
+    settings: &RenderSettings,
+    progressive_every: u32,
+    mut on_pass: F,
+) where
+    F: FnMut(RgbImage, u32) -> bool,
 {
     let context = RenderContext::new(scene, settings);
-    let mut accum = vec![Vec3::zero(); (settings.width * settings.height) as usize];
+    let mut accum = Accumulator::new(settings);
 
     let mut done = 0;
     let target = settings.spp.max(1);
     let step = progressive_every.max(1);
+    let start = Instant::now();
 
     while done < target {
         let pass = (target - done).min(step);
-        render_scene_accum(&context, settings, &mut accum, done, pass);
+        accum.render(&context, settings, done, pass);
         done += pass;
-        let image = image_from_accum(&accum, settings.width, settings.height, done);
-        on_pass(&image, done);
+        let mut image = image_from_accum(
+            &accum.sums(),
+            settings.width,
+            settings.height,
+            done,
+            context.bloom.as_ref(),
+        );
+        if settings.show_labels {
+            draw_labels(&mut image, scene, &context.camera);
+        }
+        mask_crop(&mut image, settings);
+        if let Some(callback) = &settings.progress_fn {
+            callback.call(done, target, start.elapsed().as_secs_f64());
+        }
+        if !on_pass(image, done) {
+            return;
+        }
+    }
+}
+
+// Fixed-point scale for quantized pass means: u16::MAX maps to 4.0.
+const ACCUM_QUANT_SCALE: f32 = u16::MAX as f32 / 4.0;
+
+/// Per-pixel sample sums, flushed every `max_accum_depth` samples as
+/// described on [`RenderSettings::max_accum_depth`].
+struct Accumulator {
+    live: Vec<Vec3>,
+    live_samples: u32,
+    depth: u32,
+    quantized: Vec<[u16; 3]>,
+    // Sum over flushed passes of quantized mean times pass samples.
+    flushed: Vec<[u64; 3]>,
+}
+
+impl Accumulator {
+    fn new(settings: &RenderSettings) -> Self {
+        let pixels = (settings.width * settings.height) as usize;
+        let flushed_pixels = if settings.max_accum_depth > 0 {
+            pixels
+        } else {
+            0
+        };
+        Self {
+            live: vec![Vec3::zero(); pixels],
+            live_samples: 0,
+            depth: settings.max_accum_depth,
+            quantized: vec![[0; 3]; flushed_pixels],
+            flushed: vec![[0; 3]; flushed_pixels],
+        }
+    }
+
+    fn render(
+        &mut self,
+        context: &RenderContext,
+        settings: &RenderSettings,
+        mut sample_offset: u32,
+        samples: u32,
+    ) {
+        if self.depth == 0 {
+            render_scene_accum(context, settings, &mut self.live, sample_offset, samples);
+            return;
+        }
+        let end = sample_offset + samples;
+        while sample_offset < end {
+            let chunk = (self.depth - self.live_samples).min(end - sample_offset);
+            render_scene_accum(context, settings, &mut self.live, sample_offset, chunk);
+            self.live_samples += chunk;
+            sample_offset += chunk;
+            if self.live_samples == self.depth {
+                self.flush();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let samples = self.live_samples.max(1);
+        let scale = ACCUM_QUANT_SCALE / samples as f32;
+        for ((live, quantized), flushed) in self
+            .live
+            .iter_mut()
+            .zip(&mut self.quantized)
+            .zip(&mut self.flushed)
+        {
+            for (channel, value) in [live.x, live.y, live.z].into_iter().enumerate() {
+                quantized[channel] = (value * scale).round().clamp(0.0, u16::MAX as f32) as u16;
+                flushed[channel] += u64::from(quantized[channel]) * u64::from(samples);
+            }
+            *live = Vec3::zero();
+        }
+        self.live_samples = 0;
+    }
+
+    /// Sample sums per pixel, as [`image_from_accum`] expects.
+    fn sums(&self) -> Vec<Vec3> {
+        if self.flushed.is_empty() {
+            return self.live.clone();
+        }
+        self.live
+            .iter()
+            .zip(&self.flushed)
+            .map(|(&live, flushed)| {
+                let [r, g, b] =
+                    flushed.map(|total| (total as f64 / ACCUM_QUANT_SCALE as f64) as f32);
+                live + Vec3::new(r, g, b)
+            })
+            .collect()
     }
 }
 
@@ -57,82 +707,336 @@ fn render_scene_accum(
     sample_offset: u32,
     samples: u32,
 ) {
-    let width = settings.width as usize;
-    let height = settings.height;
-    let spp = samples.max(1);
-    let bounces = settings.bounces.max(1);
-    let progress_every = settings.progress_every;
-    let start = Instant::now();
-    let counter = AtomicU32::new(0);
+    let pass = Pass {
+        context,
+        settings,
+        sample_offset,
+        spp: samples.max(1),
+        bounces: settings.bounces.max(1),
+    };
+    let threads = match settings.threads {
+        0 => {
+            let available = rayon::current_num_threads();
+            let auto = threads_for_settings(settings.width, settings.height, available);
+            // The global pool already has `available` threads; reuse it.
+            if auto >= available {
+                0
+            } else {
+                auto
+            }
+        }
+        threads => threads,
+    };
+    with_thread_pool(threads, || {
+        if settings.tile_size == 0 {
+            render_rows(&pass, accum);
+        } else {
+            render_tiles(&pass, accum, settings.tile_size);
+        }
+    });
+}
+
+struct Pass<'a> {
+    context: &'a RenderContext,
+    settings: &'a RenderSettings,
+    sample_offset: u32,
+    spp: u32,
+    bounces: u32,
+}
+
+impl Pass<'_> {
+    fn sample_pixel(&self, x: u32, y: u32) -> Vec3 {
+        if !self.settings.in_crop(x, y) {
+            return Vec3::zero();
+        }
+        (0..self.spp).fold(Vec3::zero(), |color, sample| {
+            color + self.sample(x, y, self.sample_offset + sample)
+        })
+    }
+
+    fn sample(&self, x: u32, y: u32, sample_index: u32) -> Vec3 {
+        let settings = self.settings;
+        let mut rng = sample_rng(settings.seed, settings.seed_strategy, x, y, sample_index);
+        let u = (x as f32 + rng.next_f32()) / settings.width as f32;
+        let v = (y as f32 + rng.next_f32()) / settings.height as f32;
+        let ray = self.context.camera.ray(u, 1.0 - v);
+        trace(
+            &ray,
+            &self.context.bvh,
+            self.bounces,
+            settings.high_quality_materials,
+            &mut rng,
+        )
+    }
+}
 
+/// Debug heatmap of per-pixel sample variance after `spp / 4` samples (at
+/// least 2), showing which regions stay noisy: blue is the lowest variance
+/// in the image, red the highest. Colors scale with the standard deviation
+/// of sample luminance so that low-variance regions still separate.
+pub fn render_scene_importance_map(scene: &SceneFile, settings: &RenderSettings) -> RgbImage {
+    let context = RenderContext::new(scene, settings);
+    let pass = Pass {
+        context: &context,
+        settings,
+        sample_offset: 0,
+        spp: (settings.spp / 4).max(2),
+        bounces: settings.bounces.max(1),
+    };
+    let width = settings.width as usize;
+    let mut variance = vec![0.0f32; width * settings.height as usize];
     with_thread_pool(settings.threads, || {
-        accum
-            .par_chunks_mut(width)
+        variance
+            .par_chunks_mut(width.max(1))
             .enumerate()
             .for_each(|(y, row)| {
-                for x in 0..width {
-                    let mut color = Vec3::zero();
-                    for sample in 0..spp {
-                        let sample_index = sample_offset + sample;
-                        let mut rng = Rng::new(hash_seed(settings.seed, x as u32, y as u32, sample_index));
-                        let u = (x as f32 + rng.next_f32()) / settings.width as f32;
-                        let v = (y as f32 + rng.next_f32()) / settings.height as f32;
-                        let ray = context.camera.ray(u, 1.0 - v);
-                        color = color + trace(&ray, &context.bvh, bounces, &mut rng);
-                    }
-                    row[x] = row[x] + color;
-                }
-
-                if progress_every > 0 {
-                    let done = counter.fetch_add(1, Ordering::Relaxed) + 1;
-                    if done == height || done % progress_every == 0 {
-                        let elapsed = start.elapsed().as_secs_f64();
-                        let percent = (done as f64 / height as f64) * 100.0;
-                        let total = if done > 0 {
-                            elapsed * height as f64 / done as f64
-                        } else {
-                            0.0
-                        };
-                        let remaining = (total - elapsed).max(0.0);
-                        eprintln!(
-                            "render: {}/{} ({:.1}%) elapsed {:.1}s eta {:.1}s",
-                            done, height, percent, elapsed, remaining
-                        );
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let (mut sum, mut sum_sq) = (0.0f64, 0.0f64);
+                    for sample in 0..pass.spp {
+                        let value = f64::from(luminance(pass.sample(x as u32, y as u32, sample)));
+                        sum += value;
+                        sum_sq += value * value;
                     }
+                    let n = f64::from(pass.spp);
+                    let mean = sum / n;
+                    *pixel = ((sum_sq / n - mean * mean) * n / (n - 1.0)).max(0.0) as f32;
                 }
             });
     });
+
+    let max_std = variance.iter().fold(0.0f32, |max, &v| max.max(v.sqrt()));
+    let mut image = RgbImage::new(settings.width, settings.height);
+    for (pixel, &v) in image.pixels_mut().zip(&variance) {
+        let t = if max_std > 0.0 {
+            v.sqrt() / max_std
+        } else {
+            0.0
+        };
+        *pixel = Rgb([
+            (t * 255.0).round() as u8,
+            0,
+            ((1.0 - t) * 255.0).round() as u8,
+        ]);
+    }
+    image
 }
 
-fn image_from_accum(accum: &[Vec3], width: u32, height: u32, samples: u32) -> RgbImage {
+fn render_rows(pass: &Pass, accum: &mut [Vec3]) {
+    let width = pass.settings.width as usize;
+    let progress = Progress::new(pass.settings.height, pass.settings);
+    accum
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = *pixel + pass.sample_pixel(x as u32, y as u32);
+            }
+            progress.finish_one();
+        });
+}
+
+// Renders `tile_size` squares in the settings' `tile_order` so neighbouring
+// work shares BVH nodes in cache, then scatters each tile back into the
+// row-major buffer.
+fn render_tiles(pass: &Pass, accum: &mut [Vec3], tile_size: u32) {
+    let (width, height) = (pass.settings.width, pass.settings.height);
+    let (cols, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+    let tiles = pass.settings.tile_order.tiles(cols, rows);
+
+    let progress = Progress::new(tiles.len() as u32, pass.settings);
+    let rendered: Vec<(u32, u32, Vec<Vec3>)> = tiles
+        .par_iter()
+        .map(|&(tx, ty)| {
+            let (x0, y0) = (tx * tile_size, ty * tile_size);
+            let (x1, y1) = ((x0 + tile_size).min(width), (y0 + tile_size).min(height));
+            let pixels = (y0..y1)
+                .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                .map(|(x, y)| pass.sample_pixel(x, y))
+                .collect();
+            progress.finish_one();
+            (x0, y0, pixels)
+        })
+        .collect();
+
+    for (x0, y0, pixels) in rendered {
+        let tile_width = (x0 + tile_size).min(width) - x0;
+        for (i, color) in pixels.into_iter().enumerate() {
+            let x = x0 + i as u32 % tile_width;
+            let y = y0 + i as u32 / tile_width;
+            let pixel = &mut accum[(y * width + x) as usize];
+            *pixel = *pixel + color;
+        }
+    }
+}
+
+/// Reports `done/total` every `every` finished work units (scanlines or
+/// tiles), to the settings' [`ProgressFn`] if any and stderr otherwise.
+struct Progress<'a> {
+    total: u32,
+    every: u32,
+    callback: Option<&'a ProgressFn>,
+    done: AtomicU32,
+    start: Instant,
+}
+
+impl<'a> Progress<'a> {
+    fn new(total: u32, settings: &'a RenderSettings) -> Self {
+        Self {
+            total,
+            every: settings.progress_every,
+            callback: settings.progress_fn.as_ref(),
+            done: AtomicU32::new(0),
+            start: Instant::now(),
+        }
+    }
+
+    fn finish_one(&self) {
+        if self.every == 0 {
+            return;
+        }
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if done == self.total || done.is_multiple_of(self.every) {
+            let elapsed = self.start.elapsed().as_secs_f64();
+            if let Some(callback) = self.callback {
+                callback.call(done, self.total, elapsed);
+                return;
+            }
+            let percent = (done as f64 / self.total as f64) * 100.0;
+            let total = elapsed * self.total as f64 / done as f64;
+            let remaining = (total - elapsed).max(0.0);
+            eprintln!(
+                "render: {}/{} ({:.1}%) elapsed {:.1}s eta {:.1}s",
+                done, self.total, percent, elapsed, remaining
+            );
+        }
+    }
+}
+
+fn image_from_accum(
+    accum: &[Vec3],
+    width: u32,
+    height: u32,
+    samples: u32,
+    bloom: Option<&Bloom>,
+) -> RgbImage {
     let mut image = RgbImage::new(width, height);
     let scale = 1.0 / samples.max(1) as f32;
+    let mut colors: Vec<Vec3> = accum.iter().map(|&color| color * scale).collect();
+
+    if let Some(bloom) = bloom {
+        bloom.apply(&mut colors, width as usize, height as usize);
+    }
 
     for y in 0..height {
         for x in 0..width {
             let idx = (y * width + x) as usize;
-            let color = accum[idx] * scale;
-            image.put_pixel(x, y, to_rgb(color));
+            image.put_pixel(x, y, to_rgb(colors[idx]));
         }
     }
 
     image
 }
 
-fn trace(ray: &Ray, bvh: &Bvh, bounces: u32, rng: &mut Rng) -> Vec3 {
+struct Bloom {
+    threshold: f32,
+    intensity: f32,
+    kernel: Vec<f32>,
+}
+
+impl Bloom {
+    fn from_settings(settings: &RenderSettings) -> Option<Self> {
+        if settings.bloom_threshold == f32::MAX || settings.bloom_intensity <= 0.0 {
+            return None;
+        }
+
+        let radius = settings.bloom_radius.max(1) as i32;
+        let sigma = radius as f32 * 0.5;
+        let mut kernel: Vec<f32> = (-radius..=radius)
+            .map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let sum: f32 = kernel.iter().sum();
+        for weight in &mut kernel {
+            *weight /= sum;
+        }
+
+        Some(Self {
+            threshold: settings.bloom_threshold,
+            intensity: settings.bloom_intensity,
+            kernel,
+        })
+    }
+
+    fn apply(&self, colors: &mut [Vec3], width: usize, height: usize) {
+        let bright: Vec<Vec3> = colors
+            .iter()
+            .map(|&color| {
+                if luminance(color) > self.threshold {
+                    color
+                } else {
+                    Vec3::zero()
+                }
+            })
+            .collect();
+
+        let horizontal = self.blur(&bright, width, height, 1, 0);
+        let blurred = self.blur(&horizontal, width, height, 0, 1);
+
+        for (color, glow) in colors.iter_mut().zip(blurred) {
+            *color = *color + glow * self.intensity;
+        }
+    }
+
+    fn blur(&self, src: &[Vec3], width: usize, height: usize, dx: usize, dy: usize) -> Vec<Vec3> {
+        let radius = (self.kernel.len() / 2) as isize;
+        let mut out = vec![Vec3::zero(); src.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Vec3::zero();
+                for (k, weight) in self.kernel.iter().enumerate() {
+                    let offset = k as isize - radius;
+                    let sx = (x as isize + offset * dx as isize).clamp(0, width as isize - 1);
+                    let sy = (y as isize + offset * dy as isize).clamp(0, height as isize - 1);
+                    sum = sum + src[sy as usize * width + sx as usize] * *weight;
+                }
+                out[y * width + x] = sum;
+            }
+        }
+        out
+    }
+}
+
+fn luminance(color: Vec3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn trace(ray: &Ray, bvh: &Bvh, bounces: u32, high_quality: bool, rng: &mut Rng) -> Vec3 {
     let mut current_ray = *ray;
     let mut throughput = Vec3::new(1.0, 1.0, 1.0);
     let mut color = Vec3::zero();
 
     for _ in 0..bounces {
         if let Some(hit) = bvh.hit(&current_ray, 0.001, f32::INFINITY) {
-            color = color + throughput.mul_elem(hit.emission);
-            let direction = random_in_hemisphere(hit.normal, rng);
+            color = color + throughput.mul_elem(hit.material.emission());
+            let direction = if high_quality {
+                match scatter(&current_ray, &hit, rng) {
+                    Some(direction) => direction,
+                    None => return color,
+                }
+            } else {
+                random_in_hemisphere(hit.normal, rng)
+            };
+            // Step off the surface on the side the new ray leaves from, which
+            // is the far side for refracted rays.
+            let side = if direction.dot(hit.normal) < 0.0 {
+                -0.001
+            } else {
+                0.001
+            };
             current_ray = Ray {
-                origin: hit.point + hit.normal * 0.001,
+                origin: hit.point + hit.normal * side,
                 direction,
             };
-            throughput = throughput.mul_elem(hit.albedo);
+            throughput = throughput.mul_elem(hit.material.albedo());
         } else {
             color = color + throughput.mul_elem(background(&current_ray));
             return color;
@@ -142,6 +1046,43 @@ fn trace(ray: &Ray, bvh: &Bvh, bounces: u32, rng: &mut Rng) -> Vec3 {
     color
 }
 
+/// Picks the outgoing direction for `hit.material`, or `None` when the ray is
+/// absorbed (a fuzzed metal reflection pointing into the surface).
+fn scatter(ray: &Ray, hit: &Hit, rng: &mut Rng) -> Option<Vec3> {
+    let incoming = ray.direction.normalized();
+    match hit.material {
+        Material::Lambertian { .. } | Material::Emissive { .. } => {
+            Some(random_in_hemisphere(hit.normal, rng))
+        }
+        Material::Metal { fuzz, .. } => {
+            let reflected =
+                incoming.reflect(hit.normal) + random_unit_vector(rng) * fuzz.clamp(0.0, 1.0);
+            (reflected.dot(hit.normal) > 0.0).then(|| reflected.normalized())
+        }
+        Material::Dielectric { ior } => {
+            let entering = incoming.dot(hit.normal) < 0.0;
+            let (normal, eta_ratio) = if entering {
+                (hit.normal, 1.0 / ior)
+            } else {
+                (hit.normal * -1.0, ior)
+            };
+            let cos_theta = (incoming * -1.0).dot(normal).min(1.0);
+            match incoming.refract(normal, eta_ratio) {
+                Some(refracted) if schlick(cos_theta, eta_ratio) <= rng.next_f32() => {
+                    Some(refracted.normalized())
+                }
+                _ => Some(incoming.reflect(normal)),
+            }
+        }
+    }
+}
+
+/// Schlick's approximation of Fresnel reflectance.
+fn schlick(cosine: f32, eta_ratio: f32) -> f32 {
+    let r0 = ((1.0 - eta_ratio) / (1.0 + eta_ratio)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
 fn random_in_hemisphere(normal: Vec3, rng: &mut Rng) -> Vec3 {
     let mut dir = random_unit_vector(rng);
     if dir.dot(normal) < 0.0 {
@@ -170,32 +1111,119 @@ fn background(ray: &Ray) -> Vec3 {
     ground * (1.0 - t) + sky * t
 }
 
+/// Builds the BVH that [`render_scene`] traces `scene` against, e.g. to
+/// inspect [`Bvh::stats`].
+pub fn scene_bvh(scene: &SceneFile, settings: &RenderSettings) -> Bvh {
+    let mut spheres = build_spheres(scene, &SphereBuildOptions::from_settings(settings));
+    let triangles = if settings.ground_plane {
+        ground_plane(&spheres)
+    } else {
+        Vec::new()
+    };
+    if settings.frustum_cull {
+        spheres = cull_spheres(spheres, &build_camera(scene, settings));
+    }
+    Bvh::with_triangles(spheres, triangles)
+}
+
+/// A square floor touching the bottom of the lowest sphere, centered under
+/// the scene and reaching well past it on every side.
+fn ground_plane(spheres: &[Sphere]) -> Vec<Triangle> {
+    if spheres.is_empty() {
+        return Vec::new();
+    }
+    let (min, max) = spheres.iter().map(Sphere::bounds).fold(
+        (
+            Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        ),
+        |(lo, hi), (min, max)| (lo.min(min), hi.max(max)),
+    );
+    let center = (min + max) * 0.5;
+    let half = (max.x - min.x).max(max.z - min.z).max(1.0) * GROUND_MARGIN;
+    let corner = |dx: f32, dz: f32| Vec3::new(center.x + dx * half, min.y, center.z + dz * half);
+    let (a, b, c, d) = (
+        corner(-1.0, -1.0),
+        corner(1.0, -1.0),
+        corner(1.0, 1.0),
+        corner(-1.0, 1.0),
+    );
+    let material = Material::Lambertian {
+        albedo: Vec3::from(GROUND_ALBEDO),
+    };
+    vec![
+        Triangle {
+            v0: a,
+            v1: b,
+            v2: c,
+            material,
+        },
+        Triangle {
+            v0: a,
+            v1: c,
+            v2: d,
+            material,
+        },
+    ]
+}
+
+/// Drops every sphere lying entirely behind one of the camera's
+/// [`Camera::frustum_planes`].
+fn cull_spheres(spheres: Vec<Sphere>, camera: &Camera) -> Vec<Sphere> {
+    let planes = camera.frustum_planes();
+    spheres
+        .into_iter()
+        .filter(|sphere| {
+            planes
+                .iter()
+                .all(|plane| plane.distance(sphere.center) >= -sphere.radius)
+        })
+        .collect()
+}
+
 struct RenderContext {
     bvh: Bvh,
     camera: Camera,
+    bloom: Option<Bloom>,
 }
 
 impl RenderContext {
     fn new(scene: &SceneFile, settings: &RenderSettings) -> Self {
-        let spheres = build_spheres(scene);
-        let bvh = Bvh::new(spheres);
+        let bvh = scene_bvh(scene, settings);
         let camera = build_camera(scene, settings);
-        Self { bvh, camera }
+        let bloom = Bloom::from_settings(settings);
+        Self { bvh, camera, bloom }
     }
 }
 
-fn build_spheres(scene: &SceneFile) -> Vec<Sphere> {
+fn build_spheres(scene: &SceneFile, options: &SphereBuildOptions) -> Vec<Sphere> {
     let mut spheres = Vec::new();
     let mut positions: HashMap<String, Vec3> = HashMap::new();
+    let incoming_rtt = incoming_rtt_by_node(scene);
+    let max_pagerank = scene
+        .nodes
+        .iter()
+        .filter_map(|node| node.pagerank)
+        .fold(0.0_f64, f64::max);
 
     for node in &scene.nodes {
         let position = Vec3::new(node.position[0], node.position[1], node.position[2]);
         positions.insert(node.id.clone(), position);
+        let rtt = incoming_rtt.get(node.id.as_str()).copied().unwrap_or(0.0);
+        let albedo = node_albedo(node, rtt, max_pagerank, options);
         spheres.push(Sphere {
             center: position,
-            radius: node_radius(node.seen),
-            albedo: color_from_id(&node.id),
-            emission: Vec3::zero(),
+            radius: options.node_radius.radius(node.seen),
+            material: match options.node_material {
+                NodeMaterial::Diffuse => Material::Lambertian { albedo },
+                NodeMaterial::Metal => Material::Metal {
+                    albedo,
+                    fuzz: NODE_METAL_FUZZ,
+                },
+                NodeMaterial::Glass => Material::Dielectric {
+                    ior: NODE_GLASS_IOR,
+                },
+            },
         });
     }
 
@@ -209,14 +1237,16 @@ fn build_spheres(scene: &SceneFile) -> Vec<Sphere> {
             continue;
         }
 
-        let radius = link_radius(edge.seen);
+        let radius = options.link_radius.radius(edge.seen);
         let spacing = (radius * 3.0).max(0.05);
         let steps = ((distance / spacing).ceil() as u32).max(2);
 
-        let base_color = color_from_id(&format!("{}->{}", edge.from, edge.to));
-        let intensity = link_intensity(edge.seen, edge.rtt_delta_ms_avg);
-        let emission = base_color * intensity;
-        let albedo = Vec3::new(0.08, 0.08, 0.08);
+        let base_color = Vec3::from(color_from_id(&format!("{}->{}", edge.from, edge.to)));
+        let intensity = link_intensity(edge, options);
+        let material = Material::Emissive {
+            color: base_color,
+            intensity,
+        };
 
         for i in 1..steps {
             let t = i as f32 / steps as f32;
@@ -224,8 +1254,7 @@ fn build_spheres(scene: &SceneFile) -> Vec<Sphere> {
             spheres.push(Sphere {
                 center,
                 radius,
-                albedo,
-                emission,
+                material,
             });
         }
     }
@@ -233,59 +1262,110 @@ fn build_spheres(scene: &SceneFile) -> Vec<Sphere> {
     spheres
 }
 
-fn build_camera(scene: &SceneFile, settings: &RenderSettings) -> Camera {
-    let mut min = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
-    let mut max = Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+fn node_albedo(
+    node: &SceneNode,
+    incoming_rtt_ms: f32,
+    max_pagerank: f64,
+    options: &SphereBuildOptions,
+) -> Vec3 {
+    match options.node_color_scheme {
+        NodeColorScheme::ById => Vec3::from(color_from_id(&node.id)),
+        NodeColorScheme::ByAsn => match node.asn {
+            Some(asn) => Vec3::from(color_from_id(&format!("AS{asn}"))),
+            None => Vec3::from(color_from_id(&node.id)),
+        },
+        NodeColorScheme::ByLoss => lerp_color(LOSS_CLEAN, LOSS_LOST, node.loss_ratio() as f32),
+        NodeColorScheme::ByRtt => {
+            let rtt = node.rtt_ms_avg.unwrap_or(f64::from(incoming_rtt_ms));
+            let t = (rtt / options.rtt_color_scale_ms.max(1e-3)).clamp(0.0, 1.0);
+            lerp_color(RTT_SCALE_LOW, RTT_SCALE_HIGH, t as f32)
+        }
+        NodeColorScheme::ByPageRank => {
+            let rank = match node.pagerank {
+                Some(score) if max_pagerank > 0.0 => (score / max_pagerank) as f32,
+                _ => 0.0,
+            };
+            let brightness = PAGERANK_MIN_BRIGHTNESS + (1.0 - PAGERANK_MIN_BRIGHTNESS) * rank;
+            Vec3::from(color_from_id(&node.id)) * brightness
+        }
+    }
+}
 
-    for node in &scene.nodes {
-        let pos = Vec3::new(node.position[0], node.position[1], node.position[2]);
-        min = min.min(pos);
-        max = max.max(pos);
+/// Average RTT delta of the edges arriving at each node.
+fn incoming_rtt_by_node(scene: &SceneFile) -> HashMap<&str, f32> {
+    let mut sums: HashMap<&str, (f32, u32)> = HashMap::new();
+    for edge in &scene.edges {
+        let entry = sums.entry(edge.to.as_str()).or_default();
+        entry.0 += edge.rtt_delta_ms_avg as f32;
+        entry.1 += 1;
     }
+    sums.into_iter()
+        .map(|(id, (sum, count))| (id, sum / count as f32))
+        .collect()
+}
 
-    let center = (min + max) * 0.5;
-    let extent = (max - min).length().max(1.0);
-    let distance = extent * 1.6;
+/// Blue for fast hops fading to red as `rtt_ms` passes `knee_ms`.
+pub(crate) fn rtt_color(rtt_ms: f32, knee_ms: f32) -> Vec3 {
+    let rtt = rtt_ms.max(0.0);
+    let knee = knee_ms.max(1e-3);
+    lerp_color(RTT_FAST, RTT_SLOW, rtt / (rtt + knee))
+}
 
-    let look_from = center + Vec3::new(distance, distance * 0.6, distance);
-    let look_at = center;
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let aspect = settings.width as f32 / settings.height as f32;
+fn lerp_color(from: [f32; 3], to: [f32; 3], t: f32) -> Vec3 {
+    Vec3::from(from) * (1.0 - t) + Vec3::from(to) * t
+}
 
-    Camera::new(look_from, look_at, vup, 35.0, aspect)
+pub(crate) fn build_camera(scene: &SceneFile, settings: &RenderSettings) -> Camera {
+    let aspect = settings.width as f32 / settings.height as f32;
+    Camera::new_from_scene(scene, aspect, settings.camera_style)
 }
 
-fn node_radius(seen: u32) -> f32 {
-    let base = 0.15;
-    let scale = (seen.max(1) as f32).ln() * 0.05;
-    base + scale
+/// Sphere radius used for a node that was seen `seen` times.
+pub fn node_radius(seen: u32) -> f32 {
+    RadiusScale {
+        base: NODE_RADIUS_BASE,
+        seen_scale: NODE_RADIUS_SEEN_SCALE,
+        seen_exponent: 1.0,
+    }
+    .radius(seen)
 }
 
-fn link_radius(seen: u32) -> f32 {
-    let base = 0.04;
-    let scale = (seen.max(1) as f32).ln() * 0.01;
-    base + scale
+/// Radius of the small spheres that make up a link seen `seen` times.
+pub fn link_radius(seen: u32) -> f32 {
+    RadiusScale {
+        base: LINK_RADIUS_BASE,
+        seen_scale: LINK_RADIUS_SEEN_SCALE,
+        seen_exponent: 1.0,
+    }
+    .radius(seen)
 }
 
-fn link_intensity(seen: u32, rtt_delta: f64) -> f32 {
-    let freq = (seen.max(1) as f32).ln() + 1.0;
-    let rtt = 1.0 / (1.0 + (rtt_delta.abs() as f32 / 50.0));
-    3.0 * freq * rtt
+fn link_intensity(edge: &SceneEdge, options: &SphereBuildOptions) -> f32 {
+    let freq = (edge.seen.max(1) as f32).ln() + 1.0;
+    let knee = options.emission_rtt_knee_ms.max(f32::EPSILON);
+    let rtt = 1.0 / (1.0 + (edge.rtt_delta_ms_avg.abs() as f32 / knee));
+    let decay = (-(edge.depth as f32) * (options.emission_depth_decay - 1.0)).exp();
+    (3.0 * freq * rtt * decay * options.emission_scale).max(options.emission_min)
 }
 
-fn color_from_id(id: &str) -> Vec3 {
+/// Stable RGB color (each channel in `0.2..=1.0`) for a node or link id.
+///
+/// The color is derived from a 64-bit FNV-1a hash of the id bytes. The hash
+/// and channel mapping will not change across minor versions, so external
+/// tools can rely on matching the colors in ptroute renders.
+pub fn color_from_id(id: &str) -> [f32; 3] {
     let mut hash = 0xcbf29ce484222325u64;
     for byte in id.as_bytes() {
         hash ^= u64::from(*byte);
         hash = hash.wrapping_mul(0x100000001b3);
     }
-    let r = ((hash >> 0) & 0xFF) as f32 / 255.0;
+    let r = (hash & 0xFF) as f32 / 255.0;
     let g = ((hash >> 8) & 0xFF) as f32 / 255.0;
     let b = ((hash >> 16) & 0xFF) as f32 / 255.0;
-    Vec3::new(0.2 + 0.8 * r, 0.2 + 0.8 * g, 0.2 + 0.8 * b)
+    [0.2 + 0.8 * r, 0.2 + 0.8 * g, 0.2 + 0.8 * b]
 }
 
-fn to_rgb(color: Vec3) -> Rgb<u8> {
+pub(crate) fn to_rgb(color: Vec3) -> Rgb<u8> {
     let c = color.clamp01();
     let gamma = Vec3::new(c.x.sqrt(), c.y.sqrt(), c.z.sqrt());
     Rgb([
@@ -295,7 +1375,9 @@ fn to_rgb(color: Vec3) -> Rgb<u8> {
     ])
 }
 
-fn hash_seed(seed: u64, x: u32, y: u32, sample: u32) -> u64 {
+/// Mixes a render seed with pixel coordinates and a sample index into a
+/// per-sample RNG seed (splitmix64 finalizer).
+pub fn hash_seed(seed: u64, x: u32, y: u32, sample: u32) -> u64 {
     let mut v = seed ^ ((x as u64) << 32) ^ ((y as u64) << 16) ^ sample as u64;
     v = v.wrapping_add(0x9e3779b97f4a7c15);
     v = (v ^ (v >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
@@ -303,28 +1385,125 @@ fn hash_seed(seed: u64, x: u32, y: u32, sample: u32) -> u64 {
     v ^ (v >> 31)
 }
 
-struct Rng {
+fn pixel_rng(seed: u64, x: u32, y: u32, sample: u32) -> Rng {
+    Rng::new(hash_seed(seed, x, y, sample))
+}
+
+fn sample_rng(seed: u64, strategy: SeedStrategy, x: u32, y: u32, sample: u32) -> Rng {
+    match strategy {
+        SeedStrategy::GlobalProgression => pixel_rng(seed, x, y, sample),
+        SeedStrategy::PixelIndependent => {
+            let mut rng = pixel_rng(seed, x, y, 0);
+            rng.advance(u64::from(sample) * SAMPLE_STREAM_STRIDE);
+            rng
+        }
+        SeedStrategy::BlueNoise => {
+            let side = BLUE_NOISE_SIZE as u32;
+            let rank = blue_noise_ranks()[((y % side) * side + x % side) as usize];
+            // Seed per 64x64 tile first, so the sample index cannot collide
+            // with the tile coordinates inside a single hash.
+            let tile = hash_seed(seed, x / side, y / side, 0);
+            let index = sample.wrapping_mul(side * side).wrapping_add(rank);
+            pixel_rng(tile, 0, 0, index)
+        }
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Ranks `0..64*64` laid out as blue noise: cells are filled one at a time,
+/// each time picking the free cell with the least energy from a toroidal
+/// Gaussian splat around every filled one, so consecutive ranks land far
+/// apart. Built once on first use.
+fn blue_noise_ranks() -> &'static [u32] {
+    static RANKS: OnceLock<Vec<u32>> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        const SIGMA: f32 = 1.9;
+        let n = BLUE_NOISE_SIZE;
+        let wrap = |d: usize| d.min(n - d) as f32;
+        let kernel: Vec<f32> = (0..n * n)
+            .map(|i| {
+                let (dx, dy) = (wrap(i % n), wrap(i / n));
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+
+        let mut energy = vec![0.0f32; n * n];
+        let mut ranks = vec![u32::MAX; n * n];
+        for rank in 0..(n * n) as u32 {
+            let cell = (0..n * n)
+                .filter(|&cell| ranks[cell] == u32::MAX)
+                .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .expect("a free cell remains");
+            ranks[cell] = rank;
+            let (cx, cy) = (cell % n, cell / n);
+            for (other, value) in energy.iter_mut().enumerate() {
+                let dx = (other % n + n - cx) % n;
+                let dy = (other / n + n - cy) % n;
+                *value += kernel[dy * n + dx];
+            }
+        }
+        ranks
+    })
+}
+
+const RNG_MULTIPLIER: u64 = 6364136223846793005;
+const RNG_INCREMENT: u64 = 1;
+
+/// Small deterministic LCG used for all sampling in the renderer.
+#[derive(Debug, Clone)]
+pub struct Rng {
     state: u64,
 }
 
 impl Rng {
-    fn new(seed: u64) -> Self {
+    pub fn new(seed: u64) -> Self {
         let state = if seed == 0 { 0xdeadbeefcafebabe } else { seed };
         Self { state }
     }
 
-    fn next_u32(&mut self) -> u32 {
+    /// Skips `steps` draws in O(log steps), as if [`Rng::next_u32`] had been
+    /// called that many times.
+    pub fn advance(&mut self, mut steps: u64) {
+        let (mut acc_mult, mut acc_plus) = (1u64, 0u64);
+        let (mut cur_mult, mut cur_plus) = (RNG_MULTIPLIER, RNG_INCREMENT);
+        while steps > 0 {
+            if steps & 1 == 1 {
+                acc_mult = acc_mult.wrapping_mul(cur_mult);
+                acc_plus = acc_plus.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+            }
+            cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+            cur_mult = cur_mult.wrapping_mul(cur_mult);
+            steps >>= 1;
+        }
+        self.state = acc_mult.wrapping_mul(self.state).wrapping_add(acc_plus);
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
         self.state = self
             .state
-            .wrapping_mul(6364136223846793005)
-            .wrapping_add(1);
+            .wrapping_mul(RNG_MULTIPLIER)
+            .wrapping_add(RNG_INCREMENT);
         (self.state >> 32) as u32
     }
 
-    fn next_f32(&mut self) -> f32 {
+    pub fn next_f32(&mut self) -> f32 {
         let value = self.next_u32();
         value as f32 / u32::MAX as f32
     }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+/// Worker threads for an automatic (`threads = 0`) render: one per 256
+/// pixels, at least one and at most `available`. Small images skip the cost
+/// of waking threads that would each get only a few pixels.
+pub fn threads_for_settings(width: u32, height: u32, available: usize) -> usize {
+    let pixels = u64::from(width) * u64::from(height);
+    let wanted = usize::try_from(pixels / PIXELS_PER_THREAD).unwrap_or(usize::MAX);
+    wanted.max(1).min(available.max(1))
 }
 
 fn with_thread_pool<T: Send>(threads: usize, f: impl FnOnce() -> T + Send) -> T {
@@ -338,3 +1517,457 @@ fn with_thread_pool<T: Send>(threads: usize, f: impl FnOnce() -> T + Send) -> T
             .install(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_rng_matches_public_rng() {
+        let mut integrator = pixel_rng(1, 0, 0, 0);
+        let mut public = Rng::new(hash_seed(1, 0, 0, 0));
+        assert_eq!(integrator.next_f32(), public.next_f32());
+        assert_eq!(integrator.next_u32(), public.next_u32());
+    }
+
+    #[test]
+    fn advance_matches_repeated_draws() {
+        let mut stepped = Rng::new(42);
+        for _ in 0..1000 {
+            stepped.next_u32();
+        }
+        let mut jumped = Rng::new(42);
+        jumped.advance(1000);
+        assert_eq!(jumped.next_u32(), stepped.next_u32());
+    }
+
+    #[test]
+    fn blue_noise_ranks_are_a_spread_out_permutation() {
+        let ranks = blue_noise_ranks();
+        let mut sorted = ranks.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..4096).collect::<Vec<u32>>());
+
+        // The first 16 ranks land on a roughly even grid, far from each other.
+        let first: Vec<(i32, i32)> = (0..ranks.len())
+            .filter(|&cell| ranks[cell] < 16)
+            .map(|cell| ((cell % 64) as i32, (cell / 64) as i32))
+            .collect();
+        for (i, a) in first.iter().enumerate() {
+            for b in &first[i + 1..] {
+                let dx = (a.0 - b.0).abs().min(64 - (a.0 - b.0).abs());
+                let dy = (a.1 - b.1).abs().min(64 - (a.1 - b.1).abs());
+                assert!(dx * dx + dy * dy >= 64, "{a:?} and {b:?} are too close");
+            }
+        }
+    }
+
+    #[test]
+    fn cull_spheres_drops_spheres_outside_the_view() {
+        let sphere = |center: Vec3| Sphere {
+            center,
+            radius: 0.5,
+            material: Material::Lambertian {
+                albedo: Vec3::new(0.5, 0.5, 0.5),
+            },
+        };
+        let target = Vec3::new(0.0, 0.0, -10.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        // Looking away from the origin: a sphere there is behind the camera.
+        let away = Camera::new(Vec3::new(0.0, 0.0, -5.0), target, up, 40.0, 1.0);
+        let kept = cull_spheres(vec![sphere(Vec3::zero()), sphere(target)], &away);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].center, target);
+
+        // Far off to the side of the view, and just grazing its edge.
+        let toward = Camera::new(Vec3::zero(), target, up, 40.0, 1.0);
+        let side = sphere(Vec3::new(20.0, 0.0, -10.0));
+        let edge = sphere(Vec3::new(3.9, 0.0, -10.0));
+        assert!(cull_spheres(vec![side], &toward).is_empty());
+        assert_eq!(cull_spheres(vec![edge], &toward).len(), 1);
+
+        let ortho = Camera::new_orthographic(Vec3::zero(), target, up, 4.0, 1.0);
+        let kept = cull_spheres(
+            vec![
+                sphere(target),
+                sphere(Vec3::new(20.0, 0.0, -10.0)),
+                sphere(Vec3::new(0.0, 0.0, 5.0)),
+            ],
+            &ortho,
+        );
+        assert_eq!(kept.len(), 1);
+    }
+
+    fn single_sphere_bvh(material: Material) -> Bvh {
+        Bvh::new(vec![Sphere {
+            center: Vec3::zero(),
+            radius: 1.0,
+            material,
+        }])
+    }
+
+    #[test]
+    fn high_quality_metal_mirrors_the_background() {
+        let bvh = single_sphere_bvh(Material::Metal {
+            albedo: Vec3::new(1.0, 1.0, 1.0),
+            fuzz: 0.0,
+        });
+        // Straight down onto the top of the sphere, so the mirror sends the
+        // ray straight back up into the brightest sky.
+        let ray = Ray {
+            origin: Vec3::new(0.0, 5.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+        };
+        let sky = background(&Ray {
+            origin: Vec3::zero(),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+        });
+        let mut rng = Rng::new(3);
+        let mirrored = trace(&ray, &bvh, 4, true, &mut rng);
+        assert!((mirrored - sky).length() < 1e-5);
+
+        // Without high quality materials the metal scatters diffusely and
+        // sees some of the darker horizon.
+        let diffuse = (0..64)
+            .map(|_| trace(&ray, &bvh, 4, false, &mut rng))
+            .fold(Vec3::zero(), |sum, color| sum + color)
+            / 64.0;
+        assert!(diffuse.length() < sky.length() - 0.05);
+    }
+
+    #[test]
+    fn high_quality_glass_transmits_what_is_behind_it() {
+        let bvh = single_sphere_bvh(Material::Dielectric { ior: 1.5 });
+        let ray = Ray {
+            origin: Vec3::new(0.0, 5.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+        };
+        let ground = background(&ray);
+        let mut rng = Rng::new(5);
+        let samples = 256;
+        let transmitted = (0..samples)
+            .filter(|_| (trace(&ray, &bvh, 6, true, &mut rng) - ground).length() < 1e-4)
+            .count();
+        // Schlick reflects about 4% at normal incidence on each face.
+        assert!(transmitted > samples * 8 / 10, "{transmitted}/{samples}");
+    }
+
+    #[test]
+    fn beads_are_emissive_and_nodes_lambertian() {
+        let spheres = build_spheres(
+            &two_node_scene(),
+            &SphereBuildOptions::from_settings(&RenderSettings::default()),
+        );
+        assert!(spheres
+            .iter()
+            .any(|sphere| matches!(sphere.material, Material::Lambertian { .. })));
+        assert!(spheres
+            .iter()
+            .any(|sphere| matches!(sphere.material, Material::Emissive { .. })));
+        assert!(spheres.iter().all(|sphere| matches!(
+            sphere.material,
+            Material::Lambertian { .. } | Material::Emissive { .. }
+        )));
+    }
+
+    fn two_node_scene() -> SceneFile {
+        SceneFile {
+            version: 1,
+            nodes: vec![
+                SceneNode {
+                    id: "a".to_string(),
+                    position: [0.0, 0.0, 0.0],
+                    seen: 4,
+                    loss_probes: 0,
+                    sent_probes: 0,
+                    pagerank: None,
+                    rtt_ms_avg: None,
+                    asn: None,
+                    label: None,
+                    velocity: [0.0; 3],
+                },
+                SceneNode {
+                    id: "b".to_string(),
+                    position: [3.0, 0.0, 0.0],
+                    seen: 4,
+                    loss_probes: 0,
+                    sent_probes: 0,
+                    pagerank: None,
+                    rtt_ms_avg: None,
+                    asn: None,
+                    label: None,
+                    velocity: [0.0; 3],
+                },
+            ],
+            edges: vec![SceneEdge {
+                from: "a".to_string(),
+                to: "b".to_string(),
+                seen: 4,
+                rtt_delta_ms_avg: 20.0,
+                depth: 0,
+            }],
+        }
+    }
+
+    #[test]
+    fn zero_emission_scale_turns_links_off() {
+        let options = SphereBuildOptions {
+            emission_scale: 0.0,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let spheres = build_spheres(&two_node_scene(), &options);
+        assert!(spheres.len() > 2);
+        assert!(spheres
+            .iter()
+            .all(|sphere| sphere.material.emission() == Vec3::zero()));
+    }
+
+    #[test]
+    fn emission_min_keeps_links_lit() {
+        let options = SphereBuildOptions {
+            emission_scale: 0.0,
+            emission_min: 0.5,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let spheres = build_spheres(&two_node_scene(), &options);
+        assert!(spheres[2..]
+            .iter()
+            .all(|sphere| sphere.material.emission() != Vec3::zero()));
+    }
+
+    #[test]
+    fn emission_depth_decay_dims_deeper_links() {
+        let edge = |depth: u32| SceneEdge {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            seen: 4,
+            rtt_delta_ms_avg: 20.0,
+            depth,
+        };
+        let intensities = |decay: f32| -> Vec<f32> {
+            let options = SphereBuildOptions {
+                emission_depth_decay: decay,
+                ..SphereBuildOptions::from_settings(&RenderSettings::default())
+            };
+            (0..6)
+                .map(|depth| link_intensity(&edge(depth), &options))
+                .collect()
+        };
+
+        let flat = intensities(1.0);
+        assert!(flat[0] > 0.0);
+        assert!(flat.iter().all(|&intensity| intensity == flat[0]));
+
+        let decayed = intensities(2.0);
+        assert_eq!(decayed[0], flat[0]);
+        assert!(decayed.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!((decayed[3] - flat[0] * (-3.0f32).exp()).abs() < 1e-6);
+
+        // Spheres built from the scene carry the same decay.
+        let mut scene = two_node_scene();
+        scene.edges[0].depth = 3;
+        let options = SphereBuildOptions {
+            emission_depth_decay: 2.0,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let spheres = build_spheres(&scene, &options);
+        let Material::Emissive { intensity, .. } = spheres[2].material else {
+            panic!("expected an emissive link bead");
+        };
+        assert_eq!(intensity, decayed[3]);
+
+        // Below 1.0 the decay is clamped instead of growing with depth.
+        for decay in [0.5, -3.0, f32::NAN] {
+            let settings = RenderSettings {
+                emission_depth_decay: decay,
+                ..RenderSettings::default()
+            };
+            let options = SphereBuildOptions::from_settings(&settings);
+            assert_eq!(link_intensity(&edge(5), &options), flat[0]);
+        }
+    }
+
+    #[test]
+    fn zero_exponent_gives_uniform_node_radius() {
+        let settings = RenderSettings {
+            node_radius_base: 0.3,
+            node_radius_seen_exponent: 0.0,
+            ..RenderSettings::default()
+        };
+        let mut scene = two_node_scene();
+        scene.nodes[1].seen = 1000;
+        scene.edges.clear();
+
+        let spheres = build_spheres(&scene, &SphereBuildOptions::from_settings(&settings));
+        assert_eq!(spheres.len(), 2);
+        assert!(spheres.iter().all(|sphere| sphere.radius == 0.3));
+    }
+
+    #[test]
+    fn default_settings_match_public_radii() {
+        let options = SphereBuildOptions::from_settings(&RenderSettings::default());
+        for seen in [0, 1, 7, 1000] {
+            assert_eq!(options.node_radius.radius(seen), node_radius(seen));
+            assert_eq!(options.link_radius.radius(seen), link_radius(seen));
+        }
+    }
+
+    #[test]
+    fn loss_scheme_runs_from_green_to_red() {
+        let options = SphereBuildOptions {
+            node_color_scheme: NodeColorScheme::ByLoss,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let mut scene = two_node_scene();
+        scene.nodes[0].loss_probes = 0;
+        scene.nodes[0].sent_probes = 20;
+        scene.nodes[1].loss_probes = 20;
+        scene.nodes[1].sent_probes = 20;
+
+        let spheres = build_spheres(&scene, &options);
+        assert_eq!(spheres[0].material.albedo(), Vec3::new(0.2, 0.8, 0.2));
+        assert_eq!(spheres[1].material.albedo(), Vec3::new(0.8, 0.2, 0.2));
+
+        // Half the probes lost, whatever --probes was and however often
+        // the node was seen.
+        scene.nodes[1].loss_probes = 10;
+        let spheres = build_spheres(&scene, &options);
+        assert!((spheres[1].material.albedo() - Vec3::new(0.5, 0.5, 0.2)).length() < 1e-6);
+    }
+
+    #[test]
+    fn rtt_scheme_reddens_slow_nodes() {
+        let options = SphereBuildOptions {
+            node_color_scheme: NodeColorScheme::ByRtt,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let mut scene = two_node_scene();
+        let spheres = build_spheres(&scene, &options);
+        assert_eq!(spheres[0].material.albedo(), Vec3::from(RTT_SCALE_LOW));
+        assert!(spheres[1].material.albedo().x > spheres[0].material.albedo().x);
+        assert!(spheres[1].material.albedo().z < spheres[0].material.albedo().z);
+
+        // The incoming-link fallback shares the palette of the node's own RTT.
+        let fallback = spheres[1].material.albedo();
+        scene.nodes[1].rtt_ms_avg = Some(scene.edges[0].rtt_delta_ms_avg);
+        assert_eq!(
+            build_spheres(&scene, &options)[1].material.albedo(),
+            fallback
+        );
+    }
+
+    #[test]
+    fn rtt_scheme_scales_node_rtt_from_blue_to_red() {
+        let options = SphereBuildOptions {
+            node_color_scheme: NodeColorScheme::ByRtt,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let mut scene = two_node_scene();
+        scene.nodes[0].rtt_ms_avg = Some(0.0);
+        scene.nodes[1].rtt_ms_avg = Some(450.0);
+
+        let spheres = build_spheres(&scene, &options);
+        let fast = spheres[0].material.albedo();
+        let slow = spheres[1].material.albedo();
+        assert_eq!(fast, Vec3::from(RTT_SCALE_LOW));
+        assert!(fast.z > fast.x && fast.z > fast.y);
+        assert_eq!(slow, Vec3::from(RTT_SCALE_HIGH));
+        assert!(slow.x > slow.y && slow.x > slow.z);
+
+        scene.nodes[1].rtt_ms_avg = Some(100.0);
+        let half = build_spheres(&scene, &options)[1].material.albedo();
+        assert!((half.x - 0.45).abs() < 1e-6 && (half.z - 0.45).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pagerank_scheme_dims_low_ranked_nodes() {
+        let options = SphereBuildOptions {
+            node_color_scheme: NodeColorScheme::ByPageRank,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let mut scene = two_node_scene();
+        scene.nodes[0].pagerank = Some(0.2);
+        scene.nodes[1].pagerank = Some(0.8);
+
+        let spheres = build_spheres(&scene, &options);
+        let full = |id: &str| Vec3::from(color_from_id(id));
+        assert_eq!(spheres[1].material.albedo(), full(&scene.nodes[1].id));
+        assert!(spheres[0].material.albedo().length() < full(&scene.nodes[0].id).length());
+    }
+
+    #[test]
+    fn color_scheme_parses_cli_names() {
+        assert_eq!("loss".parse(), Ok(NodeColorScheme::ByLoss));
+        assert_eq!("RTT".parse(), Ok(NodeColorScheme::ByRtt));
+        assert_eq!("pagerank".parse(), Ok(NodeColorScheme::ByPageRank));
+        assert!("hue".parse::<NodeColorScheme>().is_err());
+        assert_eq!("asn".parse(), Ok(NodeColorScheme::ByAsn));
+    }
+
+    #[test]
+    fn asn_colors_group_nodes_by_asn() {
+        let options = SphereBuildOptions {
+            node_color_scheme: NodeColorScheme::ByAsn,
+            ..SphereBuildOptions::from_settings(&RenderSettings::default())
+        };
+        let mut scene = two_node_scene();
+        let albedos = |scene: &SceneFile| {
+            build_spheres(scene, &options)
+                .iter()
+                .take(scene.nodes.len())
+                .map(|sphere| sphere.material.albedo())
+                .collect::<Vec<_>>()
+        };
+
+        let by_id = [color_from_id("a"), color_from_id("b")].map(Vec3::from);
+        assert_eq!(albedos(&scene), by_id);
+
+        scene.nodes[0].asn = Some(64500);
+        scene.nodes[1].asn = Some(64500);
+        let shared = albedos(&scene);
+        assert_eq!(shared[0], shared[1]);
+        assert_eq!(shared[0], Vec3::from(color_from_id("AS64500")));
+
+        scene.nodes[1].asn = Some(64501);
+        let split = albedos(&scene);
+        assert_ne!(split[0], split[1]);
+    }
+
+    #[test]
+    fn node_material_applies_to_nodes_only() {
+        let materials = |node_material: NodeMaterial| {
+            let options = SphereBuildOptions {
+                node_material,
+                ..SphereBuildOptions::from_settings(&RenderSettings::default())
+            };
+            build_spheres(&two_node_scene(), &options)
+                .into_iter()
+                .map(|sphere| sphere.material)
+                .collect::<Vec<_>>()
+        };
+        let albedo = Vec3::from(color_from_id("a"));
+
+        assert_eq!(
+            materials(NodeMaterial::Diffuse)[0],
+            Material::Lambertian { albedo }
+        );
+        let metal = materials(NodeMaterial::Metal);
+        assert_eq!(
+            metal[0],
+            Material::Metal {
+                albedo,
+                fuzz: NODE_METAL_FUZZ
+            }
+        );
+        let glass = materials(NodeMaterial::Glass);
+        assert!(matches!(glass[1], Material::Dielectric { .. }));
+        for spheres in [metal, glass] {
+            assert!(spheres[2..]
+                .iter()
+                .all(|material| matches!(material, Material::Emissive { .. })));
+        }
+        assert_eq!("Glass".parse(), Ok(NodeMaterial::Glass));
+        assert!("chrome".parse::<NodeMaterial>().is_err());
+    }
+}
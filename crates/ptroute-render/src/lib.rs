@@ -1,11 +1,94 @@
-//! CPU path tracer and image output.
+//! CPU path tracer (with an optional wgpu port behind the `gpu` feature)
+//! and image output.
 
+#[cfg(feature = "tokio")]
+pub mod async_render;
 pub mod bvh;
 pub mod camera;
+pub mod compare;
 pub mod geometry;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod image_out;
 pub mod integrator;
+mod label;
 pub mod math;
+pub mod obj_out;
+pub mod svg_out;
 
-pub use integrator::{render_scene, render_scene_progressive, RenderSettings};
-pub use image_out::write_png;
+pub use bvh::{BvhQualityReport, BvhStats};
+pub use camera::{Camera, CameraStyle};
+pub use compare::{compose_comparison, render_scene_comparison};
+#[cfg(feature = "gpu")]
+pub use gpu::{render_scene_gpu, GpuError};
+pub use image_out::{write_animated_png, write_png};
+pub use integrator::{
+    color_from_id, hash_seed, link_radius, node_radius, render_scene, render_scene_importance_map,
+    render_scene_progressive, render_scene_streaming, scene_bvh, threads_for_settings,
+    NodeColorScheme, NodeMaterial, ProgressFn, RenderSettings, RenderSettingsBuilder, Rng,
+    SeedStrategy, TileOrder,
+};
+pub use obj_out::{to_obj, ObjExport};
+pub use svg_out::to_svg;
+
+use ptroute_model::SceneFile;
+use std::error::Error;
+use std::path::Path;
+
+/// Renders `scene` and writes it to `path` as a PNG with [`write_png`].
+pub fn render_scene_to_file(
+    scene: &SceneFile,
+    settings: &RenderSettings,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let image = render_scene(scene, settings);
+    write_png(path, &image)?;
+    Ok(())
+}
+
+/// [`render_scene_progressive`] that rewrites `path` after every pass,
+/// calling `on_update` with the samples per pixel written so far. After a
+/// failed write the remaining passes still render but are not written, and
+/// the first error is returned.
+pub fn render_scene_progressive_to_file<F>(
+    scene: &SceneFile,
+    settings: &RenderSettings,
+    progressive_every: u32,
+    path: &Path,
+    mut on_update: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(u32),
+{
+    let mut write_error = None;
+    render_scene_progressive(scene, settings, progressive_every, |image, done| {
+        if write_error.is_some() {
+            return;
+        }
+        match write_png(path, image) {
+            Ok(()) => on_update(done),
+            Err(err) => write_error = Some(err),
+        }
+    });
+    match write_error {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// Renders `scene` progressively, keeping every `pass_size`-sample pass as
+/// one frame, and writes the convergence as an APNG with
+/// [`write_animated_png`].
+pub fn render_scene_progressive_to_apng(
+    scene: &SceneFile,
+    settings: &RenderSettings,
+    pass_size: u32,
+    fps: u32,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut frames = Vec::new();
+    render_scene_progressive(scene, settings, pass_size, |image, _| {
+        frames.push(image.clone())
+    });
+    write_animated_png(path, &frames, fps)
+}
@@ -58,10 +58,36 @@ impl Vec3 {
     pub fn mul_elem(self, other: Self) -> Self {
         Self::new(self.x * other.x, self.y * other.y, self.z * other.z)
     }
+
+    /// Mirrors `self` about the unit `normal`.
+    pub fn reflect(self, normal: Self) -> Self {
+        self - normal * (2.0 * self.dot(normal))
+    }
+
+    /// Refracts the unit vector `self` through a surface with unit `normal`
+    /// facing against it, where `eta_ratio` is the incident over the
+    /// transmitted index of refraction. Returns `None` on total internal
+    /// reflection.
+    pub fn refract(self, normal: Self, eta_ratio: f32) -> Option<Self> {
+        let cos_theta = (self * -1.0).dot(normal).min(1.0);
+        let sin2_theta = 1.0 - cos_theta * cos_theta;
+        if eta_ratio * eta_ratio * sin2_theta > 1.0 {
+            return None;
+        }
+        let perpendicular = (self + normal * cos_theta) * eta_ratio;
+        let parallel = normal * -(1.0 - perpendicular.dot(perpendicular)).abs().sqrt();
+        Some(perpendicular + parallel)
+    }
 }
 
 use std::ops::{Add, Div, Mul, Sub};
 
+impl From<[f32; 3]> for Vec3 {
+    fn from(value: [f32; 3]) -> Self {
+        Self::new(value[0], value[1], value[2])
+    }
+}
+
 impl Add for Vec3 {
     type Output = Self;
 
@@ -102,6 +128,33 @@ impl Mul<Vec3> for f32 {
     }
 }
 
+/// A plane `x * p.x + y * p.y + z * p.z + w = 0`, as returned by
+/// [`crate::Camera::frustum_planes`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vec4 {
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    /// The plane through `point` facing `normal` (which must be unit length).
+    pub fn plane(normal: Vec3, point: Vec3) -> Self {
+        Self::new(normal.x, normal.y, normal.z, -normal.dot(point))
+    }
+
+    /// Signed distance from this plane to `point`; positive on the side the
+    /// normal points to.
+    pub fn distance(self, point: Vec3) -> f32 {
+        self.x * point.x + self.y * point.y + self.z * point.z + self.w
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3,
@@ -113,3 +166,99 @@ impl Ray {
         self.origin + self.direction * t
     }
 }
+
+/// Z-order (Morton) index of `(x, y)`: the bits of `x` land in the even
+/// positions and the bits of `y` in the odd ones.
+pub fn morton_encode(x: u32, y: u32) -> u64 {
+    spread_bits(x) | (spread_bits(y) << 1)
+}
+
+/// Index of `(x, y)` along the Hilbert curve filling an `n`x`n` grid, where
+/// `n` is a power of two and both coordinates are below it. Unlike Z-order,
+/// consecutive indices are always edge-adjacent cells.
+pub fn hilbert_encode(n: u32, x: u32, y: u32) -> u64 {
+    let (mut x, mut y) = (x, y);
+    let mut index = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the sub-curve starts where this one enters.
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+fn spread_bits(value: u32) -> u64 {
+    let mut v = value as u64;
+    v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+    v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+    v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+    v = (v | (v << 1)) & 0x5555_5555_5555_5555;
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn morton_interleaves_bits() {
+        assert_eq!(morton_encode(0, 0), 0);
+        assert_eq!(morton_encode(1, 0), 1);
+        assert_eq!(morton_encode(0, 1), 2);
+        assert_eq!(morton_encode(3, 3), 15);
+        assert_eq!(morton_encode(2, 1), 6);
+        assert_eq!(morton_encode(u32::MAX, 0), 0x5555_5555_5555_5555);
+    }
+
+    #[test]
+    fn hilbert_walks_between_neighbours() {
+        assert_eq!(hilbert_encode(1, 0, 0), 0);
+        let quadrants: Vec<u64> = [(0, 0), (0, 1), (1, 1), (1, 0)]
+            .iter()
+            .map(|&(x, y)| hilbert_encode(2, x, y))
+            .collect();
+        assert_eq!(quadrants, vec![0, 1, 2, 3]);
+
+        let n = 16;
+        let mut cells: Vec<(u64, u32, u32)> = (0..n)
+            .flat_map(|y| (0..n).map(move |x| (hilbert_encode(n, x, y), x, y)))
+            .collect();
+        cells.sort();
+        for (i, pair) in cells.windows(2).enumerate() {
+            assert_eq!(pair[0].0, i as u64);
+            let step = pair[0].1.abs_diff(pair[1].1) + pair[0].2.abs_diff(pair[1].2);
+            assert_eq!(step, 1, "{:?} -> {:?}", pair[0], pair[1]);
+        }
+        assert_eq!(cells.last().unwrap().0, (n * n - 1) as u64);
+    }
+
+    #[test]
+    fn reflect_and_refract_follow_snell() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let incoming = Vec3::new(1.0, -1.0, 0.0).normalized();
+        assert_eq!(
+            incoming.reflect(normal),
+            Vec3::new(incoming.x, -incoming.y, 0.0)
+        );
+
+        let refracted = incoming.refract(normal, 1.0 / 1.5).unwrap();
+        let sin_in = incoming.x;
+        let sin_out = refracted.x / refracted.length();
+        assert!((sin_in - 1.5 * sin_out).abs() < 1e-5);
+        assert!(refracted.y < 0.0);
+
+        // Leaving glass at 45 degrees exceeds the critical angle.
+        assert_eq!(incoming.refract(normal, 1.5), None);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::integrator::{color_from_id, link_radius, node_radius, rtt_color, RenderSettings};
+use crate::math::Vec3;
+use ptroute_model::SceneFile;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt::Write;
+
+/// Meridians of each node's UV sphere.
+pub const OBJ_SPHERE_LONGITUDES: usize = 8;
+/// Bands between the poles of each node's UV sphere.
+pub const OBJ_SPHERE_LATITUDES: usize = 4;
+/// Vertices written per node: both poles plus the rings in between.
+pub const OBJ_SPHERE_VERTICES: usize = 2 + OBJ_SPHERE_LONGITUDES * (OBJ_SPHERE_LATITUDES - 1);
+/// Vertices written per link: two squares, one around each end.
+pub const OBJ_LINK_VERTICES: usize = 8;
+
+/// A scene as Wavefront OBJ text plus the MTL library it points to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjExport {
+    pub obj: String,
+    pub mtl: String,
+}
+
+/// Exports `scene` as meshes for Blender, three.js and the like.
+///
+/// Every node is a UV sphere sized like the rendered one, with a material in
+/// its `id` color. Every link is an open square prism between its nodes,
+/// `link_radius` thick and colored blue to red by `rtt_delta_ms_avg`; links
+/// to unknown nodes or onto themselves are left out. The OBJ names
+/// `mtl_file` in its `mtllib` line, so write the MTL text under that name
+/// next to it.
+pub fn to_obj(scene: &SceneFile, mtl_file: &str) -> ObjExport {
+    let knee_ms = RenderSettings::default().emission_rtt_knee_ms;
+    let mut obj = String::new();
+    let mut mtl = String::new();
+    let _ = writeln!(obj, "# ptroute scene");
+    let _ = writeln!(obj, "mtllib {mtl_file}");
+
+    let mut mesh = Mesh::default();
+    let mut positions: HashMap<&str, Vec3> = HashMap::new();
+    for (index, node) in scene.nodes.iter().enumerate() {
+        positions
+            .entry(node.id.as_str())
+            .or_insert(Vec3::from(node.position));
+        let name = format!("node_{index}");
+        write_material(&mut mtl, &name, Vec3::from(color_from_id(&node.id)));
+        let _ = writeln!(obj, "o {}", object_name(&node.id));
+        let _ = writeln!(obj, "usemtl {name}");
+        mesh.sphere(&mut obj, Vec3::from(node.position), node_radius(node.seen));
+    }
+
+    for (index, edge) in scene.edges.iter().enumerate() {
+        let (Some(&from), Some(&to)) = (
+            positions.get(edge.from.as_str()),
+            positions.get(edge.to.as_str()),
+        ) else {
+            continue;
+        };
+        if (to - from).length() <= f32::EPSILON {
+            continue;
+        }
+        let name = format!("link_{index}");
+        write_material(
+            &mut mtl,
+            &name,
+            rtt_color(edge.rtt_delta_ms_avg as f32, knee_ms),
+        );
+        let _ = writeln!(
+            obj,
+            "o {}",
+            object_name(&format!("{}->{}", edge.from, edge.to))
+        );
+        let _ = writeln!(obj, "usemtl {name}");
+        mesh.prism(&mut obj, from, to, link_radius(edge.seen));
+    }
+
+    ObjExport { obj, mtl }
+}
+
+/// Running 1-based index of the next vertex (and its normal).
+#[derive(Default)]
+struct Mesh {
+    written: usize,
+}
+
+impl Mesh {
+    fn vertex(&mut self, out: &mut String, position: Vec3, normal: Vec3) -> usize {
+        let _ = writeln!(out, "v {} {} {}", position.x, position.y, position.z);
+        let _ = writeln!(out, "vn {} {} {}", normal.x, normal.y, normal.z);
+        self.written += 1;
+        self.written
+    }
+
+    fn sphere(&mut self, out: &mut String, center: Vec3, radius: f32) {
+        let mut point =
+            |out: &mut String, normal: Vec3| self.vertex(out, center + normal * radius, normal);
+        let north = point(out, Vec3::new(0.0, 1.0, 0.0));
+        let rings: Vec<Vec<usize>> = (1..OBJ_SPHERE_LATITUDES)
+            .map(|band| {
+                let theta = PI * band as f32 / OBJ_SPHERE_LATITUDES as f32;
+                (0..OBJ_SPHERE_LONGITUDES)
+                    .map(|meridian| {
+                        let phi = 2.0 * PI * meridian as f32 / OBJ_SPHERE_LONGITUDES as f32;
+                        let normal = Vec3::new(
+                            theta.sin() * phi.cos(),
+                            theta.cos(),
+                            theta.sin() * phi.sin(),
+                        );
+                        point(out, normal)
+                    })
+                    .collect()
+            })
+            .collect();
+        let south = point(out, Vec3::new(0.0, -1.0, 0.0));
+
+        // Counter-clockwise seen from outside; phi turns from +X toward +Z,
+        // which is clockwise seen from above.
+        for j in 0..OBJ_SPHERE_LONGITUDES {
+            let next = (j + 1) % OBJ_SPHERE_LONGITUDES;
+            face(out, &[north, rings[0][next], rings[0][j]]);
+            for pair in rings.windows(2) {
+                let (upper, lower) = (&pair[0], &pair[1]);
+                face(out, &[upper[j], upper[next], lower[next], lower[j]]);
+            }
+            let last = &rings[rings.len() - 1];
+            face(out, &[south, last[j], last[next]]);
+        }
+    }
+
+    fn prism(&mut self, out: &mut String, from: Vec3, to: Vec3, radius: f32) {
+        let axis = (to - from).normalized();
+        let helper = if axis.y.abs() < 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u = axis.cross(helper).normalized();
+        let v = axis.cross(u);
+        let sides = [u, v, u * -1.0, v * -1.0];
+
+        let start: Vec<usize> = sides
+            .iter()
+            .map(|&side| self.vertex(out, from + side * radius, side))
+            .collect();
+        let end: Vec<usize> = sides
+            .iter()
+            .map(|&side| self.vertex(out, to + side * radius, side))
+            .collect();
+        for k in 0..sides.len() {
+            let next = (k + 1) % sides.len();
+            face(out, &[start[k], start[next], end[next], end[k]]);
+        }
+    }
+}
+
+fn face(out: &mut String, vertices: &[usize]) {
+    out.push('f');
+    for vertex in vertices {
+        let _ = write!(out, " {vertex}//{vertex}");
+    }
+    out.push('\n');
+}
+
+fn write_material(out: &mut String, name: &str, color: Vec3) {
+    let _ = writeln!(out, "newmtl {name}");
+    let _ = writeln!(out, "Kd {} {} {}", color.x, color.y, color.z);
+    out.push('\n');
+}
+
+/// OBJ names end at whitespace, so replace it.
+fn object_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join("_")
+}
@@ -0,0 +1,140 @@
+use crate::camera::Camera;
+use crate::integrator::{
+    build_camera, color_from_id, link_radius, node_radius, rtt_color, to_rgb, RenderSettings,
+};
+use crate::math::Vec3;
+use ptroute_model::SceneFile;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const BACKGROUND: &str = "#101418";
+// Keeps tiny or distant nodes and links visible.
+const MIN_NODE_RADIUS_PX: f32 = 1.0;
+const MIN_LINK_WIDTH_PX: f32 = 0.5;
+
+/// Draws `scene` as an SVG from the same viewpoint as [`crate::render_scene`].
+///
+/// Nodes are circles in their `id` color, sized like the rendered spheres.
+/// Links are lines whose width follows `seen` and whose color runs blue to red
+/// with `rtt_delta_ms_avg`. Anything behind the camera is left out.
+pub fn to_svg(scene: &SceneFile, width: u32, height: u32) -> String {
+    let settings = RenderSettings {
+        width,
+        height,
+        ..RenderSettings::default()
+    };
+    let camera = build_camera(scene, &settings);
+    let view = View {
+        camera: &camera,
+        width: width as f32,
+        height: height as f32,
+    };
+
+    let mut points = HashMap::new();
+    for node in &scene.nodes {
+        let center = Vec3::new(node.position[0], node.position[1], node.position[2]);
+        if let Some(point) = view.point(center) {
+            points.insert(node.id.as_str(), (center, point));
+        }
+    }
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\">"
+    );
+    let _ = writeln!(
+        out,
+        "  <rect width=\"{width}\" height=\"{height}\" fill=\"{BACKGROUND}\"/>"
+    );
+
+    out.push_str("  <g stroke-linecap=\"round\">\n");
+    for edge in &scene.edges {
+        let (Some(&(from, a)), Some(&(to, b))) =
+            (points.get(edge.from.as_str()), points.get(edge.to.as_str()))
+        else {
+            continue;
+        };
+        let stroke_width = view
+            .size((from + to) * 0.5, link_radius(edge.seen) * 2.0)
+            .max(MIN_LINK_WIDTH_PX);
+        let color = rtt_color(edge.rtt_delta_ms_avg as f32, settings.emission_rtt_knee_ms);
+        let _ = writeln!(
+            out,
+            "    <line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" \
+             stroke-width=\"{:.2}\"/>",
+            a.0,
+            a.1,
+            b.0,
+            b.1,
+            hex(color),
+            stroke_width
+        );
+    }
+    out.push_str("  </g>\n");
+
+    out.push_str("  <g>\n");
+    for node in &scene.nodes {
+        let Some(&(center, (x, y))) = points.get(node.id.as_str()) else {
+            continue;
+        };
+        let radius = view
+            .size(center, node_radius(node.seen))
+            .max(MIN_NODE_RADIUS_PX);
+        let _ = writeln!(
+            out,
+            "    <circle cx=\"{x:.2}\" cy=\"{y:.2}\" r=\"{radius:.2}\" fill=\"{}\">\
+             <title>{}</title></circle>",
+            hex(Vec3::from(color_from_id(&node.id))),
+            escape_xml(node.label.as_deref().unwrap_or(&node.id))
+        );
+    }
+    out.push_str("  </g>\n");
+
+    out.push_str("</svg>\n");
+    out
+}
+
+struct View<'a> {
+    camera: &'a Camera,
+    width: f32,
+    height: f32,
+}
+
+impl View<'_> {
+    /// Pixel coordinates of `point`, origin top-left.
+    fn point(&self, point: Vec3) -> Option<(f32, f32)> {
+        let (u, v) = self.camera.project(point)?;
+        Some((u * self.width, (1.0 - v) * self.height))
+    }
+
+    /// On-screen length of a world-space `size` placed upright at `at`.
+    fn size(&self, at: Vec3, size: f32) -> f32 {
+        match (self.point(at), self.point(at + Vec3::new(0.0, size, 0.0))) {
+            (Some(a), Some(b)) => ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt(),
+            _ => 0.0,
+        }
+    }
+}
+
+fn hex(color: Vec3) -> String {
+    let rgb = to_rgb(color);
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
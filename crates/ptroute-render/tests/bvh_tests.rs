@@ -1,11 +1,12 @@
-use ptroute_render::bvh::Bvh;
-use ptroute_render::geometry::Sphere;
+use ptroute_render::bvh::{Bvh, BvhQualityReport, BvhStats, LEAF_SIZE};
+use ptroute_render::geometry::{Material, Sphere, Triangle};
 use ptroute_render::math::{Ray, Vec3};
+use ptroute_render::Rng;
 
 #[test]
 fn bvh_hit_matches_bruteforce() {
     let mut spheres = Vec::new();
-    let mut rng = TestRng::new(1);
+    let mut rng = Rng::new(1);
 
     for _ in 0..64 {
         let center = Vec3::new(
@@ -17,8 +18,9 @@ fn bvh_hit_matches_bruteforce() {
         spheres.push(Sphere {
             center,
             radius,
-            albedo: Vec3::new(0.5, 0.5, 0.5),
-            emission: Vec3::zero(),
+            material: Material::Lambertian {
+                albedo: Vec3::new(0.5, 0.5, 0.5),
+            },
         });
     }
 
@@ -60,28 +62,250 @@ fn brute_hit(ray: &Ray, spheres: &[Sphere]) -> Option<ptroute_render::geometry::
     closest
 }
 
-struct TestRng {
-    state: u64,
+#[test]
+fn refit_grows_bounds_and_keeps_hits_correct() {
+    let mut rng = Rng::new(7);
+    let spheres: Vec<Sphere> = (0..32)
+        .map(|_| Sphere {
+            center: Vec3::new(
+                rng.range(-5.0, 5.0),
+                rng.range(-5.0, 5.0),
+                rng.range(-5.0, 5.0),
+            ),
+            radius: rng.range(0.2, 0.6),
+            material: Material::Lambertian {
+                albedo: Vec3::new(0.5, 0.5, 0.5),
+            },
+        })
+        .collect();
+    let mut bvh = Bvh::new(spheres);
+
+    bvh.spheres_mut()[5].radius = 4.0;
+    bvh.refit();
+
+    let grown = bvh.spheres()[5].clone();
+    let (min, max) = bvh.bounds();
+    let r = Vec3::new(grown.radius, grown.radius, grown.radius);
+    let (lo, hi) = (grown.center - r, grown.center + r);
+    assert!(min.x <= lo.x && min.y <= lo.y && min.z <= lo.z);
+    assert!(max.x >= hi.x && max.y >= hi.y && max.z >= hi.z);
+
+    let spheres = bvh.spheres().to_vec();
+    for _ in 0..128 {
+        let origin = Vec3::new(
+            rng.range(-8.0, 8.0),
+            rng.range(-8.0, 8.0),
+            rng.range(-8.0, 8.0),
+        );
+        let direction = Vec3::new(
+            rng.range(-1.0, 1.0),
+            rng.range(-1.0, 1.0),
+            rng.range(-1.0, 1.0),
+        )
+        .normalized();
+        let ray = Ray { origin, direction };
+
+        let brute = brute_hit(&ray, &spheres);
+        let bvh_hit = bvh.hit(&ray, 0.001, f32::INFINITY);
+
+        assert_eq!(brute.is_some(), bvh_hit.is_some());
+        if let (Some(a), Some(b)) = (brute, bvh_hit) {
+            assert!((a.t - b.t).abs() < 1e-3);
+        }
+    }
+}
+
+fn unit_triangle() -> Triangle {
+    Triangle {
+        v0: Vec3::new(0.0, 0.0, 0.0),
+        v1: Vec3::new(1.0, 0.0, 0.0),
+        v2: Vec3::new(0.0, 1.0, 0.0),
+        material: Material::Lambertian {
+            albedo: Vec3::new(0.5, 0.5, 0.5),
+        },
+    }
+}
+
+#[test]
+fn triangle_hit_returns_the_facing_normal() {
+    let triangle = unit_triangle();
+    let down = Ray {
+        origin: Vec3::new(0.25, 0.25, 2.0),
+        direction: Vec3::new(0.0, 0.0, -1.0),
+    };
+    let hit = triangle.hit(&down, 0.001, f32::INFINITY).unwrap();
+    assert!((hit.t - 2.0).abs() < 1e-6);
+    assert_eq!(hit.point, Vec3::new(0.25, 0.25, 0.0));
+    assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+    // Two-sided: from below the normal points down.
+    let up = Ray {
+        origin: Vec3::new(0.25, 0.25, -2.0),
+        direction: Vec3::new(0.0, 0.0, 1.0),
+    };
+    let hit = triangle.hit(&up, 0.001, f32::INFINITY).unwrap();
+    assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+    assert!(triangle.hit(&up, 0.001, 1.0).is_none());
+}
+
+#[test]
+fn triangle_miss_returns_none() {
+    let triangle = unit_triangle();
+    for (origin, direction) in [
+        // Past the hypotenuse.
+        (Vec3::new(0.75, 0.75, 2.0), Vec3::new(0.0, 0.0, -1.0)),
+        // Outside along each edge.
+        (Vec3::new(-0.1, 0.5, 2.0), Vec3::new(0.0, 0.0, -1.0)),
+        (Vec3::new(0.5, -0.1, 2.0), Vec3::new(0.0, 0.0, -1.0)),
+        // Parallel to the plane.
+        (Vec3::new(-1.0, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+        // Pointing away.
+        (Vec3::new(0.25, 0.25, 2.0), Vec3::new(0.0, 0.0, 1.0)),
+    ] {
+        let ray = Ray { origin, direction };
+        assert!(
+            triangle.hit(&ray, 0.001, f32::INFINITY).is_none(),
+            "{origin:?}"
+        );
+    }
 }
 
-impl TestRng {
-    fn new(seed: u64) -> Self {
-        Self { state: seed }
+#[test]
+fn bvh_with_triangles_matches_bruteforce() {
+    let mut rng = Rng::new(3);
+    let spheres = grid_spheres(40);
+    let material = Material::Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    };
+    // A floor in y = -1 (flat bounds on one axis) plus scattered triangles.
+    let mut triangles = vec![
+        Triangle {
+            v0: Vec3::new(-10.0, -1.0, -10.0),
+            v1: Vec3::new(10.0, -1.0, -10.0),
+            v2: Vec3::new(10.0, -1.0, 10.0),
+            material,
+        },
+        Triangle {
+            v0: Vec3::new(-10.0, -1.0, -10.0),
+            v1: Vec3::new(10.0, -1.0, 10.0),
+            v2: Vec3::new(-10.0, -1.0, 10.0),
+            material,
+        },
+    ];
+    for _ in 0..16 {
+        let corner = Vec3::new(
+            rng.range(-5.0, 5.0),
+            rng.range(-5.0, 5.0),
+            rng.range(-5.0, 5.0),
+        );
+        triangles.push(Triangle {
+            v0: corner,
+            v1: corner + Vec3::new(rng.range(0.5, 2.0), 0.0, 0.0),
+            v2: corner + Vec3::new(0.0, rng.range(0.5, 2.0), rng.range(-1.0, 1.0)),
+            material,
+        });
     }
+    let bvh = Bvh::with_triangles(spheres.clone(), triangles.clone());
+    assert_eq!(
+        bvh.stats().total_primitives,
+        spheres.len() + triangles.len()
+    );
+
+    let mut floor_hits = 0;
+    for _ in 0..256 {
+        let origin = Vec3::new(
+            rng.range(-8.0, 8.0),
+            rng.range(-0.5, 8.0),
+            rng.range(-8.0, 8.0),
+        );
+        let direction = Vec3::new(
+            rng.range(-1.0, 1.0),
+            rng.range(-1.0, 1.0),
+            rng.range(-1.0, 1.0),
+        )
+        .normalized();
+        let ray = Ray { origin, direction };
+
+        let brute = triangles
+            .iter()
+            .filter_map(|triangle| triangle.hit(&ray, 0.001, f32::INFINITY))
+            .chain(brute_hit(&ray, &spheres))
+            .min_by(|a, b| a.t.total_cmp(&b.t));
+        let bvh_hit = bvh.hit(&ray, 0.001, f32::INFINITY);
 
-    fn next_u32(&mut self) -> u32 {
-        self.state = self
-            .state
-            .wrapping_mul(6364136223846793005)
-            .wrapping_add(1);
-        (self.state >> 32) as u32
+        assert_eq!(brute.is_some(), bvh_hit.is_some());
+        if let (Some(a), Some(b)) = (brute, bvh_hit) {
+            assert!((a.t - b.t).abs() < 1e-3);
+            if (b.point.y + 1.0).abs() < 1e-4 {
+                floor_hits += 1;
+            }
+        }
     }
+    assert!(floor_hits > 0);
+}
+
+fn grid_spheres(count: usize) -> Vec<Sphere> {
+    (0..count)
+        .map(|i| Sphere {
+            center: Vec3::new((i % 8) as f32, (i / 8 % 8) as f32, (i / 64) as f32),
+            radius: 0.3,
+            material: Material::Lambertian {
+                albedo: Vec3::new(0.5, 0.5, 0.5),
+            },
+        })
+        .collect()
+}
+
+#[test]
+fn bvh_stats_account_for_every_sphere() {
+    for count in [1, 5, 64, 100, 513] {
+        let stats = Bvh::new(grid_spheres(count)).stats();
 
-    fn next_f32(&mut self) -> f32 {
-        self.next_u32() as f32 / u32::MAX as f32
+        assert_eq!(stats.total_primitives, count);
+        assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+        let covered = stats.leaf_count as f32 * stats.avg_leaf_size;
+        assert!((covered - count as f32).abs() < 1e-3, "{count}: {stats:?}");
+        assert!(stats.avg_leaf_size <= LEAF_SIZE as f32);
+
+        let min_depth = (count as f32 / LEAF_SIZE as f32).log2().ceil().max(0.0) as usize;
+        assert!(stats.max_depth >= min_depth, "{count}: {stats:?}");
+        assert!(stats.quality_score() > 0.0 && stats.quality_score() <= 1.0);
     }
+}
 
-    fn range(&mut self, min: f32, max: f32) -> f32 {
-        min + (max - min) * self.next_f32()
+#[test]
+fn quality_report_matches_tree_shape() {
+    for count in [1, 5, 64, 100, 513] {
+        let bvh = Bvh::new(grid_spheres(count));
+        let stats = bvh.stats();
+        let report = bvh.quality_report();
+
+        assert_eq!(report.total_primitives, count);
+        assert_eq!(report.leaf_count, stats.leaf_count);
+        assert!(report.average_leaf_size <= LEAF_SIZE as f32);
+        assert_eq!(report.empty_leaves, 0);
+        assert!(report.average_depth <= stats.max_depth as f32);
+        // Every leaf fits inside the root, so the cost never exceeds testing
+        // every sphere, and a split tree does better than that.
+        assert!(
+            report.sah_cost <= count as f32 + 1e-3,
+            "{count}: {report:?}"
+        );
+        if count > LEAF_SIZE {
+            assert!(report.sah_cost < count as f32, "{count}: {report:?}");
+        }
+        assert!(report.to_string().contains(&format!("{count} spheres")));
     }
+
+    assert_eq!(
+        Bvh::new(Vec::new()).quality_report(),
+        BvhQualityReport::default()
+    );
+}
+
+#[test]
+fn bvh_stats_of_empty_tree_are_zero() {
+    let stats = Bvh::new(Vec::new()).stats();
+    assert_eq!(stats, BvhStats::default());
+    assert_eq!(stats.quality_score(), 1.0);
 }
@@ -0,0 +1,113 @@
+use ptroute_model::{SceneEdge, SceneFile, SceneNode};
+use ptroute_render::math::Vec3;
+use ptroute_render::{render_scene, Camera, CameraStyle, RenderSettings};
+
+fn node(id: &str, position: [f32; 3]) -> SceneNode {
+    SceneNode {
+        id: id.to_string(),
+        position,
+        seen: 3,
+        loss_probes: 0,
+        sent_probes: 0,
+        pagerank: None,
+        rtt_ms_avg: None,
+        asn: None,
+        label: None,
+        velocity: [0.0; 3],
+    }
+}
+
+fn scene() -> SceneFile {
+    let nodes = vec![
+        node("a", [-2.0, 0.0, -1.0]),
+        node("b", [0.0, 1.5, 0.0]),
+        node("c", [2.0, 0.0, 1.0]),
+        node("d", [0.5, -1.0, 2.0]),
+    ];
+    let edges = nodes
+        .windows(2)
+        .map(|pair| SceneEdge {
+            from: pair[0].id.clone(),
+            to: pair[1].id.clone(),
+            seen: 3,
+            rtt_delta_ms_avg: 5.0,
+            depth: 0,
+        })
+        .collect();
+    SceneFile {
+        version: 1,
+        nodes,
+        edges,
+    }
+}
+
+#[test]
+fn camera_styles_render_different_images() {
+    let scene = scene();
+    let styles = [
+        CameraStyle::default(),
+        CameraStyle::Perspective {
+            vfov_deg: 60.0,
+            distance_scale: 1.0,
+        },
+        CameraStyle::TopDown,
+        CameraStyle::Isometric,
+    ];
+    let images: Vec<_> = styles
+        .iter()
+        .map(|&style| {
+            let settings = RenderSettings::builder()
+                .width(32)
+                .height(24)
+                .spp(2)
+                .bounces(2)
+                .progress_every(0)
+                .camera_style(style)
+                .build();
+            render_scene(&scene, &settings)
+        })
+        .collect();
+
+    for (i, a) in images.iter().enumerate() {
+        for b in &images[i + 1..] {
+            assert_ne!(a.as_raw(), b.as_raw());
+        }
+    }
+    let defaults = RenderSettings {
+        width: 32,
+        height: 24,
+        spp: 2,
+        bounces: 2,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+    assert_eq!(render_scene(&scene, &defaults).as_raw(), images[0].as_raw());
+}
+
+#[test]
+fn orthographic_styles_center_the_scene() {
+    let scene = scene();
+    // Bounds run from (-2, -1, -1) to (2, 1.5, 2).
+    let center = Vec3::new(0.0, 0.25, 0.5);
+    for style in [CameraStyle::TopDown, CameraStyle::Isometric] {
+        let camera = Camera::new_from_scene(&scene, 2.0, style);
+        let (u, v) = camera.project(center).unwrap();
+        assert!(
+            (u - 0.5).abs() < 1e-5 && (v - 0.5).abs() < 1e-5,
+            "{style:?}"
+        );
+        for node in &scene.nodes {
+            let (u, v) = camera.project(Vec3::from(node.position)).unwrap();
+            assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+        }
+        // Every ray of an orthographic camera points the same way.
+        let corner = camera.ray(0.0, 0.0).direction;
+        let middle = camera.ray(0.5, 0.5).direction;
+        assert!((corner - middle).length() < 1e-6);
+    }
+
+    let top = Camera::new_from_scene(&scene, 2.0, CameraStyle::TopDown);
+    assert!(top.ray(0.5, 0.5).direction.y < -0.999);
+    assert_eq!("TopDown".parse(), Ok(CameraStyle::TopDown));
+    assert_eq!("perspective".parse(), Ok(CameraStyle::default()));
+}
@@ -0,0 +1,74 @@
+use image::{Rgb, RgbImage};
+use ptroute_model::{SceneFile, SceneNode};
+use ptroute_render::{compose_comparison, render_scene_comparison, RenderSettings};
+
+fn scene(x: f32) -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: vec![SceneNode {
+            id: "node".to_string(),
+            position: [x, 0.0, 0.0],
+            seen: 2,
+            loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: None,
+            velocity: [0.0; 3],
+        }],
+        edges: Vec::new(),
+    }
+}
+
+fn settings() -> RenderSettings {
+    RenderSettings {
+        width: 24,
+        height: 16,
+        spp: 2,
+        bounces: 2,
+        progress_every: 0,
+        ..RenderSettings::default()
+    }
+}
+
+#[test]
+fn comparison_is_three_renders_wide() {
+    let settings = settings();
+    let image = render_scene_comparison(&scene(0.0), &scene(0.5), &settings);
+    assert_eq!(image.width(), settings.width * 3);
+    assert_eq!(image.height(), settings.height);
+}
+
+#[test]
+fn identical_scenes_have_black_diff() {
+    let settings = settings();
+    let image = render_scene_comparison(&scene(0.0), &scene(0.0), &settings);
+    for x in settings.width * 2..settings.width * 3 {
+        for y in 0..settings.height {
+            assert_eq!(*image.get_pixel(x, y), Rgb([0, 0, 0]), "pixel {x},{y}");
+        }
+    }
+}
+
+#[test]
+fn diff_is_amplified_and_clamped() {
+    let before = RgbImage::from_pixel(2, 1, Rgb([10, 10, 200]));
+    let after = RgbImage::from_pixel(2, 1, Rgb([20, 10, 0]));
+    let image = compose_comparison(&before, &after);
+    assert_eq!(*image.get_pixel(4, 0), Rgb([40, 0, 255]));
+}
+
+#[test]
+fn mismatched_sizes_are_padded_not_rejected() {
+    let before = RgbImage::from_pixel(3, 1, Rgb([10, 10, 10]));
+    let after = RgbImage::from_pixel(2, 2, Rgb([10, 10, 10]));
+    let image = compose_comparison(&before, &after);
+    assert_eq!(image.dimensions(), (9, 2));
+    // Equal where both exist, padding against content everywhere else.
+    assert_eq!(*image.get_pixel(6, 0), Rgb([0, 0, 0]));
+    assert_eq!(*image.get_pixel(7, 0), Rgb([0, 0, 0]));
+    assert_eq!(*image.get_pixel(6, 1), Rgb([40, 40, 40]));
+    assert_eq!(*image.get_pixel(8, 0), Rgb([40, 40, 40]));
+    assert_eq!(*image.get_pixel(0, 1), Rgb([0, 0, 0]));
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "gpu")]
+
+use ptroute_model::{SceneEdge, SceneFile, SceneNode};
+use ptroute_render::{render_scene, render_scene_gpu, GpuError, RenderSettings};
+
+fn node(id: &str, position: [f32; 3], seen: u32) -> SceneNode {
+    SceneNode {
+        id: id.to_string(),
+        position,
+        seen,
+        loss_probes: 0,
+        sent_probes: 0,
+        pagerank: None,
+        rtt_ms_avg: None,
+        asn: None,
+        label: None,
+        velocity: [0.0; 3],
+    }
+}
+
+fn scene() -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: vec![
+            node("a", [-1.0, 0.0, 0.0], 3),
+            node("b", [0.0, 0.5, 0.0], 1),
+            node("c", [1.0, 0.0, 0.5], 2),
+        ],
+        edges: vec![
+            SceneEdge {
+                from: "a".to_string(),
+                to: "b".to_string(),
+                seen: 3,
+                rtt_delta_ms_avg: 5.0,
+                depth: 0,
+            },
+            SceneEdge {
+                from: "b".to_string(),
+                to: "c".to_string(),
+                seen: 1,
+                rtt_delta_ms_avg: 120.0,
+                depth: 0,
+            },
+        ],
+    }
+}
+
+/// Mean of each channel over `block`x`block` tiles, to compare two noisy
+/// renders without depending on their random streams.
+fn block_means(image: &image::RgbImage, block: u32) -> Vec<f32> {
+    let mut means = Vec::new();
+    for by in (0..image.height()).step_by(block as usize) {
+        for bx in (0..image.width()).step_by(block as usize) {
+            let mut sum = [0.0f32; 3];
+            let mut count = 0.0;
+            for y in by..(by + block).min(image.height()) {
+                for x in bx..(bx + block).min(image.width()) {
+                    let pixel = image.get_pixel(x, y);
+                    for channel in 0..3 {
+                        sum[channel] += f32::from(pixel[channel]);
+                    }
+                    count += 1.0;
+                }
+            }
+            means.extend(sum.iter().map(|value| value / count));
+        }
+    }
+    means
+}
+
+#[test]
+fn gpu_render_matches_cpu_within_tolerance() {
+    let settings = RenderSettings {
+        width: 48,
+        height: 32,
+        spp: 64,
+        bounces: 3,
+        seed: 7,
+        ..RenderSettings::default()
+    };
+
+    let gpu = match render_scene_gpu(&scene(), &settings) {
+        Ok(image) => image,
+        Err(GpuError::NoAdapter(err)) => {
+            eprintln!("skipping: {err}");
+            return;
+        }
+        Err(err) => panic!("{err}"),
+    };
+    let cpu = render_scene(&scene(), &settings);
+    assert_eq!(gpu.dimensions(), cpu.dimensions());
+
+    let gpu = block_means(&gpu, 8);
+    let cpu = block_means(&cpu, 8);
+    let worst = gpu
+        .iter()
+        .zip(&cpu)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f32::max);
+    assert!(worst < 4.0, "block means differ by up to {worst}");
+}
+
+#[test]
+fn gpu_rejects_cpu_only_settings() {
+    for settings in [
+        RenderSettings {
+            ground_plane: true,
+            ..RenderSettings::default()
+        },
+        RenderSettings {
+            high_quality_materials: true,
+            ..RenderSettings::default()
+        },
+    ] {
+        assert!(matches!(
+            render_scene_gpu(&scene(), &settings),
+            Err(GpuError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,47 @@
+use ptroute_model::{SceneFile, SceneNode};
+use ptroute_render::{render_scene, RenderSettings};
+
+fn scene() -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: vec![SceneNode {
+            id: "10.0.0.1".to_string(),
+            position: [0.0, 0.0, 0.0],
+            seen: 1,
+            loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: Some("gw".to_string()),
+            velocity: [0.0; 3],
+        }],
+        edges: Vec::new(),
+    }
+}
+
+#[test]
+fn labels_change_rows_near_node() {
+    let settings = RenderSettings {
+        width: 64,
+        height: 48,
+        spp: 1,
+        bounces: 1,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+    let plain = render_scene(&scene(), &settings);
+    let labeled = render_scene(
+        &scene(),
+        &RenderSettings {
+            show_labels: true,
+            ..settings.clone()
+        },
+    );
+
+    // The lone node sits at the scene center, which the camera looks at.
+    let center = settings.height / 2;
+    let changed = (center - 4..center + 4)
+        .any(|y| (0..settings.width).any(|x| plain.get_pixel(x, y) != labeled.get_pixel(x, y)));
+    assert!(changed);
+}
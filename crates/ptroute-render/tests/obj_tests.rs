@@ -0,0 +1,148 @@
+use ptroute_model::{SceneEdge, SceneFile, SceneNode};
+use ptroute_render::math::Vec3;
+use ptroute_render::obj_out::{OBJ_LINK_VERTICES, OBJ_SPHERE_VERTICES};
+use ptroute_render::{color_from_id, node_radius, to_obj};
+
+fn node(id: &str, position: [f32; 3], seen: u32) -> SceneNode {
+    SceneNode {
+        id: id.to_string(),
+        position,
+        seen,
+        loss_probes: 0,
+        sent_probes: 0,
+        pagerank: None,
+        rtt_ms_avg: None,
+        asn: None,
+        label: None,
+        velocity: [0.0; 3],
+    }
+}
+
+fn edge(from: &str, to: &str, seen: u32) -> SceneEdge {
+    SceneEdge {
+        from: from.to_string(),
+        to: to.to_string(),
+        seen,
+        rtt_delta_ms_avg: 12.0,
+        depth: 0,
+    }
+}
+
+fn scene() -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: vec![
+            node("10.0.0.1", [0.0, 0.0, 0.0], 1),
+            node("10.0.0.2", [1.0, 0.5, 0.0], 4),
+            node("10.0.0.3", [1.0, 3.0, 0.0], 2),
+        ],
+        edges: vec![
+            edge("10.0.0.1", "10.0.0.2", 3),
+            // Straight up, where the prism needs a different helper axis.
+            edge("10.0.0.2", "10.0.0.3", 1),
+            edge("10.0.0.2", "missing", 1),
+            edge("10.0.0.1", "10.0.0.1", 1),
+        ],
+    }
+}
+
+fn lines<'a>(text: &'a str, keyword: &str) -> Vec<Vec<&'a str>> {
+    text.lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            (tokens.next() == Some(keyword)).then(|| tokens.collect())
+        })
+        .collect()
+}
+
+fn vec3(tokens: &[&str]) -> Vec3 {
+    let value = |i: usize| tokens[i].parse::<f32>().unwrap();
+    Vec3::new(value(0), value(1), value(2))
+}
+
+#[test]
+fn obj_counts_match_the_tessellation() {
+    let export = to_obj(&scene(), "scene.mtl");
+    assert!(export.obj.contains("mtllib scene.mtl\n"));
+
+    let vertices = lines(&export.obj, "v");
+    let normals = lines(&export.obj, "vn");
+    let faces = lines(&export.obj, "f");
+    // The edge to a missing node and the self-loop are left out.
+    assert_eq!(
+        vertices.len(),
+        3 * OBJ_SPHERE_VERTICES + 2 * OBJ_LINK_VERTICES
+    );
+    assert_eq!(normals.len(), vertices.len());
+    // 8 meridians: two pole fans plus two bands of quads, and 4 prism sides.
+    assert_eq!(faces.len(), 3 * 8 * 4 + 2 * 4);
+    for face in &faces {
+        for corner in face {
+            let (v, vn) = corner.split_once("//").unwrap();
+            let v: usize = v.parse().unwrap();
+            assert_eq!(v, vn.parse::<usize>().unwrap());
+            assert!((1..=vertices.len()).contains(&v));
+        }
+    }
+
+    let nodes_only = SceneFile {
+        edges: Vec::new(),
+        ..scene()
+    };
+    let export = to_obj(&nodes_only, "scene.mtl");
+    assert_eq!(
+        lines(&export.obj, "v").len(),
+        nodes_only.nodes.len() * OBJ_SPHERE_VERTICES
+    );
+}
+
+#[test]
+fn obj_spheres_are_sized_and_wound_outward() {
+    let scene = scene();
+    let export = to_obj(&scene, "scene.mtl");
+    let vertices: Vec<Vec3> = lines(&export.obj, "v").iter().map(|v| vec3(v)).collect();
+    let normals: Vec<Vec3> = lines(&export.obj, "vn").iter().map(|n| vec3(n)).collect();
+
+    for (index, node) in scene.nodes.iter().enumerate() {
+        let center = Vec3::from(node.position);
+        for vertex in &vertices[index * OBJ_SPHERE_VERTICES..(index + 1) * OBJ_SPHERE_VERTICES] {
+            assert!(((*vertex - center).length() - node_radius(node.seen)).abs() < 1e-4);
+        }
+    }
+
+    // Every face's winding agrees with its vertex normals.
+    for face in lines(&export.obj, "f") {
+        let index: Vec<usize> = face
+            .iter()
+            .map(|corner| corner.split_once("//").unwrap().0.parse::<usize>().unwrap() - 1)
+            .collect();
+        let (a, b, c) = (vertices[index[0]], vertices[index[1]], vertices[index[2]]);
+        let winding = (b - a).cross(c - a);
+        let normal = index.iter().fold(Vec3::zero(), |sum, &i| sum + normals[i]);
+        assert!(winding.dot(normal) > 0.0, "{face:?}");
+    }
+}
+
+#[test]
+fn obj_materials_use_node_colors() {
+    let scene = scene();
+    let export = to_obj(&scene, "scene.mtl");
+
+    let materials: Vec<&str> = lines(&export.mtl, "newmtl")
+        .into_iter()
+        .map(|tokens| tokens[0])
+        .collect();
+    assert_eq!(
+        materials,
+        ["node_0", "node_1", "node_2", "link_0", "link_1"]
+    );
+    for used in lines(&export.obj, "usemtl") {
+        assert!(materials.contains(&used[0]));
+    }
+
+    let colors = lines(&export.mtl, "Kd");
+    for (index, node) in scene.nodes.iter().enumerate() {
+        let expected = Vec3::from(color_from_id(&node.id));
+        assert!((vec3(&colors[index]) - expected).length() < 1e-6);
+    }
+}
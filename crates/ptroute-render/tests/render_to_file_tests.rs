@@ -0,0 +1,130 @@
+use image::{Rgb, RgbImage};
+use ptroute_model::{SceneFile, SceneNode};
+use ptroute_render::{
+    render_scene_progressive_to_apng, render_scene_progressive_to_file, render_scene_to_file,
+    write_animated_png, RenderSettings,
+};
+use std::path::PathBuf;
+
+fn scene() -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: vec![SceneNode {
+            id: "node".to_string(),
+            position: [0.0, 0.0, 0.0],
+            seen: 1,
+            loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: None,
+            velocity: [0.0; 3],
+        }],
+        edges: Vec::new(),
+    }
+}
+
+fn settings() -> RenderSettings {
+    RenderSettings {
+        width: 16,
+        height: 12,
+        spp: 4,
+        bounces: 1,
+        progress_every: 0,
+        ..RenderSettings::default()
+    }
+}
+
+fn temp_png(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ptroute-{name}-{}.png", std::process::id()))
+}
+
+#[test]
+fn render_scene_to_file_writes_png() {
+    let path = temp_png("render-to-file");
+    render_scene_to_file(&scene(), &settings(), &path).unwrap();
+    let len = std::fs::metadata(&path).unwrap().len();
+    let written = image::open(&path).unwrap().to_rgb8();
+    let _ = std::fs::remove_file(&path);
+    assert!(len > 0);
+    assert_eq!(written.dimensions(), (16, 12));
+}
+
+#[test]
+fn progressive_render_rewrites_file_each_pass() {
+    let path = temp_png("render-progressive-to-file");
+    let mut updates = Vec::new();
+    render_scene_progressive_to_file(&scene(), &settings(), 1, &path, |done| {
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        updates.push(done);
+    })
+    .unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(updates, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn progressive_render_reports_write_errors() {
+    let path = std::env::temp_dir()
+        .join(format!("ptroute-missing-{}", std::process::id()))
+        .join("out.png");
+    let mut updates = 0;
+    let result = render_scene_progressive_to_file(&scene(), &settings(), 2, &path, |_| {
+        updates += 1;
+    });
+    assert!(result.is_err());
+    assert_eq!(updates, 0);
+}
+
+/// `(num_frames, num_plays)` from the `acTL` chunk, which must come before
+/// the first `IDAT`.
+fn actl(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+    let mut at = 8;
+    while at + 8 <= bytes.len() {
+        let len = be(at) as usize;
+        match &bytes[at + 4..at + 8] {
+            b"acTL" if len == 8 => return Some((be(at + 8), be(at + 12))),
+            b"IDAT" => return None,
+            _ => at += 12 + len,
+        }
+    }
+    None
+}
+
+#[test]
+fn animated_png_has_one_frame_per_image() {
+    let path = temp_png("animated");
+    let frames = [
+        RgbImage::from_pixel(4, 4, Rgb([255, 0, 0])),
+        RgbImage::from_pixel(4, 4, Rgb([0, 0, 255])),
+    ];
+    write_animated_png(&path, &frames, 5).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    let first = image::open(&path).unwrap().to_rgb8();
+    let _ = std::fs::remove_file(&path);
+
+    assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert_eq!(actl(&bytes), Some((2, 0)));
+    assert_eq!(first, frames[0]);
+}
+
+#[test]
+fn animated_png_rejects_mismatched_frames() {
+    let path = temp_png("animated-mismatch");
+    let frames = [RgbImage::new(4, 4), RgbImage::new(4, 3)];
+    assert!(write_animated_png(&path, &frames, 5).is_err());
+    assert!(write_animated_png(&path, &[], 5).is_err());
+    assert!(write_animated_png(&path, &frames[..1], 0).is_err());
+    assert!(!path.exists());
+}
+
+#[test]
+fn progressive_apng_keeps_every_pass() {
+    let path = temp_png("render-apng");
+    render_scene_progressive_to_apng(&scene(), &settings(), 2, 5, &path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(actl(&bytes), Some((2, 0)));
+}
@@ -0,0 +1,239 @@
+use ptroute_model::{SceneFile, SceneNode};
+use ptroute_render::{
+    render_scene, render_scene_progressive, render_scene_streaming, threads_for_settings,
+    RenderSettings, SeedStrategy,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[test]
+fn builder_matches_struct_literal() {
+    let built = RenderSettings::builder()
+        .width(1920)
+        .height(1080)
+        .spp(256)
+        .bounces(4)
+        .seed(7)
+        .progress_every(0)
+        .threads(2)
+        .max_accum_depth(16)
+        .build();
+    let literal = RenderSettings {
+        width: 1920,
+        height: 1080,
+        spp: 256,
+        bounces: 4,
+        seed: 7,
+        progress_every: 0,
+        threads: 2,
+        max_accum_depth: 16,
+        ..RenderSettings::default()
+    };
+
+    assert_eq!(built, literal);
+}
+
+#[test]
+fn builder_keeps_defaults_for_unset_fields() {
+    let built = RenderSettings::builder().width(320).build();
+    let defaults = RenderSettings::default();
+
+    assert_eq!(built.width, 320);
+    assert_eq!(
+        built,
+        RenderSettings {
+            width: 320,
+            ..defaults
+        }
+    );
+    assert_eq!(built.progress_every, 32);
+    assert_eq!(built.threads, 0);
+    assert_eq!(RenderSettings::builder().build(), RenderSettings::default());
+}
+
+fn one_node_scene() -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: vec![SceneNode {
+            id: "node".to_string(),
+            position: [0.0, 0.0, 0.0],
+            seen: 1,
+            loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: None,
+            velocity: [0.0; 3],
+        }],
+        edges: Vec::new(),
+    }
+}
+
+fn recording_settings(calls: &Arc<Mutex<Vec<(u32, u32)>>>) -> RenderSettings {
+    let calls = Arc::clone(calls);
+    RenderSettings::builder()
+        .width(8)
+        .height(6)
+        .spp(3)
+        .bounces(1)
+        .tile_size(0)
+        .progress_fn(move |done, total, _elapsed| calls.lock().unwrap().push((done, total)))
+        .build()
+}
+
+#[test]
+fn progress_fn_reports_each_scanline_batch() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let settings = RenderSettings {
+        progress_every: 2,
+        ..recording_settings(&calls)
+    };
+
+    render_scene(&one_node_scene(), &settings);
+
+    let mut calls = calls.lock().unwrap().clone();
+    calls.sort();
+    assert_eq!(calls, vec![(2, 6), (4, 6), (6, 6)]);
+}
+
+#[test]
+fn progress_fn_reports_progressive_passes() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let settings = RenderSettings {
+        progress_every: 0,
+        ..recording_settings(&calls)
+    };
+
+    let mut passes = 0;
+    render_scene_progressive(&one_node_scene(), &settings, 1, |_, _| passes += 1);
+
+    assert_eq!(passes, 3);
+    assert_eq!(*calls.lock().unwrap(), vec![(1, 3), (2, 3), (3, 3)]);
+}
+
+#[test]
+fn streaming_render_sends_each_pass() {
+    let settings = RenderSettings::builder()
+        .width(8)
+        .height(6)
+        .spp(4)
+        .bounces(1)
+        .progress_every(0)
+        .build();
+    let expected = render_scene(&one_node_scene(), &settings);
+
+    let passes: Vec<_> = render_scene_streaming(one_node_scene(), settings, 2)
+        .iter()
+        .collect();
+    assert_eq!(
+        passes.iter().map(|(_, done)| *done).collect::<Vec<_>>(),
+        vec![2, 4]
+    );
+    assert_eq!(passes.last().unwrap().0, expected);
+}
+
+#[test]
+fn dropping_stream_receiver_stops_the_render() {
+    // The render thread owns the settings, so the marker is released once
+    // it exits.
+    let marker = Arc::new(());
+    let held = Arc::clone(&marker);
+    let settings = RenderSettings::builder()
+        .width(8)
+        .height(6)
+        .spp(100_000)
+        .bounces(1)
+        .progress_every(0)
+        .progress_fn(move |_, _, _| {
+            let _ = &held;
+        })
+        .build();
+
+    let receiver = render_scene_streaming(one_node_scene(), settings, 1);
+    assert_eq!(receiver.recv().unwrap().1, 1);
+    drop(receiver);
+
+    let start = Instant::now();
+    while Arc::strong_count(&marker) > 1 {
+        assert!(
+            start.elapsed() < Duration::from_secs(30),
+            "render thread still running"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn seed_strategies_give_distinct_but_pass_independent_renders() {
+    let scene = one_node_scene();
+    let renders: Vec<_> = [
+        SeedStrategy::GlobalProgression,
+        SeedStrategy::PixelIndependent,
+        SeedStrategy::BlueNoise,
+    ]
+    .into_iter()
+    .map(|strategy| {
+        let settings = RenderSettings::builder()
+            .width(24)
+            .height(16)
+            .spp(4)
+            .bounces(3)
+            .progress_every(0)
+            .seed_strategy(strategy)
+            .build();
+        let image = render_scene(&scene, &settings);
+
+        let mut last = None;
+        render_scene_progressive(&scene, &settings, 1, |pass, _| last = Some(pass.clone()));
+        assert_eq!(last.unwrap(), image, "{strategy:?}");
+        image
+    })
+    .collect();
+
+    assert_ne!(renders[0], renders[1]);
+    assert_ne!(renders[0], renders[2]);
+    assert_ne!(renders[1], renders[2]);
+}
+
+fn max_channel_diff(a: &image::RgbImage, b: &image::RgbImage) -> u8 {
+    a.pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| (0..3).map(move |channel| a[channel].abs_diff(b[channel])))
+        .max()
+        .unwrap_or(0)
+}
+
+#[test]
+fn flushed_accumulation_matches_direct_within_one_lsb() {
+    let direct = RenderSettings::builder()
+        .width(24)
+        .height(16)
+        .spp(23)
+        .bounces(3)
+        .seed(5)
+        .progress_every(0)
+        .build();
+    let flushed = RenderSettings {
+        max_accum_depth: 5,
+        ..direct.clone()
+    };
+    let scene = one_node_scene();
+
+    let expected = render_scene(&scene, &direct);
+    assert!(max_channel_diff(&expected, &render_scene(&scene, &flushed)) <= 1);
+
+    // Progressive passes that straddle flush boundaries land on the same image.
+    let mut last = None;
+    render_scene_progressive(&scene, &flushed, 7, |image, _| last = Some(image.clone()));
+    assert!(max_channel_diff(&expected, &last.unwrap()) <= 1);
+}
+
+#[test]
+fn auto_threads_scale_with_image_size() {
+    assert!(threads_for_settings(32, 24, 16) < 16);
+    assert_eq!(threads_for_settings(32, 24, 16), 3);
+    assert_eq!(threads_for_settings(4096, 2160, 16), 16);
+    assert_eq!(threads_for_settings(1, 1, 16), 1);
+    assert_eq!(threads_for_settings(u32::MAX, u32::MAX, 8), 8);
+}
@@ -1,5 +1,8 @@
 use ptroute_model::{SceneEdge, SceneFile, SceneNode};
-use ptroute_render::{render_scene, RenderSettings};
+use ptroute_render::{
+    render_scene, render_scene_importance_map, scene_bvh, NodeColorScheme, RenderSettings,
+    TileOrder,
+};
 
 #[test]
 fn render_scene_outputs_image() {
@@ -10,12 +13,19 @@ fn render_scene_outputs_image() {
             position: [0.0, 0.0, 0.0],
             seen: 1,
             loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: None,
+            velocity: [0.0; 3],
         }],
         edges: vec![SceneEdge {
             from: "node".to_string(),
             to: "node".to_string(),
             seen: 1,
             rtt_delta_ms_avg: 0.0,
+            depth: 0,
         }],
     };
 
@@ -27,9 +37,455 @@ fn render_scene_outputs_image() {
         seed: 1,
         progress_every: 0,
         threads: 0,
+        ..RenderSettings::default()
     };
 
     let image = render_scene(&scene, &settings);
     assert_eq!(image.width(), settings.width);
     assert_eq!(image.height(), settings.height);
 }
+
+#[test]
+fn asn_node_color_renders_like_id_without_asn_data() {
+    let mut scene = SceneFile {
+        version: 1,
+        nodes: vec![SceneNode {
+            id: "10.0.0.1".to_string(),
+            position: [0.0, 0.0, 0.0],
+            seen: 1,
+            loss_probes: 0,
+            sent_probes: 0,
+            pagerank: None,
+            rtt_ms_avg: None,
+            asn: None,
+            label: None,
+            velocity: [0.0; 3],
+        }],
+        edges: Vec::new(),
+    };
+    let settings = |node_color_scheme| RenderSettings {
+        width: 16,
+        height: 12,
+        spp: 2,
+        bounces: 2,
+        seed: 1,
+        progress_every: 0,
+        node_color_scheme,
+        ..RenderSettings::default()
+    };
+
+    let asn = render_scene(&scene, &settings("asn".parse().unwrap()));
+    let id = render_scene(&scene, &settings(NodeColorScheme::ById));
+    assert_eq!(asn, id);
+
+    scene.nodes[0].asn = Some(64500);
+    let asn = render_scene(&scene, &settings("asn".parse().unwrap()));
+    assert_ne!(asn, id);
+}
+
+#[test]
+fn bloom_changes_image_with_emissive_links() {
+    let scene = SceneFile {
+        version: 1,
+        nodes: vec![
+            SceneNode {
+                id: "a".to_string(),
+                position: [0.0, 0.0, 0.0],
+                seen: 3,
+                loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            },
+            SceneNode {
+                id: "b".to_string(),
+                position: [2.0, 0.0, 0.0],
+                seen: 3,
+                loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            },
+        ],
+        edges: vec![SceneEdge {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            seen: 3,
+            rtt_delta_ms_avg: 5.0,
+            depth: 0,
+        }],
+    };
+
+    let plain = RenderSettings {
+        width: 32,
+        height: 24,
+        spp: 2,
+        bounces: 2,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+    let bloom = RenderSettings {
+        bloom_threshold: 0.5,
+        bloom_radius: 3,
+        bloom_intensity: 1.0,
+        ..plain.clone()
+    };
+
+    let without = render_scene(&scene, &plain);
+    let with = render_scene(&scene, &bloom);
+    assert_ne!(without.as_raw(), with.as_raw());
+}
+
+#[test]
+fn zero_emission_scale_renders_links_dark() {
+    let node = |id: &str, x: f32| SceneNode {
+        id: id.to_string(),
+        position: [x, 0.0, 0.0],
+        seen: 3,
+        loss_probes: 0,
+        sent_probes: 0,
+        pagerank: None,
+        rtt_ms_avg: None,
+        asn: None,
+        label: None,
+        velocity: [0.0; 3],
+    };
+    let scene = SceneFile {
+        version: 1,
+        nodes: vec![node("a", 0.0), node("b", 2.0)],
+        edges: vec![SceneEdge {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            seen: 3,
+            rtt_delta_ms_avg: 20.0,
+            depth: 0,
+        }],
+    };
+    // One bounce and one sample: a pixel shows either the sky or exactly
+    // the emission of the first sphere it hits.
+    let lit = RenderSettings {
+        width: 48,
+        height: 32,
+        spp: 1,
+        bounces: 1,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+    let dark = RenderSettings {
+        emission_scale: 0.0,
+        ..lit.clone()
+    };
+
+    let lit = render_scene(&scene, &lit);
+    let dark = render_scene(&scene, &dark);
+    let glowing: Vec<_> = lit
+        .pixels()
+        .zip(dark.pixels())
+        .filter(|(lit, dark)| lit != dark)
+        .collect();
+    assert!(!glowing.is_empty());
+    for (lit, dark) in glowing {
+        assert_ne!(lit.0, [0, 0, 0]);
+        assert_eq!(dark.0, [0, 0, 0]);
+    }
+}
+
+#[test]
+fn ground_plane_adds_a_lit_floor() {
+    let node = |id: &str, x: f32| SceneNode {
+        id: id.to_string(),
+        position: [x, 0.0, 0.0],
+        seen: 3,
+        loss_probes: 0,
+        sent_probes: 0,
+        pagerank: None,
+        rtt_ms_avg: None,
+        asn: None,
+        label: None,
+        velocity: [0.0; 3],
+    };
+    let scene = SceneFile {
+        version: 1,
+        nodes: vec![node("a", 0.0), node("b", 2.0)],
+        edges: Vec::new(),
+    };
+    let plain = RenderSettings {
+        width: 48,
+        height: 32,
+        spp: 8,
+        bounces: 3,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+    let floor = RenderSettings {
+        ground_plane: true,
+        ..plain.clone()
+    };
+
+    assert!(scene_bvh(&scene, &plain).triangles().is_empty());
+    let bvh = scene_bvh(&scene, &floor);
+    assert_eq!(bvh.triangles().len(), 2);
+    assert_eq!(
+        bvh.spheres().len(),
+        scene_bvh(&scene, &plain).spheres().len()
+    );
+
+    // The bottom row looks below the horizon: dark background without the
+    // floor, sky light bounced off it with.
+    let bottom = |settings: &RenderSettings| {
+        let image = render_scene(&scene, settings);
+        let y = image.height() - 1;
+        (0..image.width())
+            .map(|x| {
+                image
+                    .get_pixel(x, y)
+                    .0
+                    .iter()
+                    .map(|&c| u32::from(c))
+                    .sum::<u32>()
+            })
+            .sum::<u32>()
+    };
+    assert!(bottom(&floor) > bottom(&plain));
+}
+
+#[test]
+fn tiled_render_matches_row_render() {
+    let scene = SceneFile {
+        version: 1,
+        nodes: vec![
+            SceneNode {
+                id: "a".to_string(),
+                position: [0.0, 0.0, 0.0],
+                seen: 2,
+                loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            },
+            SceneNode {
+                id: "b".to_string(),
+                position: [1.5, 0.5, 0.0],
+                seen: 1,
+                loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            },
+        ],
+        edges: vec![SceneEdge {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            seen: 1,
+            rtt_delta_ms_avg: 3.0,
+            depth: 0,
+        }],
+    };
+
+    let rows = RenderSettings {
+        width: 37,
+        height: 23,
+        spp: 2,
+        bounces: 2,
+        progress_every: 0,
+        tile_size: 0,
+        ..RenderSettings::default()
+    };
+    let expected = render_scene(&scene, &rows);
+
+    for tile_order in [TileOrder::Scanline, TileOrder::Morton, TileOrder::Hilbert] {
+        for tile_size in [1, 7, 16, 64] {
+            let tiled = RenderSettings {
+                tile_size,
+                tile_order,
+                ..rows.clone()
+            };
+            assert_eq!(render_scene(&scene, &tiled).as_raw(), expected.as_raw());
+        }
+    }
+}
+
+#[test]
+fn tile_orders_visit_every_tile_once() {
+    for (cols, rows) in [(1, 1), (5, 3), (30, 17)] {
+        let mut expected: Vec<(u32, u32)> = (0..rows)
+            .flat_map(|ty| (0..cols).map(move |tx| (tx, ty)))
+            .collect();
+        assert_eq!(TileOrder::Scanline.tiles(cols, rows), expected);
+        expected.sort();
+
+        for order in [TileOrder::Morton, TileOrder::Hilbert] {
+            let mut tiles = order.tiles(cols, rows);
+            tiles.sort();
+            assert_eq!(tiles, expected, "{order:?} {cols}x{rows}");
+        }
+    }
+
+    // On a square power-of-two grid the Hilbert walk only takes unit steps.
+    let hilbert = TileOrder::Hilbert.tiles(8, 8);
+    assert!(hilbert
+        .windows(2)
+        .all(|pair| pair[0].0.abs_diff(pair[1].0) + pair[0].1.abs_diff(pair[1].1) == 1));
+}
+
+#[test]
+fn importance_map_is_hot_around_emissive_links() {
+    let scene = SceneFile {
+        version: 1,
+        nodes: vec![
+            SceneNode {
+                id: "a".to_string(),
+                position: [0.0, 0.0, 0.0],
+                seen: 3,
+                loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            },
+            SceneNode {
+                id: "b".to_string(),
+                position: [2.0, 0.0, 0.0],
+                seen: 3,
+                loss_probes: 0,
+                sent_probes: 0,
+                pagerank: None,
+                rtt_ms_avg: None,
+                asn: None,
+                label: None,
+                velocity: [0.0; 3],
+            },
+        ],
+        edges: vec![SceneEdge {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            seen: 3,
+            rtt_delta_ms_avg: 5.0,
+            depth: 0,
+        }],
+    };
+    let settings = RenderSettings {
+        width: 48,
+        height: 32,
+        spp: 64,
+        bounces: 3,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+
+    let render = render_scene(&scene, &settings);
+    let map = render_scene_importance_map(&scene, &settings);
+    assert_eq!(map.dimensions(), render.dimensions());
+
+    // The brightest rendered pixels sit on the emissive link beads.
+    let brightness = |x: u32, y: u32| {
+        render
+            .get_pixel(x, y)
+            .0
+            .iter()
+            .map(|&c| u32::from(c))
+            .sum::<u32>()
+    };
+    let mut pixels: Vec<(u32, u32)> = (0..settings.height)
+        .flat_map(|y| (0..settings.width).map(move |x| (x, y)))
+        .collect();
+    pixels.sort_by_key(|&(x, y)| std::cmp::Reverse(brightness(x, y)));
+    let hot: u32 = pixels[..16]
+        .iter()
+        .map(|&(x, y)| u32::from(map.get_pixel(x, y)[0]))
+        .sum::<u32>()
+        / 16;
+    let background = map.get_pixel(0, 0);
+    assert!(hot > u32::from(background[0]), "{hot} vs {background:?}");
+}
+
+#[test]
+fn crop_region_renders_only_inside_the_crop() {
+    let node = |id: &str, x: f32| SceneNode {
+        id: id.to_string(),
+        position: [x, 0.0, 0.0],
+        seen: 3,
+        loss_probes: 0,
+        sent_probes: 0,
+        pagerank: None,
+        rtt_ms_avg: None,
+        asn: None,
+        label: None,
+        velocity: [0.0; 3],
+    };
+    let scene = SceneFile {
+        version: 1,
+        nodes: vec![node("a", 0.0), node("b", 1.0), node("c", 2.0)],
+        edges: vec![
+            SceneEdge {
+                from: "a".to_string(),
+                to: "b".to_string(),
+                seen: 3,
+                rtt_delta_ms_avg: 5.0,
+                depth: 0,
+            },
+            SceneEdge {
+                from: "b".to_string(),
+                to: "c".to_string(),
+                seen: 3,
+                rtt_delta_ms_avg: 5.0,
+                depth: 0,
+            },
+        ],
+    };
+    let full = RenderSettings {
+        width: 40,
+        height: 24,
+        spp: 2,
+        bounces: 2,
+        progress_every: 0,
+        ..RenderSettings::default()
+    };
+    let (w, h) = (full.width, full.height);
+    let expected = render_scene(&scene, &full);
+
+    for tile_size in [0, 16] {
+        let cropped = RenderSettings {
+            tile_size,
+            crop_region: Some([0, 0, w / 2, h]),
+            // Bloom from the left half must not spill into the right.
+            bloom_threshold: 0.1,
+            bloom_intensity: 1.0,
+            ..full.clone()
+        };
+        let image = render_scene(&scene, &cropped);
+        assert_eq!(image.dimensions(), (w, h));
+        let mut lit = 0;
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if x >= w / 2 {
+                assert_eq!(pixel.0, [0, 0, 0], "({x}, {y})");
+            } else if pixel.0 != [0, 0, 0] {
+                lit += 1;
+            }
+        }
+        assert!(lit > (w / 2 * h / 4) as usize, "{lit} lit pixels");
+
+        let unbloomed = RenderSettings {
+            bloom_threshold: f32::MAX,
+            ..cropped
+        };
+        let image = render_scene(&scene, &unbloomed);
+        for (x, y, pixel) in image.enumerate_pixels().filter(|(x, _, _)| *x < w / 2) {
+            assert_eq!(pixel, expected.get_pixel(x, y));
+        }
+    }
+}
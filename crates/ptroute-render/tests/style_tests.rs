@@ -0,0 +1,32 @@
+use ptroute_render::{color_from_id, link_radius, node_radius};
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "expected {expected}, got {actual}"
+    );
+}
+
+fn assert_color(id: &str, expected: [f32; 3]) {
+    let color = color_from_id(id);
+    for (actual, expected) in color.into_iter().zip(expected) {
+        assert_close(actual, expected);
+    }
+}
+
+#[test]
+fn color_from_id_is_pinned() {
+    assert_color("192.168.1.1", [0.3129412, 0.6580393, 0.54509807]);
+    assert_color("10.0.0.1->10.0.0.2", [0.89960784, 0.36627454, 0.8933333]);
+    assert_color("", [0.31607842, 0.30980393, 0.30666667]);
+}
+
+#[test]
+fn radii_are_pinned() {
+    assert_close(node_radius(0), 0.15);
+    assert_close(node_radius(1), 0.15);
+    assert_close(node_radius(10), 0.26512927);
+    assert_close(link_radius(0), 0.04);
+    assert_close(link_radius(1), 0.04);
+    assert_close(link_radius(10), 0.06302585);
+}
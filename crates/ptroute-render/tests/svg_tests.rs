@@ -0,0 +1,115 @@
+use ptroute_model::{SceneEdge, SceneFile, SceneNode};
+use ptroute_render::to_svg;
+
+fn node(id: &str, position: [f32; 3], seen: u32) -> SceneNode {
+    SceneNode {
+        id: id.to_string(),
+        position,
+        seen,
+        loss_probes: 0,
+        sent_probes: 0,
+        pagerank: None,
+        rtt_ms_avg: None,
+        asn: None,
+        label: None,
+        velocity: [0.0; 3],
+    }
+}
+
+fn scene() -> SceneFile {
+    SceneFile {
+        version: 1,
+        nodes: vec![
+            node("10.0.0.1", [-0.5, 0.0, 0.0], 1),
+            node("10.0.0.2", [0.0, 0.2, 0.1], 3),
+            node("fe80::1%<eth0>", [0.5, 0.0, -0.1], 1),
+        ],
+        edges: vec![
+            SceneEdge {
+                from: "10.0.0.1".to_string(),
+                to: "10.0.0.2".to_string(),
+                seen: 3,
+                rtt_delta_ms_avg: 2.0,
+                depth: 0,
+            },
+            SceneEdge {
+                from: "10.0.0.2".to_string(),
+                to: "fe80::1%<eth0>".to_string(),
+                seen: 1,
+                rtt_delta_ms_avg: 80.0,
+                depth: 0,
+            },
+        ],
+    }
+}
+
+/// Minimal well-formedness check: every tag is closed in order and every
+/// entity is one of the predefined ones.
+fn assert_well_formed(xml: &str) {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        let text = &rest[..open];
+        for (at, _) in text.match_indices('&') {
+            let entity = &text[at..text[at..].find(';').map_or(text.len(), |end| at + end + 1)];
+            assert!(
+                ["&amp;", "&lt;", "&gt;", "&quot;", "&apos;"].contains(&entity),
+                "bad entity {entity:?}"
+            );
+        }
+        let close = rest[open..].find('>').expect("unterminated tag") + open;
+        let tag = &rest[open + 1..close];
+        assert!(!tag.contains('<'), "nested '<' in tag {tag:?}");
+        if tag.starts_with('?') {
+            assert!(tag.ends_with('?'));
+        } else if let Some(name) = tag.strip_prefix('/') {
+            assert_eq!(stack.pop(), Some(name), "mismatched </{name}>");
+        } else if !tag.ends_with('/') {
+            stack.push(tag.split_whitespace().next().unwrap());
+        }
+        rest = &rest[close + 1..];
+    }
+    assert!(stack.is_empty(), "unclosed tags {stack:?}");
+}
+
+#[test]
+fn svg_draws_nodes_and_links() {
+    let svg = to_svg(&scene(), 320, 200);
+
+    assert!(svg.contains("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"320\" height=\"200\""));
+    assert_eq!(svg.matches("<circle ").count(), 3);
+    assert_eq!(svg.matches("<line ").count(), 2);
+    assert!(svg.contains("<title>fe80::1%&lt;eth0&gt;</title>"));
+    assert_well_formed(&svg);
+}
+
+#[test]
+fn svg_sizes_follow_seen() {
+    let svg = to_svg(&scene(), 320, 200);
+    let attr = |element: &str, name: &str| -> Vec<f32> {
+        svg.lines()
+            .filter(|line| line.trim_start().starts_with(element))
+            .map(|line| {
+                let key = format!(" {name}=\"");
+                let start = line.find(&key).unwrap() + key.len();
+                let end = line[start..].find('"').unwrap() + start;
+                line[start..end].parse().unwrap()
+            })
+            .collect()
+    };
+
+    let radii = attr("<circle", "r");
+    assert!(radii[1] > radii[0]);
+    let widths = attr("<line", "stroke-width");
+    assert!(widths[0] > widths[1]);
+}
+
+#[test]
+fn svg_skips_edges_to_missing_nodes() {
+    let mut scene = scene();
+    scene.edges[0].to = "10.9.9.9".to_string();
+    let svg = to_svg(&scene, 320, 200);
+
+    assert_eq!(svg.matches("<line ").count(), 1);
+    assert_well_formed(&svg);
+}
@@ -1,14 +1,26 @@
 //! Traceroute collection and parsing.
 
 pub mod parser;
+pub mod replay;
 pub mod runner;
 pub mod stream;
 
 pub use parser::{
-    parse_hop_line, parse_traceroute_n, parse_traceroute_n_with_target, ParsedTraceRun,
+    is_pathping_output, parse_pathping, parse_pathping_with_target, parse_traceroute_n,
+    parse_traceroute_n_strict, parse_traceroute_n_with_mode, parse_traceroute_n_with_target,
+    ParsedTraceRun, StrictMode,
 };
+pub use replay::{RecordingRunner, ReplayRunner};
+#[cfg(target_os = "windows")]
+pub use runner::WindowsPathpingRunner;
 pub use runner::{
-    run_traceroute, run_traces, run_traces_with_runner, SystemTracerouteRunner, TraceJobResult,
-    TraceSettings, TracerouteRunner,
+    detect_source_ip, detect_traceroute_flags, parse_ip_route_get, parse_route_get_interface,
+    parse_traceroute_flags, pathping_args, run_pathping, run_traceroute, run_traces,
+    run_traces_batched, run_traces_batched_with_runner, run_traces_with_runner,
+    timeout_mode_warning, traceroute_args, traceroute_args_with_flags, traceroute_commands,
+    traceroute_size_flag, MockTracerouteRunner, SystemTracerouteRunner, TimeoutMode, TraceError,
+    TraceJobResult, TraceSettings, TraceTimeout, TracerouteFlags, TracerouteRunner,
+};
+pub use stream::{
+    loss_fraction, spawn_traceroute_stream, stream_for_target, HopStream, TraceEvent, TraceHandle,
 };
-pub use stream::{spawn_traceroute_stream, stream_for_target, TraceEvent};
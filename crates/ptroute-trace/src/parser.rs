@@ -1,23 +1,55 @@
 use anyhow::{anyhow, Result};
-use ptroute_model::Hop;
+use ptroute_model::{Hop, TraceProtocol};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParsedTraceRun {
     pub target: String,
     pub hops: Vec<Hop>,
+    pub source_ip: Option<String>,
+    /// What the tool that printed the output probes with: UDP for
+    /// `traceroute`, ICMP for `pathping`.
+    pub protocol: TraceProtocol,
 }
 
+/// Whether hop TTLs must strictly increase; see
+/// [`parse_traceroute_n_with_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StrictMode {
+    Lenient,
+    Strict,
+}
+
+/// Header line of Cisco IOS `traceroute`, lowercased.
+const CISCO_HEADER: &str = "tracing the route to ";
+
 pub fn parse_traceroute_n(text: &str) -> Result<ParsedTraceRun> {
-    parse_traceroute_n_inner(text, None)
+    parse_traceroute_n_with_mode(text, StrictMode::Lenient)
+}
+
+/// Like [`parse_traceroute_n`], but fails when a hop's TTL is not greater
+/// than the previous one (out of order or repeated).
+pub fn parse_traceroute_n_strict(text: &str) -> Result<ParsedTraceRun> {
+    parse_traceroute_n_with_mode(text, StrictMode::Strict)
+}
+
+/// [`parse_traceroute_n`] or [`parse_traceroute_n_strict`], picked by `mode`.
+pub fn parse_traceroute_n_with_mode(text: &str, mode: StrictMode) -> Result<ParsedTraceRun> {
+    parse_traceroute_n_inner(text, None, mode)
 }
 
 pub fn parse_traceroute_n_with_target(text: &str, fallback_target: &str) -> Result<ParsedTraceRun> {
-    parse_traceroute_n_inner(text, Some(fallback_target))
+    parse_traceroute_n_inner(text, Some(fallback_target), StrictMode::Lenient)
 }
 
-fn parse_traceroute_n_inner(text: &str, fallback_target: Option<&str>) -> Result<ParsedTraceRun> {
+fn parse_traceroute_n_inner(
+    text: &str,
+    fallback_target: Option<&str>,
+    mode: StrictMode,
+) -> Result<ParsedTraceRun> {
     let mut target: Option<String> = None;
-    let mut hops = Vec::new();
+    let mut source_ip: Option<String> = None;
+    let mut hops: Vec<Hop> = Vec::new();
     let mut current_hop: Option<usize> = None;
 
     for line in text.lines() {
@@ -26,10 +58,14 @@ fn parse_traceroute_n_inner(text: &str, fallback_target: Option<&str>) -> Result
             continue;
         }
 
-        if line.to_ascii_lowercase().starts_with("traceroute") {
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("traceroute") || lower.starts_with(CISCO_HEADER) {
             if target.is_none() {
                 target = parse_target(line);
             }
+            if source_ip.is_none() {
+                source_ip = parse_source_ip(line);
+            }
             continue;
         }
 
@@ -41,6 +77,17 @@ fn parse_traceroute_n_inner(text: &str, fallback_target: Option<&str>) -> Result
 
         if first_token.chars().all(|c| c.is_ascii_digit()) {
             let hop = parse_hop_line(line)?;
+            if mode == StrictMode::Strict {
+                if let Some(prev) = hops.last() {
+                    if hop.ttl <= prev.ttl {
+                        return Err(anyhow!(
+                            "hop ttl {} follows ttl {} (expected increasing ttls)",
+                            hop.ttl,
+                            prev.ttl
+                        ));
+                    }
+                }
+            }
             hops.push(hop);
             current_hop = Some(hops.len() - 1);
             continue;
@@ -52,7 +99,7 @@ fn parse_traceroute_n_inner(text: &str, fallback_target: Option<&str>) -> Result
                     .chain(tokens)
                     .collect();
                 let hop = &mut hops[index];
-                append_probe_tokens(&rest, &mut hop.ip, &mut hop.rtt_ms);
+                append_probe_tokens(&rest, &mut hop.ip, &mut hop.rtt_ms, &mut hop.mpls_labels);
             }
         }
     }
@@ -65,7 +112,98 @@ fn parse_traceroute_n_inner(text: &str, fallback_target: Option<&str>) -> Result
             .ok_or_else(|| anyhow!("missing target in traceroute output"))?,
     };
 
-    Ok(ParsedTraceRun { target, hops })
+    Ok(ParsedTraceRun {
+        target,
+        hops,
+        source_ip,
+        protocol: TraceProtocol::Udp,
+    })
+}
+
+/// Whether `text` came from Windows `pathping` rather than `traceroute`.
+pub fn is_pathping_output(text: &str) -> bool {
+    text.lines()
+        .any(|line| line.trim_start().starts_with("Tracing route to "))
+}
+
+/// Parses `pathping -n` output. The route comes from the first (topology)
+/// phase; the statistics phase, when present, gives each hop a single probe
+/// holding its average RTT, or `None` when every packet to it was lost. Hop 0
+/// is the local host and becomes `source_ip`.
+pub fn parse_pathping(text: &str) -> Result<ParsedTraceRun> {
+    parse_pathping_inner(text, None)
+}
+
+pub fn parse_pathping_with_target(text: &str, fallback_target: &str) -> Result<ParsedTraceRun> {
+    parse_pathping_inner(text, Some(fallback_target))
+}
+
+fn parse_pathping_inner(text: &str, fallback_target: Option<&str>) -> Result<ParsedTraceRun> {
+    let mut target: Option<String> = None;
+    let mut source_ip: Option<String> = None;
+    let mut hops: Vec<Hop> = Vec::new();
+    let mut in_statistics = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Tracing route to ") {
+            if target.is_none() {
+                target = rest.split_whitespace().next().map(str::to_string);
+            }
+            continue;
+        }
+        if line.starts_with("Computing statistics") {
+            in_statistics = true;
+            continue;
+        }
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(ttl) = tokens.first().and_then(|token| token.parse::<u32>().ok()) else {
+            continue;
+        };
+        // Named hops read `host [ip]`; with -n the address is bare.
+        let address = tokens[1..]
+            .last()
+            .map(|token| token.trim_start_matches('[').trim_end_matches(']'))
+            .filter(|token| is_ip_token(token));
+
+        if ttl == 0 {
+            if source_ip.is_none() {
+                source_ip = address.map(str::to_string);
+            }
+        } else if in_statistics {
+            let rtt = tokens
+                .get(1)
+                .and_then(|token| token.strip_suffix("ms"))
+                .and_then(|value| value.trim_start_matches('<').parse::<f64>().ok());
+            if let Some(hop) = hops.iter_mut().find(|hop| hop.ttl == ttl) {
+                hop.rtt_ms = vec![rtt];
+            }
+        } else {
+            let answered = address.is_some();
+            hops.push(Hop {
+                ttl,
+                ip: address.map(str::to_string),
+                rtt_ms: if answered { Vec::new() } else { vec![None] },
+                mpls_labels: Vec::new(),
+            });
+        }
+    }
+
+    let target = match target {
+        Some(value) => value,
+        None => fallback_target
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| value.to_string())
+            .ok_or_else(|| anyhow!("missing target in pathping output"))?,
+    };
+
+    Ok(ParsedTraceRun {
+        target,
+        hops,
+        source_ip,
+        protocol: TraceProtocol::Icmp,
+    })
 }
 
 fn parse_target(line: &str) -> Option<String> {
@@ -80,8 +218,11 @@ fn parse_target(line: &str) -> Option<String> {
     }
 
     let lower = line.to_ascii_lowercase();
-    if let Some(idx) = lower.find("traceroute to ") {
-        let rest = &line[idx + "traceroute to ".len()..];
+    let after_prefix = ["traceroute to ", CISCO_HEADER]
+        .into_iter()
+        .find_map(|prefix| lower.find(prefix).map(|idx| idx + prefix.len()));
+    if let Some(start) = after_prefix {
+        let rest = &line[start..];
         let token = rest
             .split(|c: char| c.is_whitespace() || c == ',')
             .next()?
@@ -94,7 +235,15 @@ fn parse_target(line: &str) -> Option<String> {
     None
 }
 
-fn parse_hop_line(line: &str) -> Result<Hop> {
+/// Extracts `X` from headers such as `traceroute to 1.1.1.1 (1.1.1.1) from X, 30 hops max`.
+fn parse_source_ip(line: &str) -> Option<String> {
+    let mut tokens = line.split_whitespace();
+    tokens.find(|token| token.eq_ignore_ascii_case("from"))?;
+    let token = tokens.next()?.trim_end_matches(',');
+    is_ip_token(token).then(|| token.to_string())
+}
+
+pub(crate) fn parse_hop_line(line: &str) -> Result<Hop> {
     let tokens: Vec<&str> = line.split_whitespace().collect();
     if tokens.is_empty() {
         return Err(anyhow!("empty hop line"));
@@ -106,23 +255,66 @@ fn parse_hop_line(line: &str) -> Result<Hop> {
 
     let mut ip: Option<String> = None;
     let mut rtt_ms: Vec<Option<f64>> = Vec::new();
+    let mut mpls_labels: Vec<u32> = Vec::new();
 
-    append_probe_tokens(&tokens[1..], &mut ip, &mut rtt_ms);
+    append_probe_tokens(&tokens[1..], &mut ip, &mut rtt_ms, &mut mpls_labels);
 
-    Ok(Hop { ttl, ip, rtt_ms })
+    Ok(Hop {
+        ttl,
+        ip,
+        rtt_ms,
+        mpls_labels,
+    })
 }
 
-fn append_probe_tokens(tokens: &[&str], ip: &mut Option<String>, rtt_ms: &mut Vec<Option<f64>>) {
+fn append_probe_tokens(
+    tokens: &[&str],
+    ip: &mut Option<String>,
+    rtt_ms: &mut Vec<Option<f64>>,
+    mpls_labels: &mut Vec<u32>,
+) {
     let mut i = 0;
     while i < tokens.len() {
         let tok = tokens[i];
 
+        if let Some(labels) = parse_mpls(tok) {
+            if mpls_labels.is_empty() {
+                *mpls_labels = labels;
+            }
+            i += 1;
+            continue;
+        }
+
+        // Cisco IOS annotations such as `[MPLS: Label 16 Exp 0]` span several
+        // tokens; none of them is a probe.
+        if tok.starts_with('[') {
+            let len = tokens[i..]
+                .iter()
+                .position(|token| token.ends_with(']'))
+                .map_or(tokens.len() - i, |end| end + 1);
+            if let Some(labels) = parse_cisco_mpls(&tokens[i..i + len]) {
+                if mpls_labels.is_empty() {
+                    *mpls_labels = labels;
+                }
+            }
+            i += len;
+            continue;
+        }
+
         if tok == "*" {
             rtt_ms.push(None);
             i += 1;
             continue;
         }
 
+        // A bare `!` is BusyBox's mark for a probe that got no answer; `!H`
+        // and friends annotate the reply before them.
+        if tok == "!" {
+            rtt_ms.push(None);
+            i += 1;
+            continue;
+        }
+
         if tok.starts_with('!') {
             i += 1;
             continue;
@@ -147,6 +339,38 @@ fn append_probe_tokens(tokens: &[&str], ip: &mut Option<String>, rtt_ms: &mut Ve
     }
 }
 
+// Linux `traceroute -e` prints MPLS extensions as
+// `<MPLS:L=24006,E=0,S=0,T=1/L=16,E=0,S=1,T=1>`, outermost label first.
+fn parse_mpls(token: &str) -> Option<Vec<u32>> {
+    let inner = token.strip_prefix("<MPLS:")?.strip_suffix('>')?;
+    let labels = inner
+        .split('/')
+        .filter_map(|entry| {
+            entry
+                .split(',')
+                .find_map(|field| field.strip_prefix("L="))
+                .and_then(|value| value.parse::<u32>().ok())
+        })
+        .collect();
+    Some(labels)
+}
+
+// Cisco IOS prints `[MPLS: Label 16 Exp 0]`, or `[MPLS: Labels 24/16 Exp 0]`
+// for a stack, outermost label first.
+fn parse_cisco_mpls(annotation: &[&str]) -> Option<Vec<u32>> {
+    if annotation.first() != Some(&"[MPLS:") {
+        return None;
+    }
+    let at = annotation
+        .iter()
+        .position(|token| matches!(*token, "Label" | "Labels"))?;
+    annotation
+        .get(at + 1)?
+        .split('/')
+        .map(|value| value.parse().ok())
+        .collect()
+}
+
 fn is_probe_start(token: &str) -> bool {
     token == "*" || is_ip_token(token)
 }
@@ -185,9 +409,12 @@ fn is_ipv6(token: &str) -> bool {
         return false;
     }
 
-    token
-        .chars()
-        .all(|c| c.is_ascii_hexdigit() || c == ':')
+    let address = match token.split_once('%') {
+        Some((_, "")) => return false,
+        Some((address, _zone)) => address,
+        None => token,
+    };
+    address.parse::<std::net::Ipv6Addr>().is_ok()
 }
 
 fn parse_rtt(token: &str, next: Option<&str>) -> Option<(f64, bool)> {
@@ -219,6 +446,48 @@ mod tests {
         assert_eq!(parse_target(line), Some("1.1.1.1".to_string()));
     }
 
+    #[test]
+    fn parse_source_ip_from_header() {
+        let text = "traceroute to 1.1.1.1 (1.1.1.1) from 192.168.1.20, 30 hops max, 60 byte packets\n 1  192.168.1.1  1.0 ms";
+        let run = parse_traceroute_n(text).unwrap();
+        assert_eq!(run.source_ip.as_deref(), Some("192.168.1.20"));
+
+        let line = "traceroute to 1.1.1.1 (1.1.1.1), 30 hops max, 60 byte packets";
+        assert_eq!(parse_source_ip(line), None);
+    }
+
+    #[test]
+    fn ipv6_detection_rejects_malformed_tokens() {
+        assert!(is_ipv6("2001:0db8:85a3:0000:0000:8a2e:0370:7334"));
+        assert!(is_ipv6("fe80::1%eth0"));
+        assert!(is_ipv6("::1"));
+        assert!(!is_ipv6("2001:db8::zz"));
+        assert!(!is_ipv6("1:2:3:4:5:6:7:8:9"));
+        assert!(!is_ipv6("2001::db8::1"));
+        assert!(!is_ipv6("12:34"));
+    }
+
+    #[test]
+    fn ipv6_detection_edge_cases() {
+        for valid in [
+            "::",
+            "::1",
+            "fe80::1",
+            "2606:4700:4700::1111",
+            "1::",
+            "::ffff:192.0.2.1",
+            "FE80::ABCD",
+            "fe80::1%en0",
+        ] {
+            assert!(is_ipv6(valid), "{valid}");
+        }
+        for invalid in [
+            ":::", "gg::1", ":1", "1:", "fe80::1%", "%eth0", "[::1]", "::1/64", "", "1.1.1.1",
+        ] {
+            assert!(!is_ipv6(invalid), "{invalid}");
+        }
+    }
+
     #[test]
     fn parse_uses_fallback_target() {
         let text = "1  192.168.1.1  1.0 ms  1.1 ms  1.2 ms";
@@ -226,4 +495,14 @@ mod tests {
         assert_eq!(run.target, "9.9.9.9");
         assert_eq!(run.hops.len(), 1);
     }
+
+    #[test]
+    fn parse_mpls_labels_from_extensions() {
+        let line =
+            "3  10.0.0.2 <MPLS:L=24006,E=0,S=0,T=1/L=16,E=0,S=1,T=1>  5.1 ms  5.2 ms  5.3 ms";
+        let hop = parse_hop_line(line).unwrap();
+        assert_eq!(hop.ip.as_deref(), Some("10.0.0.2"));
+        assert_eq!(hop.mpls_labels, vec![24006, 16]);
+        assert_eq!(hop.rtt_ms.len(), 3);
+    }
 }
@@ -0,0 +1,230 @@
+use crate::runner::{TraceSettings, TracerouteRunner};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Wraps a runner and saves every successful traceroute output to `dir` as
+/// `<target>_rep<N>.txt`, where `N` counts the runs for that target from 0.
+/// A [`ReplayRunner`] over the same directory plays them back.
+///
+/// Failed runs are passed through without being recorded and do not use up
+/// an `N`, so the recordings for a target are always numbered without gaps.
+/// Runs for the same target are expected one at a time, as the job runners
+/// issue them.
+pub struct RecordingRunner<R: TracerouteRunner> {
+    inner: R,
+    dir: PathBuf,
+    repeats: Mutex<HashMap<String, u32>>,
+}
+
+impl<R: TracerouteRunner> RecordingRunner<R> {
+    pub fn new(inner: R, dir: PathBuf) -> Self {
+        Self {
+            inner,
+            dir,
+            repeats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: TracerouteRunner> TracerouteRunner for RecordingRunner<R> {
+    fn run(&self, target: &str, settings: &TraceSettings) -> Result<String> {
+        let repeat = peek_repeat(&self.repeats, target)?;
+        let output = self.inner.run(target, settings)?;
+
+        fs::create_dir_all(&self.dir).map_err(|err| {
+            anyhow!(
+                "failed to create recording directory {:?}: {}",
+                self.dir,
+                err
+            )
+        })?;
+        let path = self.dir.join(recording_file_name(target, repeat));
+        fs::write(&path, &output)
+            .map_err(|err| anyhow!("failed to write recording {:?}: {}", path, err))?;
+        commit_repeat(&self.repeats, target, repeat)?;
+        Ok(output)
+    }
+
+    fn source_ip(&self, target: &str) -> Option<String> {
+        self.inner.source_ip(target)
+    }
+
+    fn enforces_total_timeout(&self) -> bool {
+        self.inner.enforces_total_timeout()
+    }
+}
+
+/// Plays back a directory written by [`RecordingRunner`]: the `N`th run for
+/// a target returns the contents of `<target>_rep<N>.txt`, and runs past the
+/// last recording fail.
+#[derive(Debug)]
+pub struct ReplayRunner {
+    dir: PathBuf,
+    recordings: HashMap<(String, u32), String>,
+    repeats: Mutex<HashMap<String, u32>>,
+}
+
+impl ReplayRunner {
+    /// Loads every recording in `dir`; other files are ignored.
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        let entries = fs::read_dir(&dir)
+            .map_err(|err| anyhow!("failed to read recording directory {:?}: {}", dir, err))?;
+        let mut recordings = HashMap::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|err| anyhow!("failed to read recording directory {:?}: {}", dir, err))?
+                .path();
+            let Some(key) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(parse_recording_file_name)
+            else {
+                continue;
+            };
+            let output = fs::read_to_string(&path)
+                .map_err(|err| anyhow!("failed to read recording {:?}: {}", path, err))?;
+            recordings.insert(key, output);
+        }
+
+        Ok(Self {
+            dir,
+            recordings,
+            repeats: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Number of recordings loaded.
+    pub fn len(&self) -> usize {
+        self.recordings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recordings.is_empty()
+    }
+}
+
+impl TracerouteRunner for ReplayRunner {
+    fn run(&self, target: &str, _settings: &TraceSettings) -> Result<String> {
+        let repeat = next_repeat(&self.repeats, target)?;
+        self.recordings
+            .get(&(target.to_string(), repeat))
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "no recording for {target} (repeat {repeat}) in {:?}",
+                    self.dir
+                )
+            })
+    }
+}
+
+fn next_repeat(repeats: &Mutex<HashMap<String, u32>>, target: &str) -> Result<u32> {
+    let repeat = peek_repeat(repeats, target)?;
+    commit_repeat(repeats, target, repeat)?;
+    Ok(repeat)
+}
+
+/// The repeat index the next run for `target` gets, without claiming it.
+fn peek_repeat(repeats: &Mutex<HashMap<String, u32>>, target: &str) -> Result<u32> {
+    let repeats = repeats
+        .lock()
+        .map_err(|_| anyhow!("recording counter lock poisoned"))?;
+    Ok(repeats.get(target).copied().unwrap_or(0))
+}
+
+/// Marks `repeat` as used, so the next run for `target` gets `repeat + 1`.
+fn commit_repeat(repeats: &Mutex<HashMap<String, u32>>, target: &str, repeat: u32) -> Result<()> {
+    let mut repeats = repeats
+        .lock()
+        .map_err(|_| anyhow!("recording counter lock poisoned"))?;
+    let counter = repeats.entry(target.to_string()).or_insert(0);
+    *counter = (*counter).max(repeat + 1);
+    Ok(())
+}
+
+/// `<target>_rep<N>.txt`, with every byte of `target` outside
+/// `A-Z a-z 0-9 - . _ ~` percent-encoded (so `::1` becomes `%3A%3A1`).
+pub fn recording_file_name(target: &str, repeat: u32) -> String {
+    let mut name = String::with_capacity(target.len() + 12);
+    for byte in target.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    name.push_str(&format!("_rep{repeat}.txt"));
+    name
+}
+
+/// Inverse of [`recording_file_name`]; `None` for names it did not produce.
+pub fn parse_recording_file_name(name: &str) -> Option<(String, u32)> {
+    let (encoded, repeat) = name.strip_suffix(".txt")?.rsplit_once("_rep")?;
+    if repeat.is_empty() || !repeat.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let repeat = repeat.parse().ok()?;
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    let target = String::from_utf8(bytes).ok()?;
+    (!target.is_empty()).then_some((target, repeat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_round_trip_special_characters() {
+        for target in [
+            "1.1.1.1",
+            "2606:4700::1111",
+            "fe80::1%eth0",
+            "a_rep3",
+            "host/x y",
+        ] {
+            let name = recording_file_name(target, 7);
+            assert!(!name.contains(['/', ':', ' ']), "{name}");
+            assert_eq!(
+                parse_recording_file_name(&name),
+                Some((target.to_string(), 7))
+            );
+        }
+        assert_eq!(recording_file_name("::1", 0), "%3A%3A1_rep0.txt");
+    }
+
+    #[test]
+    fn foreign_file_names_are_ignored() {
+        for name in [
+            "notes.txt",
+            "x_rep.txt",
+            "x_rep1.json",
+            "%G1_rep0.txt",
+            "%+1_rep0.txt",
+            "_rep0.txt",
+        ] {
+            assert_eq!(parse_recording_file_name(name), None, "{name}");
+        }
+    }
+}
@@ -1,15 +1,38 @@
-use crate::parser::parse_traceroute_n_with_target;
+use crate::parser::{
+    is_pathping_output, parse_pathping_with_target, parse_traceroute_n_with_target, ParsedTraceRun,
+};
 use anyhow::{anyhow, Context, Result};
-use std::process::Command;
-use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{ErrorKind, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock};
 use std::thread;
 use std::time::Duration;
 
+// Extra time given to a runner to hand back partial output after its own kill.
+const TOTAL_TIMEOUT_GRACE_MS: u64 = 50;
+
 #[derive(Debug, Clone)]
 pub struct TraceSettings {
     pub max_hops: u32,
     pub probes: u32,
     pub timeout_ms: u64,
+    pub detect_source_ip: bool,
+    /// Upper bound for one whole traceroute; the process is killed once it
+    /// passes. `None` waits for traceroute to finish on its own.
+    pub total_timeout_ms: Option<u64>,
+    /// Extra attempts for a job whose traceroute could not be spawned
+    /// ([`TraceError::SpawnFailed`]); other failures are never retried.
+    pub max_retries: u32,
+    /// Pause between those attempts.
+    pub retry_delay_ms: u64,
+    /// Probe packet length handed to traceroute (e.g. 1400 to look for path
+    /// MTU trouble); `None` keeps traceroute's default.
+    pub probe_size_bytes: Option<u32>,
+    /// Whether `timeout_ms` bounds each probe or each hop.
+    pub timeout_mode: TimeoutMode,
 }
 
 impl Default for TraceSettings {
@@ -18,12 +41,115 @@ impl Default for TraceSettings {
             max_hops: 30,
             probes: 3,
             timeout_ms: 2000,
+            detect_source_ip: false,
+            total_timeout_ms: None,
+            max_retries: 0,
+            retry_delay_ms: 0,
+            probe_size_bytes: None,
+            timeout_mode: TimeoutMode::default(),
+        }
+    }
+}
+
+/// How [`TraceSettings::timeout_ms`] is handed to traceroute. `-w` is a
+/// per-probe wait on most implementations, so a per-hop bound is built from
+/// the flags the installed binary supports (see [`TracerouteFlags`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimeoutMode {
+    /// Wait up to the timeout for every probe (`-w`).
+    #[default]
+    PerProbe,
+    /// Wait up to the timeout for a hop's probes together. Where `-N` is
+    /// available (Linux) the probes are in flight at once, so the timeout is
+    /// the `-w` wait. Otherwise each probe waits `timeout / probes` rounded
+    /// down to whole seconds, but never less than one (see
+    /// [`timeout_mode_warning`]).
+    PerHop,
+}
+
+impl TimeoutMode {
+    /// The name accepted by `FromStr` (`per-probe`, `per-hop`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PerProbe => "per-probe",
+            Self::PerHop => "per-hop",
+        }
+    }
+}
+
+impl std::str::FromStr for TimeoutMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "per-probe" => Ok(Self::PerProbe),
+            "per-hop" => Ok(Self::PerHop),
+            other => Err(format!(
+                "unknown timeout mode {other:?} (expected per-probe or per-hop)"
+            )),
+        }
+    }
+}
+
+/// Failures that callers can act on, carried through `anyhow` like
+/// [`TraceTimeout`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum TraceError {
+    /// The traceroute process could not be started (e.g. a momentary
+    /// resource limit); `run_traces_with_runner` retries these.
+    SpawnFailed { target: String, message: String },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::SpawnFailed { target, message } => {
+                write!(f, "failed to spawn traceroute for {target}: {message}")
+            }
         }
     }
 }
 
+impl std::error::Error for TraceError {}
+
+/// Returned (via `anyhow`) when a traceroute hits `total_timeout_ms`. Carries
+/// whatever output was produced before the process was killed.
+#[derive(Debug, Clone)]
+pub struct TraceTimeout {
+    pub target: String,
+    pub after_ms: u64,
+    pub partial_output: String,
+}
+
+impl fmt::Display for TraceTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "traceroute for {} timed out after {} ms",
+            self.target, self.after_ms
+        )
+    }
+}
+
+impl std::error::Error for TraceTimeout {}
+
 pub trait TracerouteRunner {
     fn run(&self, target: &str, settings: &TraceSettings) -> Result<String>;
+
+    /// Local address the probes to `target` leave from, if it can be determined.
+    fn source_ip(&self, _target: &str) -> Option<String> {
+        None
+    }
+
+    /// Whether [`Self::run`] kills its own process once `total_timeout_ms`
+    /// has passed. Other runners are cut off by the job runner instead: an
+    /// overrunning call is left to finish in the background, still holding
+    /// its concurrency slot.
+    fn enforces_total_timeout(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,7 +157,90 @@ pub struct SystemTracerouteRunner;
 
 impl TracerouteRunner for SystemTracerouteRunner {
     fn run(&self, target: &str, settings: &TraceSettings) -> Result<String> {
-        run_traceroute(target, settings)
+        if cfg!(target_os = "windows") {
+            run_pathping(target, settings)
+        } else {
+            run_traceroute(target, settings)
+        }
+    }
+
+    fn source_ip(&self, target: &str) -> Option<String> {
+        detect_source_ip(target)
+    }
+
+    fn enforces_total_timeout(&self) -> bool {
+        // run_traceroute's watchdog kills the process; pathping has no limit.
+        !cfg!(target_os = "windows")
+    }
+}
+
+/// Traces with `pathping.exe`, the closest thing Windows ships to
+/// traceroute. The job runners recognise its output and parse it with
+/// [`crate::parse_pathping`]. `total_timeout_ms` and `probe_size_bytes` are
+/// not supported.
+#[cfg(target_os = "windows")]
+#[derive(Debug, Clone)]
+pub struct WindowsPathpingRunner;
+
+#[cfg(target_os = "windows")]
+impl TracerouteRunner for WindowsPathpingRunner {
+    fn run(&self, target: &str, settings: &TraceSettings) -> Result<String> {
+        run_pathping(target, settings)
+    }
+}
+
+/// Canned-output runner for tests: returns the registered traceroute text for
+/// each target, optionally after a fixed delay, and counts calls. The
+/// arguments a real run would have passed are kept in [`Self::commands`].
+///
+/// Panics when asked to trace a target that has no registered response.
+#[derive(Debug, Default)]
+pub struct MockTracerouteRunner {
+    responses: HashMap<String, String>,
+    latency: Duration,
+    calls: AtomicUsize,
+    commands: Mutex<Vec<Vec<String>>>,
+}
+
+impl MockTracerouteRunner {
+    pub fn new(responses: HashMap<String, String>) -> Self {
+        Self::new_with_latency(responses, Duration::ZERO)
+    }
+
+    pub fn new_with_latency(responses: HashMap<String, String>, latency: Duration) -> Self {
+        Self {
+            responses,
+            latency,
+            calls: AtomicUsize::new(0),
+            commands: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Number of times [`TracerouteRunner::run`] has been called.
+    pub fn call_count(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// [`traceroute_args`] for every call so far, in call order.
+    pub fn commands(&self) -> Vec<Vec<String>> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+impl TracerouteRunner for MockTracerouteRunner {
+    fn run(&self, target: &str, settings: &TraceSettings) -> Result<String> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.commands
+            .lock()
+            .unwrap()
+            .push(traceroute_args(target, settings));
+        let Some(response) = self.responses.get(target) else {
+            panic!("MockTracerouteRunner: no response registered for target {target:?}");
+        };
+        if !self.latency.is_zero() {
+            thread::sleep(self.latency);
+        }
+        Ok(response.clone())
     }
 }
 
@@ -40,6 +249,9 @@ pub struct TraceJobResult {
     pub target: String,
     pub repeat: u32,
     pub result: Result<crate::parser::ParsedTraceRun, String>,
+    pub timed_out: bool,
+    /// Times the traceroute was run, including retries.
+    pub attempts: u32,
 }
 
 pub fn run_traces(
@@ -59,6 +271,58 @@ pub fn run_traces(
     )
 }
 
+/// [`run_traces`] over at most `batch_size` targets at a time, so a long
+/// target list never has every traceroute thread and process alive at once.
+/// Each batch finishes before the next starts; results keep the order
+/// `run_traces` would give. A `batch_size` of 0 runs a single batch.
+pub fn run_traces_batched(
+    targets: &[String],
+    settings: &TraceSettings,
+    repeat: u32,
+    interval_ms: u64,
+    concurrency: usize,
+    batch_size: usize,
+) -> Vec<TraceJobResult> {
+    run_traces_batched_with_runner(
+        targets,
+        settings,
+        repeat,
+        interval_ms,
+        concurrency,
+        batch_size,
+        Arc::new(SystemTracerouteRunner),
+    )
+}
+
+pub fn run_traces_batched_with_runner<R: TracerouteRunner + Send + Sync + 'static>(
+    targets: &[String],
+    settings: &TraceSettings,
+    repeat: u32,
+    interval_ms: u64,
+    concurrency: usize,
+    batch_size: usize,
+    runner: Arc<R>,
+) -> Vec<TraceJobResult> {
+    let batch_size = if batch_size == 0 {
+        targets.len().max(1)
+    } else {
+        batch_size
+    };
+    targets
+        .chunks(batch_size)
+        .flat_map(|batch| {
+            run_traces_with_runner(
+                batch,
+                settings,
+                repeat,
+                interval_ms,
+                concurrency,
+                Arc::clone(&runner),
+            )
+        })
+        .collect()
+}
+
 pub fn run_traces_with_runner<R: TracerouteRunner + Send + Sync + 'static>(
     targets: &[String],
     settings: &TraceSettings,
@@ -85,14 +349,46 @@ pub fn run_traces_with_runner<R: TracerouteRunner + Send + Sync + 'static>(
         let handle = thread::spawn(move || {
             let base_index = target_index * repeat as usize;
             for rep in 0..repeat {
-                let raw = {
-                    let _permit = semaphore.acquire();
-                    runner.run(&target_clone, &settings)
+                let mut attempts = 0;
+                let (raw, detected_source) = loop {
+                    attempts += 1;
+                    let permit = Semaphore::acquire(&semaphore);
+                    let detected_source = if settings.detect_source_ip {
+                        runner.source_ip(&target_clone)
+                    } else {
+                        None
+                    };
+                    let raw = run_with_total_timeout(&runner, &target_clone, &settings, permit);
+                    let spawn_failed = raw.as_ref().is_err_and(|err| {
+                        matches!(
+                            err.downcast_ref::<TraceError>(),
+                            Some(TraceError::SpawnFailed { .. })
+                        )
+                    });
+                    if !spawn_failed || attempts > settings.max_retries {
+                        break (raw, detected_source);
+                    }
+                    if settings.retry_delay_ms > 0 {
+                        thread::sleep(Duration::from_millis(settings.retry_delay_ms));
+                    }
+                };
+
+                let (raw, timed_out) = match raw {
+                    Ok(output) => (Ok(output), false),
+                    Err(err) => match err.downcast::<TraceTimeout>() {
+                        Ok(timeout) => (Ok(timeout.partial_output), true),
+                        Err(err) => (Err(err), false),
+                    },
                 };
 
                 let result = match raw {
-                    Ok(output) => match parse_traceroute_n_with_target(&output, &target_clone) {
-                        Ok(parsed) => Ok(parsed),
+                    Ok(output) => match parse_runner_output(&output, &target_clone) {
+                        Ok(mut parsed) => {
+                            if parsed.source_ip.is_none() {
+                                parsed.source_ip = detected_source;
+                            }
+                            Ok(parsed)
+                        }
                         Err(err) => Err(format_parse_error(
                             &target_clone,
                             rep,
@@ -107,6 +403,8 @@ pub fn run_traces_with_runner<R: TracerouteRunner + Send + Sync + 'static>(
                     target: target_clone.clone(),
                     repeat: rep,
                     result,
+                    timed_out,
+                    attempts,
                 };
                 let _ = tx.send((base_index + rep as usize, job));
 
@@ -128,10 +426,57 @@ pub fn run_traces_with_runner<R: TracerouteRunner + Send + Sync + 'static>(
     }
 
     for handle in handles {
-        let _ = handle.join();
+        if let Err(panic) = handle.join() {
+            std::panic::resume_unwind(panic);
+        }
     }
 
-    results.into_iter().filter_map(|job| job).collect()
+    results.into_iter().flatten().collect()
+}
+
+/// Runs one traceroute while holding `permit`, giving up once
+/// `total_timeout_ms` (plus a short grace period) has passed. Runners that
+/// enforce the limit themselves are called directly. Any other runner that
+/// overruns is reported as a timeout without output; its call is left to
+/// finish on a detached thread, which keeps `permit` until it does. A panic
+/// on that thread becomes an error for this job.
+fn run_with_total_timeout<R: TracerouteRunner + Send + Sync + 'static>(
+    runner: &Arc<R>,
+    target: &str,
+    settings: &TraceSettings,
+    permit: Permit,
+) -> Result<String> {
+    let Some(total_ms) = settings
+        .total_timeout_ms
+        .filter(|_| !runner.enforces_total_timeout())
+    else {
+        let result = runner.run(target, settings);
+        drop(permit);
+        return result;
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let runner = Arc::clone(runner);
+    let job_target = target.to_string();
+    let job_settings = settings.clone();
+    thread::spawn(move || {
+        let result = runner.run(&job_target, &job_settings);
+        drop(permit);
+        let _ = tx.send(result);
+    });
+
+    match rx.recv_timeout(Duration::from_millis(total_ms + TOTAL_TIMEOUT_GRACE_MS)) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err(anyhow!("traceroute runner panicked for {target}"))
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(TraceTimeout {
+            target: target.to_string(),
+            after_ms: total_ms,
+            partial_output: String::new(),
+        }
+        .into()),
+    }
 }
 
 fn format_run_error(target: &str, repeat: u32, message: &str) -> String {
@@ -162,13 +507,15 @@ impl Semaphore {
         }
     }
 
-    fn acquire(&self) -> Permit<'_> {
-        let mut guard = self.state.lock().unwrap();
-        while *guard >= self.max {
-            guard = self.cvar.wait(guard).unwrap();
+    fn acquire(semaphore: &Arc<Self>) -> Permit {
+        let mut guard = semaphore.state.lock().unwrap();
+        while *guard >= semaphore.max {
+            guard = semaphore.cvar.wait(guard).unwrap();
         }
         *guard += 1;
-        Permit { semaphore: self }
+        Permit {
+            semaphore: Arc::clone(semaphore),
+        }
     }
 
     fn release(&self) {
@@ -180,41 +527,579 @@ impl Semaphore {
     }
 }
 
-struct Permit<'a> {
-    semaphore: &'a Semaphore,
+struct Permit {
+    semaphore: Arc<Semaphore>,
 }
 
-impl<'a> Drop for Permit<'a> {
+impl Drop for Permit {
     fn drop(&mut self) {
         self.semaphore.release();
     }
 }
 
 pub fn run_traceroute(target: &str, settings: &TraceSettings) -> Result<String> {
-    let timeout_secs = ((settings.timeout_ms + 999) / 1000).max(1);
-
-    let output = Command::new("traceroute")
-        .arg("-n")
-        .arg("-q")
-        .arg(settings.probes.to_string())
-        .arg("-m")
-        .arg(settings.max_hops.to_string())
-        .arg("-w")
-        .arg(timeout_secs.to_string())
-        .arg(target)
-        .output()
-        .with_context(|| format!("failed to spawn traceroute for {target}"))?;
+    let mut child = spawn_traceroute(target, settings)?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("missing traceroute stdout"))?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("missing traceroute stderr"))?;
+    let stderr_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        buf
+    });
 
-    if !output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    let child = Arc::new(Mutex::new(child));
+    let watchdog = settings
+        .total_timeout_ms
+        .map(|ms| Watchdog::start(Arc::clone(&child), Duration::from_millis(ms)));
+
+    let mut out = Vec::new();
+    stdout
+        .read_to_end(&mut out)
+        .with_context(|| format!("failed to read traceroute output for {target}"))?;
+    let timed_out = watchdog.is_some_and(Watchdog::finish);
+    let status = child
+        .lock()
+        .map_err(|_| anyhow!("traceroute process lock poisoned"))?
+        .wait()
+        .with_context(|| format!("failed to wait for traceroute for {target}"))?;
+    let err_out = stderr_reader.join().unwrap_or_default();
+
+    let stdout = String::from_utf8_lossy(&out).to_string();
+    if timed_out {
+        return Err(TraceTimeout {
+            target: target.to_string(),
+            after_ms: settings.total_timeout_ms.unwrap_or_default(),
+            partial_output: stdout,
+        }
+        .into());
+    }
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&err_out);
         return Err(anyhow!(
             "traceroute failed for {target} (status: {}): {}{}",
-            output.status,
+            status,
             stderr,
             stdout
         ));
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    Ok(stdout)
+}
+
+/// Arguments for `pathping` tracing `target`: numeric output, `max_hops`,
+/// `probes` queries per hop and a per-reply wait of `timeout_ms`.
+pub fn pathping_args(target: &str, settings: &TraceSettings) -> Vec<String> {
+    vec![
+        "-n".to_string(),
+        "-h".to_string(),
+        settings.max_hops.to_string(),
+        "-q".to_string(),
+        settings.probes.to_string(),
+        "-w".to_string(),
+        settings.timeout_ms.to_string(),
+        target.to_string(),
+    ]
+}
+
+/// Runs `pathping` to completion and returns its stdout.
+pub fn run_pathping(target: &str, settings: &TraceSettings) -> Result<String> {
+    let output = Command::new("pathping")
+        .args(pathping_args(target, settings))
+        .output()
+        .map_err(|err| TraceError::SpawnFailed {
+            target: target.to_string(),
+            message: format!("pathping: {err}"),
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if !output.status.success() {
+        return Err(anyhow!(
+            "pathping failed for {target} (status: {}): {}{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr),
+            stdout
+        ));
+    }
+    Ok(stdout)
+}
+
+fn parse_runner_output(output: &str, target: &str) -> Result<ParsedTraceRun> {
+    if is_pathping_output(output) {
+        parse_pathping_with_target(output, target)
+    } else {
+        parse_traceroute_n_with_target(output, target)
+    }
+}
+
+/// Kills a child process (SIGKILL on Unix) unless `finish` is called first.
+struct Watchdog {
+    finished: Arc<(Mutex<bool>, Condvar)>,
+    handle: thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn start(child: Arc<Mutex<Child>>, limit: Duration) -> Self {
+        let finished = Arc::new((Mutex::new(false), Condvar::new()));
+        let state = Arc::clone(&finished);
+        let handle = thread::spawn(move || {
+            let (lock, cvar) = &*state;
+            let Ok(guard) = lock.lock() else {
+                return false;
+            };
+            let Ok((guard, wait)) = cvar.wait_timeout_while(guard, limit, |done| !*done) else {
+                return false;
+            };
+            if *guard || !wait.timed_out() {
+                return false;
+            }
+            if let Ok(mut child) = child.lock() {
+                let _ = child.kill();
+            }
+            true
+        });
+        Self { finished, handle }
+    }
+
+    /// Stops the watchdog and reports whether it had to kill the process.
+    fn finish(self) -> bool {
+        let (lock, cvar) = &*self.finished;
+        if let Ok(mut done) = lock.lock() {
+            *done = true;
+        }
+        cvar.notify_all();
+        self.handle.join().unwrap_or(false)
+    }
+}
+
+/// Programs (with leading arguments) to try for `target`, in order. IPv6
+/// targets prefer `traceroute6` and fall back to `traceroute -6`.
+pub fn traceroute_commands(target: &str) -> &'static [(&'static str, &'static [&'static str])] {
+    if target.contains(':') {
+        &[("traceroute6", &[]), ("traceroute", &["-6"])]
+    } else {
+        &[("traceroute", &[])]
+    }
+}
+
+/// Arguments passed to traceroute for `target`, after any per-binary extras
+/// from [`traceroute_commands`], assuming only `-w` is supported. Real runs
+/// use [`traceroute_args_with_flags`] with the flags detected for the binary.
+pub fn traceroute_args(target: &str, settings: &TraceSettings) -> Vec<String> {
+    traceroute_args_with_flags(target, settings, &TracerouteFlags::WAIT_ONLY)
+}
+
+/// Linux traceroute's default `-N`: probes in flight at once, across hops.
+const LINUX_DEFAULT_SIM_QUERIES: u32 = 16;
+
+/// Explains why a binary supporting `flags` cannot keep a hop within a
+/// [`TimeoutMode::PerHop`] timeout: without `-N`, each probe would get less
+/// than the one second `-w` can express, so it gets `-w 1` instead. `None`
+/// when the timeout is honored.
+pub fn timeout_mode_warning(settings: &TraceSettings, flags: &TracerouteFlags) -> Option<String> {
+    if settings.timeout_mode != TimeoutMode::PerHop || flags.sim_queries {
+        return None;
+    }
+    let probes = settings.probes.max(1);
+    (settings.timeout_ms / u64::from(probes) < 1000).then(|| {
+        format!(
+            "per-hop timeout of {} ms leaves under a second for each of {probes} probes, \
+             and this traceroute cannot send them at once; using -w 1 per probe",
+            settings.timeout_ms
+        )
+    })
+}
+
+/// [`traceroute_args`] for a binary supporting `flags`: `-e` is added when
+/// it prints ICMP extensions, and [`TimeoutMode::PerHop`] depends on them.
+/// A per-hop timeout too short to split still gets `-w 1` (see
+/// [`timeout_mode_warning`]).
+pub fn traceroute_args_with_flags(
+    target: &str,
+    settings: &TraceSettings,
+    flags: &TracerouteFlags,
+) -> Vec<String> {
+    let probes = settings.probes.max(1);
+    let mut args = vec![
+        "-n".to_string(),
+        "-q".to_string(),
+        settings.probes.to_string(),
+        "-m".to_string(),
+        settings.max_hops.to_string(),
+    ];
+    if flags.extensions {
+        args.push("-e".to_string());
+    }
+    let wait_secs = match settings.timeout_mode {
+        TimeoutMode::PerProbe => settings.timeout_ms.div_ceil(1000),
+        TimeoutMode::PerHop if flags.sim_queries => {
+            // A hop's probes already go out together under the default;
+            // lowering -N would also cut how many hops are probed at once.
+            if probes > LINUX_DEFAULT_SIM_QUERIES {
+                args.extend(["-N".to_string(), probes.to_string()]);
+            }
+            settings.timeout_ms.div_ceil(1000)
+        }
+        // Round down so the probes together stay within the timeout.
+        TimeoutMode::PerHop => settings.timeout_ms / u64::from(probes) / 1000,
+    };
+    args.extend([
+        "-w".to_string(),
+        wait_secs.max(1).to_string(),
+        target.to_string(),
+    ]);
+    if let Some(size) = settings.probe_size_bytes {
+        args.extend(traceroute_size_flag(std::env::consts::OS, size));
+    }
+    args
+}
+
+/// Options a traceroute binary advertises in its help text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TracerouteFlags {
+    /// `-w <secs>`.
+    pub wait: bool,
+    /// `--wait=<secs>` (Linux traceroute).
+    pub long_wait: bool,
+    /// `-N <n>`: probes sent at once (Linux traceroute).
+    pub sim_queries: bool,
+    /// `-e` / `--extensions`: ICMP extensions such as MPLS label stacks
+    /// (Linux traceroute). Only the long form is matched, since BSD
+    /// traceroute uses `-e` for firewall evasion.
+    pub extensions: bool,
+}
+
+impl TracerouteFlags {
+    /// What every supported traceroute (BSD, macOS, BusyBox) understands.
+    pub const WAIT_ONLY: Self = Self {
+        wait: true,
+        long_wait: false,
+        sim_queries: false,
+        extensions: false,
+    };
+}
+
+/// Reads the flags out of `traceroute --help` (or usage) output.
+pub fn parse_traceroute_flags(help: &str) -> TracerouteFlags {
+    let mut flags = TracerouteFlags::default();
+    for token in help.split_whitespace() {
+        let token = token.trim_matches(|c| matches!(c, '[' | ']' | ',' | '|'));
+        match token.split('=').next().unwrap_or(token) {
+            "-w" => flags.wait = true,
+            "--wait" => flags.long_wait = true,
+            "-N" | "--sim-queries" => flags.sim_queries = true,
+            "--extensions" => flags.extensions = true,
+            _ => {}
+        }
+    }
+    flags
+}
+
+/// Runs `program --help` once per process and caches the parsed flags.
+/// Falls back to [`TracerouteFlags::WAIT_ONLY`] when the help text says
+/// nothing useful (or the binary is missing).
+pub fn detect_traceroute_flags(program: &str) -> TracerouteFlags {
+    static CACHE: OnceLock<Mutex<HashMap<String, TracerouteFlags>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(flags) = cache.lock().unwrap().get(program) {
+        return *flags;
+    }
+
+    let flags = Command::new(program)
+        .arg("--help")
+        .stdin(Stdio::null())
+        .output()
+        .ok()
+        .map(|output| {
+            let mut help = String::from_utf8_lossy(&output.stdout).to_string();
+            help.push_str(&String::from_utf8_lossy(&output.stderr));
+            parse_traceroute_flags(&help)
+        })
+        .filter(|flags| *flags != TracerouteFlags::default())
+        .unwrap_or(TracerouteFlags::WAIT_ONLY);
+    cache.lock().unwrap().insert(program.to_string(), flags);
+    flags
+}
+
+/// Arguments that set the probe packet length on `platform` (as in
+/// `std::env::consts::OS`). Linux and the BSD-derived traceroutes (macOS
+/// included) take it as a positional argument after the host, where `-s`
+/// would set the source address; other platforms get nothing.
+pub fn traceroute_size_flag(platform: &str, size: u32) -> Vec<String> {
+    match platform {
+        "linux" | "macos" | "freebsd" | "openbsd" | "netbsd" => vec![size.to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// Spawns the first available traceroute binary for `target` with piped
+/// stdout/stderr. Only a missing binary moves on to the next candidate.
+pub(crate) fn spawn_traceroute(target: &str, settings: &TraceSettings) -> Result<Child> {
+    let candidates = traceroute_commands(target);
+
+    for (program, extra) in candidates {
+        let args = traceroute_args_with_flags(target, settings, &detect_traceroute_flags(program));
+        let spawned = Command::new(program)
+            .args(*extra)
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        match spawned {
+            Ok(child) => return Ok(child),
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(TraceError::SpawnFailed {
+                    target: target.to_string(),
+                    message: format!("{program}: {err}"),
+                }
+                .into());
+            }
+        }
+    }
+
+    let tried: Vec<String> = candidates
+        .iter()
+        .map(|(program, extra)| {
+            std::iter::once(*program)
+                .chain(extra.iter().copied())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    Err(anyhow!(
+        "no traceroute binary found for {target} (tried: {})",
+        tried.join(", ")
+    ))
+}
+
+/// Asks the routing table which local address would be used to reach
+/// `target`: `ip route get` on Linux, `route get` plus `ipconfig getifaddr`
+/// on macOS. Returns `None` when the tools are missing or the output is not
+/// understood.
+pub fn detect_source_ip(target: &str) -> Option<String> {
+    if cfg!(target_os = "linux") {
+        let output = command_stdout(Command::new("ip").args(["route", "get", target]))?;
+        parse_ip_route_get(&output)
+    } else if cfg!(target_os = "macos") {
+        let output = command_stdout(Command::new("route").args(["-n", "get", target]))?;
+        let interface = parse_route_get_interface(&output)?;
+        let address = command_stdout(Command::new("ipconfig").args(["getifaddr", &interface]))?;
+        let address = address.trim();
+        (!address.is_empty()).then(|| address.to_string())
+    } else {
+        None
+    }
+}
+
+fn command_stdout(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Parses `1.1.1.1 via 192.168.1.1 dev wlan0 src 192.168.1.20 uid 1000`.
+pub fn parse_ip_route_get(output: &str) -> Option<String> {
+    let mut tokens = output.split_whitespace();
+    tokens.find(|token| *token == "src")?;
+    tokens.next().map(|token| token.to_string())
+}
+
+/// Parses the `interface: en0` line of macOS `route get` output.
+pub fn parse_route_get_interface(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("interface:")?.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_kills_overrunning_process() {
+        let child = Command::new("sleep").arg("5").spawn().unwrap();
+        let child = Arc::new(Mutex::new(child));
+        let started = std::time::Instant::now();
+
+        let watchdog = Watchdog::start(Arc::clone(&child), Duration::from_millis(100));
+        let status = loop {
+            if let Some(status) = child.lock().unwrap().try_wait().unwrap() {
+                break status;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        assert!(watchdog.finish());
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_stays_quiet_when_finished_in_time() {
+        let child = Command::new("true").spawn().unwrap();
+        let child = Arc::new(Mutex::new(child));
+        let watchdog = Watchdog::start(Arc::clone(&child), Duration::from_secs(5));
+        child.lock().unwrap().wait().unwrap();
+        assert!(!watchdog.finish());
+    }
+
+    #[test]
+    fn ipv6_targets_prefer_traceroute6() {
+        let programs: Vec<&str> = traceroute_commands("2606:4700::1111")
+            .iter()
+            .map(|(program, _)| *program)
+            .collect();
+        assert_eq!(programs, vec!["traceroute6", "traceroute"]);
+        assert_eq!(traceroute_commands("2606:4700::1111")[1].1, &["-6"]);
+        assert_eq!(traceroute_commands("1.1.1.1"), &[("traceroute", &[][..])]);
+    }
+
+    #[test]
+    fn probe_size_is_a_trailing_positional_argument() {
+        assert_eq!(traceroute_size_flag("linux", 1400), vec!["1400"]);
+        assert_eq!(traceroute_size_flag("macos", 1400), vec!["1400"]);
+        assert!(traceroute_size_flag("windows", 1400).is_empty());
+
+        let settings = TraceSettings::default();
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &settings, &TracerouteFlags::WAIT_ONLY),
+            vec!["-n", "-q", "3", "-m", "30", "-w", "2", "1.1.1.1"]
+        );
+        assert_eq!(
+            traceroute_args("1.1.1.1", &settings),
+            vec!["-n", "-q", "3", "-m", "30", "-w", "2", "1.1.1.1"]
+        );
+    }
+
+    #[test]
+    fn parse_flags_from_help_text() {
+        let linux = "  -e  --extensions              Show ICMP extensions (if present), including MPLS\n  -N squeries  --sim-queries=squeries\n                              Set the number of probes to be tried simultaneously\n  -w MAX,HERE,NEAR  --wait=MAX,HERE,NEAR\n";
+        assert_eq!(
+            parse_traceroute_flags(linux),
+            TracerouteFlags {
+                wait: true,
+                long_wait: true,
+                sim_queries: true,
+                extensions: true,
+            }
+        );
+        let bsd = "usage: traceroute [-adDeFInrSvx] [-f first_ttl] [-g gateway] [-i iface]\n\t[-w waittime] [-z pausemsecs] host [packetlen]\n";
+        assert_eq!(parse_traceroute_flags(bsd), TracerouteFlags::WAIT_ONLY);
+        assert_eq!(
+            parse_traceroute_flags("traceroute: unknown option"),
+            TracerouteFlags::default()
+        );
+    }
+
+    #[test]
+    fn extensions_flag_asks_for_mpls_labels() {
+        let flags = TracerouteFlags {
+            extensions: true,
+            ..TracerouteFlags::WAIT_ONLY
+        };
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &TraceSettings::default(), &flags),
+            vec!["-n", "-q", "3", "-m", "30", "-e", "-w", "2", "1.1.1.1"]
+        );
+    }
+
+    #[test]
+    fn both_timeout_modes_work_with_wait_only() {
+        let mut settings = TraceSettings {
+            timeout_ms: 6000,
+            ..TraceSettings::default()
+        };
+        let flags = TracerouteFlags::WAIT_ONLY;
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &settings, &flags),
+            vec!["-n", "-q", "3", "-m", "30", "-w", "6", "1.1.1.1"]
+        );
+
+        settings.timeout_mode = TimeoutMode::PerHop;
+        assert_eq!(timeout_mode_warning(&settings, &flags), None);
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &settings, &flags),
+            vec!["-n", "-q", "3", "-m", "30", "-w", "2", "1.1.1.1"]
+        );
+        // 2.5 s per probe rounds down, keeping the hop within 5 s.
+        settings.timeout_ms = 5000;
+        settings.probes = 2;
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &settings, &flags),
+            vec!["-n", "-q", "2", "-m", "30", "-w", "2", "1.1.1.1"]
+        );
+        // Under a second per probe cannot be expressed with -w, so the
+        // command still runs with -w 1 and the caller is warned.
+        settings.probes = 6;
+        assert!(timeout_mode_warning(&settings, &flags).is_some());
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &settings, &flags),
+            vec!["-n", "-q", "6", "-m", "30", "-w", "1", "1.1.1.1"]
+        );
+        // The defaults (2000 ms over 3 probes) fall back the same way.
+        let defaults = TraceSettings {
+            timeout_mode: TimeoutMode::PerHop,
+            ..TraceSettings::default()
+        };
+        assert!(timeout_mode_warning(&defaults, &flags).is_some());
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &defaults, &flags),
+            vec!["-n", "-q", "3", "-m", "30", "-w", "1", "1.1.1.1"]
+        );
+
+        let linux = TracerouteFlags {
+            sim_queries: true,
+            ..flags
+        };
+        assert_eq!(timeout_mode_warning(&settings, &linux), None);
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &settings, &linux),
+            vec!["-n", "-q", "6", "-m", "30", "-w", "5", "1.1.1.1"]
+        );
+        settings.probes = 20;
+        assert_eq!(
+            traceroute_args_with_flags("1.1.1.1", &settings, &linux),
+            vec!["-n", "-q", "20", "-m", "30", "-N", "20", "-w", "5", "1.1.1.1"]
+        );
+        assert_eq!("per-hop".parse::<TimeoutMode>(), Ok(TimeoutMode::PerHop));
+        assert!("hop".parse::<TimeoutMode>().is_err());
+    }
+
+    #[test]
+    fn pathping_args_follow_settings() {
+        let settings = TraceSettings {
+            max_hops: 12,
+            probes: 50,
+            timeout_ms: 1500,
+            ..TraceSettings::default()
+        };
+        assert_eq!(
+            pathping_args("8.8.8.8", &settings),
+            vec!["-n", "-h", "12", "-q", "50", "-w", "1500", "8.8.8.8"]
+        );
+    }
+
+    #[test]
+    fn parse_source_from_ip_route_get() {
+        let output = "1.1.1.1 via 192.168.1.1 dev wlan0 src 192.168.1.20 uid 1000 \n    cache \n";
+        assert_eq!(parse_ip_route_get(output).as_deref(), Some("192.168.1.20"));
+        assert_eq!(parse_ip_route_get("unreachable 10.9.9.9"), None);
+    }
+
+    #[test]
+    fn parse_interface_from_route_get() {
+        let output = "   route to: 1.1.1.1\ndestination: default\n    gateway: 192.168.1.1\n  interface: en0\n      flags: <UP,GATEWAY,DONE,STATIC,PRCLONING>\n";
+        assert_eq!(parse_route_get_interface(output).as_deref(), Some("en0"));
+    }
 }
@@ -1,16 +1,28 @@
 use crate::parser::parse_hop_line;
+use crate::runner::spawn_traceroute;
 use anyhow::{anyhow, Result};
+use ptroute_model::Hop;
 use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio};
-use std::sync::mpsc::{self, Sender};
+use std::process::Child;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum TraceEvent {
     HopUpdate {
         ttl: u32,
         ip: Option<String>,
         rtts: Vec<Option<f64>>,
+        mpls_labels: Vec<u32>,
+    },
+    /// Sent right after the `HopUpdate` for `ttl`: the share of its probes
+    /// that went unanswered. Hops with no probes get no `PacketLoss`.
+    PacketLoss {
+        ttl: u32,
+        loss_fraction: f64,
     },
     Done {
         status: i32,
@@ -20,26 +32,29 @@ pub enum TraceEvent {
     },
 }
 
+/// Handle to a streaming traceroute process. Dropping it leaves the process
+/// running; call [`TraceHandle::kill`] to stop it early.
+#[derive(Debug, Clone)]
+pub struct TraceHandle {
+    child: Arc<Mutex<Child>>,
+}
+
+impl TraceHandle {
+    /// Stops the traceroute if it is still running. A `Done` event still
+    /// follows once the process has been reaped.
+    pub fn kill(&self) {
+        if let Ok(mut child) = self.child.lock() {
+            let _ = child.kill();
+        }
+    }
+}
+
 pub fn spawn_traceroute_stream(
     target: &str,
     settings: &crate::runner::TraceSettings,
     sender: Sender<TraceEvent>,
-) -> Result<()> {
-    let timeout_secs = ((settings.timeout_ms + 999) / 1000).max(1);
-
-    let mut child = Command::new("traceroute")
-        .arg("-n")
-        .arg("-q")
-        .arg(settings.probes.to_string())
-        .arg("-m")
-        .arg(settings.max_hops.to_string())
-        .arg("-w")
-        .arg(timeout_secs.to_string())
-        .arg(target)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|err| anyhow!("failed to spawn traceroute for {target}: {err}"))?;
+) -> Result<TraceHandle> {
+    let mut child = spawn_traceroute(target, settings)?;
 
     let stdout = child
         .stdout
@@ -53,13 +68,9 @@ pub fn spawn_traceroute_stream(
     let tx_out = sender.clone();
     thread::spawn(move || {
         let reader = BufReader::new(stdout);
-        for line in reader.lines().flatten() {
-            if let Ok(hop) = parse_hop_line(&line) {
-                let _ = tx_out.send(TraceEvent::HopUpdate {
-                    ttl: hop.ttl,
-                    ip: hop.ip,
-                    rtts: hop.rtt_ms,
-                });
+        for line in reader.lines().map_while(Result::ok) {
+            for event in hop_line_events(&line) {
+                let _ = tx_out.send(event);
             }
         }
     });
@@ -68,7 +79,7 @@ pub fn spawn_traceroute_stream(
     thread::spawn(move || {
         let reader = BufReader::new(stderr);
         let mut buf = String::new();
-        for line in reader.lines().flatten() {
+        for line in reader.lines().map_while(Result::ok) {
             if !buf.is_empty() {
                 buf.push(' ');
             }
@@ -79,14 +90,60 @@ pub fn spawn_traceroute_stream(
         }
     });
 
+    let child = Arc::new(Mutex::new(child));
+    let waited = Arc::clone(&child);
     let tx_done = sender.clone();
-    thread::spawn(move || {
-        let status = child.wait().ok();
-        let code = status.and_then(|s| s.code()).unwrap_or(-1);
-        let _ = tx_done.send(TraceEvent::Done { status: code });
+    thread::spawn(move || loop {
+        let status = match waited.lock() {
+            Ok(mut child) => child.try_wait(),
+            Err(_) => break,
+        };
+        match status {
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Ok(Some(status)) => {
+                let code = status.code().unwrap_or(-1);
+                let _ = tx_done.send(TraceEvent::Done { status: code });
+                break;
+            }
+            Err(_) => {
+                let _ = tx_done.send(TraceEvent::Done { status: -1 });
+                break;
+            }
+        }
     });
 
-    Ok(())
+    Ok(TraceHandle { child })
+}
+
+/// Events for one line of traceroute output: nothing for lines that aren't
+/// hops, otherwise a `HopUpdate` followed by its `PacketLoss`.
+fn hop_line_events(line: &str) -> Vec<TraceEvent> {
+    let Ok(hop) = parse_hop_line(line) else {
+        return Vec::new();
+    };
+    let loss = loss_fraction(&hop.rtt_ms);
+    let mut events = vec![TraceEvent::HopUpdate {
+        ttl: hop.ttl,
+        ip: hop.ip,
+        rtts: hop.rtt_ms,
+        mpls_labels: hop.mpls_labels,
+    }];
+    if let Some(loss_fraction) = loss {
+        events.push(TraceEvent::PacketLoss {
+            ttl: hop.ttl,
+            loss_fraction,
+        });
+    }
+    events
+}
+
+/// Unanswered probes over total probes, or `None` when there are no probes.
+pub fn loss_fraction(rtts: &[Option<f64>]) -> Option<f64> {
+    if rtts.is_empty() {
+        return None;
+    }
+    let lost = rtts.iter().filter(|rtt| rtt.is_none()).count();
+    Some(lost as f64 / rtts.len() as f64)
 }
 
 pub fn stream_for_target(
@@ -97,3 +154,158 @@ pub fn stream_for_target(
     spawn_traceroute_stream(target, settings, tx)?;
     Ok(rx)
 }
+
+/// Hop-by-hop view of a [`TraceEvent`] stream such as the one returned by
+/// [`stream_for_target`]. `Error` events are skipped and kept for
+/// [`Self::errors`]; the stream ends at `Done` or when every sender is gone.
+pub struct HopStream {
+    rx: Receiver<TraceEvent>,
+    done: bool,
+    errors: Vec<String>,
+}
+
+impl HopStream {
+    pub fn new(rx: Receiver<TraceEvent>) -> Self {
+        Self {
+            rx,
+            done: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Messages of the `Error` events read so far, in order.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// Blocks until the next hop arrives, returning its `(ttl, ip, rtts)`.
+    pub fn next_hop(&mut self) -> Option<(u32, Option<String>, Vec<Option<f64>>)> {
+        self.next_update().map(|hop| (hop.ttl, hop.ip, hop.rtt_ms))
+    }
+
+    /// Consumes the rest of the stream into hops sorted by TTL. A TTL that is
+    /// reported more than once keeps its latest update.
+    pub fn collect_all_hops(&mut self) -> Vec<Hop> {
+        let mut hops: Vec<Hop> = Vec::new();
+        while let Some(hop) = self.next_update() {
+            match hops.iter_mut().find(|existing| existing.ttl == hop.ttl) {
+                Some(existing) => *existing = hop,
+                None => hops.push(hop),
+            }
+        }
+        hops.sort_by_key(|hop| hop.ttl);
+        hops
+    }
+
+    fn next_update(&mut self) -> Option<Hop> {
+        while !self.done {
+            match self.rx.recv() {
+                Ok(TraceEvent::HopUpdate {
+                    ttl,
+                    ip,
+                    rtts,
+                    mpls_labels,
+                }) => {
+                    return Some(Hop {
+                        ttl,
+                        ip,
+                        rtt_ms: rtts,
+                        mpls_labels,
+                    })
+                }
+                Ok(TraceEvent::PacketLoss { .. }) => {}
+                Ok(TraceEvent::Error { message }) => self.errors.push(message),
+                Ok(TraceEvent::Done { .. }) | Err(_) => self.done = true,
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(ttl: u32, ip: Option<&str>, rtt: Option<f64>) -> TraceEvent {
+        TraceEvent::HopUpdate {
+            ttl,
+            ip: ip.map(str::to_string),
+            rtts: vec![rtt],
+            mpls_labels: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn collect_all_hops_stops_at_done() {
+        let (tx, rx) = mpsc::channel();
+        for event in [
+            update(2, Some("10.0.0.2"), Some(4.0)),
+            TraceEvent::Error {
+                message: "icmp socket: permission denied".to_string(),
+            },
+            update(1, Some("10.0.0.1"), Some(1.0)),
+            update(3, None, None),
+            update(2, Some("10.0.0.22"), Some(5.0)),
+            TraceEvent::Done { status: 0 },
+            update(4, Some("10.0.0.4"), Some(9.0)),
+        ] {
+            tx.send(event).unwrap();
+        }
+
+        let mut stream = HopStream::new(rx);
+        let hops = stream.collect_all_hops();
+        assert_eq!(stream.errors(), ["icmp socket: permission denied"]);
+        let summary: Vec<_> = hops
+            .iter()
+            .map(|hop| (hop.ttl, hop.ip.as_deref(), hop.rtt_ms.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, Some("10.0.0.1"), vec![Some(1.0)]),
+                (2, Some("10.0.0.22"), vec![Some(5.0)]),
+                (3, None, vec![None]),
+            ]
+        );
+    }
+
+    #[test]
+    fn hop_lines_report_packet_loss() {
+        let events = hop_line_events(" 3  * * *");
+        assert!(matches!(
+            events.as_slice(),
+            [
+                TraceEvent::HopUpdate { ttl: 3, ip: None, .. },
+                TraceEvent::PacketLoss {
+                    ttl: 3,
+                    loss_fraction
+                },
+            ] if *loss_fraction == 1.0
+        ));
+
+        let events = hop_line_events(" 2  10.0.0.2  1.000 ms * 3.000 ms");
+        assert!(matches!(
+            events.last(),
+            Some(TraceEvent::PacketLoss { ttl: 2, loss_fraction })
+                if (*loss_fraction - 1.0 / 3.0).abs() < 1e-9
+        ));
+
+        assert!(hop_line_events("traceroute to 8.8.8.8 (8.8.8.8), 30 hops max").is_empty());
+        assert_eq!(loss_fraction(&[]), None);
+    }
+
+    #[test]
+    fn next_hop_ends_when_senders_are_gone() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(update(1, Some("10.0.0.1"), Some(1.0))).unwrap();
+        drop(tx);
+
+        let mut stream = HopStream::new(rx);
+        assert_eq!(
+            stream.next_hop(),
+            Some((1, Some("10.0.0.1".to_string()), vec![Some(1.0)]))
+        );
+        assert_eq!(stream.next_hop(), None);
+        assert!(stream.collect_all_hops().is_empty());
+    }
+}
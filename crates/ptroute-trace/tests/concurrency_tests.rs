@@ -1,8 +1,11 @@
-use ptroute_trace::{run_traces_with_runner, TraceSettings, TracerouteRunner};
+use ptroute_trace::{
+    run_traces_batched_with_runner, run_traces_with_runner, traceroute_size_flag,
+    MockTracerouteRunner, TraceError, TraceSettings, TraceTimeout, TracerouteRunner,
+};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Clone)]
 struct FakeRunner {
@@ -34,6 +37,52 @@ impl TracerouteRunner for FakeRunner {
     }
 }
 
+#[test]
+fn batched_runs_keep_single_batch_order() {
+    let mut delays = HashMap::new();
+    for (target, ms) in [("a", 30), ("c", 10), ("f", 20)] {
+        delays.insert(target.to_string(), Duration::from_millis(ms));
+    }
+    let targets: Vec<String> = ["a", "b", "c", "d", "e", "f"]
+        .iter()
+        .map(|target| target.to_string())
+        .collect();
+    let settings = TraceSettings::default();
+    let order = |results: Vec<ptroute_trace::TraceJobResult>| {
+        results
+            .into_iter()
+            .map(|job| (job.target, job.repeat))
+            .collect::<Vec<_>>()
+    };
+
+    let runner = Arc::new(FakeRunner::new(delays));
+    let single = order(run_traces_batched_with_runner(
+        &targets,
+        &settings,
+        2,
+        0,
+        4,
+        0,
+        Arc::clone(&runner),
+    ));
+    let batched = order(run_traces_batched_with_runner(
+        &targets,
+        &settings,
+        2,
+        0,
+        4,
+        2,
+        Arc::clone(&runner),
+    ));
+
+    assert_eq!(single.len(), 12);
+    assert_eq!(batched, single);
+    assert_eq!(
+        order(run_traces_with_runner(&targets, &settings, 2, 0, 4, runner)),
+        single
+    );
+}
+
 #[test]
 fn ordering_is_stable_with_concurrency() {
     let mut delays = HashMap::new();
@@ -83,3 +132,271 @@ fn ordering_matches_between_concurrency_levels() {
 
     assert_eq!(order_one, order_two);
 }
+
+struct SourceRunner;
+
+impl TracerouteRunner for SourceRunner {
+    fn run(&self, target: &str, _settings: &TraceSettings) -> anyhow::Result<String> {
+        Ok(format!(
+            "traceroute to {0} ({0}), 30 hops max\n 1  {0}  1.0 ms",
+            target
+        ))
+    }
+
+    fn source_ip(&self, _target: &str) -> Option<String> {
+        Some("192.0.2.10".to_string())
+    }
+}
+
+#[test]
+fn source_ip_is_detected_only_when_enabled() {
+    let targets = vec!["a".to_string()];
+    let mut settings = TraceSettings::default();
+
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, Arc::new(SourceRunner));
+    assert_eq!(results[0].result.as_ref().unwrap().source_ip, None);
+
+    settings.detect_source_ip = true;
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, Arc::new(SourceRunner));
+    assert_eq!(
+        results[0].result.as_ref().unwrap().source_ip.as_deref(),
+        Some("192.0.2.10")
+    );
+}
+
+#[test]
+fn total_timeout_cuts_off_slow_runner() {
+    let mut delays = HashMap::new();
+    delays.insert("stuck".to_string(), Duration::from_secs(2));
+    let runner = Arc::new(FakeRunner::new(delays));
+    let targets = vec!["stuck".to_string(), "quick".to_string()];
+    let settings = TraceSettings {
+        total_timeout_ms: Some(200),
+        ..TraceSettings::default()
+    };
+
+    let started = Instant::now();
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 2, runner);
+    let elapsed = started.elapsed();
+
+    assert!(elapsed < Duration::from_millis(300), "took {elapsed:?}");
+    assert!(results[0].timed_out);
+    assert!(results[0].result.as_ref().unwrap().hops.is_empty());
+    assert!(!results[1].timed_out);
+    assert_eq!(results[1].result.as_ref().unwrap().hops.len(), 1);
+}
+
+#[test]
+fn overrunning_call_keeps_its_concurrency_slot() {
+    let mut delays = HashMap::new();
+    delays.insert("stuck".to_string(), Duration::from_millis(300));
+    let runner = Arc::new(FakeRunner::new(delays));
+    let targets = vec!["stuck".to_string(), "quick".to_string()];
+    let settings = TraceSettings {
+        total_timeout_ms: Some(50),
+        ..TraceSettings::default()
+    };
+
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, Arc::clone(&runner));
+
+    // With one slot, "quick" only ran once the stuck call had returned.
+    assert!(results[0].timed_out);
+    assert!(!results[1].timed_out);
+    assert_eq!(runner.counts.lock().unwrap()["stuck"], 1);
+}
+
+struct PartialRunner;
+
+impl TracerouteRunner for PartialRunner {
+    fn run(&self, target: &str, _settings: &TraceSettings) -> anyhow::Result<String> {
+        Err(TraceTimeout {
+            target: target.to_string(),
+            after_ms: 100,
+            partial_output: format!(
+                "traceroute to {0} ({0}), 30 hops max\n 1  10.0.0.1  1.0 ms\n 2  10.0.0.2  2.0 ms",
+                target
+            ),
+        }
+        .into())
+    }
+}
+
+#[test]
+fn timeout_keeps_partial_output() {
+    let targets = vec!["203.0.113.9".to_string()];
+    let settings = TraceSettings::default();
+
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, Arc::new(PartialRunner));
+
+    assert!(results[0].timed_out);
+    let parsed = results[0].result.as_ref().unwrap();
+    assert_eq!(parsed.target, "203.0.113.9");
+    assert_eq!(parsed.hops.len(), 2);
+}
+
+#[test]
+fn mock_runner_substitutes_for_system_runner() {
+    let responses = HashMap::from([
+        (
+            "1.1.1.1".to_string(),
+            "traceroute to 1.1.1.1 (1.1.1.1), 30 hops max\n 1  192.168.1.1  1.0 ms\n 2  1.1.1.1  9.0 ms"
+                .to_string(),
+        ),
+        (
+            "8.8.8.8".to_string(),
+            "traceroute to 8.8.8.8 (8.8.8.8), 30 hops max\n 1  192.168.1.1  1.1 ms".to_string(),
+        ),
+    ]);
+    let runner = Arc::new(MockTracerouteRunner::new_with_latency(
+        responses,
+        Duration::from_millis(5),
+    ));
+    let targets = vec!["1.1.1.1".to_string(), "8.8.8.8".to_string()];
+
+    let results = run_traces_with_runner(
+        &targets,
+        &TraceSettings::default(),
+        2,
+        0,
+        2,
+        Arc::clone(&runner),
+    );
+
+    assert_eq!(runner.call_count(), 4);
+    assert_eq!(results.len(), 4);
+    let first = results[0].result.as_ref().unwrap();
+    assert_eq!(first.target, "1.1.1.1");
+    assert_eq!(first.hops.len(), 2);
+    assert_eq!(results[2].result.as_ref().unwrap().hops.len(), 1);
+}
+
+#[test]
+fn mock_runner_records_probe_size_argument() {
+    let responses = HashMap::from([(
+        "1.1.1.1".to_string(),
+        "traceroute to 1.1.1.1 (1.1.1.1), 30 hops max\n 1  192.168.1.1  1.0 ms".to_string(),
+    )]);
+    let runner = Arc::new(MockTracerouteRunner::new(responses));
+    let targets = vec!["1.1.1.1".to_string()];
+
+    let settings = TraceSettings::default();
+    run_traces_with_runner(&targets, &settings, 1, 0, 1, Arc::clone(&runner));
+    let settings = TraceSettings {
+        probe_size_bytes: Some(1400),
+        ..settings
+    };
+    run_traces_with_runner(&targets, &settings, 1, 0, 1, Arc::clone(&runner));
+
+    let commands = runner.commands();
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].last().unwrap(), "1.1.1.1");
+    let expected = traceroute_size_flag(std::env::consts::OS, 1400);
+    assert!(commands[1].ends_with(&expected), "{:?}", commands[1]);
+    if cfg!(any(target_os = "linux", target_os = "macos")) {
+        assert_eq!(commands[1][commands[1].len() - 2..], ["1.1.1.1", "1400"]);
+    }
+}
+
+#[test]
+#[should_panic(expected = "no response registered for target \"9.9.9.9\"")]
+fn mock_runner_panics_on_unknown_target() {
+    let runner = MockTracerouteRunner::new(HashMap::new());
+    let _ = runner.run("9.9.9.9", &TraceSettings::default());
+}
+
+#[test]
+#[should_panic(expected = "no response registered for target \"9.9.9.9\"")]
+fn worker_panics_reach_the_caller() {
+    let runner = Arc::new(MockTracerouteRunner::new(HashMap::new()));
+    let targets = vec!["9.9.9.9".to_string()];
+    let _ = run_traces_with_runner(&targets, &TraceSettings::default(), 1, 0, 1, runner);
+}
+
+#[test]
+fn panics_under_total_timeout_fail_only_their_job() {
+    let runner = Arc::new(MockTracerouteRunner::new(HashMap::new()));
+    let targets = vec!["9.9.9.9".to_string()];
+    let settings = TraceSettings {
+        total_timeout_ms: Some(1000),
+        ..TraceSettings::default()
+    };
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, runner);
+    assert_eq!(results.len(), 1);
+    assert!(!results[0].timed_out);
+    assert!(results[0].result.as_ref().unwrap_err().contains("panicked"));
+}
+
+/// Fails to spawn `failures` times, then returns `output`.
+struct FlakyRunner {
+    failures: u32,
+    output: String,
+    calls: Mutex<u32>,
+}
+
+impl FlakyRunner {
+    fn new(failures: u32, output: &str) -> Self {
+        Self {
+            failures,
+            output: output.to_string(),
+            calls: Mutex::new(0),
+        }
+    }
+}
+
+impl TracerouteRunner for FlakyRunner {
+    fn run(&self, target: &str, _settings: &TraceSettings) -> anyhow::Result<String> {
+        let mut calls = self.calls.lock().unwrap();
+        *calls += 1;
+        if *calls <= self.failures {
+            return Err(TraceError::SpawnFailed {
+                target: target.to_string(),
+                message: "Resource temporarily unavailable".to_string(),
+            }
+            .into());
+        }
+        Ok(self.output.clone())
+    }
+}
+
+const FLAKY_OUTPUT: &str = "traceroute to flaky (10.0.0.9), 30 hops max\n 1  10.0.0.9  1.0 ms";
+
+#[test]
+fn spawn_failures_are_retried() {
+    let targets = vec!["flaky".to_string()];
+    let settings = TraceSettings {
+        max_retries: 3,
+        retry_delay_ms: 1,
+        ..TraceSettings::default()
+    };
+
+    let runner = Arc::new(FlakyRunner::new(2, FLAKY_OUTPUT));
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, runner);
+    assert!(results[0].result.is_ok(), "{:?}", results[0].result);
+    assert_eq!(results[0].attempts, 3);
+
+    let runner = Arc::new(FlakyRunner::new(2, FLAKY_OUTPUT));
+    let settings = TraceSettings {
+        max_retries: 1,
+        ..settings
+    };
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, runner);
+    let err = results[0].result.as_ref().unwrap_err();
+    assert!(err.contains("failed to spawn"), "{err}");
+    assert_eq!(results[0].attempts, 2);
+}
+
+#[test]
+fn parse_failures_are_not_retried() {
+    let targets = vec!["flaky".to_string()];
+    let settings = TraceSettings {
+        max_retries: 3,
+        ..TraceSettings::default()
+    };
+
+    let runner = Arc::new(FlakyRunner::new(0, " 99999999999  10.0.0.9  1.0 ms"));
+    let results = run_traces_with_runner(&targets, &settings, 1, 0, 1, Arc::clone(&runner));
+    let err = results[0].result.as_ref().unwrap_err();
+    assert!(err.starts_with("parse failed"), "{err}");
+    assert_eq!(results[0].attempts, 1);
+    assert_eq!(*runner.calls.lock().unwrap(), 1);
+}
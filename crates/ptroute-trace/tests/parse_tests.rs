@@ -1,4 +1,11 @@
-use ptroute_trace::parse_traceroute_n;
+use ptroute_model::TraceProtocol;
+use ptroute_trace::{
+    is_pathping_output, loss_fraction, parse_pathping, parse_traceroute_n,
+    parse_traceroute_n_strict, parse_traceroute_n_with_mode, run_traces_with_runner,
+    MockTracerouteRunner, StrictMode, TimeoutMode, TraceEvent, TraceSettings,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[test]
 fn parse_linux_numeric_with_edge_cases() {
@@ -6,6 +13,7 @@ fn parse_linux_numeric_with_edge_cases() {
     let run = parse_traceroute_n(text).unwrap();
 
     assert_eq!(run.target, "203.0.113.1");
+    assert_eq!(run.protocol, TraceProtocol::Udp);
     assert_eq!(run.hops.len(), 5);
 
     let hop1 = &run.hops[0];
@@ -42,6 +50,49 @@ fn parse_macos_numeric() {
     assert_eq!(run.hops[3].ip.as_deref(), Some("1.1.1.1"));
 }
 
+#[test]
+fn parse_busybox_numeric() {
+    let text = include_str!("fixtures/traceroute_busybox_1.txt");
+    let run = parse_traceroute_n(text).unwrap();
+
+    // BusyBox prints the name it was given first and the resolved address in
+    // parentheses; the address is the target.
+    assert_eq!(run.target, "8.8.8.8");
+    assert_eq!(run.source_ip, None);
+    assert_eq!(run.hops.len(), 5);
+    assert_eq!(
+        run.hops[0].rtt_ms,
+        vec![Some(1.008), Some(0.987), Some(0.882)]
+    );
+    // A bare `!` is a lost probe.
+    assert_eq!(run.hops[1].rtt_ms, vec![Some(8.412), None, Some(8.101)]);
+    assert_eq!(loss_fraction(&run.hops[1].rtt_ms), Some(1.0 / 3.0));
+    assert_eq!(run.hops[2].ip, None);
+    assert_eq!(run.hops[2].rtt_ms, vec![None, None, None]);
+    assert_eq!(run.hops[3].rtt_ms, vec![Some(14.22), None, Some(13.87)]);
+    assert_eq!(run.hops[4].ip.as_deref(), Some("8.8.8.8"));
+}
+
+#[test]
+fn parse_cisco_ios_msec() {
+    let text = include_str!("fixtures/traceroute_cisco_ios_1.txt");
+    let run = parse_traceroute_n(text).unwrap();
+
+    assert_eq!(run.target, "8.8.8.8");
+    assert_eq!(run.hops.len(), 5);
+    // The MPLS annotation between address and RTTs is not a probe.
+    assert_eq!(run.hops[0].ip.as_deref(), Some("192.168.1.1"));
+    assert_eq!(run.hops[0].rtt_ms, vec![Some(8.0), Some(4.0), Some(4.0)]);
+    assert_eq!(run.hops[0].mpls_labels, vec![16]);
+    // One probe per line when the hop answers from several addresses.
+    assert_eq!(run.hops[1].ip.as_deref(), Some("10.0.12.2"));
+    assert_eq!(run.hops[1].rtt_ms, vec![Some(12.0), Some(16.0), Some(12.0)]);
+    assert_eq!(run.hops[1].mpls_labels, vec![24, 16]);
+    assert_eq!(run.hops[2].rtt_ms, vec![None, None, None]);
+    assert_eq!(run.hops[3].rtt_ms, vec![Some(20.0), Some(16.0), None]);
+    assert!(run.hops[3].mpls_labels.is_empty());
+}
+
 #[test]
 fn parse_ipv6_numeric() {
     let text = include_str!("fixtures/traceroute_ipv6_1.txt");
@@ -53,6 +104,28 @@ fn parse_ipv6_numeric() {
     assert_eq!(run.hops[3].ip.as_deref(), Some("2606:4700:4700::1111"));
 }
 
+#[test]
+fn parse_ipv6_full_addresses() {
+    let text = include_str!("fixtures/traceroute_ipv6_extended_1.txt");
+    let run = parse_traceroute_n(text).unwrap();
+
+    assert_eq!(run.target, "2001:4860:4860::8888");
+    assert_eq!(run.source_ip.as_deref(), Some("2001:db8:1:2::20"));
+    assert_eq!(run.hops.len(), 5);
+    assert_eq!(
+        run.hops[0].ip.as_deref(),
+        Some("2001:0db8:0001:0002:0000:0000:0000:0001")
+    );
+    assert_eq!(run.hops[0].rtt_ms.len(), 3);
+    assert!(run.hops[2].ip.is_none());
+    assert_eq!(
+        run.hops[3].ip.as_deref(),
+        Some("2001:4860:0000:0001:0000:0000:0000:6a1d")
+    );
+    assert_eq!(run.hops[3].rtt_ms.len(), 3);
+    assert_eq!(run.hops[4].ip.as_deref(), Some("2001:4860:4860::8888"));
+}
+
 #[test]
 fn parse_multi_ip_per_hop() {
     let text = include_str!("fixtures/traceroute_multi_ip_1.txt");
@@ -91,3 +164,108 @@ fn parse_continuation_lines() {
     assert_eq!(run.hops[2].ttl, 13);
     assert_eq!(run.hops[2].rtt_ms.len(), 3);
 }
+
+#[test]
+fn strict_mode_accepts_sequential_ttls() {
+    let text = include_str!("fixtures/traceroute_linux_1.txt");
+    let strict = parse_traceroute_n_strict(text).unwrap();
+    assert_eq!(strict, parse_traceroute_n(text).unwrap());
+}
+
+#[test]
+fn strict_mode_rejects_out_of_order_ttls() {
+    let text = include_str!("fixtures/traceroute_out_of_order_1.txt");
+    assert_eq!(parse_traceroute_n(text).unwrap().hops.len(), 4);
+
+    let err = parse_traceroute_n_strict(text).unwrap_err();
+    assert!(err.to_string().contains("hop ttl 2 follows ttl 3"), "{err}");
+
+    assert!(parse_traceroute_n_with_mode(text, StrictMode::Strict).is_err());
+    assert_eq!(
+        parse_traceroute_n_with_mode(text, StrictMode::Lenient).unwrap(),
+        parse_traceroute_n(text).unwrap()
+    );
+}
+
+#[test]
+fn strict_mode_rejects_duplicate_ttls() {
+    let text = include_str!("fixtures/traceroute_duplicate_ttl_1.txt");
+    assert!(parse_traceroute_n(text).is_ok());
+    assert!(parse_traceroute_n_strict(text).is_err());
+}
+
+#[test]
+fn parse_pathping_topology_and_statistics() {
+    let text = include_str!("fixtures/traceroute_pathping_1.txt");
+    let linux = include_str!("fixtures/traceroute_linux_1.txt");
+    assert!(is_pathping_output(text));
+    assert!(!is_pathping_output(linux));
+
+    let run = parse_pathping(text).unwrap();
+    assert_eq!(run.target, "8.8.8.8");
+    assert_eq!(run.source_ip.as_deref(), Some("192.168.1.20"));
+    assert_eq!(run.protocol, TraceProtocol::Icmp);
+    let ttls: Vec<u32> = run.hops.iter().map(|hop| hop.ttl).collect();
+    assert_eq!(ttls, vec![1, 2, 3, 4, 5]);
+
+    assert_eq!(run.hops[0].ip.as_deref(), Some("192.168.1.1"));
+    assert_eq!(run.hops[0].rtt_ms, vec![Some(1.0)]);
+    assert_eq!(run.hops[1].rtt_ms, vec![Some(12.0)]);
+    assert!(run.hops[2].ip.is_none());
+    assert_eq!(run.hops[2].rtt_ms, vec![None]);
+    assert_eq!(run.hops[4].ip.as_deref(), Some("8.8.8.8"));
+    assert_eq!(run.hops[4].rtt_ms, vec![Some(17.0)]);
+}
+
+#[test]
+fn parse_pathping_without_statistics_keeps_route() {
+    let text = "Tracing route to 1.1.1.1 over a maximum of 30 hops\n\n  0  10.0.0.5\n  1  router.lan [10.0.0.1]\n  2  1.1.1.1\n";
+    let run = parse_pathping(text).unwrap();
+    assert_eq!(run.hops.len(), 2);
+    assert_eq!(run.hops[0].ip.as_deref(), Some("10.0.0.1"));
+    assert!(run.hops[0].rtt_ms.is_empty());
+    assert!(parse_pathping("  1  10.0.0.1\n").is_err());
+}
+
+#[test]
+fn job_runner_parses_pathping_output() {
+    let responses = HashMap::from([(
+        "8.8.8.8".to_string(),
+        include_str!("fixtures/traceroute_pathping_1.txt").to_string(),
+    )]);
+    let runner = Arc::new(MockTracerouteRunner::new(responses));
+    let targets = vec!["8.8.8.8".to_string()];
+    let results = run_traces_with_runner(&targets, &TraceSettings::default(), 1, 0, 1, runner);
+    let run = results[0].result.as_ref().unwrap();
+    assert_eq!(run.hops.len(), 5);
+    assert_eq!(run.source_ip.as_deref(), Some("192.168.1.20"));
+    assert_eq!(run.protocol, TraceProtocol::Icmp);
+}
+
+#[test]
+fn public_enums_need_wildcard_arms_downstream() {
+    // These enums are #[non_exhaustive]: outside the crate, each match below
+    // only compiles with its `_` arm.
+    let describe = |event: &TraceEvent| match event {
+        TraceEvent::HopUpdate { ttl, .. } => format!("hop {ttl}"),
+        TraceEvent::PacketLoss { ttl, .. } => format!("loss {ttl}"),
+        TraceEvent::Done { status } => format!("done {status}"),
+        TraceEvent::Error { message } => format!("error {message}"),
+        _ => "unknown".to_string(),
+    };
+    assert_eq!(describe(&TraceEvent::Done { status: 0 }), "done 0");
+
+    let timeout_flag = |mode: TimeoutMode| match mode {
+        TimeoutMode::PerProbe => "probe",
+        TimeoutMode::PerHop => "hop",
+        _ => "unknown",
+    };
+    assert_eq!(timeout_flag(TimeoutMode::PerHop), "hop");
+
+    let strictness = |mode: StrictMode| match mode {
+        StrictMode::Lenient => "lenient",
+        StrictMode::Strict => "strict",
+        _ => "unknown",
+    };
+    assert_eq!(strictness(StrictMode::Strict), "strict");
+}
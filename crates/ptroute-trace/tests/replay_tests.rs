@@ -0,0 +1,127 @@
+use ptroute_trace::{
+    run_traces_with_runner, MockTracerouteRunner, RecordingRunner, ReplayRunner,
+    SystemTracerouteRunner, TraceSettings, TracerouteRunner,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ptroute-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn replay_reproduces_recorded_runs() {
+    let dir = temp_dir("replay-round-trip");
+    let responses = HashMap::from([
+        ("1.1.1.1".to_string(), fixture("traceroute_linux_1.txt")),
+        (
+            "2606:4700:4700::1111".to_string(),
+            fixture("traceroute_ipv6_1.txt"),
+        ),
+    ]);
+    let targets: Vec<String> = responses.keys().cloned().collect();
+    let settings = TraceSettings::default();
+
+    let recorder = Arc::new(RecordingRunner::new(
+        MockTracerouteRunner::new(responses),
+        dir.clone(),
+    ));
+    let recorded = run_traces_with_runner(&targets, &settings, 2, 0, 2, recorder);
+
+    let replay = ReplayRunner::new(dir.clone()).unwrap();
+    assert_eq!(replay.len(), 4);
+    assert!(dir.join("2606%3A4700%3A4700%3A%3A1111_rep1.txt").exists());
+    let replayed = run_traces_with_runner(&targets, &settings, 2, 0, 2, Arc::new(replay));
+    let _ = fs::remove_dir_all(&dir);
+
+    assert_eq!(recorded.len(), replayed.len());
+    for (before, after) in recorded.iter().zip(&replayed) {
+        assert_eq!(before.target, after.target);
+        assert_eq!(before.repeat, after.repeat);
+        assert!(before.result.is_ok());
+        assert_eq!(before.result, after.result);
+    }
+}
+
+#[test]
+fn replay_fails_past_the_last_recording() {
+    let dir = temp_dir("replay-exhausted");
+    let responses = HashMap::from([("1.1.1.1".to_string(), fixture("traceroute_linux_1.txt"))]);
+    let settings = TraceSettings::default();
+    let recorder = RecordingRunner::new(MockTracerouteRunner::new(responses), dir.clone());
+    recorder.run("1.1.1.1", &settings).unwrap();
+    assert_eq!(recorder.into_inner().call_count(), 1);
+
+    let replay = ReplayRunner::new(dir.clone()).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(replay.run("1.1.1.1", &settings).is_ok());
+    let err = replay.run("1.1.1.1", &settings).unwrap_err();
+    assert!(err.to_string().contains("repeat 1"), "{err}");
+    assert!(replay.run("8.8.8.8", &settings).is_err());
+}
+
+/// Fails its first call, then returns `output`.
+struct FailsOnceRunner {
+    calls: AtomicUsize,
+    output: String,
+}
+
+impl TracerouteRunner for FailsOnceRunner {
+    fn run(&self, target: &str, _settings: &TraceSettings) -> anyhow::Result<String> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            anyhow::bail!("traceroute failed for {target}");
+        }
+        Ok(self.output.clone())
+    }
+}
+
+#[test]
+fn failed_runs_leave_no_gap_in_recordings() {
+    let dir = temp_dir("record-after-failure");
+    let settings = TraceSettings::default();
+    let recorder = RecordingRunner::new(
+        FailsOnceRunner {
+            calls: AtomicUsize::new(0),
+            output: fixture("traceroute_linux_1.txt"),
+        },
+        dir.clone(),
+    );
+    assert!(recorder.run("1.1.1.1", &settings).is_err());
+    recorder.run("1.1.1.1", &settings).unwrap();
+    recorder.run("1.1.1.1", &settings).unwrap();
+
+    let recorded = dir.join("1.1.1.1_rep0.txt").exists() && dir.join("1.1.1.1_rep1.txt").exists();
+    let replay = ReplayRunner::new(dir.clone()).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(recorded);
+    assert_eq!(replay.len(), 2);
+}
+
+#[test]
+fn replay_requires_an_existing_directory() {
+    assert!(ReplayRunner::new(temp_dir("replay-missing")).is_err());
+}
+
+#[test]
+fn recording_forwards_total_timeout_enforcement() {
+    let dir = temp_dir("record-timeout");
+    let system = RecordingRunner::new(SystemTracerouteRunner, dir.clone());
+    assert_eq!(
+        system.enforces_total_timeout(),
+        SystemTracerouteRunner.enforces_total_timeout()
+    );
+    let mock = RecordingRunner::new(MockTracerouteRunner::new(HashMap::new()), dir);
+    assert!(!mock.enforces_total_timeout());
+}